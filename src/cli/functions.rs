@@ -1,11 +1,18 @@
-use std::{fs, io::Write, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
 
-use crate::data::prelude::*;
+use crate::{cli::StashCommand, data::prelude::*};
 
 use self::{
     base::write_tree,
     commits::OgitCommit,
+    diff::{DiffFormat, DiffOptions},
     filesystem::{get_object, hash_object, ogit_init},
+    pretty::{PrettyFormat, PrettyOptions},
 };
 
 pub fn init_cmd() {
@@ -15,36 +22,110 @@ pub fn init_cmd() {
     };
 }
 
-pub fn hash_object_cmd(file_path: &str) {
-    let file_content = match filesystem::read_file(Path::new(file_path)) {
-        Ok(f) => f,
+/// Id of `data` as an object of type `variant`, stored in the database when `write` is set.
+fn hash_data(
+    data: &[u8],
+    variant: &objects::OgitObjectType,
+    write: bool,
+    literally: bool,
+) -> Result<String, std::io::Error> {
+    if !literally {
+        objects::check_syntax(variant, data).map_err(std::io::Error::other)?;
+    }
+    let object = if write {
+        hash_object(data, Some(variant.clone()))?
+    } else {
+        objects::OgitObject::new(data, variant.clone())
+    };
+    Ok(object.hex_string())
+}
+
+pub fn hash_object_cmd(
+    files: &[PathBuf],
+    variant: &objects::OgitObjectType,
+    write: bool,
+    stdin: bool,
+    stdin_paths: bool,
+    literally: bool,
+) {
+    let mut inputs = Vec::new();
+    if stdin {
+        let mut data = Vec::new();
+        let read = std::io::stdin().read_to_end(&mut data);
+        inputs.push(read.map(|_| (PathBuf::from("<stdin>"), data)));
+    }
+    let paths = if stdin_paths {
+        std::io::stdin()
+            .lines()
+            .map_while(Result::ok)
+            .map(PathBuf::from)
+            .collect()
+    } else {
+        files.to_vec()
+    };
+    for path in paths {
+        let read = fs::read(&path).map_err(|e| {
+            std::io::Error::other(format!(
+                "could not open '{}' for reading: {e}",
+                path.display()
+            ))
+        });
+        inputs.push(read.map(|data| (path, data)));
+    }
+    for input in inputs {
+        let hashed = input.and_then(|(path, data)| {
+            hash_data(&data, variant, write, literally)
+                .map_err(|e| std::io::Error::other(format!("{}: {e}", path.display())))
+        });
+        match hashed {
+            Ok(id) => println!("{id}"),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(128);
+            }
+        }
+    }
+}
+
+pub fn cat_object_cmd(name: &str, mode: catfile::CatFileMode) {
+    let object = revision::resolve(name).and_then(|id| get_object(&id, None));
+    let object = match object {
+        Ok(object) => object,
+        Err(_) if mode == catfile::CatFileMode::Exists => std::process::exit(1),
         Err(e) => {
-            eprintln!("Error reading content file: {e}");
-            return;
+            eprintln!("error: Not a valid object name {name}: {e}");
+            std::process::exit(128);
         }
     };
-    let object = hash_object(&file_content, None);
-    match object {
-        Ok(obj) => println!("{obj}"),
-        Err(e) => eprintln!("Error: {e}"),
+    let stdout = std::io::stdout();
+    let mut handle = stdout.lock();
+    let written = match mode {
+        catfile::CatFileMode::Raw => handle.write_all(&object.data),
+        catfile::CatFileMode::Type => writeln!(handle, "{}", object.variant),
+        catfile::CatFileMode::Size => writeln!(handle, "{}", object.data.len()),
+        catfile::CatFileMode::Exists => Ok(()),
+        catfile::CatFileMode::Pretty => {
+            catfile::pretty_print(&object).and_then(|out| handle.write_all(&out))
+        }
+    };
+    if let Err(e) = written {
+        eprintln!("error: {e}");
+        std::process::exit(128);
     }
 }
 
-pub fn cat_object_cmd(object_id: &str) {
-    let object = get_object(object_id, None);
-    match object {
-        Ok(obj) => {
-            let stdout = std::io::stdout();
-            let mut handle = stdout.lock();
-            handle.write_all(&obj.data).unwrap();
-        }
-        Err(e) => eprintln!("Error: {e}"),
+pub fn cat_file_batch_cmd(mode: catfile::BatchMode, all_objects: bool) {
+    let stdout = std::io::stdout();
+    let mut handle = stdout.lock();
+    if let Err(e) = catfile::batch(std::io::stdin().lock(), &mut handle, mode, all_objects) {
+        eprintln!("error: {e}");
+        std::process::exit(128);
     }
 }
 
 pub fn write_tree_cmd(directory: Option<&str>) {
     let directory = directory.map(|s| fs::canonicalize(s).unwrap());
-    let tree = write_tree(directory);
+    let tree = write_tree(directory, true);
     match tree {
         Ok(t) => println!("{t}"),
         Err(e) => eprintln!("Error: {e}"),
@@ -59,6 +140,152 @@ pub fn read_tree_cmd(tree_id: &str) {
     }
 }
 
+pub fn ls_tree_cmd(tree_ish: &str, paths: &[String], options: &lstree::LsTreeOptions) {
+    let listed =
+        revision::resolve_tree(tree_ish).and_then(|tree| lstree::ls_tree(&tree, paths, options));
+    match listed {
+        Ok(out) => print!("{out}"),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+pub fn ls_files_cmd(options: &lsfiles::LsFilesOptions) {
+    match lsfiles::ls_files(options) {
+        Ok(out) => print!("{out}"),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+/// Point `name` at the first of `values` when it is at the second, `-d` only takes the old one.
+pub fn update_ref_cmd(name: &str, values: &[String], delete: bool, no_deref: bool, message: &str) {
+    let updated = (|| {
+        let mut transaction = refs::RefTransaction::new();
+        transaction.no_deref = no_deref;
+        let old = |value: Option<&String>| value.map(|old| refs::resolve_value(old)).transpose();
+        match (delete, values) {
+            (true, old_value) if old_value.len() <= 1 => {
+                transaction.delete(name, old(old_value.first())?.as_deref());
+            }
+            (false, [new, old_value @ ..]) => {
+                let new = refs::resolve_value(new)?;
+                transaction.update(name, &new, old(old_value.first())?.as_deref());
+            }
+            _ => {
+                return Err(std::io::Error::other(
+                    "usage: og update-ref [-d] <ref> <new> [<old>]",
+                ))
+            }
+        }
+        transaction.commit(Some(message))
+    })();
+    if let Err(e) = updated {
+        eprintln!("error: {e}");
+        std::process::exit(128);
+    }
+}
+
+pub fn update_ref_stdin_cmd(no_deref: bool, message: &str) {
+    let stdout = std::io::stdout();
+    let mut handle = stdout.lock();
+    if let Err(e) = refs::run_stdin(std::io::stdin().lock(), &mut handle, message, no_deref) {
+        eprintln!("error: {e}");
+        std::process::exit(128);
+    }
+}
+
+/// Show what the symbolic ref `name` points at, point it at `target`, or delete it.
+pub fn symbolic_ref_cmd(
+    name: &str,
+    target: Option<&str>,
+    delete: bool,
+    quiet: bool,
+    short: bool,
+    message: Option<&str>,
+) {
+    let done = (|| {
+        let current = filesystem::read_symbolic_ref(name)?;
+        if delete {
+            if name == "HEAD" {
+                return Err(std::io::Error::other("deleting 'HEAD' is not allowed"));
+            }
+            if current.is_none() {
+                return Err(std::io::Error::other(format!(
+                    "cannot delete {name}, not a symbolic ref"
+                )));
+            }
+            return filesystem::delete_ref(name);
+        }
+        let Some(target) = target else {
+            return match current {
+                Some(current) => {
+                    println!(
+                        "{}",
+                        if short {
+                            refs::shorten(&current)
+                        } else {
+                            &current
+                        }
+                    );
+                    Ok(())
+                }
+                None if quiet => std::process::exit(1),
+                None => Err(std::io::Error::other(format!(
+                    "ref {name} is not a symbolic ref"
+                ))),
+            };
+        };
+        let mut transaction = refs::RefTransaction::new();
+        transaction.update_symbolic(name, target, None);
+        transaction.commit(message)
+    })();
+    if let Err(e) = done {
+        eprintln!("error: {e}");
+        std::process::exit(128);
+    }
+}
+
+/// List refs matching `patterns`, exiting with 1 when there are none.
+pub fn show_ref_cmd(patterns: &[String], options: &showref::ShowRefOptions, quiet: bool) {
+    match showref::show_ref(patterns, options) {
+        Ok(Some(out)) if !quiet => print!("{out}"),
+        Ok(Some(_)) => (),
+        Ok(None) => std::process::exit(1),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if !quiet {
+                eprintln!("error: {e}");
+            }
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+pub fn for_each_ref_cmd(options: &foreachref::ForEachRefOptions) {
+    match foreachref::for_each_ref(options) {
+        Ok(out) => print!("{out}"),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+pub fn pack_refs_cmd(all: bool, prune: bool) {
+    if let Err(e) = packedrefs::pack(all, prune) {
+        eprintln!("error: {e}");
+        std::process::exit(128);
+    }
+}
+
 pub fn commit_tree_cmd(tree_id: &str, parents: Option<&[String]>, message: Option<String>) {
     let commit = base::commit_tree(tree_id, parents.unwrap_or_default(), message);
     match commit {
@@ -67,43 +294,720 @@ pub fn commit_tree_cmd(tree_id: &str, parents: Option<&[String]>, message: Optio
     }
 }
 
-pub fn commit_cmd(message: Option<String>) {
-    let commit = porcelain::commit(message);
+pub fn commit_cmd(message: Option<String>, all: bool) {
+    let commit = porcelain::commit(message, all);
     match commit {
         Ok(c) => println!("{c}"),
         Err(e) => eprintln!("Error: {e}. Aborting commit."),
     }
 }
 
-pub fn log_cmd(commit: Option<String>) {
-    let mut curr_head_id = if let Some(commit) = commit {
-        commit
+pub fn add_cmd(pathspecs: &[PathBuf]) {
+    if let Err(e) = porcelain::add(pathspecs) {
+        eprintln!("fatal: {e}");
+        std::process::exit(128);
+    }
+}
+
+/// Text `log` shows for one commit, without the graph.
+fn log_entry(
+    id: &str,
+    commit: &OgitCommit,
+    decorations: &[String],
+    pretty: &PrettyOptions,
+    paths: &[PathBuf],
+    options: &DiffOptions,
+    format: &DiffFormat,
+) -> Result<String, std::io::Error> {
+    let now = time::get_current_local().fixed_offset();
+    let mut text = pretty::format_commit(id, commit, pretty, decorations, &now);
+    match pretty.format {
+        PrettyFormat::Oneline => (),
+        PrettyFormat::Format { separator, .. } => {
+            if !separator {
+                text.push('\n');
+            }
+        }
+        // the message is followed by an empty line
+        _ => text.push('\n'),
+    }
+    // like git, merges are not diffed since there is no single parent to compare against
+    if format.is_enabled() && commit.parents.len() <= 1 {
+        let changes = diff::limit_to_paths(diff::diff_commit(commit, options)?, paths);
+        let out = diff::render(&changes, format)?;
+        if !out.is_empty() {
+            text.push_str(&out);
+            text.push('\n');
+        }
+    }
+    Ok(text)
+}
+
+pub fn log_cmd(
+    revisions: &[String],
+    walk: &revwalk::WalkOptions,
+    options: &DiffOptions,
+    format: &DiffFormat,
+    graph: bool,
+    pretty: &PrettyOptions,
+    decorate: bool,
+) {
+    let head = ["HEAD".to_string()];
+    let revisions = if revisions.is_empty() {
+        &head
     } else {
-        match filesystem::read_head_file() {
-            Ok(h) => h,
+        revisions
+    };
+    let mut walk = walk.clone();
+    // lanes are only drawn right when children come before their parents
+    if graph && walk.order == revwalk::WalkOrder::Default {
+        walk.order = revwalk::WalkOrder::Topo;
+    }
+    let commits = match revwalk::walk(revisions, &walk) {
+        Ok(commits) => commits,
+        Err(e) => {
+            eprintln!("Error reading commits: {e}");
+            return;
+        }
+    };
+    let placeholders = matches!(pretty.format, PrettyFormat::Format { .. });
+    let decorations = if decorate || placeholders {
+        match revision::decorations() {
+            Ok(decorations) => decorations,
             Err(e) => {
-                eprintln!("Error reading HEAD file: {e}");
+                eprintln!("Error reading refs: {e}");
                 return;
             }
         }
+    } else {
+        HashMap::new()
+    };
+    let shown = commits
+        .iter()
+        .map(|(id, _)| id.clone())
+        .collect::<HashSet<_>>();
+    // a followed file changes its name, its diffs show the rename and older ones the old name
+    let follow_options = DiffOptions {
+        rename_score: options.rename_score.or(Some(diff::DEFAULT_SIMILARITY)),
+        ..options.clone()
+    };
+    let options = if walk.follow {
+        &follow_options
+    } else {
+        options
     };
-    loop {
-        let commit = match OgitCommit::get(&curr_head_id) {
-            Ok(c) => c,
+    let mut paths = walk.paths.clone();
+    let mut lanes = asciigraph::AsciiGraph::new();
+    for (i, (id, commit)) in commits.iter().enumerate() {
+        let names = decorations.get(id).map_or(&[][..], Vec::as_slice);
+        let entry = log_entry(id, commit, names, pretty, &paths, options, format);
+        // --follow takes exactly one path, older commits know it by the name it had before
+        let entry = match entry {
+            Ok(text) if walk.follow => simplify::name_before(commit, &paths[0]).map(|old| {
+                paths = old.map_or(paths.clone(), |old| vec![old]);
+                text
+            }),
+            entry => entry,
+        };
+        let text = match entry {
+            Ok(text) => text,
             Err(e) => {
-                eprintln!("Error reading commit: {e}");
+                eprintln!("Error comparing commit {id}: {e}");
                 return;
             }
         };
-        println!("commit {curr_head_id}");
-        // could be something bad here about using println. such as formatting and other such should be handled by the OgitCommit
-        println!("{commit}");
-        // TODO: this index can be controlled by options passed on the command line
-        // Check out --first-parent
-        // https://git-scm.com/docs/git-log#Documentation/git-log.txt---first-parent
-        curr_head_id = match commit.parents.get(0) {
-            Some(p) => p.clone(),
-            None => break,
+        if !graph {
+            if i > 0
+                && matches!(
+                    pretty.format,
+                    PrettyFormat::Format {
+                        separator: true,
+                        ..
+                    }
+                )
+            {
+                println!();
+            }
+            print!("{text}");
+            continue;
+        }
+        let parents = commit
+            .parents
+            .iter()
+            .take(if walk.first_parent { 1 } else { usize::MAX })
+            .filter(|parent| shown.contains(*parent))
+            .cloned()
+            .collect::<Vec<_>>();
+        let (commit_row, rows) = lanes.commit(id, &parents);
+        // the text keeps the column it started in, however the lanes change below it
+        let padding = lanes.padding();
+        let width = commit_row.len();
+        let mut rows = rows.into_iter();
+        let mut lines = text.lines();
+        println!("{commit_row}{}", lines.next().unwrap_or_default());
+        for line in lines {
+            let prefix = rows.next().unwrap_or_else(|| padding.clone());
+            println!("{}", format!("{prefix:<width$}{line}").trim_end());
+        }
+        for row in rows {
+            println!("{row}");
+        }
+    }
+}
+
+pub fn commit_graph_write_cmd() {
+    if let Err(e) = graph::write_reachable() {
+        eprintln!("error: {e}");
+        std::process::exit(128);
+    }
+}
+
+pub fn merge_base_cmd(
+    commits: &[String],
+    all: bool,
+    octopus: bool,
+    is_ancestor: bool,
+    fork_point: bool,
+) {
+    let (wanted, usage) = if is_ancestor {
+        (2..=2, "--is-ancestor <commit> <commit>")
+    } else if fork_point {
+        (1..=2, "--fork-point <ref> [<commit>]")
+    } else if octopus {
+        (1..=usize::MAX, "--octopus <commit>...")
+    } else {
+        (2..=usize::MAX, "[-a | --all] <commit> <commit>...")
+    };
+    if !wanted.contains(&commits.len()) {
+        eprintln!("usage: og merge-base {usage}");
+        std::process::exit(129);
+    }
+    let result = graph::CommitGraph::load().and_then(|mut graph| {
+        let mut ids = commits
+            .iter()
+            .map(|c| revision::resolve(c))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let bases = if is_ancestor {
+            let found = graph.is_ancestor(&ids[0], &ids[1])?;
+            std::process::exit(if found { 0 } else { 1 });
+        } else if fork_point {
+            if ids.len() == 1 {
+                ids.push(revision::resolve("HEAD")?);
+            }
+            let history = revision::ref_history(&commits[0])?;
+            graph.fork_point(&history, &ids[1])?.into_iter().collect()
+        } else if octopus {
+            graph.octopus_merge_bases(&ids)?
+        } else {
+            graph.merge_bases(&ids[0], &ids[1..])?
         };
+        Ok(bases)
+    });
+    let bases = match result {
+        Ok(bases) => bases,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    };
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+    let shown = if all || octopus { bases.len() } else { 1 };
+    for base in &bases[..shown] {
+        println!("{base}");
+    }
+}
+
+pub fn diff_tree_cmd(
+    tree_ish: &str,
+    other_tree_ish: Option<&str>,
+    options: &DiffOptions,
+    name_status: bool,
+) {
+    let trees = match other_tree_ish {
+        Some(other) => revision::resolve_tree(tree_ish)
+            .and_then(|old| Ok((Some(old), revision::resolve_tree(other)?))),
+        None => revision::resolve(tree_ish).and_then(|id| {
+            // a lone commit is compared against its first parent
+            let commit = OgitCommit::get(&id)?;
+            let parent_tree = match commit.parents.first() {
+                Some(parent) => Some(OgitCommit::get(parent)?.tree),
+                None => None,
+            };
+            println!("{id}");
+            Ok((parent_tree, commit.tree))
+        }),
+    };
+    let (old_tree, new_tree) = match trees {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return;
+        }
+    };
+    let entries = match diff::diff_trees(old_tree.as_deref(), Some(&new_tree), options) {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("Error comparing trees: {e}");
+            return;
+        }
+    };
+    for entry in entries {
+        if name_status {
+            println!("{entry}");
+        } else {
+            println!("{}", entry.raw());
+        }
+    }
+}
+
+pub fn diff_cmd(commits: &[String], cached: bool, options: &DiffOptions, format: &DiffFormat) {
+    let sides = match commits {
+        [range] if range.contains("...") && !cached => {
+            let (left, right) = range.split_once("...").unwrap();
+            revision::symmetric_diff_sides(left, right).and_then(|(base, new)| {
+                Ok((
+                    Some(revision::resolve_tree(&base)?),
+                    revision::resolve_tree(&new)?,
+                ))
+            })
+        }
+        [range] if range.contains("..") && !cached => {
+            let (old, new) = range.split_once("..").unwrap();
+            let new = if new.is_empty() { "HEAD" } else { new };
+            revision::resolve_tree(old)
+                .and_then(|old| Ok((Some(old), revision::resolve_tree(new)?)))
+        }
+        // the working tree and the index are only hashed, a diff leaves the object database alone
+        [] => porcelain::diff_sides(None, cached),
+        [rev] => porcelain::diff_sides(Some(rev), cached),
+        [old, new] if !cached => revision::resolve_tree(old)
+            .and_then(|old| Ok((Some(old), revision::resolve_tree(new)?))),
+        _ => {
+            eprintln!("Error: too many revisions given to diff");
+            return;
+        }
+    };
+    let (old_tree, new_tree) = match sides {
+        Ok(sides) => sides,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return;
+        }
+    };
+    let output = diff::diff_trees(old_tree.as_deref(), Some(&new_tree), options)
+        .and_then(|entries| diff::render(&entries, format));
+    match output {
+        Ok(out) => print!("{out}"),
+        Err(e) => eprintln!("Error comparing trees: {e}"),
+    }
+}
+
+/// Object `id`, named `name` on the command line, the way `show` prints it.
+///
+/// Commits come with their changes, tags with the object they tag, trees as a listing of their
+/// entries and blobs as their content.
+fn show_object(
+    name: &str,
+    id: &str,
+    variant: Option<objects::OgitObjectType>,
+    pretty: &PrettyOptions,
+    options: &DiffOptions,
+    format: &DiffFormat,
+) -> Result<Vec<u8>, std::io::Error> {
+    let object = get_object(id, variant)?;
+    let now = time::get_current_local().fixed_offset();
+    match object.variant {
+        objects::OgitObjectType::Blob => Ok(object.data),
+        objects::OgitObjectType::Tree => {
+            let mut out = format!("tree {name}\n\n");
+            for entry in base::parse_tree_data(Some(id))? {
+                let slash = if entry.variant == objects::OgitObjectType::Tree {
+                    "/"
+                } else {
+                    ""
+                };
+                out.push_str(&format!("{}{slash}\n", entry.filename.display()));
+            }
+            Ok(out.into_bytes())
+        }
+        objects::OgitObjectType::Commit => {
+            let commit = OgitCommit::get(id)?;
+            let changes = diff::render(&diff::diff_commit(&commit, options)?, format)?;
+            let mut out = pretty::format_commit(id, &commit, pretty, &[], &now);
+            if let PrettyFormat::Format { .. } = pretty.format {
+                out.push('\n');
+            }
+            if !changes.is_empty() && pretty.format != PrettyFormat::Oneline {
+                out.push('\n');
+            }
+            out.push_str(&changes);
+            Ok(out.into_bytes())
+        }
+        objects::OgitObjectType::Tag => {
+            let tag = tags::OgitTag::parse(&object.data)?;
+            let mut out = format!("tag {}\n", tag.name).into_bytes();
+            if let Some((tagger, time)) = &tag.tagger {
+                let date = pretty.date.format(time, &now);
+                out.extend(format!("Tagger: {tagger}\nDate:   {date}\n").into_bytes());
+            }
+            out.push(b'\n');
+            out.extend(&tag.message);
+            if !tag.message.is_empty() && !tag.message.ends_with(b"\n") {
+                out.push(b'\n');
+            }
+            out.push(b'\n');
+            out.extend(show_object(
+                &tag.object,
+                &tag.object,
+                Some(tag.variant),
+                pretty,
+                options,
+                format,
+            )?);
+            Ok(out)
+        }
+    }
+}
+
+pub fn show_cmd(
+    objects: &[String],
+    pretty: &PrettyOptions,
+    options: &DiffOptions,
+    format: &DiffFormat,
+) {
+    let stdout = std::io::stdout();
+    let mut handle = stdout.lock();
+    for name in objects {
+        let shown = revision::resolve(name)
+            .and_then(|id| show_object(name, &id, None, pretty, options, format));
+        match shown {
+            Ok(out) => handle.write_all(&out).unwrap(),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(128);
+            }
+        }
+    }
+}
+
+/// Concatenated content of `files`, or standard input when there are none.
+fn read_inputs(files: &[String]) -> Result<Vec<u8>, std::io::Error> {
+    let mut text = Vec::new();
+    if files.is_empty() {
+        std::io::stdin().read_to_end(&mut text)?;
+    } else {
+        for file in files {
+            text.extend(fs::read(file)?);
+        }
+    }
+    Ok(text)
+}
+
+pub fn apply_cmd(patch_files: &[String], strip: usize, stat: bool, options: &apply::ApplyOptions) {
+    let text = match read_inputs(patch_files) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Error reading patch: {e}");
+            std::process::exit(128);
+        }
+    };
+    let patches = match patch::parse_patch(&text, strip) {
+        Ok(patches) => patches,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    };
+    if stat {
+        let stats = apply::patch_stats(&patches);
+        print!(
+            "{}",
+            diffstat::format_stat(&stats, &diffstat::StatOptions::default())
+        );
+        return;
+    }
+    match apply::apply_patches(&patches, options) {
+        Ok(outcome) => {
+            for message in &outcome.messages {
+                eprintln!("{message}");
+            }
+            if outcome.rejected {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn format_patch_cmd(
+    range: &str,
+    output_directory: Option<&str>,
+    stdout: bool,
+    cover_letter: bool,
+    numbered: bool,
+    start_number: usize,
+    prefix: &str,
+) {
+    // a lone revision means everything after it, unlike other commands
+    let (exclude, include) = if range.contains("..") {
+        revision::parse_range(range)
+    } else {
+        (Some(range), "HEAD")
+    };
+    let commits = match revision::walk_range(include, exclude) {
+        Ok(commits) => commits,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return;
+        }
+    };
+    // merges have no single diff to send, so they are left out like git does
+    let commits = commits
+        .into_iter()
+        .rev()
+        .filter(|(_, commit)| commit.parents.len() <= 1)
+        .collect::<Vec<_>>();
+    if commits.is_empty() {
+        return;
+    }
+    let format = DiffFormat {
+        patch: true,
+        context: patch::DEFAULT_CONTEXT,
+        binary: true,
+        stat: Some(diffstat::StatOptions {
+            width: Some(72),
+            ..Default::default()
+        }),
+        summary: true,
+        ..Default::default()
+    };
+    let options = DiffOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    let last_number = start_number + commits.len() - 1;
+    let numbered = numbered || cover_letter || commits.len() > 1;
+    let number = |n: usize| numbered.then_some((n, last_number));
+
+    let mut emails = Vec::new();
+    if cover_letter {
+        let (_, first) = &commits[0];
+        let (_, last) = &commits[commits.len() - 1];
+        let stat_format = DiffFormat {
+            patch: false,
+            ..format.clone()
+        };
+        let stat = first
+            .parents
+            .first()
+            .map(|parent| OgitCommit::get(parent).map(|c| c.tree))
+            .transpose()
+            .and_then(|old| diff::diff_trees(old.as_deref(), Some(&last.tree), &options))
+            .and_then(|entries| diff::render(&entries, &stat_format));
+        match stat {
+            Ok(stat) => emails.push((
+                "0000-cover-letter.patch".to_string(),
+                mailbox::format_cover_letter(
+                    config::AUTHOR,
+                    &mailbox::subject_prefix(prefix, number(0)),
+                    &commits,
+                    &stat,
+                ),
+            )),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                return;
+            }
+        }
+    }
+    for (i, (id, commit)) in commits.iter().enumerate() {
+        let n = start_number + i;
+        let diff = diff::diff_commit(commit, &options).and_then(|e| diff::render(&e, &format));
+        match diff {
+            Ok(diff) => emails.push((
+                mailbox::patch_file_name(n, &commit.subject()),
+                mailbox::format_email(
+                    id,
+                    commit,
+                    &mailbox::subject_prefix(prefix, number(n)),
+                    &diff,
+                ),
+            )),
+            Err(e) => {
+                eprintln!("Error comparing commit {id}: {e}");
+                return;
+            }
+        }
+    }
+
+    let directory = Path::new(output_directory.unwrap_or(""));
+    if !stdout && !directory.as_os_str().is_empty() {
+        if let Err(e) = fs::create_dir_all(directory) {
+            eprintln!("Error creating {}: {e}", directory.display());
+            return;
+        }
+    }
+    for (name, email) in emails {
+        if stdout {
+            print!("{email}");
+            continue;
+        }
+        let path = directory.join(name);
+        match fs::write(&path, email) {
+            Ok(()) => println!("{}", path.display()),
+            Err(e) => {
+                eprintln!("Error writing {}: {e}", path.display());
+                return;
+            }
+        }
+    }
+}
+
+pub fn am_cmd(mbox_files: &[String], resume: bool, skip: bool, abort: bool) {
+    let finished = if abort {
+        am::abort().map(|()| true)
+    } else if resume || skip {
+        am::resume(skip)
+    } else {
+        read_inputs(mbox_files).and_then(|mbox| am::start(&mbox))
+    };
+    match finished {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+pub fn merge_cmd(rev: Option<&str>, resume: bool, abort: bool, options: &merge::MergeOptions) {
+    let status = if abort {
+        merge::abort().map(|()| merge::MergeStatus::Merged)
+    } else if resume {
+        merge::resume().map(|()| merge::MergeStatus::Merged)
+    } else {
+        merge::merge(rev.unwrap_or_default(), options)
+    };
+    match status {
+        Ok(status) if status.exit_code() != 0 => std::process::exit(status.exit_code()),
+        Ok(_) => (),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+pub fn sequencer_cmd(
+    action: sequencer::Action,
+    commits: &[String],
+    resume: bool,
+    skip: bool,
+    abort: bool,
+    options: &sequencer::PickOptions,
+) {
+    let finished = if abort {
+        sequencer::abort().map(|()| true)
+    } else if resume {
+        sequencer::resume()
+    } else if skip {
+        sequencer::skip()
+    } else {
+        sequencer::commits_to_replay(action, commits)
+            .and_then(|ids| sequencer::start(action, &ids, options))
+    };
+    match finished {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+pub fn rebase_cmd(
+    upstream: Option<&str>,
+    resume: bool,
+    skip: bool,
+    abort: bool,
+    options: &rebase::RebaseOptions,
+) {
+    let finished = if abort {
+        rebase::abort().map(|()| true)
+    } else if resume {
+        rebase::resume()
+    } else if skip {
+        rebase::skip()
+    } else {
+        rebase::start(upstream.unwrap_or_default(), options)
+    };
+    match finished {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+pub fn stash_cmd(action: StashCommand) {
+    let number = |name: Option<String>| {
+        stash::parse_stash_name(name.as_deref()).map_err(std::io::Error::other)
+    };
+    let finished = match action {
+        StashCommand::Push(push) => {
+            stash::push(push.message.as_deref(), push.include_untracked).map(|_| true)
+        }
+        StashCommand::List => stash::list().map(|stashes| {
+            for line in stashes {
+                println!("{line}");
+            }
+            true
+        }),
+        StashCommand::Show {
+            format,
+            renames,
+            stash,
+        } => number(stash)
+            .and_then(|n| stash::show(n, &renames.diff_options(true), &format.diff_format(false)))
+            .map(|changes| {
+                print!("{changes}");
+                true
+            }),
+        StashCommand::Apply { stash } => number(stash).and_then(stash::apply),
+        StashCommand::Pop { stash } => number(stash).and_then(stash::pop),
+        StashCommand::Drop { stash } => number(stash).and_then(stash::drop).map(|()| true),
+        StashCommand::Clear => stash::clear().map(|()| true),
+        StashCommand::Branch { branch, stash } => {
+            number(stash).and_then(|n| stash::branch(&branch, n))
+        }
+    };
+    match finished {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(128);
+        }
+    }
+}
+
+pub fn reset_cmd(rev: &str, paths: &[PathBuf], mode: reset::ResetMode) {
+    let reset = if paths.is_empty() {
+        reset::reset(rev, mode)
+    } else {
+        reset::reset_paths(rev, paths, mode)
+    };
+    if let Err(e) = reset {
+        eprintln!("error: {e}");
+        std::process::exit(128);
     }
 }
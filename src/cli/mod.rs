@@ -1,9 +1,107 @@
 /// Code is divided into understanding the logic of a command
 pub mod functions;
 mod subcommands;
-pub use subcommands::{Cli, Commands};
+use clap::{Arg, Command, CommandFactory};
+pub use subcommands::{Cli, Commands, CommitGraphCommand, StashCommand};
+
+/// Short flags whose value is optional and therefore has to be attached, git style (`-M90%`).
+const OPTIONAL_VALUE_SHORT_FLAGS: [&str; 2] = ["-M", "-C"];
+
+/// Rewrite attached optional values like `-M90%` into the `-M=90%` form clap understands.
+///
+/// Clap cannot tell `-M90` apart from `-M` followed by other flags unless `=` is used,
+/// while git users expect the value glued to the flag. Only the flags of commands showing
+/// diffs are rewritten, never option values or anything after `--`.
+pub fn git_style_args(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut args = args.collect::<Vec<_>>();
+    let root = Cli::command();
+    let mut command = &root;
+    let mut index = 1;
+    while args.get(index).is_some_and(|arg| arg.starts_with('-')) {
+        index += 1;
+    }
+    while let Some(sub) = args.get(index).and_then(|arg| command.find_subcommand(arg)) {
+        command = sub;
+        index += 1;
+    }
+    if !command
+        .get_arguments()
+        .any(|arg| arg.get_id() == "find_renames")
+    {
+        return args;
+    }
+    while let Some(arg) = args.get(index) {
+        index += 1;
+        if arg == "--" {
+            break;
+        }
+        if let Some(flag) = OPTIONAL_VALUE_SHORT_FLAGS.into_iter().find(|flag| {
+            arg.strip_prefix(flag)
+                .is_some_and(|value| value.starts_with(|c: char| c.is_ascii_digit()))
+        }) {
+            args[index - 1] = format!("{flag}={}", &arg[flag.len()..]);
+        } else if consumes_next(command, arg) {
+            index += 1;
+        }
+    }
+    args
+}
+
+/// Whether `arg` is an option of `command` whose value is the next argument.
+fn consumes_next(command: &Command, arg: &str) -> bool {
+    let takes_value = |option: &Arg| {
+        option.get_action().takes_values()
+            && !option.is_require_equals_set()
+            && option
+                .get_num_args()
+                .is_none_or(|range| range.min_values() > 0)
+    };
+    if let Some(long) = arg.strip_prefix("--") {
+        return !long.contains('=')
+            && command
+                .get_arguments()
+                .find(|option| option.get_long() == Some(long))
+                .is_some_and(takes_value);
+    }
+    let Some(shorts) = arg.strip_prefix('-') else {
+        return false;
+    };
+    // in a group of short flags, the first one taking a value gets the rest as its value
+    for (position, short) in shorts.char_indices() {
+        let option = command
+            .get_arguments()
+            .find(|option| option.get_short() == Some(short));
+        if option.is_some_and(takes_value) {
+            return position + short.len_utf8() == shorts.len();
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(args: &str) -> Vec<String> {
+        git_style_args(args.split(' ').map(String::from))
+    }
+
+    #[test]
+    fn test_git_style_args() {
+        assert_eq!(rewrite("og diff -M50 -C"), ["og", "diff", "-M=50", "-C"]);
+        assert_eq!(
+            rewrite("og stash show -M90%"),
+            ["og", "stash", "show", "-M=90%"]
+        );
+        assert_eq!(rewrite("og commit -m -M50"), ["og", "commit", "-m", "-M50"]);
+        assert_eq!(
+            rewrite("og log --grep -M50 -- -C50"),
+            ["og", "log", "--grep", "-M50", "--", "-C50"]
+        );
+    }
+}
 
 pub mod prelude {
-    pub use super::{functions, Cli, Commands};
+    pub use super::{functions, git_style_args, Cli, Commands, CommitGraphCommand, StashCommand};
     pub use clap::Parser;
 }
@@ -1,4 +1,19 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::data::{
+    diff::{parse_similarity, DiffFormat, DiffOptions},
+    diffstat::{parse_dirstat_options, parse_stat_options, DirstatOptions, StatOptions},
+    objects::OgitObjectType,
+    patch::DEFAULT_CONTEXT,
+    pretty::{PrettyFormat, PrettyOptions},
+    revwalk::{WalkOptions, WalkOrder},
+    simplify::Simplification,
+    time::{self, DateFormat, DateTime, FixedOffset},
+    worddiff::WordDiffMode,
+    xmerge::ConflictStyle,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -17,14 +32,231 @@ pub enum Commands {
     Init,
     /// Compute object ID and optionally creates a blob from a file.
     #[command(name = "hash-object")]
-    HashObject { file: String },
+    HashObject {
+        /// Write the object into the object database
+        #[arg(short = 'w')]
+        write: bool,
+        /// Type of the object to create
+        #[arg(short = 't', value_name = "type", default_value = "blob")]
+        object_type: OgitObjectType,
+        /// Read the object from standard input instead of a file
+        #[arg(long)]
+        stdin: bool,
+        /// Read the paths of the files to hash from standard input, one per line
+        #[arg(long, conflicts_with_all = ["stdin", "files"])]
+        stdin_paths: bool,
+        /// Hash the content without checking that it is well formed for its type
+        #[arg(long)]
+        literally: bool,
+        /// Hash the content as it is, ogit applies no filters to files yet
+        #[arg(long)]
+        no_filters: bool,
+        files: Vec<PathBuf>,
+    },
     /// Read the content of the object with the given ID
     #[command(name = "cat-file")]
-    CatObject { object_id: String },
+    CatObject {
+        /// Show the type of the object
+        #[arg(short = 't', group = "mode")]
+        show_type: bool,
+        /// Show the size of the object content
+        #[arg(short = 's', group = "mode")]
+        size: bool,
+        /// Exit with zero status when the object exists and is valid, without output
+        #[arg(short = 'e', group = "mode")]
+        exists: bool,
+        /// Show the content, with trees decoded into readable lines
+        #[arg(short = 'p', group = "mode")]
+        pretty: bool,
+        /// Print the id, type, size and content of each object named on standard input
+        #[arg(long, group = "mode")]
+        batch: bool,
+        /// Print the id, type and size of each object named on standard input
+        #[arg(long, group = "mode")]
+        batch_check: bool,
+        /// With --batch or --batch-check, go through every stored object instead of standard input
+        #[arg(long)]
+        batch_all_objects: bool,
+        /// The object to show, like <rev> or <rev>:<path>
+        #[arg(required_unless_present_any = ["batch", "batch_check"])]
+        object: Option<String>,
+    },
     #[command(name = "write-tree")]
     WriteTree { directory: Option<String> },
     #[command(name = "read-tree", about = "Read a tree object into the index")]
     ReadTree { tree_id: String },
+    #[command(name = "ls-tree", about = "List the contents of a tree object")]
+    LsTree {
+        #[arg(short = 'r', help = "Recurse into sub trees")]
+        recursive: bool,
+        #[arg(short = 't', help = "Show trees even when recursing into them")]
+        show_trees: bool,
+        #[arg(short = 'd', help = "Only show trees")]
+        only_trees: bool,
+        #[arg(short = 'l', long = "long", help = "Show the size of blobs")]
+        long: bool,
+        #[arg(long, visible_alias = "name-status", help = "Only show the paths")]
+        name_only: bool,
+        #[arg(short = 'z', help = "End lines with NUL and do not quote paths")]
+        null_terminated: bool,
+        #[arg(help = "The tree, or the commit whose tree to list")]
+        tree_ish: String,
+        #[arg(help = "Only list these paths, a trailing / lists inside a directory")]
+        paths: Vec<String>,
+    },
+    #[command(
+        name = "ls-files",
+        about = "Show files of the index and the working tree"
+    )]
+    LsFiles {
+        #[arg(
+            short = 's',
+            long,
+            help = "Show the mode, id and stage of index entries"
+        )]
+        stage: bool,
+        #[arg(short = 'm', long, help = "Show files changed in the working tree")]
+        modified: bool,
+        #[arg(short = 'd', long, help = "Show files deleted from the working tree")]
+        deleted: bool,
+        #[arg(short = 'o', long, help = "Show untracked files")]
+        others: bool,
+        #[arg(
+            short = 'i',
+            long,
+            requires = "others",
+            help = "Only show untracked files that are ignored"
+        )]
+        ignored: bool,
+        #[arg(long, help = "Leave out untracked files that are ignored")]
+        exclude_standard: bool,
+        #[arg(short = 'z', help = "End lines with NUL and do not quote paths")]
+        null_terminated: bool,
+    },
+    #[command(
+        name = "update-ref",
+        about = "Update the id a ref points at, if it still points where expected"
+    )]
+    UpdateRef {
+        #[arg(short = 'm', help = "Reason recorded in the reflog")]
+        message: Option<String>,
+        #[arg(short = 'd', help = "Delete the ref instead")]
+        delete: bool,
+        #[arg(
+            long,
+            help = "Update symbolic refs themselves, not the refs they point at"
+        )]
+        no_deref: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["delete", "name"],
+            help = "Read updates from standard input and apply them all or none"
+        )]
+        stdin: bool,
+        #[arg(required_unless_present = "stdin", help = "The ref to update")]
+        name: Option<String>,
+        /// The new value then the expected old one, only the old one with -d
+        #[arg(num_args = 0..=2)]
+        values: Vec<String>,
+    },
+    #[command(name = "symbolic-ref", about = "Read, change or delete a symbolic ref")]
+    SymbolicRef {
+        #[arg(short = 'q', long, help = "Fail silently when the ref is not symbolic")]
+        quiet: bool,
+        #[arg(long, help = "Show the target by its short name")]
+        short: bool,
+        #[arg(short = 'd', long, help = "Delete the symbolic ref")]
+        delete: bool,
+        #[arg(short = 'm', help = "Reason recorded in the reflog")]
+        message: Option<String>,
+        #[arg(help = "The symbolic ref, like HEAD")]
+        name: String,
+        #[arg(conflicts_with = "delete", help = "The ref to point it at")]
+        target: Option<String>,
+    },
+    #[command(name = "show-ref", about = "List refs with the ids they point at")]
+    ShowRef {
+        #[arg(long, help = "Show HEAD too")]
+        head: bool,
+        #[arg(long, help = "Only show branches")]
+        heads: bool,
+        #[arg(long, help = "Only show tags")]
+        tags: bool,
+        #[arg(
+            short = 's',
+            long,
+            num_args = 0..=1,
+            default_missing_value = "40",
+            help = "Only show the ids, abbreviated to the given length"
+        )]
+        hash: Option<usize>,
+        #[arg(short = 'd', long, help = "Show what tags point at as <ref>^{}")]
+        dereference: bool,
+        #[arg(long, help = "Only accept full ref names, which must all exist")]
+        verify: bool,
+        #[arg(
+            short = 'q',
+            long,
+            help = "Do not print anything, only set the exit status"
+        )]
+        quiet: bool,
+        #[arg(help = "Show refs whose name ends with one of these")]
+        patterns: Vec<String>,
+    },
+    #[command(name = "for-each-ref", about = "List refs in a format of your choice")]
+    ForEachRef {
+        #[arg(long, help = "Format of each line, using %(<atom>) placeholders")]
+        format: Option<String>,
+        #[arg(long, help = "Sort by this atom, descending with a leading -")]
+        sort: Vec<String>,
+        #[arg(long, help = "Stop after this many refs")]
+        count: Option<usize>,
+        #[arg(long, help = "Only refs pointing at this object")]
+        points_at: Vec<String>,
+        #[arg(
+            long,
+            num_args = 0..=1,
+            default_missing_value = "HEAD",
+            help = "Only refs whose commit contains this one"
+        )]
+        contains: Vec<String>,
+        #[arg(
+            long,
+            num_args = 0..=1,
+            default_missing_value = "HEAD",
+            help = "Only refs whose commit does not contain this one"
+        )]
+        no_contains: Vec<String>,
+        #[arg(
+            long,
+            num_args = 0..=1,
+            default_missing_value = "HEAD",
+            help = "Only refs whose commit is reachable from this one"
+        )]
+        merged: Vec<String>,
+        #[arg(
+            long,
+            num_args = 0..=1,
+            default_missing_value = "HEAD",
+            help = "Only refs whose commit is not reachable from this one"
+        )]
+        no_merged: Vec<String>,
+        #[arg(help = "Only refs matching one of these globs or leading paths")]
+        patterns: Vec<String>,
+    },
+    #[command(name = "pack-refs", about = "Pack refs into the packed-refs file")]
+    PackRefs {
+        #[arg(long, help = "Pack every ref, not only tags")]
+        all: bool,
+        #[arg(long, help = "Remove the loose files of packed refs, the default")]
+        prune: bool,
+        #[arg(
+            long,
+            conflicts_with = "prune",
+            help = "Keep the loose files of packed refs"
+        )]
+        no_prune: bool,
+    },
     #[command(
         name = "commit-tree",
         about = "Create a new commit object based on provided tree"
@@ -42,18 +274,873 @@ pub enum Commands {
         about = "Creates a new commit as a high-level operation including HEAD and reflog side effects."
     )]
     Commit {
-        /// currently implied that everything is in the index, so assume a '-a' flag is always present
         #[arg(short = 'm', long = "message", help = "The commit message")]
         message: Option<String>,
+        #[arg(
+            short = 'a',
+            long = "all",
+            help = "Take in the changes of every tracked file first, deleted files included"
+        )]
+        all: bool,
+    },
+    #[command(name = "add", about = "Add file contents to the index")]
+    Add {
+        #[arg(
+            required = true,
+            help = "Files or directories to add, removed files leave the index"
+        )]
+        pathspecs: Vec<PathBuf>,
     },
     #[command(name = "log", about = "Show commit logs.")]
     Log {
-        #[arg(help = "The commit object")]
-        commit: Option<String>,
+        #[arg(help = "Commits to start from, ^<rev> to exclude and <rev>..<rev> ranges")]
+        revisions: Vec<String>,
+        #[command(flatten)]
+        walk: WalkArgs,
+        #[arg(long, help = "Draw the history as text next to the commits")]
+        graph: bool,
+        #[command(flatten)]
+        pretty: PrettyArgs,
+        #[arg(
+            long,
+            overrides_with = "no_decorate",
+            help = "Show the names of refs pointing at each commit, the default on a terminal"
+        )]
+        decorate: bool,
+        #[arg(long, help = "Do not show the names of refs pointing at each commit")]
+        no_decorate: bool,
+        #[command(flatten)]
+        format: DiffFormatArgs,
+        #[command(flatten)]
+        renames: RenameArgs,
     },
     #[command(name = "checkout", about = "Checkout commit (for now) and move HEAD")]
     Checkout {
         #[arg(help = "The commit to checkout")]
         commit: String,
     },
+    #[command(
+        name = "diff-tree",
+        about = "Compares the content and mode of blobs found via two tree objects"
+    )]
+    DiffTree {
+        #[arg(short = 'r', help = "Recurse into sub-trees")]
+        recursive: bool,
+        #[arg(long, help = "Show only names and status of changed files")]
+        name_status: bool,
+        #[command(flatten)]
+        renames: RenameArgs,
+        #[arg(help = "Tree to compare from, a single commit is compared against its parent")]
+        tree_ish: String,
+        #[arg(help = "Tree to compare to")]
+        other_tree_ish: Option<String>,
+    },
+    #[command(
+        name = "diff",
+        about = "Show changes between the index and the working tree, or between commits"
+    )]
+    Diff {
+        #[command(flatten)]
+        format: DiffFormatArgs,
+        #[command(flatten)]
+        renames: RenameArgs,
+        #[arg(
+            long,
+            visible_alias = "staged",
+            help = "Compare the index against the given commit, HEAD by default"
+        )]
+        cached: bool,
+        #[arg(
+            help = "Commits to compare, the working tree is compared against the index when none are given",
+            num_args = 0..=2
+        )]
+        commits: Vec<String>,
+    },
+    #[command(
+        name = "show",
+        about = "Show commits with their changes, tags, trees and blobs"
+    )]
+    Show {
+        #[command(flatten)]
+        pretty: PrettyArgs,
+        #[command(flatten)]
+        format: DiffFormatArgs,
+        #[command(flatten)]
+        renames: RenameArgs,
+        #[arg(
+            help = "The objects to show, like <rev> or <rev>:<path>",
+            default_value = "HEAD"
+        )]
+        objects: Vec<String>,
+    },
+    #[command(name = "apply", about = "Apply a patch to files and/or to the index")]
+    Apply {
+        #[arg(
+            long,
+            help = "Only check whether the patch applies, without applying it"
+        )]
+        check: bool,
+        #[arg(long, help = "Show a diffstat of the patch instead of applying it")]
+        stat: bool,
+        #[arg(
+            long,
+            conflicts_with = "cached",
+            help = "Apply to both the working tree and the index"
+        )]
+        index: bool,
+        #[arg(long, help = "Apply to the index only, leaving the working tree alone")]
+        cached: bool,
+        #[arg(short = 'R', long, help = "Apply the patch in reverse")]
+        reverse: bool,
+        #[arg(
+            short = 'p',
+            value_name = "n",
+            default_value_t = 1,
+            help = "Remove <n> leading path components from the names in the patch"
+        )]
+        strip: usize,
+        #[arg(
+            short = 'C',
+            value_name = "n",
+            help = "Only require <n> lines of context around each hunk to match"
+        )]
+        context: Option<usize>,
+        #[arg(
+            long,
+            help = "Apply the hunks that apply and leave the rest in <path>.rej files"
+        )]
+        reject: bool,
+        #[arg(help = "Patch files to read, standard input is read when none are given")]
+        patches: Vec<String>,
+    },
+    #[command(
+        name = "commit-graph",
+        about = "Remember the generation numbers of commits for faster walks"
+    )]
+    CommitGraph {
+        #[command(subcommand)]
+        action: CommitGraphCommand,
+    },
+    #[command(
+        name = "merge-base",
+        about = "Find the best common ancestors of commits, as a merge would use"
+    )]
+    MergeBase {
+        #[arg(short, long, help = "Show all best common ancestors instead of one")]
+        all: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["is_ancestor", "fork_point"],
+            help = "Find the common ancestors of all commits at once, as for an octopus merge"
+        )]
+        octopus: bool,
+        #[arg(
+            long,
+            conflicts_with = "fork_point",
+            help = "Exit with 0 when the first commit is an ancestor of the second, 1 otherwise"
+        )]
+        is_ancestor: bool,
+        #[arg(
+            long,
+            help = "Find where <commit> forked from <ref>, HEAD when <commit> is left out"
+        )]
+        fork_point: bool,
+        #[arg(required = true, help = "Commits to find common ancestors of")]
+        commits: Vec<String>,
+    },
+    #[command(name = "merge", about = "Join the history of another commit into HEAD")]
+    Merge {
+        #[arg(
+            long = "continue",
+            conflicts_with_all = ["abort", "rev"],
+            help = "Conclude a merge once its conflicts are resolved"
+        )]
+        resume: bool,
+        #[arg(
+            long,
+            conflicts_with = "rev",
+            help = "Give up on the merge and restore the state before it"
+        )]
+        abort: bool,
+        #[arg(
+            long,
+            conflicts_with = "no_ff",
+            help = "Only move HEAD forward, refuse to create a merge commit"
+        )]
+        ff_only: bool,
+        #[arg(long, help = "Create a merge commit even when HEAD could move forward")]
+        no_ff: bool,
+        #[arg(
+            long,
+            conflicts_with = "no_ff",
+            help = "Prepare the merged tree for the next commit without recording a merge"
+        )]
+        squash: bool,
+        #[arg(
+            long,
+            value_name = "style",
+            default_value = "merge",
+            help = "How conflicts are shown: merge, diff3 or zdiff3"
+        )]
+        conflict: ConflictStyle,
+        #[arg(short, long, help = "Message of the merge commit")]
+        message: Option<String>,
+        #[arg(
+            required_unless_present_any = ["resume", "abort"],
+            help = "Commit to merge into HEAD"
+        )]
+        rev: Option<String>,
+    },
+    #[command(
+        name = "cherry-pick",
+        about = "Apply the changes introduced by existing commits"
+    )]
+    CherryPick {
+        #[arg(
+            long = "continue",
+            conflicts_with_all = ["skip", "abort", "commits"],
+            help = "Commit the resolved conflicts and carry on with the remaining commits"
+        )]
+        resume: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["abort", "commits"],
+            help = "Drop the stopped commit and carry on with the remaining commits"
+        )]
+        skip: bool,
+        #[arg(
+            long,
+            conflicts_with = "commits",
+            help = "Give up and go back to where HEAD was before"
+        )]
+        abort: bool,
+        #[arg(
+            short = 'n',
+            long,
+            help = "Apply the changes to the working tree and the index without committing"
+        )]
+        no_commit: bool,
+        #[arg(
+            short = 'x',
+            help = "Add a line saying which commit was cherry-picked to the message"
+        )]
+        record_origin: bool,
+        #[arg(
+            short = 'm',
+            long,
+            value_name = "parent-number",
+            help = "Compare merge commits against this parent, counting from 1"
+        )]
+        mainline: Option<usize>,
+        #[arg(
+            long,
+            value_name = "style",
+            default_value = "merge",
+            help = "How conflicts are shown: merge, diff3 or zdiff3"
+        )]
+        conflict: ConflictStyle,
+        #[arg(
+            required_unless_present_any = ["resume", "skip", "abort"],
+            help = "Commits or ranges of commits to pick"
+        )]
+        commits: Vec<String>,
+    },
+    #[command(
+        name = "revert",
+        about = "Create commits undoing the changes of existing commits"
+    )]
+    Revert {
+        #[arg(
+            long = "continue",
+            conflicts_with_all = ["skip", "abort", "commits"],
+            help = "Commit the resolved conflicts and carry on with the remaining commits"
+        )]
+        resume: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["abort", "commits"],
+            help = "Drop the stopped commit and carry on with the remaining commits"
+        )]
+        skip: bool,
+        #[arg(
+            long,
+            conflicts_with = "commits",
+            help = "Give up and go back to where HEAD was before"
+        )]
+        abort: bool,
+        #[arg(
+            short = 'n',
+            long,
+            help = "Apply the changes to the working tree and the index without committing"
+        )]
+        no_commit: bool,
+        #[arg(
+            short = 'm',
+            long,
+            value_name = "parent-number",
+            help = "Compare merge commits against this parent, counting from 1"
+        )]
+        mainline: Option<usize>,
+        #[arg(
+            long,
+            value_name = "style",
+            default_value = "merge",
+            help = "How conflicts are shown: merge, diff3 or zdiff3"
+        )]
+        conflict: ConflictStyle,
+        #[arg(
+            required_unless_present_any = ["resume", "skip", "abort"],
+            help = "Commits or ranges of commits to revert"
+        )]
+        commits: Vec<String>,
+    },
+    #[command(
+        name = "rebase",
+        about = "Replay the commits of HEAD that upstream lacks on top of a new base"
+    )]
+    Rebase {
+        #[arg(
+            short = 'i',
+            long,
+            help = "Edit the list of commits to replay before starting"
+        )]
+        interactive: bool,
+        #[arg(
+            long,
+            value_name = "newbase",
+            help = "Replay onto this commit instead of upstream"
+        )]
+        onto: Option<String>,
+        #[arg(
+            long,
+            help = "Move \"fixup! \" and \"squash! \" commits right after the commit they amend"
+        )]
+        autosquash: bool,
+        #[arg(
+            short = 'x',
+            long,
+            value_name = "cmd",
+            help = "Run this shell command after each replayed commit, stopping when it fails"
+        )]
+        exec: Vec<String>,
+        #[arg(
+            long,
+            value_name = "style",
+            default_value = "merge",
+            help = "How conflicts are shown: merge, diff3 or zdiff3"
+        )]
+        conflict: ConflictStyle,
+        #[arg(
+            long = "continue",
+            conflicts_with_all = ["skip", "abort", "upstream"],
+            help = "Commit the resolved or amended changes and carry on with the todo list"
+        )]
+        resume: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["abort", "upstream"],
+            help = "Drop the stopped commit and carry on with the todo list"
+        )]
+        skip: bool,
+        #[arg(
+            long,
+            conflicts_with = "upstream",
+            help = "Give up and go back to where HEAD was before the rebase"
+        )]
+        abort: bool,
+        #[arg(
+            required_unless_present_any = ["resume", "skip", "abort"],
+            help = "Commit whose history the replayed commits are missing from"
+        )]
+        upstream: Option<String>,
+    },
+    #[command(
+        name = "reset",
+        about = "Move HEAD to another commit, or reset index entries of some paths"
+    )]
+    Reset {
+        #[arg(long, group = "mode", help = "Only move HEAD")]
+        soft: bool,
+        #[arg(
+            long,
+            group = "mode",
+            help = "Move HEAD and reset the index but not the working tree, the default"
+        )]
+        mixed: bool,
+        #[arg(
+            long,
+            group = "mode",
+            help = "Move HEAD and make the index and the tracked files match"
+        )]
+        hard: bool,
+        #[arg(
+            long,
+            group = "mode",
+            help = "Like --hard, but keep local changes and refuse to overwrite them"
+        )]
+        keep: bool,
+        #[arg(
+            long,
+            group = "mode",
+            help = "Like --keep, but also throw away the conflicts of a failed merge"
+        )]
+        merge: bool,
+        #[arg(help = "Commit to reset to", default_value = "HEAD")]
+        rev: String,
+        #[arg(last = true, help = "Only reset the index entries of these paths")]
+        paths: Vec<PathBuf>,
+    },
+    #[command(
+        name = "stash",
+        about = "Shelve local changes away and bring them back later"
+    )]
+    #[command(args_conflicts_with_subcommands = true)]
+    Stash {
+        #[command(flatten)]
+        push: StashPushArgs,
+        #[command(subcommand)]
+        action: Option<StashCommand>,
+    },
+    #[command(
+        name = "format-patch",
+        about = "Prepare each commit with its patch as an email, ready to be sent or applied with am"
+    )]
+    FormatPatch {
+        #[arg(
+            short = 'o',
+            long = "output-directory",
+            help = "Write the patch files into this directory instead of the current one"
+        )]
+        output_directory: Option<String>,
+        #[arg(
+            long,
+            help = "Print all emails to standard output instead of writing files"
+        )]
+        stdout: bool,
+        #[arg(long, help = "Add an introductory email listing the series")]
+        cover_letter: bool,
+        #[arg(
+            short = 'n',
+            long,
+            help = "Number the subjects even for a single patch"
+        )]
+        numbered: bool,
+        #[arg(
+            long,
+            value_name = "n",
+            default_value_t = 1,
+            help = "Start numbering the patches at <n>"
+        )]
+        start_number: usize,
+        #[arg(
+            long,
+            default_value = "PATCH",
+            help = "Use this instead of PATCH in the subject tag"
+        )]
+        subject_prefix: String,
+        #[arg(
+            help = "Commits to prepare, either <since> for the commits after it up to HEAD, or <a>..<b>"
+        )]
+        range: String,
+    },
+    #[command(
+        name = "am",
+        about = "Apply a series of patch emails from a mailbox as commits"
+    )]
+    Am {
+        #[arg(
+            long = "continue",
+            conflicts_with_all = ["skip", "abort"],
+            help = "Commit the resolved working tree for the stopped patch and carry on"
+        )]
+        resume: bool,
+        #[arg(
+            long,
+            conflicts_with = "abort",
+            help = "Drop the stopped patch and carry on"
+        )]
+        skip: bool,
+        #[arg(
+            long,
+            help = "Stop and restore HEAD and the working tree to before am started"
+        )]
+        abort: bool,
+        #[arg(help = "Mailbox files to read, standard input is read when none are given")]
+        mbox: Vec<String>,
+    },
+}
+
+/// Options of `stash push`, which plain `stash` takes as well.
+#[derive(Args, Debug, Clone, Default)]
+pub struct StashPushArgs {
+    /// Describe the stash with this message
+    #[arg(short, long)]
+    pub message: Option<String>,
+    /// Stash untracked files as well, and remove them
+    #[arg(short = 'u', long)]
+    pub include_untracked: bool,
+}
+
+#[derive(Subcommand)]
+pub enum CommitGraphCommand {
+    #[command(
+        name = "write",
+        about = "Store the generation of every commit reachable from a ref or HEAD"
+    )]
+    Write,
+}
+
+#[derive(Subcommand)]
+pub enum StashCommand {
+    #[command(
+        name = "push",
+        about = "Save the local changes as a new stash and go back to HEAD, the default"
+    )]
+    Push(StashPushArgs),
+    #[command(name = "list", about = "List the stashes, newest first")]
+    List,
+    #[command(
+        name = "show",
+        about = "Show the changes of a stash, as a diffstat unless asked otherwise"
+    )]
+    Show {
+        #[command(flatten)]
+        format: DiffFormatArgs,
+        #[command(flatten)]
+        renames: RenameArgs,
+        stash: Option<String>,
+    },
+    #[command(name = "apply", about = "Bring the changes of a stash back")]
+    Apply { stash: Option<String> },
+    #[command(
+        name = "pop",
+        about = "Bring the changes of a stash back and drop it when there are no conflicts"
+    )]
+    Pop { stash: Option<String> },
+    #[command(name = "drop", about = "Forget a stash")]
+    Drop { stash: Option<String> },
+    #[command(name = "clear", about = "Forget every stash")]
+    Clear,
+    #[command(
+        name = "branch",
+        about = "Create a branch at the commit a stash was made on and pop the stash there"
+    )]
+    Branch {
+        branch: String,
+        stash: Option<String>,
+    },
+}
+
+/// Options selecting, ordering and limiting the commits `log` shows.
+#[derive(Args, Debug, Clone, Default)]
+pub struct WalkArgs {
+    /// Show at most this many commits
+    #[arg(short = 'n', long, value_name = "number")]
+    pub max_count: Option<usize>,
+    /// Skip this many commits before starting to show them
+    #[arg(long, value_name = "number", default_value_t = 0)]
+    pub skip: usize,
+    /// Show commits more recent than this date, like `2024-01-31` or `2 weeks ago`
+    #[arg(long, visible_alias = "after", value_name = "date", value_parser = parse_approxidate)]
+    pub since: Option<DateTime<FixedOffset>>,
+    /// Show commits older than this date
+    #[arg(long, visible_alias = "before", value_name = "date", value_parser = parse_approxidate)]
+    pub until: Option<DateTime<FixedOffset>>,
+    /// Only show commits whose author matches this pattern, any of them when repeated
+    #[arg(long, value_name = "pattern")]
+    pub author: Vec<String>,
+    /// Only show commits whose committer matches this pattern, any of them when repeated
+    #[arg(long, value_name = "pattern")]
+    pub committer: Vec<String>,
+    /// Only show commits whose message matches this pattern, any of them when repeated
+    #[arg(long, value_name = "pattern")]
+    pub grep: Vec<String>,
+    /// Only show commits whose message matches every --grep pattern
+    #[arg(long)]
+    pub all_match: bool,
+    /// Match the patterns without regard to case
+    #[arg(short = 'i', long = "regexp-ignore-case")]
+    pub ignore_case: bool,
+    /// Only show merge commits
+    #[arg(long, conflicts_with = "no_merges")]
+    pub merges: bool,
+    /// Do not show merge commits
+    #[arg(long)]
+    pub no_merges: bool,
+    /// Only follow the first parent of merge commits
+    #[arg(long)]
+    pub first_parent: bool,
+    /// Show the selected commits oldest first
+    #[arg(long)]
+    pub reverse: bool,
+    /// Show no parent before all of its children, otherwise by commit date
+    #[arg(long, group = "order")]
+    pub date_order: bool,
+    /// Show no parent before all of its children, otherwise by author date
+    #[arg(long, group = "order")]
+    pub author_date_order: bool,
+    /// Show no parent before all of its children, keeping lines of history together
+    #[arg(long, group = "order")]
+    pub topo_order: bool,
+    /// Follow every parent of merges when limiting the history to paths
+    #[arg(long)]
+    pub full_history: bool,
+    /// Only show merges joining lines of history that change the paths
+    #[arg(long)]
+    pub simplify_merges: bool,
+    /// Follow the history of a single file back through renames
+    #[arg(long)]
+    pub follow: bool,
+    /// Only show commits changing these paths
+    #[arg(last = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+impl WalkArgs {
+    pub fn walk_options(&self) -> WalkOptions {
+        let order = if self.topo_order {
+            WalkOrder::Topo
+        } else if self.author_date_order {
+            WalkOrder::AuthorDate
+        } else if self.date_order {
+            WalkOrder::Date
+        } else {
+            WalkOrder::Default
+        };
+        WalkOptions {
+            max_count: self.max_count,
+            skip: self.skip,
+            since: self.since,
+            until: self.until,
+            authors: self.author.clone(),
+            committers: self.committer.clone(),
+            grep: self.grep.clone(),
+            all_match: self.all_match,
+            ignore_case: self.ignore_case,
+            merges: self.merges,
+            no_merges: self.no_merges,
+            first_parent: self.first_parent,
+            reverse: self.reverse,
+            order,
+            paths: self.paths.clone(),
+            simplification: Simplification {
+                full_history: self.full_history,
+                simplify_merges: self.simplify_merges,
+            },
+            follow: self.follow,
+        }
+    }
+}
+
+/// Options choosing how `log` and `show` display each commit.
+#[derive(Args, Debug, Clone, Default)]
+pub struct PrettyArgs {
+    /// Show commits as oneline, short, medium, full, fuller, raw, email or format:<string>
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "medium",
+        value_name = "format"
+    )]
+    pub pretty: Option<PrettyFormat>,
+    /// Show commits with a string of placeholders like `%h %s`, one per line
+    #[arg(long, value_name = "string", value_parser = parse_format, conflicts_with = "pretty")]
+    pub format: Option<PrettyFormat>,
+    /// Short for --pretty=oneline --abbrev-commit
+    #[arg(long)]
+    pub oneline: bool,
+    /// Show abbreviated commit ids
+    #[arg(long)]
+    pub abbrev_commit: bool,
+    /// Show dates as default, iso, iso-strict, rfc, short, unix, raw, relative or format:<strftime>
+    #[arg(long, value_name = "format")]
+    pub date: Option<DateFormat>,
+}
+
+impl PrettyArgs {
+    pub fn pretty_options(&self) -> PrettyOptions {
+        let format = match (&self.pretty, &self.format) {
+            (Some(format), _) | (_, Some(format)) => format.clone(),
+            _ if self.oneline => PrettyFormat::Oneline,
+            _ => PrettyFormat::default(),
+        };
+        PrettyOptions {
+            format,
+            date: self.date.clone().unwrap_or_default(),
+            abbrev_commit: self.abbrev_commit || self.oneline,
+        }
+    }
+}
+
+/// `--format` takes the builtin names too, anything else is a format string.
+fn parse_format(value: &str) -> Result<PrettyFormat, String> {
+    value.parse().or_else(|_| {
+        Ok(PrettyFormat::Format {
+            template: value.to_string(),
+            separator: false,
+        })
+    })
+}
+
+fn parse_approxidate(value: &str) -> Result<DateTime<FixedOffset>, String> {
+    time::parse_date(value, time::get_current_local().fixed_offset())
+}
+
+/// Options selecting how changes are displayed, shared by `diff`, `show` and `log`.
+#[derive(Args, Debug, Clone, Default)]
+pub struct DiffFormatArgs {
+    /// Generate a patch, the default for `diff` and `show`
+    #[arg(short = 'p', long = "patch")]
+    pub patch: bool,
+    /// Generate diffs with this many lines of context
+    #[arg(short = 'U', long = "unified", value_name = "n")]
+    pub unified: Option<usize>,
+    /// Show a diffstat, optionally limited to `<width>[,<name-width>[,<count>]]`
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        value_name = "width[,name-width[,count]]",
+        value_parser = parse_stat_options
+    )]
+    pub stat: Option<StatOptions>,
+    /// Total width of the diffstat
+    #[arg(long, value_name = "width")]
+    pub stat_width: Option<usize>,
+    /// Width of the file name part of the diffstat
+    #[arg(long, value_name = "width")]
+    pub stat_name_width: Option<usize>,
+    /// Only list this many files in the diffstat
+    #[arg(long, value_name = "count")]
+    pub stat_count: Option<usize>,
+    /// Show added and deleted line counts in a machine friendly format
+    #[arg(long)]
+    pub numstat: bool,
+    /// Only show the last line of the diffstat
+    #[arg(long)]
+    pub shortstat: bool,
+    /// Show how changes are distributed over directories
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        value_name = "param1,param2,...",
+        value_parser = parse_dirstat_options
+    )]
+    pub dirstat: Option<DirstatOptions>,
+    /// Show a summary of created, deleted, renamed and mode changed files
+    #[arg(long)]
+    pub summary: bool,
+    /// Show only the names of changed files
+    #[arg(long, conflicts_with = "name_status")]
+    pub name_only: bool,
+    /// Show only names and status of changed files
+    #[arg(long)]
+    pub name_status: bool,
+    /// Output a binary diff that can be applied, instead of just noting binary files differ
+    #[arg(long)]
+    pub binary: bool,
+    /// Show a word diff, with changed words marked up according to the mode
+    #[arg(
+        long,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "plain",
+        value_name = "plain|color|porcelain"
+    )]
+    pub word_diff: Option<WordDiffMode>,
+    /// Show a word diff using only colors to mark changed words
+    #[arg(long, conflicts_with = "word_diff")]
+    pub color_words: bool,
+}
+
+/// Options controlling rename and copy detection, shared by every command that shows a diff.
+#[derive(Args, Debug, Clone, Default)]
+pub struct RenameArgs {
+    /// Detect renames, optionally with a minimum similarity such as `-M90%`
+    #[arg(
+        short = 'M',
+        long = "find-renames",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        value_parser = parse_similarity
+    )]
+    pub find_renames: Option<u8>,
+    /// Detect copies as well as renames, optionally with a minimum similarity
+    #[arg(
+        short = 'C',
+        long = "find-copies",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        value_parser = parse_similarity
+    )]
+    pub find_copies: Option<u8>,
+    /// Inspect unmodified files as candidates for the source of copy
+    #[arg(long)]
+    pub find_copies_harder: bool,
+}
+
+impl RenameArgs {
+    pub const fn diff_options(&self, recursive: bool) -> DiffOptions {
+        DiffOptions {
+            recursive,
+            rename_score: self.find_renames,
+            copy_score: self.find_copies,
+            find_copies_harder: self.find_copies_harder,
+        }
+    }
+}
+
+impl DiffFormatArgs {
+    /// Build the display options, `default_patch` decides if a patch is shown when nothing else is asked for.
+    pub fn diff_format(&self, default_patch: bool) -> DiffFormat {
+        let stat = if self.stat.is_some()
+            || self.stat_width.is_some()
+            || self.stat_name_width.is_some()
+            || self.stat_count.is_some()
+        {
+            let options = self.stat.clone().unwrap_or_default();
+            Some(StatOptions {
+                width: self.stat_width.or(options.width),
+                name_width: self.stat_name_width.or(options.name_width),
+                count: self.stat_count.or(options.count),
+            })
+        } else {
+            None
+        };
+        let other_output = stat.is_some()
+            || self.numstat
+            || self.shortstat
+            || self.dirstat.is_some()
+            || self.summary
+            || self.name_only
+            || self.name_status;
+        let word_diff = if self.color_words {
+            Some(WordDiffMode::Color)
+        } else {
+            self.word_diff
+        };
+        DiffFormat {
+            patch: self.patch
+                || self.unified.is_some()
+                || self.binary
+                || word_diff.is_some()
+                || (default_patch && !other_output),
+            context: self.unified.unwrap_or(DEFAULT_CONTEXT),
+            binary: self.binary,
+            word_diff,
+            stat,
+            numstat: self.numstat,
+            shortstat: self.shortstat,
+            dirstat: self.dirstat.clone(),
+            summary: self.summary,
+            name_only: self.name_only,
+            name_status: self.name_status,
+        }
+    }
 }
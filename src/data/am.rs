@@ -0,0 +1,257 @@
+//! `og am`: turning patch emails back into commits, stopping at the first one that does not apply.
+//!
+//! Progress is kept in `.ogit/rebase-apply`, so a stopped run can be continued, skipped past or aborted.
+use std::{fs, path::PathBuf};
+
+use super::{
+    apply::{self, ApplyOptions},
+    base,
+    commits::OgitCommit,
+    filesystem,
+    index::Index,
+    mailbox::{self, PatchEmail},
+    patch, refs,
+};
+
+const STATE_DIR: &str = "rebase-apply";
+
+fn state_path(name: &str) -> std::io::Result<PathBuf> {
+    filesystem::ogit_path(&format!("{STATE_DIR}/{name}"))
+}
+
+fn read_number(name: &str) -> std::io::Result<usize> {
+    fs::read_to_string(state_path(name)?)?
+        .trim()
+        .parse()
+        .map_err(std::io::Error::other)
+}
+
+fn write_number(name: &str, value: usize) -> std::io::Result<()> {
+    fs::write(state_path(name)?, format!("{value}\n"))
+}
+
+fn load_email(number: usize) -> std::io::Result<PatchEmail> {
+    let message = fs::read(state_path(&format!("{number:04}"))?)?;
+    mailbox::parse_email(&message).map_err(std::io::Error::other)
+}
+
+/// Whether a stopped `am` is waiting to be continued.
+pub fn in_progress() -> std::io::Result<bool> {
+    Ok(filesystem::ogit_path(STATE_DIR)?.is_dir())
+}
+
+fn ensure_in_progress() -> std::io::Result<()> {
+    if in_progress()? {
+        Ok(())
+    } else {
+        Err(std::io::Error::other("no am session in progress"))
+    }
+}
+
+/// Commit `tree` on top of HEAD with the author, date and message of `email`.
+fn commit(email: &PatchEmail, tree: &str) -> std::io::Result<()> {
+    let head = filesystem::read_head_file()?;
+    let parents = if head.is_empty() {
+        vec![]
+    } else {
+        vec![head.clone()]
+    };
+    let commit = base::commit_tree_with_author(
+        tree,
+        &parents,
+        &email.message(),
+        &email.author,
+        &email.date,
+    )?;
+    let subject = OgitCommit::get(&commit.hex_string())?.subject();
+    refs::move_head(&commit.hex_string(), &head, &format!("am: {subject}"))
+}
+
+/// Apply and commit the remaining patches in order.
+///
+/// Returns `false` when a patch did not apply, leaving the state for `--continue`, `--skip` or `--abort`.
+fn run() -> std::io::Result<bool> {
+    let last = read_number("last")?;
+    let mut next = read_number("next")?;
+    while next <= last {
+        let email = load_email(next)?;
+        println!("Applying: {}", email.subject);
+        let applied = patch::parse_patch(&email.patch, 1)
+            .map_err(std::io::Error::other)
+            .and_then(|patches| {
+                if patches.is_empty() {
+                    return Err(std::io::Error::other("Patch is empty."));
+                }
+                let options = ApplyOptions {
+                    index: true,
+                    ..Default::default()
+                };
+                apply::apply_patches(&patches, &options)
+            });
+        match applied {
+            Ok(outcome) => {
+                for message in outcome.messages {
+                    eprintln!("{message}");
+                }
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                eprintln!("Patch failed at {next:04} {}", email.subject);
+                eprintln!("When you have resolved this problem, run \"og am --continue\".");
+                eprintln!("If you prefer to skip this patch, run \"og am --skip\" instead.");
+                eprintln!(
+                    "To restore the original branch and stop patching, run \"og am --abort\"."
+                );
+                return Ok(false);
+            }
+        }
+        commit(&email, &Index::load()?.write_tree()?)?;
+        next += 1;
+        write_number("next", next)?;
+    }
+    fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+    Ok(true)
+}
+
+/// Start turning the messages of `mbox` into commits on top of HEAD.
+pub fn start(mbox: &[u8]) -> std::io::Result<bool> {
+    if in_progress()? {
+        return Err(std::io::Error::other(
+            "previous am session still in progress, use --continue, --skip or --abort",
+        ));
+    }
+    let messages = mailbox::split_mbox(mbox);
+    if messages.is_empty() {
+        return Err(std::io::Error::other("no patches found"));
+    }
+    fs::create_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+    for (i, message) in messages.iter().enumerate() {
+        fs::write(state_path(&format!("{:04}", i + 1))?, message)?;
+    }
+    fs::write(
+        state_path("orig-head")?,
+        format!("{}\n", filesystem::read_head_file()?),
+    )?;
+    write_number("last", messages.len())?;
+    write_number("next", 1)?;
+    run()
+}
+
+/// Carry on after a stopped patch, committing the working tree as its resolution unless `skip`.
+pub fn resume(skip: bool) -> std::io::Result<bool> {
+    ensure_in_progress()?;
+    let next = read_number("next")?;
+    if !skip {
+        let email = load_email(next)?;
+        // the resolution is what was added to the index, untracked files stay out of the commit
+        let index = Index::load()?;
+        if let Some(path) = index.unmerged_paths().first() {
+            return Err(std::io::Error::other(format!(
+                "{}: needs merge\nYou still have unmerged paths in your index.",
+                path.display()
+            )));
+        }
+        let tree = index.write_tree()?;
+        let head = filesystem::read_head_file()?;
+        if !head.is_empty() && OgitCommit::get(&head)?.tree == tree {
+            return Err(std::io::Error::other(
+                "No changes - did you forget to use 'og add'? Use --skip to drop the patch.",
+            ));
+        }
+        commit(&email, &tree)?;
+    }
+    write_number("next", next + 1)?;
+    run()
+}
+
+/// Stop and move HEAD and the working tree back to where they were before `am` started.
+pub fn abort() -> std::io::Result<()> {
+    ensure_in_progress()?;
+    let orig_head = fs::read_to_string(state_path("orig-head")?)?;
+    let orig_head = orig_head.trim();
+    if !orig_head.is_empty() {
+        let head = filesystem::read_head_file()?;
+        let tree = OgitCommit::get(orig_head)?.tree;
+        base::read_tree(&tree)?;
+        refs::move_head(orig_head, &head, "am --abort")?;
+        Index::from_tree(&tree)?.save()?;
+    }
+    fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::data::{
+        diff::{self, DiffFormat, DiffOptions},
+        porcelain,
+        testrepo::TestRepo,
+    };
+
+    /// The email `format-patch` makes of `id`.
+    fn email(id: &str) -> String {
+        let commit = OgitCommit::get(id).unwrap();
+        let options = DiffOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let format = DiffFormat {
+            patch: true,
+            context: patch::DEFAULT_CONTEXT,
+            ..Default::default()
+        };
+        let diff = diff::render(&diff::diff_commit(&commit, &options).unwrap(), &format).unwrap();
+        mailbox::format_email(id, &commit, "[PATCH]", &diff)
+    }
+
+    #[test]
+    fn test_am_round_trip() {
+        let repo = TestRepo::new();
+        repo.write("a.txt", "one\ntwo\nthree\n");
+        let base = repo.commit_all("first");
+        repo.write("a.txt", "one\n2\nthree\n");
+        repo.write("b.txt", "new\n");
+        let sent = repo.commit_all("Change two\n\nAnd add b.");
+        let mbox = email(&sent);
+        repo.rewind(&base);
+
+        assert!(start(mbox.as_bytes()).unwrap());
+        assert!(!in_progress().unwrap());
+        let sent = OgitCommit::get(&sent).unwrap();
+        let applied = OgitCommit::get(&filesystem::read_head_file().unwrap()).unwrap();
+        assert_eq!(applied.parents, vec![base]);
+        assert_eq!(applied.tree, sent.tree);
+        assert_eq!(applied.message, sent.message);
+        assert_eq!(applied.author, sent.author);
+        assert_eq!(applied.author_time, sent.author_time);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n2\nthree\n");
+    }
+
+    #[test]
+    fn test_am_continue_commits_the_index() {
+        let repo = TestRepo::new();
+        repo.write("a.txt", "one\ntwo\nthree\n");
+        let base = repo.commit_all("first");
+        repo.write("a.txt", "one\n2\nthree\n");
+        let sent = repo.commit_all("Change two");
+        let mbox = email(&sent);
+        repo.rewind(&base);
+        repo.write("a.txt", "uno\ndos\ntres\n");
+        repo.commit_all("Translate");
+
+        assert!(!start(mbox.as_bytes()).unwrap());
+        repo.write("a.txt", "uno\n2\ntres\n");
+        repo.write("untracked.log", "noise\n");
+        assert!(resume(false).is_err());
+        porcelain::add(&[PathBuf::from("a.txt")]).unwrap();
+        assert!(resume(false).unwrap());
+
+        let applied = OgitCommit::get(&filesystem::read_head_file().unwrap()).unwrap();
+        assert_eq!(applied.subject(), "Change two");
+        let tree = Index::from_tree(&applied.tree).unwrap();
+        assert!(tree.get(Path::new("a.txt")).is_some());
+        assert!(tree.get(Path::new("untracked.log")).is_none());
+    }
+}
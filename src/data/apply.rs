@@ -0,0 +1,538 @@
+//! Applying patches read by [`patch::parse_patch`] to the working directory and the index.
+//!
+//! Every file patch is checked before anything is written, so a patch applies completely or not
+//! at all unless rejected hunks are asked for.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    diffstat::{self, FileStat},
+    filesystem,
+    index::{Index, IndexEntry},
+    objects::{OgitObject, OgitObjectType},
+    patch::{FilePatch, PatchHunk, PatchLine},
+    xdiff,
+};
+
+/// Mode given to created files when the patch does not name one.
+const DEFAULT_MODE: &str = "100644";
+
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    /// Only report whether the patches apply
+    pub check: bool,
+    /// Apply to both the working directory and the index, which must agree
+    pub index: bool,
+    /// Apply to the index only
+    pub cached: bool,
+    /// Undo the patches instead
+    pub reverse: bool,
+    /// Apply what applies and write the remaining hunks to `<path>.rej`
+    pub reject: bool,
+    /// Context lines that must match around every hunk, all of them when unset
+    pub context: Option<usize>,
+}
+
+/// Messages of a successful run, and whether some hunks ended up in reject files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyOutcome {
+    pub messages: Vec<String>,
+    pub rejected: bool,
+}
+
+/// Content and mode of a path, `None` when the path does not exist.
+type FileState = Option<(Vec<u8>, String)>;
+
+/// Where a hunk landed.
+struct Placement {
+    /// Zero based line the hunk's (possibly reduced) preimage starts at
+    position: usize,
+    fuzz: usize,
+    /// Context lines left out at the start and end of the hunk
+    leading: usize,
+    trailing: usize,
+}
+
+fn find_hunk(
+    image: &[Vec<u8>],
+    hunk: &PatchHunk,
+    expected: usize,
+    earliest: usize,
+    max_fuzz: usize,
+) -> Option<Placement> {
+    let leading_context = hunk
+        .lines
+        .iter()
+        .take_while(|l| matches!(l, PatchLine::Context(_)))
+        .count();
+    let trailing_context = hunk
+        .lines
+        .iter()
+        .rev()
+        .take_while(|l| matches!(l, PatchLine::Context(_)))
+        .count();
+    let preimage = hunk.preimage();
+    for fuzz in 0..=max_fuzz {
+        let leading = fuzz.min(leading_context);
+        let trailing = fuzz.min(trailing_context);
+        if leading + trailing > preimage.len() {
+            break;
+        }
+        let wanted = &preimage[leading..preimage.len() - trailing];
+        if wanted.len() > image.len() {
+            continue;
+        }
+        let last = image.len() - wanted.len();
+        let matches = |position: usize| {
+            position >= earliest
+                && position <= last
+                && image[position..position + wanted.len()]
+                    .iter()
+                    .zip(wanted)
+                    .all(|(a, b)| a.as_slice() == *b)
+        };
+        let start = (expected + leading).min(last);
+        // search outwards from where the hunk claims to be, nearest match wins
+        for distance in 0..=image.len() {
+            let candidates = [start.checked_add(distance), start.checked_sub(distance)];
+            if let Some(position) = candidates.into_iter().flatten().find(|p| matches(*p)) {
+                return Some(Placement {
+                    position,
+                    fuzz,
+                    leading,
+                    trailing,
+                });
+            }
+        }
+    }
+    None
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// Apply the hunks of `patch` to `content`, returning the result and the hunks that did not apply.
+fn apply_hunks(
+    content: &[u8],
+    hunks: &[PatchHunk],
+    options: &ApplyOptions,
+    messages: &mut Vec<String>,
+) -> (Vec<u8>, Vec<PatchHunk>) {
+    let mut image = xdiff::split_lines(content)
+        .into_iter()
+        .map(<[u8]>::to_vec)
+        .collect::<Vec<_>>();
+    let mut rejected = Vec::new();
+    let mut offset = 0isize;
+    let mut earliest = 0;
+    for (number, hunk) in hunks.iter().enumerate().map(|(i, h)| (i + 1, h)) {
+        let context = hunk
+            .lines
+            .iter()
+            .take_while(|l| matches!(l, PatchLine::Context(_)))
+            .count();
+        let max_fuzz = options.context.map_or(0, |c| context.saturating_sub(c));
+        // an empty preimage names the line the change goes after
+        let claimed = if hunk.old_len == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = claimed.saturating_add_signed(offset);
+        let Some(placement) = find_hunk(&image, hunk, expected, earliest, max_fuzz) else {
+            rejected.push(hunk.clone());
+            continue;
+        };
+        let preimage = hunk.preimage();
+        let postimage = hunk.postimage();
+        let removed = preimage.len() - placement.leading - placement.trailing;
+        let added = &postimage[placement.leading..postimage.len() - placement.trailing];
+        image.splice(
+            placement.position..placement.position + removed,
+            added.iter().map(|l| l.to_vec()),
+        );
+        let start = placement.position - placement.leading;
+        let moved = start as isize - claimed as isize;
+        if moved != 0 || placement.fuzz > 0 {
+            let mut message = format!("Hunk #{number} succeeded at {}", start + 1);
+            if placement.fuzz > 0 {
+                message.push_str(&format!(" with fuzz {}", placement.fuzz));
+            }
+            if moved != 0 {
+                message.push_str(&format!(
+                    " (offset {moved} line{})",
+                    plural(moved.unsigned_abs())
+                ));
+            }
+            message.push('.');
+            messages.push(message);
+        }
+        offset = moved + (postimage.len() as isize - preimage.len() as isize);
+        earliest = placement.position + added.len();
+    }
+    (image.concat(), rejected)
+}
+
+fn blob_id(content: &[u8]) -> String {
+    OgitObject::new(content, OgitObjectType::Blob).hex_string()
+}
+
+fn is_full_id(id: &str) -> bool {
+    id.len() == 40 && id.bytes().any(|b| b != b'0')
+}
+
+/// Reads paths from where the patch applies, remembering the effect of earlier file patches.
+struct Workspace<'a> {
+    options: &'a ApplyOptions,
+    index: Index,
+    changes: BTreeMap<PathBuf, FileState>,
+}
+
+impl Workspace<'_> {
+    fn read(&self, path: &Path) -> Result<FileState, String> {
+        if let Some(state) = self.changes.get(path) {
+            return Ok(state.clone());
+        }
+        let entry = self.index.get(path);
+        if self.options.cached {
+            return match entry {
+                Some(entry) => {
+                    let object = filesystem::get_object(&entry.id, Some(OgitObjectType::Blob))
+                        .map_err(|e| format!("{}: {e}", path.display()))?;
+                    Ok(Some((object.data, entry.mode.clone())))
+                }
+                None => Ok(None),
+            };
+        }
+        if !path.is_file() {
+            if self.options.index && entry.is_some() {
+                return Err(format!("{}: does not match index", path.display()));
+            }
+            return Ok(None);
+        }
+        let content = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mode = filesystem::get_filemode(path).map_err(|e| e.to_string())?;
+        if self.options.index && entry.is_none_or(|e| e.id != blob_id(&content)) {
+            return Err(format!("{}: does not match index", path.display()));
+        }
+        Ok(Some((content, format!("{mode:o}"))))
+    }
+
+    fn location(&self) -> &'static str {
+        if self.options.cached {
+            "index"
+        } else {
+            "working directory"
+        }
+    }
+
+    /// Work out the result of a single file patch, returning the hunks that did not apply.
+    fn apply(
+        &mut self,
+        patch: &FilePatch,
+        messages: &mut Vec<String>,
+    ) -> Result<Vec<PatchHunk>, String> {
+        let name = patch.path();
+        let original = match &patch.old_path {
+            Some(old_path) => Some(
+                self.read(old_path)?
+                    .ok_or_else(|| format!("{}: No such file or directory", old_path.display()))?,
+            ),
+            None => None,
+        };
+        let creates_path = patch.old_path.is_none() || patch.old_path != patch.new_path;
+        if let (Some(new_path), true) = (&patch.new_path, creates_path) {
+            if self.read(new_path)?.is_some() {
+                return Err(format!(
+                    "{}: already exists in {}",
+                    new_path.display(),
+                    self.location()
+                ));
+            }
+        }
+        let (old_content, old_mode) = original.unwrap_or_default();
+        if let Some(expected) = &patch.old_mode {
+            if !old_mode.is_empty() && *expected != old_mode {
+                messages.push(format!(
+                    "warning: {} has type {old_mode}, expected {expected}",
+                    name.display()
+                ));
+            }
+        }
+
+        let (content, rejected) = if let Some(binary) = &patch.binary {
+            (self.apply_binary(patch, binary, &old_content)?, Vec::new())
+        } else if patch.binary_without_data {
+            return Err(format!(
+                "cannot apply binary patch to '{}' without full index line",
+                name.display()
+            ));
+        } else {
+            apply_hunks(&old_content, &patch.hunks, self.options, messages)
+        };
+        if !rejected.is_empty() && !self.options.reject {
+            return Err(format!("patch failed: {}", name.display()));
+        }
+
+        match &patch.new_path {
+            Some(new_path) => {
+                let mode = patch
+                    .new_mode
+                    .clone()
+                    .or_else(|| (!old_mode.is_empty()).then_some(old_mode))
+                    .unwrap_or_else(|| DEFAULT_MODE.to_string());
+                if patch.is_rename {
+                    self.changes.insert(patch.old_path.clone().unwrap(), None);
+                }
+                self.changes.insert(new_path.clone(), Some((content, mode)));
+            }
+            None if !content.is_empty() => {
+                return Err(format!(
+                    "removal patch leaves file contents: {}",
+                    name.display()
+                ));
+            }
+            None => {
+                self.changes.insert(patch.old_path.clone().unwrap(), None);
+            }
+        }
+        Ok(rejected)
+    }
+
+    fn apply_binary(
+        &self,
+        patch: &FilePatch,
+        binary: &super::binary::BinaryPatch,
+        old_content: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let name = patch.path();
+        let old_id = patch.old_id.as_deref().unwrap_or_default();
+        let new_id = patch.new_id.as_deref().unwrap_or_default();
+        if patch.old_path.is_some() && (!is_full_id(old_id) || !is_full_id(new_id)) {
+            return Err(format!(
+                "cannot apply binary patch to '{}' without full index line",
+                name.display()
+            ));
+        }
+        if patch.old_path.is_some() && blob_id(old_content) != old_id {
+            return Err(format!(
+                "the patch applies to '{}' ({old_id}), which does not match the current contents",
+                name.display()
+            ));
+        }
+        let hunk = binary
+            .forward
+            .as_ref()
+            .ok_or_else(|| format!("binary patch for '{}' is not reversible", name.display()))?;
+        let content = hunk.apply(old_content)?;
+        if is_full_id(new_id) && blob_id(&content) != new_id {
+            return Err(format!(
+                "binary patch to '{}' creates incorrect result (expecting {new_id}, got {})",
+                name.display(),
+                blob_id(&content)
+            ));
+        }
+        Ok(content)
+    }
+}
+
+fn reject_file(patch: &FilePatch, hunks: &[PatchHunk]) -> String {
+    let name = patch.path();
+    let mut out = format!(
+        "diff a/{} b/{}\t(rejected hunks)\n",
+        name.display(),
+        name.display()
+    );
+    for hunk in hunks {
+        out.push_str(&hunk.to_text());
+    }
+    out
+}
+
+/// Apply `patches` in order, writing nothing unless all of them apply (or `reject` is set).
+pub fn apply_patches(
+    patches: &[FilePatch],
+    options: &ApplyOptions,
+) -> Result<ApplyOutcome, std::io::Error> {
+    let patches = if options.reverse {
+        patches.iter().map(FilePatch::reversed).collect()
+    } else {
+        patches.to_vec()
+    };
+    let mut workspace = Workspace {
+        options,
+        index: Index::load()?,
+        changes: BTreeMap::new(),
+    };
+    let mut outcome = ApplyOutcome::default();
+    let mut errors = Vec::new();
+    let mut rejects = Vec::new();
+    for patch in &patches {
+        let mut messages = Vec::new();
+        match workspace.apply(patch, &mut messages) {
+            Ok(rejected) if rejected.is_empty() => outcome.messages.extend(messages),
+            Ok(rejected) => {
+                let name = patch.path();
+                outcome.messages.push(format!(
+                    "Applying patch {} with {} reject{}...",
+                    name.display(),
+                    rejected.len(),
+                    plural(rejected.len())
+                ));
+                outcome.messages.extend(messages);
+                for hunk in &rejected {
+                    let number = patch.hunks.iter().position(|h| h == hunk).unwrap() + 1;
+                    outcome.messages.push(format!("Rejected hunk #{number}."));
+                }
+                rejects.push((name, reject_file(patch, &rejected)));
+            }
+            Err(e) => {
+                outcome.messages.extend(messages);
+                errors.push(format!("error: {e}"));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        outcome.messages.extend(errors);
+        return Err(std::io::Error::other(outcome.messages.join("\n")));
+    }
+    outcome.rejected = !rejects.is_empty();
+    if options.check {
+        return Ok(outcome);
+    }
+
+    if !options.cached {
+        for (path, state) in &workspace.changes {
+            match state {
+                Some((content, mode)) => filesystem::write_worktree_file(path, content, mode)?,
+                None if path.exists() => fs::remove_file(path)?,
+                None => (),
+            }
+        }
+        for (path, content) in rejects {
+            let mut reject_path = path.into_os_string();
+            reject_path.push(".rej");
+            fs::write(reject_path, content)?;
+        }
+    }
+    if options.index || options.cached {
+        let mut index = workspace.index;
+        for (path, state) in workspace.changes {
+            match state {
+                Some((content, mode)) => {
+                    let object = filesystem::hash_object(&content, Some(OgitObjectType::Blob))?;
+                    index.add(IndexEntry {
+                        mode,
+                        id: object.hex_string(),
+                        stage: 0,
+                        path,
+                    });
+                }
+                None => index.remove(&path),
+            }
+        }
+        index.save()?;
+    }
+    Ok(outcome)
+}
+
+/// Lines added and removed by every file patch, for `--stat`.
+pub fn patch_stats(patches: &[FilePatch]) -> Vec<FileStat> {
+    patches
+        .iter()
+        .map(|patch| {
+            let path = patch.path().display().to_string();
+            let name = match (&patch.old_path, &patch.new_path) {
+                (Some(old), Some(new)) if old != new => {
+                    diffstat::pprint_rename(&old.display().to_string(), &new.display().to_string())
+                }
+                _ => path.clone(),
+            };
+            let count = |wanted: fn(&PatchLine) -> bool| {
+                patch
+                    .hunks
+                    .iter()
+                    .flat_map(|h| &h.lines)
+                    .filter(|l| wanted(l))
+                    .count()
+            };
+            let binary = (patch.binary.is_some() || patch.binary_without_data).then_some((0, 0));
+            FileStat {
+                name,
+                path,
+                added: count(|l| matches!(l, PatchLine::Added(_))),
+                deleted: count(|l| matches!(l, PatchLine::Removed(_))),
+                changed_bytes: 0,
+                binary,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::patch;
+
+    fn hunks(text: &str) -> Vec<PatchHunk> {
+        let text = format!("--- a/f\n+++ b/f\n{text}");
+        patch::parse_patch(text.as_bytes(), 1).unwrap()[0]
+            .hunks
+            .clone()
+    }
+
+    #[test]
+    fn test_apply_with_offset() {
+        let content = b"new first\nnew second\na\nb\nc\nd\n";
+        let hunks = hunks("@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n");
+        let mut messages = Vec::new();
+        let (result, rejected) =
+            apply_hunks(content, &hunks, &ApplyOptions::default(), &mut messages);
+        assert!(rejected.is_empty());
+        assert_eq!(result, b"new first\nnew second\na\nB\nc\nd\n");
+        assert_eq!(messages, vec!["Hunk #1 succeeded at 3 (offset 2 lines)."]);
+    }
+
+    #[test]
+    fn test_apply_with_fuzz() {
+        let content = b"a\nb\nc\nd\ne\n";
+        let hunks = hunks("@@ -2,3 +2,3 @@\n x\n-c\n+C\n d\n");
+        let mut messages = Vec::new();
+        let (_, rejected) = apply_hunks(content, &hunks, &ApplyOptions::default(), &mut messages);
+        assert_eq!(rejected.len(), 1);
+
+        let options = ApplyOptions {
+            context: Some(0),
+            ..Default::default()
+        };
+        let (result, rejected) = apply_hunks(content, &hunks, &options, &mut messages);
+        assert!(rejected.is_empty());
+        assert_eq!(result, b"a\nb\nC\nd\ne\n");
+        assert_eq!(messages, vec!["Hunk #1 succeeded at 2 with fuzz 1."]);
+    }
+
+    #[test]
+    fn test_patch_stats() {
+        let text = b"diff --git a/dir/old.txt b/dir/new.txt\n\
+            similarity index 50%\n\
+            rename from dir/old.txt\n\
+            rename to dir/new.txt\n\
+            --- a/dir/old.txt\n\
+            +++ b/dir/new.txt\n\
+            @@ -1,2 +1,2 @@\n \
+            a\n\
+            -b\n\
+            +c\n";
+        let stats = patch_stats(&patch::parse_patch(text, 1).unwrap());
+        assert_eq!(stats[0].name, "dir/{old.txt => new.txt}");
+        assert_eq!((stats[0].added, stats[0].deleted), (1, 1));
+    }
+}
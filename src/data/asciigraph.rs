@@ -0,0 +1,198 @@
+//! Drawing history as text next to `log` output, the way `git log --graph` does.
+//!
+//! Every line of history waiting for its next commit takes a lane, two characters wide. A commit
+//! is drawn as `*` in its lane, a merge opens lanes for its other parents with `\` and lanes
+//! leading to a commit that another lane already waits for close with `/`.
+#[derive(Debug, Clone, Default)]
+pub struct AsciiGraph {
+    /// The commit each lane is waiting for
+    lanes: Vec<String>,
+}
+
+/// A row of `width` lanes, drawn into a character buffer.
+struct Row(Vec<char>);
+
+impl Row {
+    fn new(width: usize) -> Self {
+        Self(vec![' '; width * 2])
+    }
+
+    fn set(&mut self, position: usize, c: char) {
+        if position >= self.0.len() {
+            self.0.resize(position + 1, ' ');
+        }
+        self.0[position] = c;
+    }
+
+    fn finish(self) -> String {
+        self.0
+            .into_iter()
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+}
+
+impl AsciiGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What goes before the lines of a commit that follow the rows drawn for it.
+    pub fn padding(&self) -> String {
+        let mut row = Row::new(self.lanes.len());
+        for lane in 0..self.lanes.len() {
+            row.set(lane * 2, '|');
+        }
+        row.0.into_iter().collect()
+    }
+
+    /// Draw commit `id`, whose shown parents are `parents`.
+    ///
+    /// Returns the row with the commit, padded for the text that follows it, and the rows that
+    /// lead from it to the next commit.
+    pub fn commit(&mut self, id: &str, parents: &[String]) -> (String, Vec<String>) {
+        let column = match self.lanes.iter().position(|lane| lane == id) {
+            Some(column) => column,
+            None => {
+                self.lanes.push(id.to_string());
+                self.lanes.len() - 1
+            }
+        };
+        let width = self.lanes.len();
+        let mut commit_row = Row::new(width + parents.len().saturating_sub(1));
+        for lane in 0..width {
+            commit_row.set(lane * 2, if lane == column { '*' } else { '|' });
+        }
+
+        let mut rows = Vec::new();
+        match parents {
+            [] => {
+                self.lanes.remove(column);
+                if column < self.lanes.len() {
+                    let mut row = Row::new(width);
+                    for lane in 0..width {
+                        match lane.cmp(&column) {
+                            std::cmp::Ordering::Less => row.set(lane * 2, '|'),
+                            std::cmp::Ordering::Equal => (),
+                            std::cmp::Ordering::Greater => row.set(lane * 2 - 1, '/'),
+                        }
+                    }
+                    rows.push(row.finish());
+                }
+            }
+            [first, others @ ..] => {
+                self.lanes[column] = first.clone();
+                if !others.is_empty() {
+                    let mut row = Row::new(width + others.len());
+                    for lane in 0..width {
+                        if lane <= column {
+                            row.set(lane * 2, '|');
+                        } else {
+                            // lanes to the right make room for the new ones
+                            row.set(lane * 2 + 1, '\\');
+                        }
+                    }
+                    for extra in 1..=others.len() {
+                        row.set((column + extra) * 2 - 1, '\\');
+                    }
+                    rows.push(row.finish());
+                    for (i, other) in others.iter().enumerate() {
+                        self.lanes.insert(column + 1 + i, other.clone());
+                    }
+                }
+            }
+        }
+        rows.extend(self.collapse());
+        (commit_row.0.into_iter().collect(), rows)
+    }
+
+    /// Close every lane waiting for a commit an earlier lane waits for as well.
+    fn collapse(&mut self) -> Vec<String> {
+        let mut rows = Vec::new();
+        while let Some((target, lane)) = (0..self.lanes.len()).find_map(|lane| {
+            let target = self.lanes[..lane]
+                .iter()
+                .position(|l| *l == self.lanes[lane])?;
+            Some((target, lane))
+        }) {
+            let mut row = Row::new(self.lanes.len());
+            for other in 0..self.lanes.len() {
+                if other < lane {
+                    row.set(other * 2, '|');
+                    if other >= target && other + 1 < lane {
+                        row.set(other * 2 + 1, '_');
+                    }
+                } else {
+                    row.set(other * 2 - 1, '/');
+                }
+            }
+            self.lanes.remove(lane);
+            rows.push(row.finish());
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(history: &[(&str, &[&str])]) -> String {
+        let mut graph = AsciiGraph::new();
+        let mut out = String::new();
+        for (id, parents) in history {
+            let parents = parents.iter().map(ToString::to_string).collect::<Vec<_>>();
+            let (commit_row, rows) = graph.commit(id, &parents);
+            out.push_str(&format!("{commit_row}{id}\n"));
+            for row in rows {
+                out.push_str(&format!("{row}\n"));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_merge_lanes() {
+        let drawn = draw(&[
+            ("merge", &["main2", "side2"]),
+            ("side2", &["side1"]),
+            ("side1", &["base"]),
+            ("main2", &["main1"]),
+            ("main1", &["base"]),
+            ("base", &[]),
+        ]);
+        let expected = "\
+*   merge
+|\\
+| * side2
+| * side1
+* | main2
+* | main1
+|/
+* base
+";
+        assert_eq!(drawn, expected);
+    }
+
+    #[test]
+    fn test_distant_lanes_join() {
+        let drawn = draw(&[
+            ("a", &["base"]),
+            ("b", &["x"]),
+            ("c", &["base"]),
+            ("x", &["base"]),
+            ("base", &[]),
+        ]);
+        let expected = "\
+* a
+| * b
+| | * c
+|_|/
+| * x
+|/
+* base
+";
+        assert_eq!(drawn, expected);
+    }
+}
@@ -0,0 +1,144 @@
+//! Per path attributes read from `.ogitattributes`, following a subset of gitattributes.
+//!
+//! More info: <https://git-scm.com/docs/gitattributes>
+use std::path::Path;
+
+use super::filesystem;
+
+/// One `pattern attr1 -attr2 attr3=value` line of the attributes file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AttributeLine {
+    pattern: String,
+    attributes: Vec<(String, bool)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    lines: Vec<AttributeLine>,
+}
+
+impl Attributes {
+    /// Read the attributes file of the current repository
+    pub fn load() -> Self {
+        Self::parse(&filesystem::load_attributes_file())
+    }
+
+    fn parse(lines: &[String]) -> Self {
+        let lines = lines
+            .iter()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let pattern = parts.next()?.to_string();
+                let attributes = parts
+                    .map(|attr| match attr.strip_prefix('-') {
+                        Some(unset) => (unset.to_string(), false),
+                        None => (attr.split('=').next().unwrap().to_string(), true),
+                    })
+                    .collect();
+                Some(AttributeLine {
+                    pattern,
+                    attributes,
+                })
+            })
+            .collect();
+        Self { lines }
+    }
+
+    /// Whether `attribute` is set (`Some(true)`), unset (`Some(false)`) or unspecified for `path`.
+    ///
+    /// Later lines win over earlier ones, same as git.
+    pub fn get(&self, path: &Path, attribute: &str) -> Option<bool> {
+        let full = path.to_string_lossy();
+        let name = path
+            .file_name()
+            .map_or_else(|| full.clone(), |n| n.to_string_lossy());
+        self.lines
+            .iter()
+            .rev()
+            .filter(|line| {
+                // patterns without a slash match the file name at any depth
+                if line.pattern.contains('/') {
+                    glob_match(line.pattern.trim_start_matches('/'), &full)
+                } else {
+                    glob_match(&line.pattern, &name)
+                }
+            })
+            .find_map(|line| {
+                line.attributes.iter().rev().find_map(|(attr, set)| {
+                    // `binary` is a macro for `-diff -merge -text`
+                    if attr == "binary" && matches!(attribute, "diff" | "merge" | "text") {
+                        Some(!set)
+                    } else {
+                        (attr == attribute).then_some(*set)
+                    }
+                })
+            })
+    }
+
+    /// Whether `path` should be diffed as binary regardless of its content.
+    ///
+    /// `None` leaves the decision to content sniffing.
+    pub fn is_binary(&self, path: &Path) -> Option<bool> {
+        self.get(path, "diff")
+            .or_else(|| self.get(path, "text"))
+            .map(|diffable| !diffable)
+    }
+}
+
+/// Shell style matching of `*` (any run of characters) and `?` (any single character).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // position after the last star and the text position it was tried at, for backtracking
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(c) if *c == b'?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.png", "logo.png"));
+        assert!(glob_match("img/*.png", "img/logo.png"));
+        assert!(glob_match("data-??.bin", "data-01.bin"));
+        assert!(!glob_match("*.png", "logo.jpg"));
+        assert!(!glob_match("data-?.bin", "data-01.bin"));
+    }
+
+    #[test]
+    fn test_binary_attributes() {
+        let attributes = Attributes::parse(&[
+            "*.png binary".to_string(),
+            "*.svg -diff".to_string(),
+            "docs/*.pdf diff".to_string(),
+            "logo.png diff".to_string(),
+        ]);
+        assert_eq!(attributes.is_binary(Path::new("img/icon.png")), Some(true));
+        assert_eq!(attributes.is_binary(Path::new("logo.png")), Some(false));
+        assert_eq!(attributes.is_binary(Path::new("a/b.svg")), Some(true));
+        assert_eq!(attributes.is_binary(Path::new("docs/x.pdf")), Some(false));
+        assert_eq!(attributes.is_binary(Path::new("main.rs")), None);
+    }
+}
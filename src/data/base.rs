@@ -1,4 +1,4 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset};
 use itertools::Itertools;
 use regex::Regex;
 use std::{
@@ -19,11 +19,11 @@ use crate::data::{
 };
 
 #[derive(Debug, Clone)]
-struct TreeEntry {
-    filemode: String,
-    filename: PathBuf,
-    id: String,
-    variant: OgitObjectType,
+pub struct TreeEntry {
+    pub filemode: String,
+    pub filename: PathBuf,
+    pub id: String,
+    pub variant: OgitObjectType,
 }
 impl Display for TreeEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -77,7 +77,7 @@ impl TreeEntry {
 }
 
 #[allow(clippy::trivial_regex)]
-fn load_ignore_regex() -> Vec<Regex> {
+pub fn load_ignore_regex() -> Vec<Regex> {
     let ignore_lines = filesystem::load_ignore_file();
     // TODO: fix bug where `target/` won't match `target` as a path
     // Follow gitignore spec better: https://git-scm.com/docs/gitignore
@@ -96,7 +96,8 @@ fn load_ignore_regex() -> Vec<Regex> {
 /// An Ogit Tree is a collection of Ogit Blobs and Ogit Trees.
 /// So find the sub items and create them as Ogit Blobs or Ogit Trees
 /// and pass up the sub roots to the parent tree.
-pub fn write_tree(directory: Option<PathBuf>) -> Result<OgitObject, std::io::Error> {
+/// Without `write` nothing is stored, the tree is only hashed to compare it with others.
+pub fn write_tree(directory: Option<PathBuf>, write: bool) -> Result<OgitObject, std::io::Error> {
     // let mut tree = OgitObject::new(OgitObjectType::Tree);
     let mut tree_entries = Vec::new();
     let ignore_regexes = load_ignore_regex();
@@ -108,19 +109,30 @@ pub fn write_tree(directory: Option<PathBuf>) -> Result<OgitObject, std::io::Err
             continue;
         }
         if path.is_dir() {
-            let sub_tree = write_tree(Some(path.clone()))?;
+            let sub_tree = write_tree(Some(path.clone()), write)?;
             tree_entries.push(TreeEntry::new(&path, sub_tree));
         } else {
             let data = fs::read(&path).unwrap();
-            let object = hash_object(&data, Some(OgitObjectType::Blob))?;
+            let object = hash(&data, OgitObjectType::Blob, write)?;
             tree_entries.push(TreeEntry::new(&path, object));
         }
     }
+    // directory listings come in no particular order, the same content must give the same tree
+    tree_entries.sort_by(|a, b| a.filename.cmp(&b.filename));
     let tree_data = build_tree_data(&tree_entries);
-    hash_object(tree_data.as_bytes(), Some(OgitObjectType::Tree))
+    hash(tree_data.as_bytes(), OgitObjectType::Tree, write)
 }
 
-fn build_tree_data(entries: &[TreeEntry]) -> String {
+/// Hash `data`, storing it in the object database only when `write` is set.
+fn hash(data: &[u8], variant: OgitObjectType, write: bool) -> Result<OgitObject, std::io::Error> {
+    if write {
+        hash_object(data, Some(variant))
+    } else {
+        Ok(filesystem::hash_object_in_memory(data, Some(variant)))
+    }
+}
+
+pub fn build_tree_data(entries: &[TreeEntry]) -> String {
     entries.iter().fold(String::new(), |mut acc, entry| {
         writeln!(&mut acc, "{entry}").unwrap();
         acc
@@ -136,25 +148,25 @@ pub fn is_ignored(path: &Path, ignores: &[Regex]) -> bool {
 }
 
 /// Returns iterator of the `TreeEntry` in the tree object specified by `tree_id`.
-fn parse_tree_data(tree_id: Option<&str>) -> Vec<TreeEntry> {
+pub fn parse_tree_data(tree_id: Option<&str>) -> Result<Vec<TreeEntry>, std::io::Error> {
     let tree = if let Some(id) = tree_id {
-        filesystem::get_object(id, Some(OgitObjectType::Tree)).unwrap()
+        filesystem::get_object(id, Some(OgitObjectType::Tree))?
     } else {
-        return Vec::new();
+        return Ok(Vec::new());
     };
     // OgitObject stores file content as bytes, but since this is tree we know its valid UTF8
     let tree_data = String::from_utf8(tree.data).unwrap();
-    tree_data
+    Ok(tree_data
         .lines()
         .map(|line| line.parse::<TreeEntry>().unwrap())
-        .collect_vec()
+        .collect_vec())
 }
 
 /// Returns path to object and the OID of its contents
-fn get_tree(tree_id: &str, path: Option<PathBuf>) -> Result<Vec<TreeEntry>, std::io::Error> {
+pub fn get_tree(tree_id: &str, path: Option<PathBuf>) -> Result<Vec<TreeEntry>, std::io::Error> {
     let base_path = path.unwrap_or_else(|| PathBuf::from("."));
     let mut result = Vec::new();
-    let mut entries = parse_tree_data(Some(tree_id));
+    let mut entries = parse_tree_data(Some(tree_id))?;
     for entry in &mut entries {
         let mut path = base_path.clone();
         match entry.variant {
@@ -168,7 +180,7 @@ fn get_tree(tree_id: &str, path: Option<PathBuf>) -> Result<Vec<TreeEntry>, std:
                 let sub_tree = get_tree(&entry.id, Some(path))?;
                 result.extend(sub_tree);
             }
-            OgitObjectType::Commit => panic!("Invalid object type in tree"),
+            OgitObjectType::Commit | OgitObjectType::Tag => panic!("Invalid object type in tree"),
         }
     }
     Ok(result)
@@ -214,19 +226,12 @@ pub fn read_tree(tree_id: &str) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-fn format_author_commit_line(author: &str, time: &DateTime<Local>) -> String {
-    format!(
-        "author {author} {epoch_duration} {utc_offset}\n",
-        epoch_duration = time.timestamp(),
-        utc_offset = time.offset(),
-    )
+// git stores the offset as `+HHMM`, without the colon chrono shows offsets with
+fn format_author_commit_line(author: &str, time: &DateTime<FixedOffset>) -> String {
+    format!("author {author} {}\n", time.format("%s %z"))
 }
-fn format_committer_commit_line(committer: &str, time: &DateTime<Local>) -> String {
-    format!(
-        "committer {committer} {epoch_duration} {utc_offset}\n",
-        epoch_duration = time.timestamp(),
-        utc_offset = time.offset(),
-    )
+fn format_committer_commit_line(committer: &str, time: &DateTime<FixedOffset>) -> String {
+    format!("committer {committer} {}\n", time.format("%s %z"))
 }
 
 fn read_stdin_for_message() -> Result<String, std::io::Error> {
@@ -247,6 +252,18 @@ pub fn commit_tree(
         None => read_stdin_for_message()?,
     };
 
+    let time = time::get_current_local().fixed_offset();
+    commit_tree_with_author(tree_id, parent, &message, config::AUTHOR, &time)
+}
+
+/// Like [`commit_tree`], but keeping the author and date of a commit made elsewhere.
+pub fn commit_tree_with_author(
+    tree_id: &str,
+    parent: &[String],
+    message: &str,
+    author: &str,
+    author_time: &DateTime<FixedOffset>,
+) -> Result<OgitObject, std::io::Error> {
     let tree = filesystem::get_object(tree_id, Some(OgitObjectType::Tree))?;
     let mut commit_message = String::new();
     commit_message.push_str(&format!("tree {tree}\n"));
@@ -256,8 +273,8 @@ pub fn commit_tree(
         // also need to do this because *EVENTUALLy* the given id might be a min identifier, not the full id
         commit_message.push_str(&format!("parent {}\n", parent_commit.hex_string()));
     }
-    let time = time::get_current_local();
-    commit_message.push_str(&format_author_commit_line(config::AUTHOR, &time));
+    let time = time::get_current_local().fixed_offset();
+    commit_message.push_str(&format_author_commit_line(author, author_time));
     // TODO: use the committer from the config
     commit_message.push_str(&format_committer_commit_line(config::AUTHOR, &time));
     commit_message.push('\n');
@@ -291,4 +308,23 @@ mod tests {
         assert_eq!(entry.filename, PathBuf::from("data"));
         assert_eq!(entry.id, test_object.hex_string());
     }
+
+    #[test]
+    fn test_commit_identity_lines_round_trip() {
+        let _repo = crate::data::testrepo::TestRepo::new();
+        let tree = hash_object(b"", Some(OgitObjectType::Tree)).unwrap();
+        let date = DateTime::parse_from_rfc3339("2005-04-07T15:13:13-07:00").unwrap();
+        let author = "A U Thor <author@example.com>";
+        let commit =
+            commit_tree_with_author(&tree.hex_string(), &[], "subject\n", author, &date).unwrap();
+        let data = String::from_utf8(commit.data.clone()).unwrap();
+        assert!(data.contains("\nauthor A U Thor <author@example.com> 1112911993 -0700\n"));
+        let committer = Regex::new(r"\ncommitter .* \d+ [+-]\d{4}\n").unwrap();
+        assert!(committer.is_match(&data));
+
+        let parsed = crate::data::commits::OgitCommit::get(&commit.hex_string()).unwrap();
+        assert_eq!(parsed.author, author);
+        assert_eq!(parsed.author_time, date);
+        assert_eq!(parsed.author_time.offset(), date.offset());
+    }
 }
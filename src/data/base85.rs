@@ -0,0 +1,58 @@
+//! The base85 flavour git uses to embed binary data in patches.
+//!
+//! More info: <https://github.com/git/git/blob/master/base85.c>
+
+const ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Encode `data` in groups of 4 bytes, each becoming 5 characters. The last group is zero padded.
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(4) * 5);
+    for chunk in data.chunks(4) {
+        let mut group = [0u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(group);
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = ALPHABET[(value % 85) as usize];
+            value /= 85;
+        }
+        out.push_str(std::str::from_utf8(&digits).unwrap());
+    }
+    out
+}
+
+fn decode_digit(c: u8) -> Option<u64> {
+    ALPHABET.iter().position(|a| *a == c).map(|d| d as u64)
+}
+
+/// Decode groups of 5 characters back into `len` bytes, the inverse of [`encode`].
+pub fn decode(text: &[u8], len: usize) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(5) || text.len() / 5 * 4 < len {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() / 5 * 4);
+    for group in text.chunks(5) {
+        let value = group
+            .iter()
+            .try_fold(0u64, |acc, c| Some(acc * 85 + decode_digit(*c)?))?;
+        out.extend_from_slice(&u32::try_from(value).ok()?.to_be_bytes());
+    }
+    out.truncate(len);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_value() {
+        // zlib stream of empty content, as found in every `literal 0` hunk git writes
+        let empty = [0x78, 0x01, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01];
+        assert_eq!(encode(&empty), "cmV?d00001");
+        assert_eq!(decode(b"cmV?d00001", 8).unwrap(), empty);
+        assert_eq!(decode(b"cmV?d00001", 7).unwrap(), empty[..7]);
+        assert!(decode(b"cmV?d0000", 7).is_none());
+    }
+}
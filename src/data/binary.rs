@@ -0,0 +1,258 @@
+//! Telling binary content apart from text, and git's `GIT binary patch` format.
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    fmt::Write as _,
+    io::{Read as _, Write as _},
+};
+
+use super::base85;
+
+/// Only this many leading bytes are inspected, same as git.
+const SNIFF_LENGTH: usize = 8000;
+/// Bytes of compressed data encoded on a single line of a binary hunk.
+const BYTES_PER_LINE: usize = 52;
+
+/// Content is considered binary when a NUL byte shows up near the start.
+pub fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(SNIFF_LENGTH)].contains(&0)
+}
+
+/// Character prefixed to every binary hunk line telling how many bytes it decodes to.
+fn line_length_char(len: usize) -> char {
+    let len = u8::try_from(len).unwrap();
+    if len <= 26 {
+        char::from(b'A' + len - 1)
+    } else {
+        char::from(b'a' + len - 27)
+    }
+}
+
+/// Number of bytes a binary hunk line decodes to, from its first character.
+fn line_length(c: u8) -> Option<usize> {
+    match c {
+        b'A'..=b'Z' => Some(usize::from(c - b'A') + 1),
+        b'a'..=b'z' => Some(usize::from(c - b'a') + 27),
+        _ => None,
+    }
+}
+
+fn literal_hunk(data: &[u8]) -> String {
+    // git deflates patch data with its fastest setting, matching it keeps our patches identical
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(data).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let mut hunk = format!("literal {}\n", data.len());
+    for line in compressed.chunks(BYTES_PER_LINE) {
+        writeln!(
+            hunk,
+            "{}{}",
+            line_length_char(line.len()),
+            base85::encode(line)
+        )
+        .unwrap();
+    }
+    hunk.push('\n');
+    hunk
+}
+
+/// The body of a binary patch: the new content, followed by the old content for reversing.
+pub fn format_binary_patch(old: &[u8], new: &[u8]) -> String {
+    format!(
+        "GIT binary patch\n{}{}",
+        literal_hunk(new),
+        literal_hunk(old)
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryHunkKind {
+    /// The hunk holds the complete resulting content
+    Literal,
+    /// The hunk holds instructions rebuilding the result from the original content
+    Delta,
+}
+
+/// One decoded hunk of a binary patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryHunk {
+    pub kind: BinaryHunkKind,
+    pub data: Vec<u8>,
+}
+
+impl BinaryHunk {
+    /// The content this hunk turns `original` into.
+    pub fn apply(&self, original: &[u8]) -> Result<Vec<u8>, String> {
+        match self.kind {
+            BinaryHunkKind::Literal => Ok(self.data.clone()),
+            BinaryHunkKind::Delta => apply_delta(original, &self.data),
+        }
+    }
+}
+
+/// The hunks of a `GIT binary patch`, the reverse hunk is what makes it reversible.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinaryPatch {
+    pub forward: Option<BinaryHunk>,
+    pub reverse: Option<BinaryHunk>,
+}
+
+impl BinaryPatch {
+    pub fn reversed(&self) -> Self {
+        Self {
+            forward: self.reverse.clone(),
+            reverse: self.forward.clone(),
+        }
+    }
+}
+
+fn parse_binary_hunk(lines: &[&[u8]], i: &mut usize) -> Result<Option<BinaryHunk>, String> {
+    let Some(header) = lines.get(*i) else {
+        return Ok(None);
+    };
+    let header = String::from_utf8_lossy(header);
+    let (kind, size) = if let Some(size) = header.strip_prefix("literal ") {
+        (BinaryHunkKind::Literal, size)
+    } else if let Some(size) = header.strip_prefix("delta ") {
+        (BinaryHunkKind::Delta, size)
+    } else {
+        return Ok(None);
+    };
+    let corrupt = |line: usize| format!("corrupt binary patch at line {line}");
+    let size = size
+        .trim_end()
+        .parse::<usize>()
+        .map_err(|_| corrupt(*i + 1))?;
+    *i += 1;
+    let mut compressed = Vec::new();
+    while let Some(line) = lines.get(*i) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        *i += 1;
+        if line.is_empty() {
+            break;
+        }
+        let len = line_length(line[0]).ok_or_else(|| corrupt(*i))?;
+        compressed.extend(base85::decode(&line[1..], len).ok_or_else(|| corrupt(*i))?);
+    }
+    let mut data = Vec::new();
+    ZlibDecoder::new(compressed.as_slice())
+        .read_to_end(&mut data)
+        .map_err(|e| format!("corrupt binary patch: {e}"))?;
+    if data.len() != size {
+        return Err(format!(
+            "binary patch hunk inflated to {} bytes instead of {size}",
+            data.len()
+        ));
+    }
+    Ok(Some(BinaryHunk { kind, data }))
+}
+
+/// Read the hunks following a `GIT binary patch` line, leaving `i` after them.
+pub fn parse_binary_patch(lines: &[&[u8]], i: &mut usize) -> Result<BinaryPatch, String> {
+    let forward = parse_binary_hunk(lines, i)?;
+    if forward.is_none() {
+        return Err(format!("binary patch without data at line {}", *i + 1));
+    }
+    let reverse = parse_binary_hunk(lines, i)?;
+    Ok(BinaryPatch { forward, reverse })
+}
+
+/// Little endian base 128 size found at the start of a delta.
+fn read_delta_size(delta: &[u8], position: &mut usize) -> Option<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*position)?;
+        *position += 1;
+        size |= usize::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(size);
+        }
+    }
+}
+
+/// Rebuild content from `base` and a git delta of copy and insert instructions.
+///
+/// More info: <https://git-scm.com/docs/pack-format#_deltified_representation>
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
+    let corrupt = || "corrupt binary delta".to_string();
+    let mut position = 0;
+    let base_size = read_delta_size(delta, &mut position).ok_or_else(corrupt)?;
+    let result_size = read_delta_size(delta, &mut position).ok_or_else(corrupt)?;
+    if base_size != base.len() {
+        return Err("binary delta does not apply to this content".to_string());
+    }
+    let mut out = Vec::with_capacity(result_size);
+    while let Some(&op) = delta.get(position) {
+        position += 1;
+        if op & 0x80 != 0 {
+            // copy from the base, the set bits tell which offset and size bytes follow
+            let mut fields = [0usize; 2];
+            for (bit, shift) in (0..7).map(|bit| (bit, if bit < 4 { bit } else { bit - 4 })) {
+                if op & (1 << bit) != 0 {
+                    let byte = usize::from(*delta.get(position).ok_or_else(corrupt)?);
+                    fields[usize::from(bit >= 4)] |= byte << (8 * shift);
+                    position += 1;
+                }
+            }
+            let [offset, mut size] = fields;
+            if size == 0 {
+                size = 0x10000;
+            }
+            out.extend_from_slice(base.get(offset..offset + size).ok_or_else(corrupt)?);
+        } else if op != 0 {
+            let size = usize::from(op);
+            out.extend_from_slice(delta.get(position..position + size).ok_or_else(corrupt)?);
+            position += size;
+        } else {
+            return Err(corrupt());
+        }
+    }
+    if out.len() != result_size {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_binary() {
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!is_binary(b"plain text\n"));
+        let mut late_nul = vec![b'a'; SNIFF_LENGTH];
+        late_nul.push(0);
+        assert!(!is_binary(&late_nul));
+    }
+
+    #[test]
+    fn test_empty_literal() {
+        assert_eq!(literal_hunk(b""), "literal 0\nHcmV?d00001\n\n");
+    }
+
+    #[test]
+    fn test_parse_binary_patch_round_trip() {
+        let old = b"\0old content".repeat(20);
+        let new = b"\0new content".repeat(30);
+        let text = format_binary_patch(&old, &new);
+        let lines = text
+            .split_inclusive('\n')
+            .map(str::as_bytes)
+            .collect::<Vec<_>>();
+        let mut i = 1;
+        let patch = parse_binary_patch(&lines, &mut i).unwrap();
+        assert_eq!(i, lines.len());
+        assert_eq!(patch.forward.unwrap().apply(&old).unwrap(), new);
+        assert_eq!(patch.reverse.unwrap().apply(&new).unwrap(), old);
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        // sizes 10 -> 8, copy 4 bytes from offset 2, insert "xyzw"
+        let delta = [10, 8, 0x91, 2, 4, 4, b'x', b'y', b'z', b'w'];
+        assert_eq!(apply_delta(b"0123456789", &delta).unwrap(), b"2345xyzw");
+        assert!(apply_delta(b"012345678", &delta).is_err());
+    }
+}
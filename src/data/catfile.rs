@@ -0,0 +1,123 @@
+//! The plumbing side of `cat-file`: type, size and content of objects, one at a time or in bulk.
+//!
+//! Batch mode reads object names from its input, one per line, and answers each with a
+//! `<id> <type> <size>` line, followed by the content with `--batch`. Names that do not resolve
+//! are answered with `<name> missing`, so tools can keep a single process busy.
+use std::io::{BufRead, Write};
+
+use super::{
+    base, filesystem,
+    objects::{OgitObject, OgitObjectType},
+    revision,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CatFileMode {
+    /// The content as stored
+    #[default]
+    Raw,
+    /// Only the type
+    Type,
+    /// Only the size of the content
+    Size,
+    /// Nothing, the exit status tells whether the object exists
+    Exists,
+    /// The content, trees decoded into readable lines
+    Pretty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchMode {
+    /// Answer with the object line and the content
+    Contents,
+    /// Answer with the object line only
+    Check,
+}
+
+/// The content of `object` the way `cat-file -p` shows it.
+///
+/// Trees become `<mode> <type> <id>\t<name>` lines, everything else is shown as stored.
+pub fn pretty_print(object: &OgitObject) -> std::io::Result<Vec<u8>> {
+    if object.variant != OgitObjectType::Tree {
+        return Ok(object.data.clone());
+    }
+    let text = String::from_utf8(object.data.clone()).map_err(std::io::Error::other)?;
+    let mut out = String::new();
+    for line in text.lines() {
+        let entry = line
+            .parse::<base::TreeEntry>()
+            .map_err(std::io::Error::other)?;
+        out.push_str(&format!(
+            "{} {} {}\t{}\n",
+            entry.filemode,
+            entry.variant,
+            entry.id,
+            entry.filename.display()
+        ));
+    }
+    Ok(out.into_bytes())
+}
+
+/// Answer one batch request for `name`.
+fn batch_one(name: &str, mode: BatchMode, out: &mut impl Write) -> std::io::Result<()> {
+    let object = match revision::resolve(name) {
+        Ok(id) => filesystem::get_object(&id, None)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return writeln!(out, "{name} missing");
+        }
+        Err(e) => return Err(e),
+    };
+    writeln!(out, "{object} {} {}", object.variant, object.data.len())?;
+    if mode == BatchMode::Contents {
+        out.write_all(&object.data)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Answer every object name read from `input`, or every stored object with `all_objects`.
+pub fn batch(
+    input: impl BufRead,
+    out: &mut impl Write,
+    mode: BatchMode,
+    all_objects: bool,
+) -> std::io::Result<()> {
+    if all_objects {
+        for id in filesystem::list_object_ids()? {
+            batch_one(&id, mode, out)?;
+        }
+        return out.flush();
+    }
+    for line in input.lines() {
+        let line = line?;
+        let name = line.trim();
+        if name.is_empty() {
+            continue;
+        }
+        batch_one(name, mode, out)?;
+        // whoever writes the next name is waiting for this answer
+        out.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pretty_print_tree() {
+        let tree = OgitObject::new(
+            b"100644 blob 95d09f2b10159347eece71399a7e2e907ea3df4f hello.txt\n\
+              040000 tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904 src\n",
+            OgitObjectType::Tree,
+        );
+        assert_eq!(
+            String::from_utf8(pretty_print(&tree).unwrap()).unwrap(),
+            "100644 blob 95d09f2b10159347eece71399a7e2e907ea3df4f\thello.txt\n\
+             040000 tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\tsrc\n"
+        );
+        let blob = OgitObject::new(b"hello world", OgitObjectType::Blob);
+        assert_eq!(pretty_print(&blob).unwrap(), b"hello world");
+    }
+}
@@ -13,19 +13,6 @@ pub struct OgitCommit {
     pub message: Vec<u8>, // message can be any byte sequence (emojis or such)
 }
 
-impl std::fmt::Display for OgitCommit {
-    /// Pretty print the commit object, similar to git log does
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Author: {}", self.author)?;
-        writeln!(f, "Date:   {}", self.author_time.format("%c %z"))?;
-        writeln!(
-            f,
-            "\n    {}",
-            std::str::from_utf8(&self.message).unwrap().trim_end()
-        )
-    }
-}
-
 impl OgitCommit {
     pub fn new(
         tree: String,
@@ -34,10 +21,8 @@ impl OgitCommit {
         committer: &str,
         message: Vec<u8>,
     ) -> Self {
-        let author_time = Self::get_epoch_offset_from_line(author);
-        let author = author.split_whitespace().next().unwrap().to_string();
-        let committer_time = Self::get_epoch_offset_from_line(committer);
-        let committer = committer.split_whitespace().next().unwrap().to_string();
+        let (author, author_time) = Self::split_identity_line(author);
+        let (committer, committer_time) = Self::split_identity_line(committer);
         Self {
             tree,
             parents,
@@ -50,12 +35,44 @@ impl OgitCommit {
     }
 
     /// Parse a line of format:
-    ///     'prefix item item EPOCH OFFSET'
-    /// Into a `DateTime` object. Works best for the author and commiter lines
-    fn get_epoch_offset_from_line(line: &str) -> time::DateTime<time::FixedOffset> {
-        let line_parts = line.split_whitespace().collect::<Vec<&str>>();
+    ///     'Name <email> EPOCH OFFSET'
+    /// Into the `Name <email>` identity and a `DateTime` object. Works best for the author and commiter lines
+    pub fn split_identity_line(line: &str) -> (String, time::DateTime<time::FixedOffset>) {
+        let mut line_parts = line.rsplitn(3, ' ');
+        let offset = line_parts.next().unwrap();
+        let epoch = line_parts.next().unwrap();
+        let identity = line_parts.next().unwrap_or_default();
+        let time = time::DateTime::parse_from_str(&format!("{epoch} {offset}"), "%s %z").unwrap();
+        (identity.to_string(), time)
+    }
+
+    /// Name part of the author identity.
+    pub fn author_name(&self) -> &str {
+        self.author
+            .split_once(" <")
+            .map_or(self.author.as_str(), |(name, _)| name)
+    }
 
-        time::DateTime::parse_from_str(&line_parts[3..=4].join(" "), "%s %z").unwrap()
+    /// First paragraph of the message, joined into a single line.
+    pub fn subject(&self) -> String {
+        String::from_utf8_lossy(&self.message)
+            .trim_start()
+            .split("\n\n")
+            .next()
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The message after the subject paragraph.
+    pub fn body(&self) -> String {
+        String::from_utf8_lossy(&self.message)
+            .trim_start()
+            .split_once("\n\n")
+            .map(|(_, body)| body.trim().to_string())
+            .unwrap_or_default()
     }
 
     pub fn get(id: &str) -> std::io::Result<Self> {
@@ -97,3 +114,24 @@ impl OgitCommit {
         Ok(Self::new(tree, parents, &author, &committer, message))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_and_message_parts() {
+        let commit = OgitCommit::new(
+            "4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string(),
+            Vec::new(),
+            "Ryan Ozawa <ryan.ozawa21@gmail.com> 1700000000 +0100",
+            "Ryan Ozawa <ryan.ozawa21@gmail.com> 1700000000 +0100",
+            b"Fix the thing\nacross two lines\n\nLonger explanation.\n".to_vec(),
+        );
+        assert_eq!(commit.author, "Ryan Ozawa <ryan.ozawa21@gmail.com>");
+        assert_eq!(commit.author_name(), "Ryan Ozawa");
+        assert_eq!(commit.author_time.timestamp(), 1_700_000_000);
+        assert_eq!(commit.subject(), "Fix the thing across two lines");
+        assert_eq!(commit.body(), "Longer explanation.");
+    }
+}
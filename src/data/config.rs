@@ -2,3 +2,116 @@
 
 // TODO: actually read this out of a config file
 pub const AUTHOR: &str = "Ryan Ozawa <ryan.ozawa21@gmail.com>";
+
+/// Program used to edit messages and todo lists, picked from the environment like git does.
+pub fn editor() -> String {
+    ["OGIT_EDITOR", "VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+const CONFIG_FILE: &str = "config";
+
+/// Value of `key` (e.g. `core.fsync`) in the git style `content`, the last one set wins.
+///
+/// Section and key names are case insensitive, `[remote "origin"]` holds `remote.origin.*` keys
+/// and a key without `=` is `true`.
+fn find_value(content: &str, key: &str) -> Option<String> {
+    let (section, name) = key.rsplit_once('.')?;
+    let mut current = String::new();
+    let mut value = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = match header.split_once(' ') {
+                Some((section, sub)) => format!(
+                    "{}.{}",
+                    section.to_ascii_lowercase(),
+                    sub.trim().trim_matches('"')
+                ),
+                None => header.to_ascii_lowercase(),
+            };
+            continue;
+        }
+        let (entry, entry_value) = match line.split_once('=') {
+            Some((entry, entry_value)) => (entry.trim(), entry_value.trim().trim_matches('"')),
+            None => (line, "true"),
+        };
+        if current == section.to_ascii_lowercase() && entry.eq_ignore_ascii_case(name) {
+            value = Some(entry_value.to_string());
+        }
+    }
+    value
+}
+
+/// Value of `key` in `.ogit/config`, `None` when it is not set.
+pub fn get(key: &str) -> std::io::Result<Option<String>> {
+    match std::fs::read_to_string(super::filesystem::ogit_path(CONFIG_FILE)?) {
+        Ok(content) => Ok(find_value(&content, key)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether the `core.fsync` component list `value` asks for loose objects to be flushed.
+fn fsyncs_loose_objects(value: &str) -> bool {
+    let mut enabled = false;
+    for component in value.split(',').map(str::trim) {
+        match component {
+            "none" => enabled = false,
+            "loose-object" | "objects" | "committed" | "added" | "all" => enabled = true,
+            _ => {}
+        }
+    }
+    enabled
+}
+
+/// Whether written objects are flushed to disk before being renamed into place.
+///
+/// Like git, they are not unless `core.fsync` lists loose objects, or the older
+/// `core.fsyncObjectFiles` is true.
+pub fn fsync_objects() -> std::io::Result<bool> {
+    if let Some(value) = get("core.fsync")? {
+        return Ok(fsyncs_loose_objects(&value));
+    }
+    Ok(get("core.fsyncObjectFiles")?.is_some_and(|value| value == "true"))
+}
+
+/// Whether objects read from the database are checked against the id they were asked for.
+///
+/// On unless `core.verifyObjects` is false, looked up once as it is asked for every object read.
+pub fn verify_objects() -> bool {
+    static VERIFY: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *VERIFY.get_or_init(|| get("core.verifyObjects").ok().flatten().as_deref() != Some("false"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_value() {
+        let content = "[core]\n\tfsync = objects,reference\n\tbare\n\
+                       [remote \"origin\"]\n\turl = https://example.com/repo.git\n\
+                       [Core]\n\tFsync = none\n";
+        assert_eq!(find_value(content, "core.fsync").as_deref(), Some("none"));
+        assert_eq!(find_value(content, "core.bare").as_deref(), Some("true"));
+        assert_eq!(
+            find_value(content, "remote.origin.url").as_deref(),
+            Some("https://example.com/repo.git")
+        );
+        assert_eq!(find_value(content, "core.editor"), None);
+    }
+
+    #[test]
+    fn test_fsyncs_loose_objects() {
+        assert!(fsyncs_loose_objects("reference,loose-object"));
+        assert!(fsyncs_loose_objects("committed"));
+        assert!(!fsyncs_loose_objects("reference,index"));
+        assert!(!fsyncs_loose_objects("all,none"));
+    }
+}
@@ -0,0 +1,707 @@
+//! Comparing two trees and pairing up what changed between them.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    attributes::Attributes,
+    base::{self, TreeEntry},
+    binary,
+    commits::OgitCommit,
+    diffstat::{self, DirstatOptions, FileStat, StatOptions},
+    filesystem,
+    objects::OgitObjectType,
+    patch,
+    worddiff::WordDiffMode,
+    xdiff,
+};
+
+/// Similarity (in percent) two files need before they are paired as a rename or copy.
+pub const DEFAULT_SIMILARITY: u8 = 50;
+/// Mode printed for the side of a change that does not exist.
+pub const NULL_MODE: &str = "000000";
+/// Id printed for the side of a change that does not exist.
+pub const NULL_ID: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffStatus {
+    Added,
+    Deleted,
+    Modified,
+    TypeChanged,
+    Renamed,
+    Copied,
+}
+
+impl DiffStatus {
+    pub const fn letter(self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Deleted => 'D',
+            Self::Modified => 'M',
+            Self::TypeChanged => 'T',
+            Self::Renamed => 'R',
+            Self::Copied => 'C',
+        }
+    }
+}
+
+/// One side (before or after) of a changed path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffSide {
+    pub path: PathBuf,
+    pub mode: String,
+    pub id: String,
+}
+
+impl DiffSide {
+    fn from_entry(entry: &TreeEntry, path: PathBuf) -> Self {
+        Self {
+            path,
+            mode: entry.filemode.clone(),
+            id: entry.id.clone(),
+        }
+    }
+    pub fn is_tree(&self) -> bool {
+        object_kind(&self.mode) == object_kind("040000")
+    }
+}
+
+/// A single changed path, `old` is missing for additions and `new` is missing for deletions.
+#[derive(Debug, Clone)]
+pub struct DiffEntry {
+    pub status: DiffStatus,
+    pub old: Option<DiffSide>,
+    pub new: Option<DiffSide>,
+    /// Similarity percentage, only present for renames and copies.
+    pub score: Option<u8>,
+}
+
+impl Display for DiffEntry {
+    /// Formats the entry the way `--name-status` prints it
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) if old.path != new.path => write!(
+                f,
+                "{}\t{}\t{}",
+                self.status_string(),
+                old.path.display(),
+                new.path.display()
+            ),
+            _ => write!(f, "{}\t{}", self.status_string(), self.path().display()),
+        }
+    }
+}
+
+impl DiffEntry {
+    fn one_sided(status: DiffStatus, side: DiffSide) -> Self {
+        let (old, new) = match status {
+            DiffStatus::Deleted => (Some(side), None),
+            _ => (None, Some(side)),
+        };
+        Self {
+            status,
+            old,
+            new,
+            score: None,
+        }
+    }
+    /// The path this change ends up at, or the deleted path for deletions.
+    pub fn path(&self) -> &Path {
+        self.new
+            .as_ref()
+            .or(self.old.as_ref())
+            .map(|side| side.path.as_path())
+            .unwrap()
+    }
+    /// Status letter followed by the similarity score when there is one, e.g. `R086`.
+    pub fn status_string(&self) -> String {
+        match self.score {
+            Some(score) => format!("{}{score:03}", self.status.letter()),
+            None => self.status.letter().to_string(),
+        }
+    }
+    /// Formats the entry the way plumbing commands print it by default
+    pub fn raw(&self) -> String {
+        let (old_mode, old_id) = self
+            .old
+            .as_ref()
+            .map_or((NULL_MODE, NULL_ID), |s| (s.mode.as_str(), s.id.as_str()));
+        let (new_mode, new_id) = self
+            .new
+            .as_ref()
+            .map_or((NULL_MODE, NULL_ID), |s| (s.mode.as_str(), s.id.as_str()));
+        let name_status = self.to_string();
+        let (_, paths) = name_status.split_once('\t').unwrap();
+        format!(
+            ":{old_mode} {new_mode} {old_id} {new_id} {}\t{paths}",
+            self.status_string()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Descend into sub trees instead of reporting them as a single entry
+    pub recursive: bool,
+    /// Minimum similarity to report a rename, `None` disables rename detection
+    pub rename_score: Option<u8>,
+    /// Minimum similarity to report a copy, `None` disables copy detection
+    pub copy_score: Option<u8>,
+    /// Consider every file of the old tree as a copy source, not only the changed ones
+    pub find_copies_harder: bool,
+}
+
+impl DiffOptions {
+    const fn detect_copies(&self) -> bool {
+        self.copy_score.is_some() || self.find_copies_harder
+    }
+    const fn detect_renames(&self) -> bool {
+        self.rename_score.is_some() || self.detect_copies()
+    }
+}
+
+/// How a list of changes gets shown, every enabled section is printed in git's order.
+#[derive(Debug, Clone, Default)]
+pub struct DiffFormat {
+    pub patch: bool,
+    /// Unchanged lines shown around each change of a patch
+    pub context: usize,
+    /// Emit binary changes as applicable `GIT binary patch` data
+    pub binary: bool,
+    /// Compare changed lines word by word
+    pub word_diff: Option<WordDiffMode>,
+    pub stat: Option<StatOptions>,
+    pub numstat: bool,
+    pub shortstat: bool,
+    pub dirstat: Option<DirstatOptions>,
+    pub summary: bool,
+    pub name_only: bool,
+    pub name_status: bool,
+}
+
+impl DiffFormat {
+    /// Whether any output section is enabled at all.
+    pub const fn is_enabled(&self) -> bool {
+        self.needs_content() || self.summary || self.name_only || self.name_status
+    }
+    const fn needs_content(&self) -> bool {
+        self.patch
+            || self.stat.is_some()
+            || self.numstat
+            || self.shortstat
+            || self.dirstat.is_some()
+    }
+}
+
+/// Parse a similarity the way git does for `-M`/`-C`.
+///
+/// `50%` is a percentage while bare digits are read as a decimal fraction, so `5` and `50` both mean 50%.
+pub fn parse_similarity(value: &str) -> Result<u8, String> {
+    let invalid = || format!("invalid similarity score '{value}'");
+    if value.is_empty() {
+        return Ok(DEFAULT_SIMILARITY);
+    }
+    if let Some(percent) = value.strip_suffix('%') {
+        return percent
+            .parse::<u8>()
+            .ok()
+            .filter(|p| *p <= 100)
+            .ok_or_else(invalid);
+    }
+    if !value.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    // only the first two digits after the implied "0." matter for a whole percentage
+    let digits = format!("{value:0<2}");
+    digits[..2].parse::<u8>().map_err(|_| invalid())
+}
+
+/// Extracts the file type bits of a tree entry mode so `100644` and `100755` compare equal.
+fn object_kind(mode: &str) -> u32 {
+    u32::from_str_radix(mode, 8).unwrap_or(0) >> 12
+}
+
+/// Compare the trees identified by `old_tree` and `new_tree`.
+///
+/// A missing tree is treated as empty, so diffing against `None` lists every path as added or deleted.
+pub fn diff_trees(
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    options: &DiffOptions,
+) -> Result<Vec<DiffEntry>, std::io::Error> {
+    let mut entries = Vec::new();
+    diff_tree_entries(
+        &base::parse_tree_data(old_tree)?,
+        &base::parse_tree_data(new_tree)?,
+        Path::new(""),
+        options.recursive,
+        &mut entries,
+    )?;
+    if !options.detect_renames() {
+        return Ok(entries);
+    }
+    let unmodified = if options.find_copies_harder {
+        let mut all = Vec::new();
+        diff_tree_entries(
+            &[],
+            &base::parse_tree_data(old_tree)?,
+            Path::new(""),
+            true,
+            &mut all,
+        )?;
+        all.into_iter().filter_map(|e| e.new).collect()
+    } else {
+        Vec::new()
+    };
+    detect_renames(entries, unmodified, options, |id| {
+        filesystem::get_object(id, Some(OgitObjectType::Blob)).map(|o| o.data)
+    })
+}
+
+/// Changes a commit introduced on top of its first parent, a root commit adds everything.
+pub fn diff_commit(
+    commit: &OgitCommit,
+    options: &DiffOptions,
+) -> Result<Vec<DiffEntry>, std::io::Error> {
+    let parent_tree = match commit.parents.first() {
+        Some(parent) => Some(OgitCommit::get(parent)?.tree),
+        None => None,
+    };
+    diff_trees(parent_tree.as_deref(), Some(&commit.tree), options)
+}
+
+/// Whether `path` is one of `pathspecs` or lies inside one of them.
+pub fn matches_pathspec(path: &Path, pathspecs: &[PathBuf]) -> bool {
+    pathspecs.iter().any(|spec| {
+        let spec = spec.strip_prefix(".").unwrap_or(spec);
+        spec.as_os_str().is_empty() || path.starts_with(spec)
+    })
+}
+
+/// The changes to paths inside `pathspecs`, every change when there are none.
+pub fn limit_to_paths(entries: Vec<DiffEntry>, pathspecs: &[PathBuf]) -> Vec<DiffEntry> {
+    if pathspecs.is_empty() {
+        return entries;
+    }
+    entries
+        .into_iter()
+        .filter(|entry| {
+            [&entry.old, &entry.new]
+                .into_iter()
+                .flatten()
+                .any(|side| matches_pathspec(&side.path, pathspecs))
+        })
+        .collect()
+}
+
+fn diff_tree_entries(
+    old: &[TreeEntry],
+    new: &[TreeEntry],
+    prefix: &Path,
+    recursive: bool,
+    out: &mut Vec<DiffEntry>,
+) -> Result<(), std::io::Error> {
+    let old_by_name: BTreeMap<&Path, &TreeEntry> =
+        old.iter().map(|e| (e.filename.as_path(), e)).collect();
+    let new_by_name: BTreeMap<&Path, &TreeEntry> =
+        new.iter().map(|e| (e.filename.as_path(), e)).collect();
+    let names: BTreeSet<&Path> = old_by_name
+        .keys()
+        .chain(new_by_name.keys())
+        .copied()
+        .collect();
+    for name in names {
+        let path = prefix.join(name);
+        match (old_by_name.get(name), new_by_name.get(name)) {
+            (Some(o), Some(n)) => {
+                // identical ids mean identical content all the way down, no need to look inside
+                if o.id == n.id && o.filemode == n.filemode {
+                    continue;
+                }
+                let old_is_tree = o.variant == OgitObjectType::Tree;
+                let new_is_tree = n.variant == OgitObjectType::Tree;
+                if old_is_tree && new_is_tree && recursive {
+                    diff_tree_entries(
+                        &base::parse_tree_data(Some(&o.id))?,
+                        &base::parse_tree_data(Some(&n.id))?,
+                        &path,
+                        recursive,
+                        out,
+                    )?;
+                } else if old_is_tree != new_is_tree {
+                    // a file turning into a directory is not comparable content
+                    push_one_sided(o, path.clone(), DiffStatus::Deleted, recursive, out)?;
+                    push_one_sided(n, path, DiffStatus::Added, recursive, out)?;
+                } else {
+                    let status = if object_kind(&o.filemode) == object_kind(&n.filemode) {
+                        DiffStatus::Modified
+                    } else {
+                        DiffStatus::TypeChanged
+                    };
+                    out.push(DiffEntry {
+                        status,
+                        old: Some(DiffSide::from_entry(o, path.clone())),
+                        new: Some(DiffSide::from_entry(n, path)),
+                        score: None,
+                    });
+                }
+            }
+            (Some(o), None) => push_one_sided(o, path, DiffStatus::Deleted, recursive, out)?,
+            (None, Some(n)) => push_one_sided(n, path, DiffStatus::Added, recursive, out)?,
+            (None, None) => unreachable!("name came from one of the trees"),
+        }
+    }
+    Ok(())
+}
+
+fn push_one_sided(
+    entry: &TreeEntry,
+    path: PathBuf,
+    status: DiffStatus,
+    recursive: bool,
+    out: &mut Vec<DiffEntry>,
+) -> Result<(), std::io::Error> {
+    if recursive && entry.variant == OgitObjectType::Tree {
+        let mut children = base::parse_tree_data(Some(&entry.id))?;
+        children.sort_by(|a, b| a.filename.cmp(&b.filename));
+        for child in &children {
+            push_one_sided(child, path.join(&child.filename), status, recursive, out)?;
+        }
+    } else {
+        out.push(DiffEntry::one_sided(
+            status,
+            DiffSide::from_entry(entry, path),
+        ));
+    }
+    Ok(())
+}
+
+/// Score how much of `dst` was carried over from `src`, as a percentage of the larger file.
+///
+/// Lines are matched regardless of their position, which is close enough to git's chunk hashing
+/// for deciding whether two files are related.
+pub fn similarity(src: &[u8], dst: &[u8]) -> u8 {
+    let larger = src.len().max(dst.len());
+    if larger == 0 {
+        return 100;
+    }
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for line in src.split_inclusive(|b| *b == b'\n') {
+        *counts.entry(line).or_default() += 1;
+    }
+    let mut common = 0;
+    for line in dst.split_inclusive(|b| *b == b'\n') {
+        if let Some(count) = counts.get_mut(line) {
+            if *count > 0 {
+                *count -= 1;
+                common += line.len();
+            }
+        }
+    }
+    u8::try_from(common * 100 / larger).unwrap_or(100)
+}
+
+struct RenameSource {
+    side: DiffSide,
+    /// Deleted sources turn into renames, everything else can only be copied
+    deleted: bool,
+    uses: usize,
+}
+
+/// Pair up added files with deleted (and with copy detection, existing) files that look alike.
+///
+/// `load` fetches blob content by id, which keeps this independent of where the blobs live.
+pub fn detect_renames<F>(
+    entries: Vec<DiffEntry>,
+    unmodified: Vec<DiffSide>,
+    options: &DiffOptions,
+    mut load: F,
+) -> Result<Vec<DiffEntry>, std::io::Error>
+where
+    F: FnMut(&str) -> Result<Vec<u8>, std::io::Error>,
+{
+    let copies = options.detect_copies();
+    let rename_score = options
+        .rename_score
+        .or(options.copy_score)
+        .unwrap_or(DEFAULT_SIMILARITY);
+    let copy_score = options.copy_score.unwrap_or(rename_score);
+
+    let mut sources = Vec::new();
+    for entry in &entries {
+        let candidate = match entry.status {
+            DiffStatus::Deleted => entry.old.clone().map(|side| (side, true)),
+            DiffStatus::Modified if copies => entry.old.clone().map(|side| (side, false)),
+            _ => None,
+        };
+        if let Some((side, deleted)) = candidate.filter(|(side, _)| !side.is_tree()) {
+            sources.push(RenameSource {
+                side,
+                deleted,
+                uses: 0,
+            });
+        }
+    }
+    sources.extend(unmodified.into_iter().map(|side| RenameSource {
+        side,
+        deleted: false,
+        uses: 0,
+    }));
+    let destinations = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.status == DiffStatus::Added && !e.new.as_ref().unwrap().is_tree())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if sources.is_empty() || destinations.is_empty() {
+        return Ok(entries);
+    }
+
+    let mut contents: HashMap<String, Vec<u8>> = HashMap::new();
+    // (score, exact match, destination index, source index)
+    let mut candidates = Vec::new();
+    for &dst_index in &destinations {
+        let dst = entries[dst_index].new.as_ref().unwrap();
+        for (src_index, src) in sources.iter().enumerate() {
+            if src.side.id == dst.id {
+                candidates.push((100, true, dst_index, src_index));
+                continue;
+            }
+            let minimum = if src.deleted {
+                rename_score
+            } else {
+                copy_score
+            };
+            for id in [&src.side.id, &dst.id] {
+                if !contents.contains_key(id) {
+                    contents.insert(id.clone(), load(id)?);
+                }
+            }
+            let (src_data, dst_data) = (&contents[&src.side.id], &contents[&dst.id]);
+            // empty files say nothing about where content came from
+            if src_data.is_empty() || dst_data.is_empty() {
+                continue;
+            }
+            let (smaller, larger) = if src_data.len() < dst_data.len() {
+                (src_data.len(), dst_data.len())
+            } else {
+                (dst_data.len(), src_data.len())
+            };
+            if smaller * 100 < larger * usize::from(minimum) {
+                continue;
+            }
+            let score = similarity(src_data, dst_data);
+            if score >= minimum {
+                candidates.push((score, false, dst_index, src_index));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+
+    let mut pairs: BTreeMap<usize, (usize, u8)> = BTreeMap::new();
+    for (score, _, dst_index, src_index) in candidates {
+        if pairs.contains_key(&dst_index) {
+            continue;
+        }
+        let source = &mut sources[src_index];
+        if source.deleted && source.uses > 0 && !copies {
+            continue;
+        }
+        source.uses += 1;
+        pairs.insert(dst_index, (src_index, score));
+    }
+
+    let mut result = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        if let Some(&(src_index, score)) = pairs.get(&index) {
+            let source = &mut sources[src_index];
+            // the last destination claiming a deleted file is the rename, any others are copies
+            let status = if source.deleted && source.uses == 1 {
+                DiffStatus::Renamed
+            } else {
+                DiffStatus::Copied
+            };
+            source.uses -= 1;
+            result.push(DiffEntry {
+                status,
+                old: Some(source.side.clone()),
+                new: entry.new,
+                score: Some(score),
+            });
+        } else if entry.status == DiffStatus::Deleted
+            && pairs.values().any(|(src, _)| {
+                sources[*src].deleted && Some(&sources[*src].side) == entry.old.as_ref()
+            })
+        {
+            // the deletion is explained by a rename
+        } else {
+            result.push(entry);
+        }
+    }
+    result.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(result)
+}
+
+/// Read the content of both sides of a change, a missing side is empty.
+pub fn load_content(entry: &DiffEntry) -> Result<(Vec<u8>, Vec<u8>), std::io::Error> {
+    let load = |side: Option<&DiffSide>| match side {
+        Some(side) if !side.is_tree() => {
+            filesystem::get_object(&side.id, Some(OgitObjectType::Blob)).map(|o| o.data)
+        }
+        _ => Ok(Vec::new()),
+    };
+    Ok((load(entry.old.as_ref())?, load(entry.new.as_ref())?))
+}
+
+/// Render `entries` with every output section `format` asks for.
+pub fn render(entries: &[DiffEntry], format: &DiffFormat) -> Result<String, std::io::Error> {
+    let mut out = String::new();
+    for entry in entries {
+        if format.name_only {
+            out.push_str(&format!("{}\n", entry.path().display()));
+        } else if format.name_status {
+            out.push_str(&format!("{entry}\n"));
+        }
+    }
+    if !format.needs_content() {
+        if format.summary {
+            out.push_str(&diffstat::format_summary(entries));
+        }
+        return Ok(out);
+    }
+
+    let attributes = Attributes::load();
+    let mut stats = Vec::with_capacity(entries.len());
+    let mut patches = String::new();
+    for entry in entries {
+        let (old, new) = load_content(entry)?;
+        // attributes win over looking at the content
+        let is_binary = attributes
+            .is_binary(entry.path())
+            .unwrap_or_else(|| binary::is_binary(&old) || binary::is_binary(&new));
+        if format.patch {
+            patches.push_str(&patch::format_patch(entry, &old, &new, is_binary, format));
+        }
+        if is_binary {
+            stats.push(FileStat::binary(entry, old.len(), new.len()));
+            continue;
+        }
+        let old_lines = xdiff::split_lines(&old);
+        let new_lines = xdiff::split_lines(&new);
+        let edits = xdiff::diff(&old_lines, &new_lines);
+        let (added, deleted) = xdiff::count_changes(&edits);
+        let changed_bytes = edits
+            .iter()
+            .map(|edit| match *edit {
+                xdiff::Edit::Delete { old } => old_lines[old].len(),
+                xdiff::Edit::Insert { new } => new_lines[new].len(),
+                xdiff::Edit::Equal { .. } => 0,
+            })
+            .sum();
+        stats.push(FileStat::new(entry, added, deleted, changed_bytes));
+    }
+    if let Some(options) = &format.dirstat {
+        out.push_str(&diffstat::format_dirstat(&stats, options));
+    }
+    if format.numstat {
+        out.push_str(&diffstat::format_numstat(&stats));
+    }
+    if let Some(options) = &format.stat {
+        out.push_str(&diffstat::format_stat(&stats, options));
+    } else if format.shortstat {
+        out.push_str(&diffstat::format_shortstat(&stats));
+    }
+    if format.summary {
+        out.push_str(&diffstat::format_summary(entries));
+    }
+    if !patches.is_empty() {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&patches);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn side(path: &str, id: &str) -> DiffSide {
+        DiffSide {
+            path: PathBuf::from(path),
+            mode: "100644".to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    fn load_fixture(id: &str) -> Result<Vec<u8>, std::io::Error> {
+        Ok(match id {
+            "old" => b"one\ntwo\nthree\nfour\n".to_vec(),
+            "new" => b"one\ntwo\nthree\nfive\n".to_vec(),
+            _ => b"something else entirely\n".to_vec(),
+        })
+    }
+
+    #[test]
+    fn test_parse_similarity() {
+        assert_eq!(parse_similarity(""), Ok(50));
+        assert_eq!(parse_similarity("5"), Ok(50));
+        assert_eq!(parse_similarity("75"), Ok(75));
+        assert_eq!(parse_similarity("05"), Ok(5));
+        assert_eq!(parse_similarity("90%"), Ok(90));
+        assert!(parse_similarity("abc").is_err());
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity(b"a\nb\n", b"a\nb\n"), 100);
+        assert_eq!(similarity(b"a\nb\n", b"a\nc\n"), 50);
+        assert_eq!(similarity(b"a\n", b"b\n"), 0);
+    }
+
+    #[test]
+    fn test_detect_rename_and_copy() {
+        let entries = vec![
+            DiffEntry::one_sided(DiffStatus::Deleted, side("a.txt", "old")),
+            DiffEntry::one_sided(DiffStatus::Added, side("b.txt", "new")),
+            DiffEntry::one_sided(DiffStatus::Added, side("c.txt", "unrelated")),
+        ];
+        let options = DiffOptions {
+            rename_score: Some(50),
+            ..Default::default()
+        };
+        let result = detect_renames(entries, Vec::new(), &options, load_fixture).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].to_string(), "R073\ta.txt\tb.txt");
+        assert_eq!(result[1].to_string(), "A\tc.txt");
+
+        let entries = vec![DiffEntry::one_sided(
+            DiffStatus::Added,
+            side("b.txt", "old"),
+        )];
+        let options = DiffOptions {
+            find_copies_harder: true,
+            ..Default::default()
+        };
+        let result =
+            detect_renames(entries, vec![side("a.txt", "old")], &options, load_fixture).unwrap();
+        assert_eq!(result[0].to_string(), "C100\ta.txt\tb.txt");
+    }
+
+    #[test]
+    fn test_matches_pathspec() {
+        let specs = [PathBuf::from("src/data"), PathBuf::from("README.md")];
+        assert!(matches_pathspec(Path::new("src/data/diff.rs"), &specs));
+        assert!(matches_pathspec(Path::new("README.md"), &specs));
+        assert!(!matches_pathspec(Path::new("src/database.rs"), &specs));
+        assert!(!matches_pathspec(Path::new("src/main.rs"), &specs));
+        assert!(matches_pathspec(
+            Path::new("src/main.rs"),
+            &[PathBuf::from(".")]
+        ));
+    }
+}
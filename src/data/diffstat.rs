@@ -0,0 +1,532 @@
+//! Compact summaries of a diff: `--stat`, `--numstat`, `--shortstat`, `--dirstat` and `--summary`.
+use std::fmt::Write as _;
+
+use super::diff::{DiffEntry, DiffStatus};
+
+/// Width used for `--stat` when it is not given and the terminal width is unknown.
+const DEFAULT_STAT_WIDTH: usize = 80;
+/// Directories with less than this share of the changes (in permille) are left out of `--dirstat`.
+const DEFAULT_DIRSTAT_PERMILLE: usize = 30;
+
+/// Amount of change to a single path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    /// Name as displayed, renames are shown as `dir/{old => new}`
+    pub name: String,
+    /// Path the change is attributed to when grouping by directory
+    pub path: String,
+    pub added: usize,
+    pub deleted: usize,
+    /// Bytes of added plus removed lines, used by `--dirstat=changes`
+    pub changed_bytes: usize,
+    /// Old and new size in bytes when the content is binary, lines are meaningless then
+    pub binary: Option<(usize, usize)>,
+}
+
+impl FileStat {
+    pub fn new(entry: &DiffEntry, added: usize, deleted: usize, changed_bytes: usize) -> Self {
+        let name = match (&entry.old, &entry.new) {
+            (Some(old), Some(new)) if old.path != new.path => pprint_rename(
+                &old.path.display().to_string(),
+                &new.path.display().to_string(),
+            ),
+            _ => entry.path().display().to_string(),
+        };
+        Self {
+            name,
+            path: entry.path().display().to_string(),
+            added,
+            deleted,
+            changed_bytes,
+            binary: None,
+        }
+    }
+    pub fn binary(entry: &DiffEntry, old_size: usize, new_size: usize) -> Self {
+        Self {
+            changed_bytes: old_size + new_size,
+            binary: Some((old_size, new_size)),
+            ..Self::new(entry, 0, 0, 0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatOptions {
+    /// Total width of a stat line, defaults to the terminal width
+    pub width: Option<usize>,
+    /// Width reserved for file names
+    pub name_width: Option<usize>,
+    /// Only show this many files
+    pub count: Option<usize>,
+}
+
+/// Parse the `<width>[,<name-width>[,<count>]]` value of `--stat`.
+pub fn parse_stat_options(value: &str) -> Result<StatOptions, String> {
+    let mut numbers = value.split(',').map(|part| {
+        if part.is_empty() {
+            Ok(None)
+        } else {
+            part.parse::<usize>()
+                .map(Some)
+                .map_err(|_| format!("invalid --stat value '{value}'"))
+        }
+    });
+    Ok(StatOptions {
+        width: numbers.next().transpose()?.flatten(),
+        name_width: numbers.next().transpose()?.flatten(),
+        count: numbers.next().transpose()?.flatten(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirstatMode {
+    /// Weigh by bytes of changed lines
+    Changes,
+    /// Weigh by number of changed lines
+    Lines,
+    /// Weigh by number of changed files
+    Files,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirstatOptions {
+    pub mode: DirstatMode,
+    /// Count changes of reported sub directories in their parents as well
+    pub cumulative: bool,
+    /// Smallest share of the changes, in permille, a directory needs to be shown
+    pub permille: usize,
+}
+
+impl Default for DirstatOptions {
+    fn default() -> Self {
+        Self {
+            mode: DirstatMode::Changes,
+            cumulative: false,
+            permille: DEFAULT_DIRSTAT_PERMILLE,
+        }
+    }
+}
+
+/// Parse the comma separated parameters of `--dirstat`, e.g. `files,10,cumulative`.
+pub fn parse_dirstat_options(value: &str) -> Result<DirstatOptions, String> {
+    let mut options = DirstatOptions::default();
+    for param in value.split(',').filter(|p| !p.is_empty()) {
+        match param {
+            "changes" => options.mode = DirstatMode::Changes,
+            "lines" => options.mode = DirstatMode::Lines,
+            "files" => options.mode = DirstatMode::Files,
+            "cumulative" => options.cumulative = true,
+            "noncumulative" => options.cumulative = false,
+            limit => {
+                let (whole, fraction) = limit.split_once('.').unwrap_or((limit, "0"));
+                let whole = whole.parse::<usize>();
+                let tenth = fraction.get(..1).unwrap_or("0").parse::<usize>();
+                match (whole, tenth) {
+                    (Ok(w), Ok(t)) => options.permille = w * 10 + t,
+                    _ => return Err(format!("unknown --dirstat parameter '{param}'")),
+                }
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Shorten a rename to its differing middle part, like `src/{a => b}/lib.rs`.
+pub fn pprint_rename(old: &str, new: &str) -> String {
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+    // common prefix has to end in a slash
+    let mut prefix = 0;
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            break;
+        }
+        if *x == b'/' {
+            prefix = i + 1;
+        }
+    }
+    // common suffix has to start with a slash, and may reuse the slash ending the prefix
+    let floor = prefix.saturating_sub(1);
+    let mut suffix = 0;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > floor && j > floor && a[i - 1] == b[j - 1] {
+        if a[i - 1] == b'/' {
+            suffix = a.len() - (i - 1);
+        }
+        i -= 1;
+        j -= 1;
+    }
+    if prefix + suffix == 0 {
+        return format!("{old} => {new}");
+    }
+    let old_middle = &old[prefix..(old.len() - suffix).max(prefix)];
+    let new_middle = &new[prefix..(new.len() - suffix).max(prefix)];
+    format!(
+        "{}{{{old_middle} => {new_middle}}}{}",
+        &old[..prefix],
+        &old[old.len() - suffix..]
+    )
+}
+
+fn default_width() -> usize {
+    use std::io::IsTerminal;
+    if std::io::stdout().is_terminal() {
+        std::env::var("COLUMNS")
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(DEFAULT_STAT_WIDTH)
+    } else {
+        DEFAULT_STAT_WIDTH
+    }
+}
+
+/// Shrink a change count so the longest graph fits in `width` columns.
+const fn scale_linear(count: usize, width: usize, max_change: usize) -> usize {
+    if count == 0 {
+        0
+    } else {
+        1 + (count * (width - 1) / max_change)
+    }
+}
+
+/// The `N files changed, X insertions(+), Y deletions(-)` line.
+pub fn format_shortstat(stats: &[FileStat]) -> String {
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    let files = stats.len();
+    let added: usize = stats.iter().map(|s| s.added).sum();
+    let deleted: usize = stats.iter().map(|s| s.deleted).sum();
+    let mut line = format!(" {files} file{} changed", plural(files));
+    if added > 0 || deleted == 0 {
+        write!(line, ", {added} insertion{}(+)", plural(added)).unwrap();
+    }
+    if deleted > 0 || added == 0 {
+        write!(line, ", {deleted} deletion{}(-)", plural(deleted)).unwrap();
+    }
+    line.push('\n');
+    line
+}
+
+/// Machine friendly `added<TAB>deleted<TAB>path` lines.
+pub fn format_numstat(stats: &[FileStat]) -> String {
+    stats.iter().fold(String::new(), |mut out, stat| {
+        if stat.binary.is_some() {
+            writeln!(out, "-\t-\t{}", stat.name).unwrap();
+        } else {
+            writeln!(out, "{}\t{}\t{}", stat.added, stat.deleted, stat.name).unwrap();
+        }
+        out
+    })
+}
+
+/// The histogram of `--stat`, followed by the shortstat line.
+pub fn format_stat(stats: &[FileStat], options: &StatOptions) -> String {
+    let shown = &stats[..options.count.unwrap_or(stats.len()).min(stats.len())];
+    let max_change = shown.iter().map(|s| s.added + s.deleted).max().unwrap_or(0);
+    let max_name = shown
+        .iter()
+        .map(|s| s.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    // binary lines read "Bin 123 -> 456 bytes", which needs room of its own
+    let bin_width = shown
+        .iter()
+        .filter_map(|s| s.binary)
+        .map(|(old, new)| 14 + old.to_string().len() + new.to_string().len())
+        .max();
+    let mut number_width = max_change.to_string().len();
+    if bin_width.is_some() {
+        number_width = number_width.max(3);
+    }
+
+    // mirrors git: the graph gets at most 3/8 of the line once things stop fitting
+    let width = options
+        .width
+        .unwrap_or_else(default_width)
+        .max(16 + 6 + number_width);
+    let mut name_width = options.name_width.map_or(max_name, |w| w.min(max_name));
+    let mut graph_width = match bin_width {
+        Some(bin_width) if max_change + 4 <= bin_width => bin_width - 4,
+        _ => max_change,
+    };
+    if name_width + number_width + 6 + graph_width > width {
+        let limit = (width * 3 / 8).saturating_sub(number_width + 6);
+        if graph_width > limit {
+            graph_width = limit.max(6);
+        }
+        let remaining = width - number_width - 6;
+        if name_width > remaining.saturating_sub(graph_width) {
+            name_width = remaining.saturating_sub(graph_width);
+        } else {
+            graph_width = remaining - name_width;
+        }
+    }
+
+    let mut out = String::new();
+    for stat in shown {
+        let length = stat.name.chars().count();
+        let (prefix, name) = if length > name_width {
+            // names are cut by characters, a byte offset could land inside one
+            let keep = name_width.saturating_sub(3);
+            let start = stat
+                .name
+                .char_indices()
+                .nth(length - keep)
+                .map_or(stat.name.len(), |(index, _)| index);
+            let mut tail = &stat.name[start..];
+            if let Some(slash) = tail.find('/') {
+                tail = &tail[slash..];
+            }
+            ("...", tail)
+        } else {
+            ("", stat.name.as_str())
+        };
+        let padding = name_width.saturating_sub(prefix.len() + name.chars().count());
+        if let Some((old_size, new_size)) = stat.binary {
+            write!(
+                out,
+                " {prefix}{name}{:padding$} | {:>number_width$}",
+                "", "Bin"
+            )
+            .unwrap();
+            if old_size == 0 && new_size == 0 {
+                out.push('\n');
+            } else {
+                writeln!(out, " {old_size} -> {new_size} bytes").unwrap();
+            }
+            continue;
+        }
+        let total = stat.added + stat.deleted;
+        let (mut added, mut deleted) = (stat.added, stat.deleted);
+        if graph_width <= max_change {
+            let mut scaled = scale_linear(total, graph_width, max_change);
+            if scaled < 2 && added > 0 && deleted > 0 {
+                scaled = 2;
+            }
+            if added < deleted {
+                added = scale_linear(added, graph_width, max_change);
+                deleted = scaled - added;
+            } else {
+                deleted = scale_linear(deleted, graph_width, max_change);
+                added = scaled - deleted;
+            }
+        }
+        writeln!(
+            out,
+            " {prefix}{name}{:padding$} | {total:>number_width$}{}{}{}",
+            "",
+            if total > 0 { " " } else { "" },
+            "+".repeat(added),
+            "-".repeat(deleted),
+        )
+        .unwrap();
+    }
+    if shown.len() < stats.len() {
+        out.push_str(" ...\n");
+    }
+    out.push_str(&format_shortstat(stats));
+    out
+}
+
+/// Distribution of the changes over directories, as `--dirstat` shows it.
+pub fn format_dirstat(stats: &[FileStat], options: &DirstatOptions) -> String {
+    let mut files = stats
+        .iter()
+        .map(|s| {
+            let damage = match options.mode {
+                DirstatMode::Changes => s.changed_bytes.max(1),
+                // binary files have no lines, they are counted in chunks of 64 bytes instead
+                DirstatMode::Lines => s
+                    .binary
+                    .map_or(s.added + s.deleted, |(old, new)| (old + new).div_ceil(64)),
+                DirstatMode::Files => 1,
+            };
+            (s.path.as_str(), damage)
+        })
+        .filter(|(_, damage)| *damage > 0)
+        .collect::<Vec<_>>();
+    files.sort_unstable();
+    let total: usize = files.iter().map(|(_, d)| d).sum();
+    let mut out = String::new();
+    if total > 0 {
+        let mut remaining = files.as_slice();
+        gather_dirstat(&mut remaining, "", total, options, &mut out);
+    }
+    out
+}
+
+/// Sum the damage below `base`, printing every directory above the threshold on the way out.
+fn gather_dirstat(
+    files: &mut &[(&str, usize)],
+    base: &str,
+    total: usize,
+    options: &DirstatOptions,
+    out: &mut String,
+) -> usize {
+    let mut sum = 0;
+    // a file counts as two sources so a directory holding one file and one sub directory is shown
+    let mut sources = 0;
+    while let Some(&(name, damage)) = files.first() {
+        if !name.starts_with(base) {
+            break;
+        }
+        if let Some(slash) = name[base.len()..].find('/') {
+            let sub_dir = &name[..=base.len() + slash];
+            sum += gather_dirstat(files, sub_dir, total, options, out);
+            sources += 1;
+        } else {
+            sum += damage;
+            *files = &files[1..];
+            sources += 2;
+        }
+    }
+    // the top level and directories whose changes all come from one sub directory are not shown
+    if !base.is_empty() && sources != 1 && sum > 0 {
+        let permille = sum * 1000 / total;
+        if permille >= options.permille {
+            writeln!(out, "{:>4}.{}% {base}", permille / 10, permille % 10).unwrap();
+            if !options.cumulative {
+                return 0;
+            }
+        }
+    }
+    sum
+}
+
+/// Creation, deletion, rename, copy and mode change lines of `--summary`.
+pub fn format_summary(entries: &[DiffEntry]) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        let old_mode = entry.old.as_ref().map(|s| s.mode.as_str());
+        let new_mode = entry.new.as_ref().map(|s| s.mode.as_str());
+        let path = entry.path().display();
+        match entry.status {
+            DiffStatus::Added => {
+                writeln!(out, " create mode {} {path}", new_mode.unwrap()).unwrap();
+            }
+            DiffStatus::Deleted => {
+                writeln!(out, " delete mode {} {path}", old_mode.unwrap()).unwrap();
+            }
+            DiffStatus::Renamed | DiffStatus::Copied => {
+                let verb = if entry.status == DiffStatus::Renamed {
+                    "rename"
+                } else {
+                    "copy"
+                };
+                let name = pprint_rename(
+                    &entry.old.as_ref().unwrap().path.display().to_string(),
+                    &path.to_string(),
+                );
+                writeln!(out, " {verb} {name} ({}%)", entry.score.unwrap_or(0)).unwrap();
+                if old_mode != new_mode {
+                    writeln!(
+                        out,
+                        " mode change {} => {}",
+                        old_mode.unwrap(),
+                        new_mode.unwrap()
+                    )
+                    .unwrap();
+                }
+            }
+            DiffStatus::Modified | DiffStatus::TypeChanged => {
+                if old_mode != new_mode {
+                    writeln!(
+                        out,
+                        " mode change {} => {} {path}",
+                        old_mode.unwrap(),
+                        new_mode.unwrap()
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(name: &str, added: usize, deleted: usize) -> FileStat {
+        FileStat {
+            name: name.to_string(),
+            path: name.to_string(),
+            added,
+            deleted,
+            changed_bytes: (added + deleted) * 10,
+            binary: None,
+        }
+    }
+
+    #[test]
+    fn test_pprint_rename() {
+        assert_eq!(pprint_rename("a.txt", "b.txt"), "a.txt => b.txt");
+        assert_eq!(
+            pprint_rename("src/a/x.rs", "src/b/x.rs"),
+            "src/{a => b}/x.rs"
+        );
+        assert_eq!(pprint_rename("src/x.rs", "src/y.rs"), "src/{x.rs => y.rs}");
+        assert_eq!(pprint_rename("a/x.rs", "b/c/x.rs"), "{a => b/c}/x.rs");
+    }
+
+    #[test]
+    fn test_format_stat() {
+        let stats = vec![stat("README.md", 3, 1), stat("src/main.rs", 10, 0)];
+        let options = StatOptions {
+            width: Some(80),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_stat(&stats, &options),
+            " README.md   |  4 +++-\n \
+             src/main.rs | 10 ++++++++++\n \
+             2 files changed, 13 insertions(+), 1 deletion(-)\n"
+        );
+        let options = StatOptions {
+            width: Some(30),
+            count: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_stat(&stats, &options),
+            " README.md | 4 +++-\n ...\n 2 files changed, 13 insertions(+), 1 deletion(-)\n"
+        );
+        let stats = vec![stat("docs/één/überlang.md", 2, 0)];
+        let options = StatOptions {
+            width: Some(80),
+            name_width: Some(14),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_stat(&stats, &options),
+            " ...überlang.md | 2 ++\n 1 file changed, 2 insertions(+)\n"
+        );
+    }
+
+    #[test]
+    fn test_format_stat_binary() {
+        let mut image = stat("logo.png", 0, 0);
+        image.binary = Some((0, 1234));
+        let stats = vec![image, stat("a.txt", 1, 0)];
+        assert_eq!(
+            format_stat(&stats, &StatOptions::default()),
+            " logo.png | Bin 0 -> 1234 bytes\n \
+             a.txt    |   1 +\n \
+             2 files changed, 1 insertion(+)\n"
+        );
+        assert_eq!(format_numstat(&stats), "-\t-\tlogo.png\n1\t0\ta.txt\n");
+    }
+
+    #[test]
+    fn test_format_dirstat() {
+        let stats = vec![
+            stat("src/a/one.rs", 6, 0),
+            stat("src/b/two.rs", 2, 0),
+            stat("docs/readme.md", 2, 0),
+        ];
+        let options = parse_dirstat_options("lines").unwrap();
+        assert_eq!(
+            format_dirstat(&stats, &options),
+            "  20.0% docs/\n  60.0% src/a/\n  20.0% src/b/\n"
+        );
+    }
+}
@@ -1,17 +1,23 @@
 use flate2::Compression;
 use flate2::{read::ZlibDecoder, write::ZlibEncoder};
 use itertools::Itertools;
+use std::collections::BTreeMap;
 use std::fs::{metadata, read_to_string};
 use std::io::prelude::*;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
+use std::sync::Mutex;
 use std::{
     env::current_dir,
-    fs::{create_dir, write},
+    fs::{create_dir, create_dir_all, write},
     path::PathBuf,
 };
 
-use super::objects::{OgitObject, OgitObjectType};
+use super::{
+    config,
+    objects::{self, OgitObject, OgitObjectType},
+    packedrefs,
+};
 
 const OGIT_DIR: &str = ".ogit";
 
@@ -35,21 +41,289 @@ pub fn ogit_init() -> std::io::Result<()> {
     Ok(())
 }
 
-pub fn update_head_file(object: &OgitObject) -> std::io::Result<()> {
-    let mut head_file = current_dir()?;
-    head_file.push(PathBuf::from(OGIT_DIR.to_string()));
-    head_file.push(PathBuf::from("HEAD"));
-    // TODO: this will need to be changed when we introduce TAGS and branches
-    write(head_file, format!("{}\n", object.hex_string()))?;
-    Ok(())
+/// Path of `name` inside the ogit directory, e.g. `index`
+pub fn ogit_path(name: &str) -> std::io::Result<PathBuf> {
+    let mut path = current_dir()?;
+    path.push(PathBuf::from(OGIT_DIR));
+    path.push(PathBuf::from(name));
+    Ok(path)
+}
+
+/// Suffix of the lock file guarding a file of the ogit directory while it is rewritten.
+pub const LOCK_SUFFIX: &str = ".lock";
+
+/// Exclusive hold on a file of the ogit directory, taken by creating `<file>.lock`.
+///
+/// The lock file is created with `O_EXCL`, so only one process holds it at a time. New content
+/// is written to the lock file and renamed over the file on commit, readers see either the old
+/// or the new content. Dropping the lock without committing leaves the file as it was.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: std::fs::File,
+    done: bool,
+}
+
+impl LockFile {
+    pub fn acquire(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(LOCK_SUFFIX);
+        let lock_path = PathBuf::from(lock_path);
+        let file = match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(std::io::Error::new(
+                    e.kind(),
+                    format!(
+                        "unable to create '{}': File exists, another ogit process seems to be running",
+                        lock_path.display()
+                    ),
+                ))
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            lock_path,
+            file,
+            done: false,
+        })
+    }
+
+    pub fn write(&mut self, content: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(content)
+    }
+
+    /// Replace the file by what was written to the lock.
+    pub fn commit(mut self) -> std::io::Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(&self.lock_path, &self.path)?;
+        self.done = true;
+        Ok(())
+    }
+
+    /// Remove the file, then release the lock.
+    pub fn delete(self) -> std::io::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = std::fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// Replace the file `name` of the ogit directory by `content`, holding its lock meanwhile.
+fn write_locked(name: &str, content: &str) -> std::io::Result<()> {
+    let mut lock = LockFile::acquire(&ogit_path(name)?)?;
+    lock.write(content.as_bytes())?;
+    lock.commit()
 }
 
+/// Prefix of a symbolic ref, which names the ref it points at instead of an id.
+pub const SYMREF_PREFIX: &str = "ref: ";
+
+/// Symbolic refs pointing at symbolic refs are followed this deep, like git.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// Id HEAD points at, empty when it does not point at a commit yet.
 pub fn read_head_file() -> std::io::Result<String> {
-    let mut head_file = current_dir()?;
-    head_file.push(PathBuf::from(OGIT_DIR.to_string()));
-    head_file.push(PathBuf::from("HEAD"));
-    let head_content = read_to_string(head_file)?;
-    Ok(head_content.trim().to_owned())
+    match resolve_symbolic_ref("HEAD")?.as_str() {
+        // a missing HEAD means there is no repository, not an empty one
+        "HEAD" => Ok(read_to_string(ogit_path("HEAD")?)?.trim().to_owned()),
+        branch => Ok(read_ref(branch)?.unwrap_or_default()),
+    }
+}
+
+/// Ref the symbolic ref `name` points at, `None` when `name` is not a symbolic ref.
+pub fn read_symbolic_ref(name: &str) -> std::io::Result<Option<String>> {
+    match read_to_string(ogit_path(name)?) {
+        Ok(content) => Ok(content
+            .trim()
+            .strip_prefix(SYMREF_PREFIX)
+            .map(ToOwned::to_owned)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Make `name` a symbolic ref pointing at the ref `target`.
+pub fn write_symbolic_ref(name: &str, target: &str) -> std::io::Result<()> {
+    write_locked(name, &format!("{SYMREF_PREFIX}{target}\n"))
+}
+
+/// The ref `name` ends up at after following symbolic refs, `name` itself when it is not one.
+pub fn resolve_symbolic_ref(name: &str) -> std::io::Result<String> {
+    let mut name = name.to_owned();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read_symbolic_ref(&name)? {
+            Some(target) => name = target,
+            None => return Ok(name),
+        }
+    }
+    Err(std::io::Error::other(format!(
+        "symbolic ref '{name}' is nested too deep"
+    )))
+}
+
+/// Id the ref `name` (e.g. `refs/stash`) points at, `None` when it does not exist.
+///
+/// Symbolic refs are followed, one pointing at a missing ref does not exist either. A loose ref
+/// file wins over the packed-refs entry of the same name.
+pub fn read_ref(name: &str) -> std::io::Result<Option<String>> {
+    let target = resolve_symbolic_ref(name)?;
+    match read_to_string(ogit_path(&target)?) {
+        Ok(id) => Ok(Some(id.trim().to_owned()).filter(|id| !id.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok(packedrefs::find(&target)?.map(|packed| packed.id))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Point the ref `name` at `id`, creating it when needed.
+pub fn write_ref(name: &str, id: &str) -> std::io::Result<()> {
+    write_locked(name, &format!("{id}\n"))
+}
+
+/// Names of the refs with a file of their own under `.ogit/refs`, symbolic ones included.
+pub fn list_loose_refs() -> std::io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut pending = vec![PathBuf::from("refs")];
+    while let Some(directory) = pending.pop() {
+        let entries = match std::fs::read_dir(ogit_path(&directory.to_string_lossy())?) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = directory
+                .join(entry.file_name())
+                .to_string_lossy()
+                .into_owned();
+            if entry.file_type()?.is_dir() {
+                pending.push(PathBuf::from(name));
+            } else if !name.ends_with(LOCK_SUFFIX) {
+                // a lock file is a ref being written by someone else, not a ref of its own
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Every ref, loose or packed, with the id it points at, sorted by name.
+///
+/// Symbolic refs show up with the id of the ref they point at, unless that one is missing.
+pub fn list_refs() -> std::io::Result<Vec<(String, String)>> {
+    let mut refs = packedrefs::PackedRefs::load()?
+        .refs()
+        .map(|packed| (packed.name.clone(), packed.id.clone()))
+        .collect::<BTreeMap<_, _>>();
+    for name in list_loose_refs()? {
+        if let Some(id) = read_ref(&name)? {
+            refs.insert(name, id);
+        }
+    }
+    Ok(refs.into_iter().collect())
+}
+
+/// Delete the ref `name`, both its loose file and its packed entry.
+pub fn delete_ref(name: &str) -> std::io::Result<()> {
+    let lock = LockFile::acquire(&ogit_path(name)?)?;
+    packedrefs::remove(&[name])?;
+    lock.delete()?;
+    remove_empty_ref_dirs(name)
+}
+
+/// Remove the directories the deleted ref `name` leaves empty, `refs/<kind>` ones stay.
+pub fn remove_empty_ref_dirs(name: &str) -> std::io::Result<()> {
+    let mut directory = Path::new(name).parent();
+    while let Some(parent) = directory.filter(|parent| parent.components().count() > 2) {
+        match std::fs::remove_dir(ogit_path(&parent.to_string_lossy())?) {
+            Ok(()) => directory = parent.parent(),
+            // not empty, or already gone
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Prefix of the temporary files objects are written to before being renamed into place.
+const TEMP_OBJECT_PREFIX: &str = "tmp_obj_";
+
+/// Create a new temporary file in `directory`, with a name no other writer uses.
+fn create_temp_file(directory: &Path) -> std::io::Result<(std::fs::File, PathBuf)> {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    loop {
+        let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let path = directory.join(format!(
+            "{TEMP_OBJECT_PREFIX}{}_{count}_{nanos}",
+            std::process::id()
+        ));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Store `object` at `object_path`, going through a temporary file of `objects_dir`.
+///
+/// The object shows up complete or not at all, and is read-only once it is there.
+fn write_object_file(
+    object: &OgitObject,
+    objects_dir: &Path,
+    object_path: &Path,
+) -> std::io::Result<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&object.file_content())?;
+    let compressed_bytes = encoder.finish()?;
+    let fsync = config::fsync_objects()?;
+
+    let (mut file, temp_path) = create_temp_file(objects_dir)?;
+    let written = (|| {
+        file.write_all(&compressed_bytes)?;
+        if fsync {
+            file.sync_all()?;
+        }
+        file.set_permissions(std::fs::Permissions::from_mode(0o444))?;
+        // another writer storing the same object at the same time writes the same content
+        std::fs::rename(&temp_path, object_path)
+    })();
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    if fsync {
+        if let Some(directory) = object_path.parent() {
+            std::fs::File::open(directory)?.sync_all()?;
+        }
+    }
+    Ok(())
 }
 
 pub fn hash_object(
@@ -67,36 +341,47 @@ pub fn hash_object(
     };
     object_path.push(PathBuf::from(OGIT_DIR.to_string()));
     object_path.push(PathBuf::from("objects"));
+    let objects_dir = object_path.clone();
 
     // this result will return a 2 character directory and the rest of the hash
     let object_file_path = object.object_database_filepath();
     let (dir, file) = object_file_path.split('/').collect_tuple().unwrap();
     object_path.push(PathBuf::from(dir));
-    // check if the directory for the object exists, if not create it
-    if !object_path.exists() {
-        match create_dir(object_path.clone()) {
-            Ok(()) => (),
-            Err(e) => {
-                eprintln!(
-                    "Error creating new Object directory: {}",
-                    object_path.display()
-                );
-                return Err(e);
-            }
+    // create the directory for the object, someone else may just have done so
+    match create_dir(&object_path) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
+        Err(e) => {
+            eprintln!(
+                "Error creating new Object directory: {}",
+                object_path.display()
+            );
+            return Err(e);
         }
     }
     object_path.push(file);
 
-    // write and catch the possible IO error
-    // compress the data before writing to the file
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(&object.file_content())?;
-    let compressed_bytes = encoder.finish().unwrap();
-    // write the compressed data to the file
-    write(object_path, compressed_bytes)?;
+    // objects never change, one already stored is kept as it is
+    if !object_path.exists() {
+        write_object_file(&object, &objects_dir, &object_path)?;
+    }
     Ok(object)
 }
 
+/// Objects hashed without being stored, only this process can read them back.
+static UNWRITTEN_OBJECTS: Mutex<BTreeMap<String, OgitObject>> = Mutex::new(BTreeMap::new());
+
+/// Hash an object without writing it to the database, so read only commands can compare the
+/// working tree by id. `get_object` still finds it for as long as the process runs.
+pub fn hash_object_in_memory(data: &[u8], object_type: Option<OgitObjectType>) -> OgitObject {
+    let object = OgitObject::new(data, object_type.unwrap_or(OgitObjectType::Blob));
+    UNWRITTEN_OBJECTS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .insert(object.hex_string(), object.clone());
+    object
+}
+
 pub fn get_object(
     object_id: &str,
     expected_object_type: Option<OgitObjectType>,
@@ -111,26 +396,88 @@ pub fn get_object(
     object_path.push(PathBuf::from(OGIT_DIR.to_string()));
     object_path.push(PathBuf::from("objects"));
 
+    let unwritten = UNWRITTEN_OBJECTS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .get(object_id)
+        .cloned();
+    if let Some(object) = unwritten {
+        return check_object_type(object, expected_object_type);
+    }
+    // objects are only stored under their full id, anything else cannot name one
+    if !objects::is_object_id(object_id) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("not a valid object name {object_id}"),
+        ));
+    }
     let (dir, file) = object_id.split_at(2);
     object_path.push(PathBuf::from(dir));
     object_path.push(PathBuf::from(file));
 
     let object_data = read_file(&object_path)?;
-    let object = OgitObject::from_bytes(&object_data);
+    let object = OgitObject::from_bytes(&object_data, object_id, config::verify_objects())?;
+    check_object_type(object, expected_object_type)
+}
+
+fn check_object_type(
+    object: OgitObject,
+    expected_object_type: Option<OgitObjectType>,
+) -> Result<OgitObject, std::io::Error> {
     if let Some(ogit_type) = expected_object_type {
         if object.variant != ogit_type {
             // TODO: custom error type?
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "Object type mismatch, expected: {ogit_type}, got: {}",
-                    object.variant
-                ),
-            ));
+            return Err(std::io::Error::other(format!(
+                "Object type mismatch, expected: {ogit_type}, got: {}",
+                object.variant
+            )));
         }
     }
     Ok(object)
 }
+/// Returns the full ids of every stored object whose id starts with `prefix`.
+pub fn find_object_ids(prefix: &str) -> std::io::Result<Vec<String>> {
+    if prefix.len() < 2 {
+        return Ok(Vec::new());
+    }
+    let mut object_dir = current_dir()?;
+    object_dir.push(PathBuf::from(OGIT_DIR.to_string()));
+    object_dir.push(PathBuf::from("objects"));
+    let (dir, rest) = prefix.split_at(2);
+    object_dir.push(PathBuf::from(dir));
+    if !object_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut ids = Vec::new();
+    for entry in std::fs::read_dir(object_dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(rest) {
+            ids.push(format!("{dir}{name}"));
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+/// Returns the ids of every stored object, sorted.
+pub fn list_object_ids() -> std::io::Result<Vec<String>> {
+    let mut objects_dir = current_dir()?;
+    objects_dir.push(PathBuf::from(OGIT_DIR.to_string()));
+    objects_dir.push(PathBuf::from("objects"));
+    let mut ids = Vec::new();
+    for dir in std::fs::read_dir(objects_dir)? {
+        let dir = dir?;
+        let prefix = dir.file_name().to_string_lossy().to_string();
+        if prefix.len() != 2 || !dir.path().is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(dir.path())? {
+            ids.push(format!("{prefix}{}", entry?.file_name().to_string_lossy()));
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
 /// Gets the filename of the object
 pub fn get_filename(filepath: &Path) -> Option<&str> {
     filepath.file_name().map(|s| s.to_str().unwrap())
@@ -157,6 +504,16 @@ pub fn read_file(filepath: &Path) -> std::io::Result<Vec<u8>> {
     }
 }
 
+/// Write a file of the working tree with the permissions of its tree `mode`, e.g. `100755`
+pub fn write_worktree_file(path: &Path, content: &[u8], mode: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    write(path, content)?;
+    let permissions = u32::from_str_radix(mode, 8).map_err(std::io::Error::other)? & 0o777;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(permissions))
+}
+
 /// Load the ignorable lines from the ignore file
 /// Currently requires .ogitignore to be in the same level as where command is run
 pub fn load_ignore_file() -> Vec<String> {
@@ -179,14 +536,32 @@ pub fn load_ignore_file() -> Vec<String> {
         .collect()
 }
 
+/// Load the non comment lines of the attributes file
+/// Like the ignore file, .ogitattributes is read from where the command is run
+pub fn load_attributes_file() -> Vec<String> {
+    let mut attributes_file = current_dir().unwrap();
+    attributes_file.push(PathBuf::from(".ogitattributes"));
+    if !attributes_file.exists() {
+        return Vec::new();
+    }
+    let attributes_file = read_to_string(attributes_file).unwrap();
+    attributes_file
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
 // }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data::testrepo::TestRepo;
 
     #[test]
     fn test_create_object() {
-        let _ = ogit_init();
+        let _repo = TestRepo::new();
         let object = hash_object(b"hello world", None).unwrap();
         assert_eq!(object.variant, OgitObjectType::Blob);
         assert_eq!(object.data, "hello world".as_bytes());
@@ -200,7 +575,5 @@ mod tests {
         object_path.push(PathBuf::from(dir));
         object_path.push(PathBuf::from(file));
         assert!(object_path.exists());
-
-        std::fs::remove_file(object_path).unwrap();
     }
 }
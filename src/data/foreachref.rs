@@ -0,0 +1,583 @@
+//! Listing refs with a format of their own, the way `for-each-ref` does.
+//!
+//! Formats mix text with `%(<atom>)` placeholders, like `%(refname:short)` or `%(authordate:iso)`.
+//! An atom starting with `*` describes the object a tag points at instead of the tag. Sort keys
+//! are atoms too, prefixed with `-` for descending order, the last key given sorting first.
+use std::cmp::Ordering;
+
+use super::{
+    attributes::glob_match,
+    commits::OgitCommit,
+    filesystem,
+    graph::CommitGraph,
+    objects::{OgitObject, OgitObjectType},
+    refs, revision,
+    tags::OgitTag,
+    time,
+};
+
+/// Same as git when no format is given.
+const DEFAULT_FORMAT: &str = "%(objectname) %(objecttype)\t%(refname)";
+
+/// Every atom a format can use, some only mean something for commits or tags.
+const ATOMS: [&str; 28] = [
+    "refname",
+    "objectname",
+    "objecttype",
+    "objectsize",
+    "HEAD",
+    "symref",
+    "tree",
+    "parent",
+    "author",
+    "authorname",
+    "authoremail",
+    "authordate",
+    "committer",
+    "committername",
+    "committeremail",
+    "committerdate",
+    "tagger",
+    "taggername",
+    "taggeremail",
+    "taggerdate",
+    "creator",
+    "creatordate",
+    "object",
+    "type",
+    "tag",
+    "subject",
+    "body",
+    "contents",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct ForEachRefOptions {
+    pub format: Option<String>,
+    /// Sort keys, the last one sorts first
+    pub sort: Vec<String>,
+    /// Stop after this many refs
+    pub count: Option<usize>,
+    /// Only refs matching one of these, by glob or leading path components
+    pub patterns: Vec<String>,
+    /// Only refs pointing at one of these objects, directly or through a tag
+    pub points_at: Vec<String>,
+    /// Only refs whose commit contains one of these commits
+    pub contains: Vec<String>,
+    /// Only refs whose commit contains none of these commits
+    pub no_contains: Vec<String>,
+    /// Only refs whose commit is reachable from one of these commits
+    pub merged: Vec<String>,
+    /// Only refs whose commit is reachable from none of these commits
+    pub no_merged: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Atom {
+    /// Describe the object a tag points at
+    deref: bool,
+    name: String,
+    modifier: Option<String>,
+}
+
+impl std::str::FromStr for Atom {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (deref, s) = match s.strip_prefix('*') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let (name, modifier) = match s.split_once(':') {
+            Some((name, modifier)) => (name, Some(modifier.to_string())),
+            None => (s, None),
+        };
+        if !ATOMS.contains(&name) {
+            return Err(format!("unknown field name: {name}"));
+        }
+        Ok(Self {
+            deref,
+            name: name.to_string(),
+            modifier,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Atom(Atom),
+}
+
+/// Split `format` into text and atoms, `%%` is a `%` and `%xx` the character of hex code `xx`.
+fn parse_format(format: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('%') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if let Some(inner) = rest.strip_prefix('(') {
+            let end = inner
+                .find(')')
+                .ok_or_else(|| format!("malformed format string {format}"))?;
+            segments.push(Segment::Text(std::mem::take(&mut text)));
+            segments.push(Segment::Atom(inner[..end].parse()?));
+            rest = &inner[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('%') {
+            text.push('%');
+            rest = after;
+        } else if let Some(code) = rest
+            .get(..2)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            text.push(char::from(code));
+            rest = &rest[2..];
+        } else {
+            text.push('%');
+        }
+    }
+    text.push_str(rest);
+    segments.push(Segment::Text(text));
+    segments.retain(|segment| *segment != Segment::Text(String::new()));
+    Ok(segments)
+}
+
+/// `name` with `count` leading components removed, or kept when `count` is negative.
+fn lstrip(name: &str, count: i64) -> String {
+    let parts = name.split('/').collect::<Vec<_>>();
+    let skip = if count < 0 {
+        parts.len().saturating_sub(count.unsigned_abs() as usize)
+    } else {
+        (count as usize).min(parts.len())
+    };
+    parts[skip..].join("/")
+}
+
+/// `name` with `count` trailing components removed, or only that many kept when negative.
+fn rstrip(name: &str, count: i64) -> String {
+    let parts = name.split('/').collect::<Vec<_>>();
+    let keep = if count < 0 {
+        (count.unsigned_abs() as usize).min(parts.len())
+    } else {
+        parts.len().saturating_sub(count as usize)
+    };
+    parts[..keep].join("/")
+}
+
+/// A ref name shown the way `modifier` asks for.
+fn format_refname(name: &str, modifier: Option<&str>) -> Result<String, String> {
+    let count = |value: &str| {
+        value
+            .parse::<i64>()
+            .map_err(|_| format!("invalid refname modifier {value}"))
+    };
+    match modifier {
+        None => Ok(name.to_string()),
+        Some("short") => Ok(refs::shorten(name).to_string()),
+        Some(modifier) => match modifier.split_once('=') {
+            Some(("lstrip" | "strip", value)) => Ok(lstrip(name, count(value)?)),
+            Some(("rstrip", value)) => Ok(rstrip(name, count(value)?)),
+            _ => Err(format!("unknown refname modifier {modifier}")),
+        },
+    }
+}
+
+/// What an atom is worth, for output and for sorting.
+enum Value {
+    Text(String),
+    /// Shown as text but sorted by number, like dates
+    Number(String, i64),
+}
+
+impl Value {
+    fn text(&self) -> &str {
+        match self {
+            Self::Text(text) | Self::Number(text, _) => text,
+        }
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Number(_, a), Self::Number(_, b)) => a.cmp(b),
+            _ => self.text().cmp(other.text()),
+        }
+    }
+}
+
+/// The object a ref points at, decoded once.
+enum Decoded {
+    Commit(OgitCommit),
+    Tag(OgitTag),
+    Other,
+}
+
+/// A listed ref with its object.
+struct RefItem {
+    name: String,
+    object: OgitObject,
+    decoded: Decoded,
+    /// What the ref points at when it is a symbolic ref
+    symref: Option<String>,
+    /// The object the tag points at, when the ref points at a tag
+    peeled: Option<(OgitObject, Decoded)>,
+}
+
+fn decode(object: &OgitObject) -> std::io::Result<Decoded> {
+    Ok(match object.variant {
+        OgitObjectType::Commit => Decoded::Commit(OgitCommit::get(&object.hex_string())?),
+        OgitObjectType::Tag => Decoded::Tag(OgitTag::parse(&object.data)?),
+        _ => Decoded::Other,
+    })
+}
+
+impl RefItem {
+    fn load(name: String, id: &str) -> std::io::Result<Self> {
+        let object = filesystem::get_object(id, None)?;
+        let decoded = decode(&object)?;
+        let peeled = match &decoded {
+            Decoded::Tag(tag) => {
+                let target = filesystem::get_object(&tag.object, None)?;
+                let decoded = decode(&target)?;
+                Some((target, decoded))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            symref: filesystem::read_symbolic_ref(&name)?,
+            name,
+            object,
+            decoded,
+            peeled,
+        })
+    }
+
+    /// The commit the ref leads to, following tags all the way down.
+    fn commit_id(&self) -> std::io::Result<Option<String>> {
+        let mut object = match (&self.decoded, &self.peeled) {
+            (Decoded::Tag(_), Some((peeled, _))) => peeled.clone(),
+            _ => self.object.clone(),
+        };
+        while object.variant == OgitObjectType::Tag {
+            object = filesystem::get_object(&OgitTag::parse(&object.data)?.object, None)?;
+        }
+        Ok((object.variant == OgitObjectType::Commit).then(|| object.hex_string()))
+    }
+
+    /// The value of `atom` for this ref, empty when it does not apply to its object.
+    fn value(&self, atom: &Atom, head: &str, now: &time::DateTime<time::FixedOffset>) -> Value {
+        let modifier = atom.modifier.as_deref();
+        let (object, decoded) = match (atom.deref, &self.peeled) {
+            (false, _) => (&self.object, &self.decoded),
+            (true, Some((object, decoded))) => (object, decoded),
+            (true, None) => return Value::Text(String::new()),
+        };
+        let text = |text: &str| Value::Text(text.to_string());
+        // `authorname` and the like are parts of the `author` identity
+        let person = ["name", "email", "date"]
+            .iter()
+            .find_map(|part| atom.name.strip_suffix(part))
+            .unwrap_or(&atom.name);
+        let (identity, message) = match decoded {
+            Decoded::Commit(commit) => (
+                match person {
+                    "author" => Some((commit.author.as_str(), commit.author_time)),
+                    "committer" | "creator" => {
+                        Some((commit.committer.as_str(), commit.committer_time))
+                    }
+                    _ => None,
+                },
+                Some(commit.message.as_slice()),
+            ),
+            Decoded::Tag(tag) => (
+                match person {
+                    "tagger" | "creator" => tag
+                        .tagger
+                        .as_ref()
+                        .map(|(identity, time)| (identity.as_str(), *time)),
+                    _ => None,
+                },
+                Some(tag.message.as_slice()),
+            ),
+            Decoded::Other => (None, None),
+        };
+        match atom.name.as_str() {
+            "refname" => text(&format_refname(&self.name, modifier).unwrap_or_default()),
+            "objectname" => {
+                let id = object.hex_string();
+                match modifier.and_then(|modifier| modifier.strip_prefix("short")) {
+                    Some(length) => {
+                        let length = length.trim_start_matches('=').parse().unwrap_or(7);
+                        text(&id[..length.clamp(4, id.len())])
+                    }
+                    None => text(&id),
+                }
+            }
+            "objecttype" => text(&object.variant.to_string()),
+            "objectsize" => Value::Number(object.data.len().to_string(), object.data.len() as i64),
+            "HEAD" => text(if self.name == head { "*" } else { " " }),
+            "symref" => {
+                let symref = self.symref.as_deref().unwrap_or_default();
+                text(&format_refname(symref, modifier).unwrap_or_default())
+            }
+            "tree" => match decoded {
+                Decoded::Commit(commit) => text(&commit.tree),
+                _ => text(""),
+            },
+            "parent" => match decoded {
+                Decoded::Commit(commit) => text(&commit.parents.join(" ")),
+                _ => text(""),
+            },
+            "object" | "type" | "tag" => match decoded {
+                Decoded::Tag(tag) if atom.name == "object" => text(&tag.object),
+                Decoded::Tag(tag) if atom.name == "type" => text(&tag.variant.to_string()),
+                Decoded::Tag(tag) => text(&tag.name),
+                _ => text(""),
+            },
+            "subject" | "body" | "contents" => {
+                let message = String::from_utf8_lossy(message.unwrap_or_default());
+                let message = message.trim_start();
+                match atom.name.as_str() {
+                    "contents" => text(message),
+                    "subject" => text(
+                        &message
+                            .split("\n\n")
+                            .next()
+                            .unwrap_or_default()
+                            .lines()
+                            .map(str::trim)
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                    ),
+                    _ => text(
+                        message
+                            .split_once("\n\n")
+                            .map_or("", |(_, body)| body.trim_start_matches('\n')),
+                    ),
+                }
+            }
+            name => {
+                let Some((identity, date)) = identity else {
+                    return text("");
+                };
+                let (person, email) = identity
+                    .split_once(" <")
+                    .map_or((identity, ""), |(person, email)| (person, email));
+                if name.ends_with("name") {
+                    text(person)
+                } else if name.ends_with("email") {
+                    text(&format!("<{email}"))
+                } else if name.ends_with("date") {
+                    let format = modifier
+                        .and_then(|modifier| modifier.parse::<time::DateFormat>().ok())
+                        .unwrap_or_default();
+                    Value::Number(format.format(&date, now), date.timestamp())
+                } else {
+                    text(&format!("{identity} {}", date.format("%s %z")))
+                }
+            }
+        }
+    }
+}
+
+/// Whether `name` matches `pattern`, as a glob or by its leading path components.
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+    let prefix = pattern.trim_end_matches('/');
+    name == prefix
+        || name
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+        || glob_match(pattern, name)
+}
+
+/// Whether `item` passes the filters of `options`, with the revisions they name resolved.
+fn is_selected(
+    item: &RefItem,
+    options: &ForEachRefOptions,
+    resolved: &Resolved,
+    graph: &mut CommitGraph,
+) -> std::io::Result<bool> {
+    if !options.patterns.is_empty()
+        && !options
+            .patterns
+            .iter()
+            .any(|pattern| matches_pattern(&item.name, pattern))
+    {
+        return Ok(false);
+    }
+    if !resolved.points_at.is_empty() {
+        let id = item.object.hex_string();
+        let peeled = item.peeled.as_ref().map(|(object, _)| object.hex_string());
+        if !resolved
+            .points_at
+            .iter()
+            .any(|target| *target == id || Some(target) == peeled.as_ref())
+        {
+            return Ok(false);
+        }
+    }
+    let reachability = [
+        &resolved.contains,
+        &resolved.no_contains,
+        &resolved.merged,
+        &resolved.no_merged,
+    ];
+    if reachability.iter().all(|ids| ids.is_empty()) {
+        return Ok(true);
+    }
+    let Some(commit) = item.commit_id()? else {
+        return Ok(false);
+    };
+    let mut contains_any = |ids: &[String]| -> std::io::Result<bool> {
+        for id in ids {
+            if graph.is_ancestor(id, &commit)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    };
+    if !resolved.contains.is_empty() && !contains_any(&resolved.contains)? {
+        return Ok(false);
+    }
+    if contains_any(&resolved.no_contains)? {
+        return Ok(false);
+    }
+    let mut merged_into_any = |ids: &[String]| -> std::io::Result<bool> {
+        for id in ids {
+            if graph.is_ancestor(&commit, id)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    };
+    if !resolved.merged.is_empty() && !merged_into_any(&resolved.merged)? {
+        return Ok(false);
+    }
+    Ok(!merged_into_any(&resolved.no_merged)?)
+}
+
+/// The revisions named by the filters, as ids.
+struct Resolved {
+    points_at: Vec<String>,
+    contains: Vec<String>,
+    no_contains: Vec<String>,
+    merged: Vec<String>,
+    no_merged: Vec<String>,
+}
+
+fn resolve_all(names: &[String]) -> std::io::Result<Vec<String>> {
+    names.iter().map(|name| revision::resolve(name)).collect()
+}
+
+/// The refs selected by `options`, sorted and formatted one per line.
+pub fn for_each_ref(options: &ForEachRefOptions) -> std::io::Result<String> {
+    let format = parse_format(options.format.as_deref().unwrap_or(DEFAULT_FORMAT))
+        .map_err(std::io::Error::other)?;
+    let sort = match options.sort.as_slice() {
+        [] => vec![(
+            false,
+            "refname".parse::<Atom>().map_err(std::io::Error::other)?,
+        )],
+        keys => keys
+            .iter()
+            .map(|key| match key.strip_prefix('-') {
+                Some(key) => key.parse().map(|atom| (true, atom)),
+                None => key.parse().map(|atom| (false, atom)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(std::io::Error::other)?,
+    };
+    let resolved = Resolved {
+        points_at: resolve_all(&options.points_at)?,
+        contains: resolve_all(&options.contains)?,
+        no_contains: resolve_all(&options.no_contains)?,
+        merged: resolve_all(&options.merged)?,
+        no_merged: resolve_all(&options.no_merged)?,
+    };
+    let head = filesystem::resolve_symbolic_ref("HEAD")?;
+    let now = time::get_current_local().fixed_offset();
+
+    let mut graph = CommitGraph::load()?;
+    let mut items = Vec::new();
+    for (name, id) in filesystem::list_refs()? {
+        let item = RefItem::load(name, &id)?;
+        if is_selected(&item, options, &resolved, &mut graph)? {
+            items.push(item);
+        }
+    }
+
+    for (descending, atom) in &sort {
+        items.sort_by(|a, b| {
+            let order = a
+                .value(atom, &head, &now)
+                .compare(&b.value(atom, &head, &now));
+            if *descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+    }
+
+    let mut out = String::new();
+    for item in items.iter().take(options.count.unwrap_or(usize::MAX)) {
+        for segment in &format {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Atom(atom) => out.push_str(item.value(atom, &head, &now).text()),
+            }
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        let segments = parse_format("%(refname:short) %%%09%(*objectname)").unwrap();
+        assert_eq!(
+            segments,
+            [
+                Segment::Atom(Atom {
+                    deref: false,
+                    name: "refname".to_string(),
+                    modifier: Some("short".to_string()),
+                }),
+                Segment::Text(" %\t".to_string()),
+                Segment::Atom(Atom {
+                    deref: true,
+                    name: "objectname".to_string(),
+                    modifier: None,
+                }),
+            ]
+        );
+        assert!(parse_format("%(nothing)").is_err());
+        assert!(parse_format("%(refname").is_err());
+    }
+
+    #[test]
+    fn test_format_refname() {
+        let name = "refs/remotes/origin/main";
+        assert_eq!(format_refname(name, Some("short")).unwrap(), "origin/main");
+        assert_eq!(
+            format_refname(name, Some("lstrip=2")).unwrap(),
+            "origin/main"
+        );
+        assert_eq!(format_refname(name, Some("lstrip=-1")).unwrap(), "main");
+        assert_eq!(
+            format_refname(name, Some("rstrip=1")).unwrap(),
+            "refs/remotes/origin"
+        );
+        assert_eq!(
+            format_refname(name, Some("rstrip=-2")).unwrap(),
+            "refs/remotes"
+        );
+        assert!(matches_pattern(name, "refs/remotes"));
+        assert!(matches_pattern(name, "refs/remotes/*/main"));
+        assert!(!matches_pattern(name, "refs/rem"));
+    }
+}
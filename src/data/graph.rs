@@ -0,0 +1,485 @@
+//! Questions about the shape of history: which commits are ancestors of which, and where lines of
+//! history meet.
+//!
+//! Walks take commits from a priority queue ordered by generation number, then by commit date.
+//! A commit's generation is one more than the highest generation of its parents, so every commit
+//! is visited after all of its children and a walk can stop once the queue only holds commits too
+//! old to matter. Generations never change and are remembered in `.ogit/commit-graph`, which
+//! `commit-graph write` and the commands changing history add to, read only commands never do.
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fs,
+};
+
+use super::{commits::OgitCommit, filesystem, objects::OgitObjectType, tags};
+
+const GRAPH_FILE: &str = "commit-graph";
+
+/// Reachable from the first side of a merge base walk.
+const PARENT1: u8 = 1;
+/// Reachable from the second side of a merge base walk.
+const PARENT2: u8 = 1 << 1;
+/// Reachable from a common ancestor, so no longer a candidate.
+const STALE: u8 = 1 << 2;
+/// Already part of the result.
+const RESULT: u8 = 1 << 3;
+
+/// What the walks need to know about a commit.
+#[derive(Debug, Clone)]
+struct Node {
+    parents: Vec<String>,
+    time: i64,
+}
+
+/// A queued commit, the newest in the history comes out first.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Queued {
+    generation: u64,
+    time: i64,
+    id: String,
+}
+
+/// Commits read so far along with their generations, shared by the walks of one command.
+#[derive(Debug, Default)]
+pub struct CommitGraph {
+    nodes: HashMap<String, Node>,
+    generations: HashMap<String, u64>,
+    /// Generations computed since loading, still to be written out
+    computed: Vec<String>,
+}
+
+impl CommitGraph {
+    /// Start with the generations remembered by earlier commands.
+    pub fn load() -> std::io::Result<Self> {
+        let mut graph = Self::default();
+        let content = match fs::read_to_string(filesystem::ogit_path(GRAPH_FILE)?) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(graph),
+            Err(e) => return Err(e),
+        };
+        for line in content.lines() {
+            let generation = line
+                .split_once(' ')
+                .and_then(|(id, generation)| Some((id, generation.parse().ok()?)));
+            if let Some((id, generation)) = generation {
+                graph.generations.insert(id.to_string(), generation);
+            }
+        }
+        Ok(graph)
+    }
+
+    /// Remember the generations computed by this command for later ones.
+    pub fn save(&mut self) -> std::io::Result<()> {
+        if self.computed.is_empty() {
+            return Ok(());
+        }
+        let lines = self
+            .computed
+            .drain(..)
+            .map(|id| format!("{id} {}\n", self.generations[&id]))
+            .collect::<String>();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(filesystem::ogit_path(GRAPH_FILE)?)?;
+        std::io::Write::write_all(&mut file, lines.as_bytes())
+    }
+
+    fn node(&mut self, id: &str) -> std::io::Result<&Node> {
+        if !self.nodes.contains_key(id) {
+            let commit = OgitCommit::get(id)?;
+            let node = Node {
+                parents: commit.parents,
+                time: commit.committer_time.timestamp(),
+            };
+            self.nodes.insert(id.to_string(), node);
+        }
+        Ok(&self.nodes[id])
+    }
+
+    pub fn parents(&mut self, id: &str) -> std::io::Result<Vec<String>> {
+        Ok(self.node(id)?.parents.clone())
+    }
+
+    /// Generation number of `id`: 1 for root commits, otherwise one more than its highest parent.
+    pub fn generation(&mut self, id: &str) -> std::io::Result<u64> {
+        // iterative, histories are far deeper than the stack
+        let mut pending = vec![id.to_string()];
+        while let Some(top) = pending.last().cloned() {
+            if self.generations.contains_key(&top) {
+                pending.pop();
+                continue;
+            }
+            let parents = self.parents(&top)?;
+            let missing = parents
+                .iter()
+                .filter(|p| !self.generations.contains_key(*p))
+                .cloned()
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                let generation = parents.iter().map(|p| self.generations[p]).max();
+                self.generations
+                    .insert(top.clone(), generation.unwrap_or(0) + 1);
+                self.computed.push(top);
+                pending.pop();
+            } else {
+                pending.extend(missing);
+            }
+        }
+        Ok(self.generations[id])
+    }
+
+    fn queued(&mut self, id: &str) -> std::io::Result<Queued> {
+        Ok(Queued {
+            generation: self.generation(id)?,
+            time: self.node(id)?.time,
+            id: id.to_string(),
+        })
+    }
+
+    /// Newest first, the order `git` lists merge bases in.
+    fn sort_newest_first(&mut self, ids: &mut [String]) -> std::io::Result<()> {
+        for id in ids.iter() {
+            self.node(id)?;
+        }
+        ids.sort_by_key(|id| (Reverse(self.nodes[id].time), id.clone()));
+        Ok(())
+    }
+
+    /// Whether `ancestor` can be reached from `descendant`, a commit counts as its own ancestor.
+    pub fn is_ancestor(&mut self, ancestor: &str, descendant: &str) -> std::io::Result<bool> {
+        let min_generation = self.generation(ancestor)?;
+        let mut seen = HashSet::new();
+        let mut queue = BinaryHeap::from([self.queued(descendant)?]);
+        while let Some(commit) = queue.pop() {
+            if commit.id == ancestor {
+                return Ok(true);
+            }
+            // everything further down is older than the commit looked for
+            if commit.generation <= min_generation {
+                continue;
+            }
+            for parent in self.parents(&commit.id)? {
+                if seen.insert(parent.clone()) {
+                    queue.push(self.queued(&parent)?);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Common ancestors of `one` and any of `others`, possibly with some reachable from others.
+    fn paint_down_to_common(
+        &mut self,
+        one: &str,
+        others: &[String],
+    ) -> std::io::Result<Vec<String>> {
+        let mut flags = HashMap::from([(one.to_string(), PARENT1)]);
+        let mut queue = BinaryHeap::from([self.queued(one)?]);
+        for other in others {
+            if other == one {
+                return Ok(vec![one.to_string()]);
+            }
+            *flags.entry(other.clone()).or_default() |= PARENT2;
+            queue.push(self.queued(other)?);
+        }
+        let mut result = Vec::new();
+        while queue.iter().any(|c| flags[&c.id] & STALE == 0) {
+            let commit = queue.pop().unwrap();
+            let mut painted = flags[&commit.id] & (PARENT1 | PARENT2 | STALE);
+            if painted == PARENT1 | PARENT2 {
+                if flags[&commit.id] & RESULT == 0 {
+                    *flags.get_mut(&commit.id).unwrap() |= RESULT;
+                    result.push(commit.id.clone());
+                }
+                // ancestors of a common ancestor can not be the best one
+                painted |= STALE;
+            }
+            for parent in self.parents(&commit.id)? {
+                let parent_flags = flags.entry(parent.clone()).or_default();
+                if *parent_flags & painted == painted {
+                    continue;
+                }
+                *parent_flags |= painted;
+                queue.push(self.queued(&parent)?);
+            }
+        }
+        Ok(result
+            .into_iter()
+            .filter(|id| flags[id] & STALE == 0)
+            .collect())
+    }
+
+    /// Drop the commits of `ids` that are ancestors of another one of them.
+    fn remove_redundant(&mut self, ids: Vec<String>) -> std::io::Result<Vec<String>> {
+        let mut independent = Vec::new();
+        for (i, id) in ids.iter().enumerate() {
+            let mut redundant = false;
+            for (j, other) in ids.iter().enumerate() {
+                if i != j && other != id && self.is_ancestor(id, other)? {
+                    redundant = true;
+                    break;
+                }
+            }
+            if !redundant && !independent.contains(id) {
+                independent.push(id.clone());
+            }
+        }
+        Ok(independent)
+    }
+
+    /// The best common ancestors of `one` and a merge of all `others`, newest first.
+    ///
+    /// A best common ancestor is not an ancestor of any other common ancestor.
+    pub fn merge_bases(&mut self, one: &str, others: &[String]) -> std::io::Result<Vec<String>> {
+        let candidates = self.paint_down_to_common(one, others)?;
+        let mut bases = self.remove_redundant(candidates)?;
+        self.sort_newest_first(&mut bases)?;
+        Ok(bases)
+    }
+
+    /// Best common ancestors of all `commits` at once, as an octopus merge of them needs.
+    pub fn octopus_merge_bases(&mut self, commits: &[String]) -> std::io::Result<Vec<String>> {
+        let Some((first, rest)) = commits.split_first() else {
+            return Ok(Vec::new());
+        };
+        let mut bases = vec![first.clone()];
+        for commit in rest {
+            let mut next = Vec::new();
+            for base in &bases {
+                for found in self.merge_bases(base, std::slice::from_ref(commit))? {
+                    if !next.contains(&found) {
+                        next.push(found);
+                    }
+                }
+            }
+            bases = next;
+        }
+        self.sort_newest_first(&mut bases)?;
+        Ok(bases)
+    }
+
+    /// Where `commit` forked from a branch that pointed at each of `history` at some point.
+    ///
+    /// Only a commit the branch once pointed at qualifies, so a rewritten branch still finds
+    /// the commit `commit` was originally built on.
+    pub fn fork_point(
+        &mut self,
+        history: &[String],
+        commit: &str,
+    ) -> std::io::Result<Option<String>> {
+        let bases = self.merge_bases(commit, history)?;
+        Ok(bases
+            .into_iter()
+            .next()
+            .filter(|base| history.contains(base)))
+    }
+
+    /// Commits reachable from any of `include` but none of `exclude`, newest first.
+    ///
+    /// With `first_parent`, only the first parent of the included commits is followed, as if
+    /// merged lines of history were not there. Everything `exclude` reaches is still excluded.
+    pub fn walk(
+        &mut self,
+        include: &[String],
+        exclude: &[String],
+        first_parent: bool,
+    ) -> std::io::Result<Vec<String>> {
+        let mut uninteresting = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        for (ids, hidden) in [(include, false), (exclude, true)] {
+            for id in ids {
+                let flag = uninteresting.entry(id.clone()).or_insert(hidden);
+                *flag |= hidden;
+                queue.push(self.queued(id)?);
+            }
+        }
+        let mut seen = HashSet::new();
+        let mut commits = Vec::new();
+        // every child is taken before its parents, so a commit's flag is final once it comes out
+        while queue.iter().any(|c| !uninteresting[&c.id]) {
+            let commit = queue.pop().unwrap();
+            if !seen.insert(commit.id.clone()) {
+                continue;
+            }
+            let hidden = uninteresting[&commit.id];
+            let mut parents = self.parents(&commit.id)?;
+            if first_parent && !hidden {
+                parents.truncate(1);
+            }
+            for parent in parents {
+                let flag = uninteresting.entry(parent.clone()).or_insert(hidden);
+                *flag |= hidden;
+                queue.push(self.queued(&parent)?);
+            }
+            if !hidden {
+                commits.push(commit.id);
+            }
+        }
+        // generations keep children first, the date only orders commits of the same generation
+        commits.sort_by_key(|id| Reverse((self.generations[id], self.nodes[id].time)));
+        Ok(commits)
+    }
+}
+
+/// Store the generation of every commit a ref or HEAD leads to, the way `commit-graph write` does.
+pub fn write_reachable() -> std::io::Result<()> {
+    let mut graph = CommitGraph::load()?;
+    let tips = filesystem::list_refs()?
+        .into_iter()
+        .map(|(_, id)| id)
+        .chain(filesystem::read_ref("HEAD")?);
+    for tip in tips {
+        // refs may name tags, trees or blobs too, only commits have a generation
+        let id = tags::peel(&tip)?;
+        if filesystem::get_object(&id, None)?.variant == OgitObjectType::Commit {
+            graph.generation(&id)?;
+        }
+    }
+    graph.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{refs, revision, testrepo::TestRepo};
+
+    /// A commit of the empty tree named `name`, committed at `time` whatever the clock says.
+    fn commit(name: &str, parents: &[&str], time: i64) -> String {
+        let tree = filesystem::hash_object(b"", Some(OgitObjectType::Tree)).unwrap();
+        let mut data = format!("tree {}\n", tree.hex_string());
+        for parent in parents {
+            data.push_str(&format!("parent {parent}\n"));
+        }
+        let identity = format!("A U Thor <author@example.com> {time} +0000");
+        data.push_str(&format!(
+            "author {identity}\ncommitter {identity}\n\n{name}\n"
+        ));
+        filesystem::hash_object(data.as_bytes(), Some(OgitObjectType::Commit))
+            .unwrap()
+            .hex_string()
+    }
+
+    #[test]
+    fn test_merge_base_and_is_ancestor() {
+        let _repo = TestRepo::new();
+        let root = commit("root", &[], 100);
+        let fork = commit("fork", &[&root], 200);
+        let main = commit("main", &[&fork], 300);
+        let topic = commit("topic", &[&fork], 400);
+        let mut graph = CommitGraph::default();
+        assert_eq!(
+            graph
+                .merge_bases(&main, std::slice::from_ref(&topic))
+                .unwrap(),
+            [fork.as_str()]
+        );
+        assert_eq!(
+            graph
+                .merge_bases(&main, std::slice::from_ref(&fork))
+                .unwrap(),
+            [fork.as_str()]
+        );
+        assert!(graph.is_ancestor(&root, &main).unwrap());
+        assert!(graph.is_ancestor(&main, &main).unwrap());
+        assert!(!graph.is_ancestor(&topic, &main).unwrap());
+        assert!(!graph.is_ancestor(&main, &root).unwrap());
+    }
+
+    #[test]
+    fn test_criss_cross_merge_bases() {
+        let _repo = TestRepo::new();
+        let root = commit("root", &[], 100);
+        let left = commit("left", &[&root], 200);
+        let right = commit("right", &[&root], 300);
+        let left_merge = commit("left merge", &[&left, &right], 400);
+        let right_merge = commit("right merge", &[&right, &left], 500);
+        let mut graph = CommitGraph::default();
+        // both are best common ancestors, the newer comes first
+        assert_eq!(
+            graph
+                .merge_bases(&left_merge, std::slice::from_ref(&right_merge))
+                .unwrap(),
+            [right.clone(), left.clone()]
+        );
+        assert_eq!(
+            graph
+                .octopus_merge_bases(&[left_merge, right_merge])
+                .unwrap(),
+            [right, left]
+        );
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let _repo = TestRepo::new();
+        // the children were committed on a clock running far behind their parent's
+        let root = commit("root", &[], 1000);
+        let skewed = commit("skewed", &[&root], 10);
+        let tip = commit("tip", &[&skewed], 20);
+        let other = commit("other", &[&root], 2000);
+        let mut graph = CommitGraph::default();
+        assert!(graph.is_ancestor(&root, &tip).unwrap());
+        assert!(!graph.is_ancestor(&other, &tip).unwrap());
+        assert_eq!(
+            graph
+                .merge_bases(&tip, std::slice::from_ref(&other))
+                .unwrap(),
+            [root.as_str()]
+        );
+        assert_eq!(
+            graph.walk(std::slice::from_ref(&tip), &[], false).unwrap(),
+            [tip.clone(), skewed.clone(), root.clone()]
+        );
+        assert_eq!(
+            graph
+                .walk(std::slice::from_ref(&tip), &[other], false)
+                .unwrap(),
+            [tip, skewed]
+        );
+    }
+
+    #[test]
+    fn test_fork_point_walks_the_reflog() {
+        let repo = TestRepo::new();
+        repo.write("a.txt", "one\n");
+        let first = repo.commit_all("first");
+        repo.write("a.txt", "two\n");
+        let second = repo.commit_all("second");
+        let topic = commit("topic", &[&second], 0);
+        // the upstream drops its second commit and is rebuilt on the first
+        refs::move_head(&first, &second, "reset: moving to HEAD~1").unwrap();
+        repo.write("a.txt", "rewritten\n");
+        let rewritten = repo.commit_all("rewritten");
+
+        let mut graph = CommitGraph::default();
+        assert_eq!(
+            graph
+                .merge_bases(&rewritten, std::slice::from_ref(&topic))
+                .unwrap(),
+            [first]
+        );
+        let history = revision::ref_history("main").unwrap();
+        assert_eq!(history[0], rewritten);
+        assert_eq!(graph.fork_point(&history, &topic).unwrap(), Some(second));
+    }
+
+    #[test]
+    fn test_queue_takes_higher_generations_first() {
+        let queued = |generation, time, id: &str| Queued {
+            generation,
+            time,
+            id: id.to_string(),
+        };
+        // a skewed clock must not let a parent come out before its child
+        let mut queue = BinaryHeap::from([
+            queued(1, 300, "root"),
+            queued(3, 100, "child"),
+            queued(2, 200, "parent"),
+            queued(2, 250, "other"),
+        ]);
+        let order = std::iter::from_fn(|| queue.pop().map(|q| q.id)).collect::<Vec<_>>();
+        assert_eq!(order, ["child", "other", "parent", "root"]);
+    }
+}
@@ -0,0 +1,303 @@
+//! The index records the content the next commit starts from.
+//!
+//! It is stored as text, the same way trees are, with one `mode id stage<TAB>path` line per entry.
+//! Stage 0 holds the normal entry of a path, stages 1 to 3 only exist while a conflict is unresolved.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use super::{
+    base::{self, TreeEntry},
+    diff::{self, DiffOptions},
+    filesystem::{self, LockFile},
+    objects::OgitObjectType,
+    revision,
+};
+
+const INDEX_FILE: &str = "index";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub mode: String,
+    pub id: String,
+    pub stage: u8,
+    pub path: PathBuf,
+}
+
+impl Display for IndexEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}\t{}",
+            self.mode,
+            self.id,
+            self.stage,
+            self.path.display()
+        )
+    }
+}
+
+impl FromStr for IndexEntry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid index line '{s}'");
+        let (info, path) = s.split_once('\t').ok_or_else(invalid)?;
+        let mut parts = info.split(' ');
+        let mode = parts.next().ok_or_else(invalid)?;
+        let id = parts.next().ok_or_else(invalid)?;
+        let stage = parts
+            .next()
+            .and_then(|stage| stage.parse().ok())
+            .ok_or_else(invalid)?;
+        Ok(Self {
+            mode: mode.to_string(),
+            id: id.to_string(),
+            stage,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Index {
+    entries: BTreeMap<(PathBuf, u8), IndexEntry>,
+}
+
+impl Index {
+    /// Read the index of the current repository.
+    ///
+    /// Repositories created before the index existed get one matching HEAD.
+    pub fn load() -> Result<Self, std::io::Error> {
+        let index_file = filesystem::ogit_path(INDEX_FILE)?;
+        if !index_file.exists() {
+            return match filesystem::read_head_file()?.as_str() {
+                "" => Ok(Self::default()),
+                _ => Self::from_tree(&revision::resolve_tree("HEAD")?),
+            };
+        }
+        let mut index = Self::default();
+        for line in fs::read_to_string(index_file)?.lines() {
+            let entry = line.parse::<IndexEntry>().map_err(std::io::Error::other)?;
+            index
+                .entries
+                .insert((entry.path.clone(), entry.stage), entry);
+        }
+        Ok(index)
+    }
+
+    /// An index holding exactly the files of the tree `tree_id`.
+    pub fn from_tree(tree_id: &str) -> Result<Self, std::io::Error> {
+        let options = DiffOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let mut index = Self::default();
+        for change in diff::diff_trees(None, Some(tree_id), &options)? {
+            let side = change.new.unwrap();
+            index.add(IndexEntry {
+                mode: side.mode,
+                id: side.id,
+                stage: 0,
+                path: side.path,
+            });
+        }
+        Ok(index)
+    }
+
+    /// Replace the index file through a lock file, so a crash never leaves half an index.
+    pub fn save(&self) -> Result<(), std::io::Error> {
+        let content = self.entries().fold(String::new(), |mut acc, entry| {
+            acc.push_str(&format!("{entry}\n"));
+            acc
+        });
+        let mut lock = LockFile::acquire(&filesystem::ogit_path(INDEX_FILE)?)?;
+        lock.write(content.as_bytes())?;
+        lock.commit()
+    }
+
+    /// Every entry, ordered by path and then stage.
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.values()
+    }
+
+    /// The merged (stage 0) entry of `path`.
+    pub fn get(&self, path: &Path) -> Option<&IndexEntry> {
+        self.entries.get(&(path.to_path_buf(), 0))
+    }
+
+    /// Insert or replace an entry, a merged entry resolves any conflict stages of its path.
+    pub fn add(&mut self, entry: IndexEntry) {
+        if entry.stage == 0 {
+            self.remove(&entry.path);
+        }
+        self.entries
+            .insert((entry.path.clone(), entry.stage), entry);
+    }
+
+    /// Drop every stage of `path`.
+    pub fn remove(&mut self, path: &Path) {
+        self.entries.retain(|(p, _), _| p != path);
+    }
+
+    /// Paths with a conflict that is not resolved in the index yet.
+    pub fn unmerged_paths(&self) -> BTreeSet<PathBuf> {
+        self.entries()
+            .filter(|entry| entry.stage != 0)
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    /// Take the working tree content of every path `wanted` accepts, tracked or not.
+    ///
+    /// A wanted path missing from the working tree leaves the index, only the blobs taken are
+    /// stored. `wanted` also learns whether the path is tracked already.
+    pub fn add_from_worktree(
+        &mut self,
+        mut wanted: impl FnMut(&Path, bool) -> bool,
+    ) -> Result<(), std::io::Error> {
+        let worktree = Self::from_tree(&base::write_tree(None, false)?.hex_string())?;
+        let tracked = self
+            .entries()
+            .map(|entry| entry.path.clone())
+            .collect::<BTreeSet<_>>();
+        let paths = worktree
+            .entries()
+            .map(|entry| entry.path.clone())
+            .chain(tracked.iter().cloned())
+            .collect::<BTreeSet<_>>();
+        for path in paths {
+            if !wanted(&path, tracked.contains(&path)) {
+                continue;
+            }
+            match worktree.get(&path) {
+                Some(entry) => {
+                    filesystem::hash_object(&fs::read(&path)?, Some(OgitObjectType::Blob))?;
+                    self.add(entry.clone());
+                }
+                None => self.remove(&path),
+            }
+        }
+        Ok(())
+    }
+
+    /// Store the entries as tree objects, returning the id of the root tree.
+    pub fn write_tree(&self) -> Result<String, std::io::Error> {
+        if let Some((path, _)) = self.entries.keys().find(|(_, stage)| *stage != 0) {
+            return Err(std::io::Error::other(format!(
+                "{}: unmerged path, cannot write a tree",
+                path.display()
+            )));
+        }
+        let entries = self
+            .entries()
+            .map(|entry| (entry.path.as_path(), entry))
+            .collect::<Vec<_>>();
+        write_subtree(&entries, true)
+    }
+
+    /// Id of the tree the entries make, hashed in memory without storing anything.
+    ///
+    /// An unmerged path takes its stage 2 entry, the side that was checked out.
+    pub fn hash_tree(&self) -> Result<String, std::io::Error> {
+        let entries = self
+            .entries()
+            .filter(|entry| entry.stage == 0 || entry.stage == 2)
+            .map(|entry| (entry.path.as_path(), entry))
+            .collect::<Vec<_>>();
+        write_subtree(&entries, false)
+    }
+
+    /// The working tree content of every tracked path, hashed in memory.
+    ///
+    /// Tracked paths missing from the working tree are left out, untracked files are ignored.
+    pub fn worktree(&self) -> Result<Self, std::io::Error> {
+        let mut worktree = Self::from_tree(&base::write_tree(None, false)?.hex_string())?;
+        let tracked = self
+            .entries()
+            .map(|entry| entry.path.clone())
+            .collect::<BTreeSet<_>>();
+        worktree
+            .entries
+            .retain(|(path, _), _| tracked.contains(path));
+        Ok(worktree)
+    }
+}
+
+/// Hash the tree holding `entries`, whose paths are relative to it and sorted, storing it with `write`.
+fn write_subtree(entries: &[(&Path, &IndexEntry)], write: bool) -> Result<String, std::io::Error> {
+    let mut tree_entries = Vec::new();
+    let mut i = 0;
+    while i < entries.len() {
+        let (path, entry) = entries[i];
+        let mut components = path.components();
+        let name = PathBuf::from(components.next().unwrap().as_os_str());
+        if components.as_path().as_os_str().is_empty() {
+            tree_entries.push(TreeEntry {
+                filemode: entry.mode.clone(),
+                filename: name,
+                id: entry.id.clone(),
+                variant: OgitObjectType::Blob,
+            });
+            i += 1;
+            continue;
+        }
+        // paths sort component wise, so everything inside this directory comes next
+        let children = entries[i..]
+            .iter()
+            .take_while(|(p, _)| p.starts_with(&name))
+            .map(|(p, e)| (p.strip_prefix(&name).unwrap(), *e))
+            .collect::<Vec<_>>();
+        i += children.len();
+        tree_entries.push(TreeEntry {
+            filemode: "040000".to_string(),
+            id: write_subtree(&children, write)?,
+            filename: name,
+            variant: OgitObjectType::Tree,
+        });
+    }
+    let tree_data = base::build_tree_data(&tree_entries);
+    let tree = if write {
+        filesystem::hash_object(tree_data.as_bytes(), Some(OgitObjectType::Tree))?
+    } else {
+        filesystem::hash_object_in_memory(tree_data.as_bytes(), Some(OgitObjectType::Tree))
+    };
+    Ok(tree.hex_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_entry_round_trip() {
+        let line = "100644 95d09f2b10159347eece71399a7e2e907ea3df4f 2\tsrc/main rs.txt";
+        let entry = line.parse::<IndexEntry>().unwrap();
+        assert_eq!(entry.stage, 2);
+        assert_eq!(entry.path, PathBuf::from("src/main rs.txt"));
+        assert_eq!(entry.to_string(), line);
+    }
+
+    #[test]
+    fn test_merged_entry_replaces_stages() {
+        let entry = |stage| IndexEntry {
+            mode: "100644".to_string(),
+            id: "95d09f2b10159347eece71399a7e2e907ea3df4f".to_string(),
+            stage,
+            path: PathBuf::from("a.txt"),
+        };
+        let mut index = Index::default();
+        index.add(entry(1));
+        index.add(entry(2));
+        index.add(entry(3));
+        assert_eq!(index.entries().count(), 3);
+        assert!(index.get(Path::new("a.txt")).is_none());
+        index.add(entry(0));
+        assert_eq!(index.entries().count(), 1);
+        assert!(index.get(Path::new("a.txt")).is_some());
+    }
+}
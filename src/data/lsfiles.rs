@@ -0,0 +1,133 @@
+//! Listing the files of the index and the working tree, the way `ls-files` does.
+//!
+//! Without any selection the index is listed. Modified and deleted files compare the working tree
+//! against the index, and others are the files of the working tree the index does not know about.
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use super::{
+    base, filesystem,
+    index::{Index, IndexEntry},
+    objects::{OgitObject, OgitObjectType},
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LsFilesOptions {
+    /// Show the mode, id and stage of index entries
+    pub stage: bool,
+    /// Show files whose working tree content differs from the index, deleted ones included
+    pub modified: bool,
+    /// Show files of the index missing from the working tree
+    pub deleted: bool,
+    /// Show untracked files
+    pub others: bool,
+    /// Only show untracked files the ignore file excludes
+    pub ignored: bool,
+    /// Leave out untracked files the ignore file excludes
+    pub exclude_standard: bool,
+    /// End lines with NUL instead of a newline
+    pub null_terminated: bool,
+}
+
+/// One listed index entry, as `ls-files` prints it.
+fn format_entry(entry: &IndexEntry, options: &LsFilesOptions) -> String {
+    let terminator = if options.null_terminated { '\0' } else { '\n' };
+    if options.stage {
+        format!("{entry}{terminator}")
+    } else {
+        format!("{}{terminator}", entry.path.display())
+    }
+}
+
+/// Whether the working tree file of `entry` differs from it, content or executable bit.
+fn is_modified(entry: &IndexEntry) -> std::io::Result<bool> {
+    let mode = format!("{:0>6o}", filesystem::get_filemode(&entry.path)?);
+    let object = OgitObject::new(&fs::read(&entry.path)?, OgitObjectType::Blob);
+    Ok(object.hex_string() != entry.id || mode != entry.mode)
+}
+
+/// Every file below `directory`, except for the repository itself.
+fn worktree_files(
+    directory: &Path,
+    ignores: &[Regex],
+    out: &mut Vec<(PathBuf, bool)>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(".").unwrap_or(&path).to_path_buf();
+        if relative.starts_with(".ogit") {
+            continue;
+        }
+        if path.is_dir() {
+            worktree_files(&path, ignores, out)?;
+        } else {
+            out.push((relative, base::is_ignored(&path, ignores)));
+        }
+    }
+    Ok(())
+}
+
+/// The files `options` select, one per line.
+pub fn ls_files(options: &LsFilesOptions) -> std::io::Result<String> {
+    let index = Index::load()?;
+    let cached = !(options.modified || options.deleted || options.others);
+    let mut out = String::new();
+    for entry in index.entries() {
+        let missing = !entry.path.exists();
+        if cached {
+            out.push_str(&format_entry(entry, options));
+        }
+        if options.deleted && missing {
+            out.push_str(&format_entry(entry, options));
+        }
+        if options.modified && (missing || is_modified(entry)?) {
+            out.push_str(&format_entry(entry, options));
+        }
+    }
+    if options.others {
+        let tracked = index
+            .entries()
+            .map(|entry| entry.path.clone())
+            .collect::<BTreeSet<_>>();
+        let mut files = Vec::new();
+        worktree_files(Path::new("."), &base::load_ignore_regex(), &mut files)?;
+        files.sort();
+        let terminator = if options.null_terminated { '\0' } else { '\n' };
+        for (path, ignored) in files {
+            let excluded = if options.ignored {
+                !ignored
+            } else {
+                options.exclude_standard && ignored
+            };
+            if !tracked.contains(&path) && !excluded {
+                out.push_str(&format!("{}{terminator}", path.display()));
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_entry() {
+        let entry = "100644 95d09f2b10159347eece71399a7e2e907ea3df4f 2\tsrc/main.rs"
+            .parse::<IndexEntry>()
+            .unwrap();
+        let mut options = LsFilesOptions::default();
+        assert_eq!(format_entry(&entry, &options), "src/main.rs\n");
+        options.stage = true;
+        options.null_terminated = true;
+        assert_eq!(
+            format_entry(&entry, &options),
+            "100644 95d09f2b10159347eece71399a7e2e907ea3df4f 2\tsrc/main.rs\0"
+        );
+    }
+}
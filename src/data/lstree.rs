@@ -0,0 +1,125 @@
+//! Listing the entries of a tree, the way `ls-tree` does.
+//!
+//! Without `-r` only the top level is listed, but trees leading to a path asked for are always
+//! entered. A path ending in `/` lists what is inside that directory instead of the directory.
+use std::path::{Path, PathBuf};
+
+use super::{
+    base::{self, TreeEntry},
+    diff::matches_pathspec,
+    filesystem,
+    objects::OgitObjectType,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LsTreeOptions {
+    /// Recurse into sub trees
+    pub recursive: bool,
+    /// Show trees even when recursing into them
+    pub show_trees: bool,
+    /// Only show trees
+    pub only_trees: bool,
+    /// Show the size of blobs
+    pub long: bool,
+    pub name_only: bool,
+    /// End lines with NUL instead of a newline
+    pub null_terminated: bool,
+}
+
+/// One listed entry, as `ls-tree` prints it.
+fn format_entry(
+    entry: &TreeEntry,
+    path: &Path,
+    size: Option<usize>,
+    options: &LsTreeOptions,
+) -> String {
+    let terminator = if options.null_terminated { '\0' } else { '\n' };
+    if options.name_only {
+        return format!("{}{terminator}", path.display());
+    }
+    let object = format!("{} {} {}", entry.filemode, entry.variant, entry.id);
+    if options.long {
+        let size = size.map_or_else(|| "-".to_string(), |size| size.to_string());
+        format!("{object} {size:>7}\t{}{terminator}", path.display())
+    } else {
+        format!("{object}\t{}{terminator}", path.display())
+    }
+}
+
+fn list(
+    tree: &str,
+    prefix: &Path,
+    pathspecs: &[String],
+    options: &LsTreeOptions,
+    out: &mut String,
+) -> std::io::Result<()> {
+    let specs = pathspecs.iter().map(PathBuf::from).collect::<Vec<_>>();
+    for entry in base::parse_tree_data(Some(tree))? {
+        let path = prefix.join(&entry.filename);
+        let is_tree = entry.variant == OgitObjectType::Tree;
+        let wanted = specs.is_empty() || matches_pathspec(&path, &specs);
+        let leads_to_spec = is_tree
+            && specs
+                .iter()
+                .any(|spec| spec.starts_with(&path) && *spec != path);
+        let lists_inside = is_tree
+            && pathspecs
+                .iter()
+                .any(|spec| spec.ends_with('/') && Path::new(spec) == path);
+        let descend = is_tree && (leads_to_spec || lists_inside || (wanted && options.recursive));
+        let shown = (wanted && !lists_inside || options.show_trees && leads_to_spec)
+            && (!descend || options.show_trees || options.only_trees)
+            && (is_tree || !options.only_trees);
+        if shown {
+            let size = match (options.long, is_tree) {
+                (true, false) => Some(filesystem::get_object(&entry.id, None)?.data.len()),
+                _ => None,
+            };
+            out.push_str(&format_entry(&entry, &path, size, options));
+        }
+        if descend {
+            list(&entry.id, &path, pathspecs, options, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// The entries of `tree` inside `pathspecs`, or all of them when there are none.
+pub fn ls_tree(
+    tree: &str,
+    pathspecs: &[String],
+    options: &LsTreeOptions,
+) -> std::io::Result<String> {
+    let mut out = String::new();
+    list(tree, Path::new(""), pathspecs, options, &mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_entry() {
+        let entry = "100644 blob 95d09f2b10159347eece71399a7e2e907ea3df4f hello.txt"
+            .parse::<TreeEntry>()
+            .unwrap();
+        let path = Path::new("docs/hello.txt");
+        let mut options = LsTreeOptions::default();
+        assert_eq!(
+            format_entry(&entry, path, None, &options),
+            "100644 blob 95d09f2b10159347eece71399a7e2e907ea3df4f\tdocs/hello.txt\n"
+        );
+        options.long = true;
+        assert_eq!(
+            format_entry(&entry, path, Some(11), &options),
+            "100644 blob 95d09f2b10159347eece71399a7e2e907ea3df4f      11\tdocs/hello.txt\n"
+        );
+        options.name_only = true;
+        options.null_terminated = true;
+        assert_eq!(
+            format_entry(&entry, path, None, &options),
+            "docs/hello.txt\0"
+        );
+    }
+}
@@ -0,0 +1,378 @@
+//! Commits as patch emails, in the mbox format `format-patch` writes and `am` reads back.
+//!
+//! More info: <https://git-scm.com/docs/git-format-patch>
+use std::fmt::Write as _;
+
+use super::{commits::OgitCommit, time};
+
+/// Date on the `From <id>` line opening every message, it tells our patches apart from real mail.
+const MBOX_DATE: &str = "Mon Sep 17 00:00:00 2001";
+/// Longest file name slug `format-patch` derives from a subject.
+const MAX_SLUG_LENGTH: usize = 64;
+/// Id used on the `From` line of the cover letter, which has no commit.
+pub const COVER_LETTER_ID: &str = "0000000000000000000000000000000000000000";
+
+/// The parts of a patch email that make up a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchEmail {
+    pub author: String,
+    pub date: time::DateTime<time::FixedOffset>,
+    pub subject: String,
+    pub body: String,
+    /// Everything after the message, including the diffstat
+    pub patch: Vec<u8>,
+}
+
+impl PatchEmail {
+    /// The commit message the email describes.
+    pub fn message(&self) -> String {
+        if self.body.is_empty() {
+            format!("{}\n", self.subject)
+        } else {
+            format!("{}\n\n{}\n", self.subject, self.body)
+        }
+    }
+}
+
+/// Bytes that can be left as they are inside an RFC 2047 encoded word.
+const fn is_plain_in_encoded_word(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'!' | b'*' | b'+' | b'-' | b'/')
+}
+
+/// Header values must be ASCII, anything else is wrapped in an RFC 2047 encoded word.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    let mut out = String::from("=?UTF-8?q?");
+    for b in value.bytes() {
+        match b {
+            b' ' => out.push('_'),
+            b if is_plain_in_encoded_word(b) => out.push(char::from(b)),
+            b => write!(out, "={b:02X}").unwrap(),
+        }
+    }
+    out.push_str("?=");
+    out
+}
+
+/// Undo [`encode_header`] on every `=?UTF-8?q?...?=` word of a header value.
+fn decode_header(value: &str) -> String {
+    let mut out = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("=?") {
+        let word = &rest[start + 2..];
+        let mut parts = word.splitn(3, '?');
+        let (Some(_charset), Some(encoding), Some(text)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            break;
+        };
+        let Some(end) = text.find("?=") else {
+            break;
+        };
+        if !encoding.eq_ignore_ascii_case("q") {
+            break;
+        }
+        out.extend_from_slice(&rest.as_bytes()[..start]);
+        let encoded = &text.as_bytes()[..end];
+        let mut i = 0;
+        while i < encoded.len() {
+            match encoded[i] {
+                b'_' => out.push(b' '),
+                b'=' => {
+                    let hex = std::str::from_utf8(encoded.get(i + 1..i + 3).unwrap_or_default())
+                        .ok()
+                        .and_then(|h| u8::from_str_radix(h, 16).ok());
+                    match hex {
+                        Some(byte) => {
+                            out.push(byte);
+                            i += 2;
+                        }
+                        None => out.push(b'='),
+                    }
+                }
+                b => out.push(b),
+            }
+            i += 1;
+        }
+        // whitespace between two encoded words is not part of the text
+        rest = &text[end + 2..];
+        if rest.trim_start().starts_with("=?") {
+            rest = rest.trim_start();
+        }
+    }
+    out.extend_from_slice(rest.as_bytes());
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// `[PATCH]`, or `[PATCH n/m]` for a numbered series.
+pub fn subject_prefix(prefix: &str, number: Option<(usize, usize)>) -> String {
+    match number {
+        Some((n, total)) => {
+            let width = total.to_string().len();
+            format!("[{prefix} {n:0width$}/{total}]")
+        }
+        None => format!("[{prefix}]"),
+    }
+}
+
+/// File name `format-patch` stores a patch under, such as `0001-Fix-the-thing.patch`.
+pub fn patch_file_name(number: usize, subject: &str) -> String {
+    let mut slug = String::new();
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(MAX_SLUG_LENGTH);
+    let slug = slug.trim_end_matches(['-', '.']);
+    format!("{number:04}-{slug}.patch")
+}
+
+pub fn push_headers(out: &mut String, id: &str, author: &str, date: &str, subject: &str) {
+    writeln!(out, "From {id} {MBOX_DATE}").unwrap();
+    writeln!(out, "From: {}", encode_header(author)).unwrap();
+    writeln!(out, "Date: {date}").unwrap();
+    writeln!(out, "Subject: {}", encode_header(subject)).unwrap();
+}
+
+fn push_signature(out: &mut String) {
+    writeln!(out, "-- \nog {}\n", env!("CARGO_PKG_VERSION")).unwrap();
+}
+
+/// One commit as an email: headers, message, then `diff` (diffstat and patch) after a `---` line.
+pub fn format_email(id: &str, commit: &OgitCommit, subject_prefix: &str, diff: &str) -> String {
+    let mut out = String::new();
+    let subject = format!("{subject_prefix} {}", commit.subject());
+    push_headers(
+        &mut out,
+        id,
+        &commit.author,
+        &commit.author_time.to_rfc2822(),
+        &subject,
+    );
+    let body = commit.body();
+    if !body.is_ascii() {
+        out.push_str(
+            "MIME-Version: 1.0\n\
+             Content-Type: text/plain; charset=UTF-8\n\
+             Content-Transfer-Encoding: 8bit\n",
+        );
+    }
+    out.push('\n');
+    if !body.is_empty() {
+        writeln!(out, "{body}").unwrap();
+    }
+    write!(out, "---\n{diff}").unwrap();
+    push_signature(&mut out);
+    out
+}
+
+/// The introductory email of a series, listing its commits and the overall diffstat.
+pub fn format_cover_letter(
+    author: &str,
+    subject_prefix: &str,
+    commits: &[(String, OgitCommit)],
+    stat: &str,
+) -> String {
+    let mut out = String::new();
+    let date = time::get_current_local().fixed_offset().to_rfc2822();
+    let subject = format!("{subject_prefix} *** SUBJECT HERE ***");
+    push_headers(&mut out, COVER_LETTER_ID, author, &date, &subject);
+    out.push_str("\n*** BLURB HERE ***\n\n");
+    // commits grouped by author, like `git shortlog`
+    let mut authors: Vec<(&str, Vec<String>)> = Vec::new();
+    for (_, commit) in commits {
+        let name = commit.author_name();
+        match authors.iter_mut().find(|(a, _)| *a == name) {
+            Some((_, subjects)) => subjects.push(commit.subject()),
+            None => authors.push((name, vec![commit.subject()])),
+        }
+    }
+    authors.sort_by_key(|(name, _)| *name);
+    for (name, subjects) in authors {
+        writeln!(out, "{name} ({}):", subjects.len()).unwrap();
+        for subject in subjects {
+            writeln!(out, "  {subject}").unwrap();
+        }
+        out.push('\n');
+    }
+    writeln!(out, "{stat}").unwrap();
+    push_signature(&mut out);
+    out
+}
+
+/// Whether `line` opens a new message of an mbox.
+fn is_mbox_separator(line: &[u8]) -> bool {
+    line.strip_prefix(b"From ")
+        .is_some_and(|rest| rest.split(|b| *b == b' ').count() > 1)
+}
+
+/// Split an mbox into its messages, text without `From ` lines is a single message.
+pub fn split_mbox(text: &[u8]) -> Vec<&[u8]> {
+    let mut messages = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut previous_blank = true;
+    for line in text.split_inclusive(|b| *b == b'\n') {
+        if previous_blank && is_mbox_separator(line) && offset > start {
+            messages.push(&text[start..offset]);
+            start = offset;
+        }
+        previous_blank = line.trim_ascii().is_empty();
+        offset += line.len();
+    }
+    if text[start..].iter().any(|b| !b.is_ascii_whitespace()) {
+        messages.push(&text[start..]);
+    }
+    messages
+}
+
+/// Drop the `[PATCH n/m]` style tags and `Re:` replies mail clients put before a subject.
+fn strip_subject(subject: &str) -> &str {
+    let mut subject = subject.trim();
+    loop {
+        if let Some(rest) = subject.strip_prefix('[') {
+            match rest.split_once(']') {
+                Some((_, rest)) => subject = rest.trim_start(),
+                None => return subject,
+            }
+        } else if subject.len() >= 3 && subject[..3].eq_ignore_ascii_case("re:") {
+            subject = subject[3..].trim_start();
+        } else {
+            return subject;
+        }
+    }
+}
+
+/// Whether `line` starts the patch part of a message.
+fn starts_patch(line: &str) -> bool {
+    line == "---" || line.starts_with("diff --git ") || line.starts_with("Index: ")
+}
+
+/// Read the commit described by a single message of an mbox.
+pub fn parse_email(message: &[u8]) -> Result<PatchEmail, String> {
+    let text = String::from_utf8_lossy(message);
+    let mut lines = text.split_inclusive('\n').peekable();
+    if lines
+        .peek()
+        .is_some_and(|l| is_mbox_separator(l.as_bytes()))
+    {
+        lines.next();
+    }
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines.by_ref() {
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            // folded header, continuing the previous one
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| decode_header(v))
+    };
+    let author = header("from").ok_or("patch email without a From header")?;
+    let date = header("date").ok_or("patch email without a Date header")?;
+    let date = time::DateTime::parse_from_rfc2822(&date)
+        .map_err(|e| format!("invalid date '{date}' in patch email: {e}"))?;
+    let subject = header("subject").ok_or("patch email without a Subject header")?;
+
+    let mut body = String::new();
+    let mut patch = Vec::new();
+    for line in lines.by_ref() {
+        if starts_patch(line.trim_end_matches(['\n', '\r'])) {
+            patch.extend_from_slice(line.as_bytes());
+            break;
+        }
+        body.push_str(line);
+    }
+    for line in lines {
+        patch.extend_from_slice(line.as_bytes());
+    }
+    Ok(PatchEmail {
+        author,
+        date,
+        subject: strip_subject(&subject).to_string(),
+        body: body.trim().to_string(),
+        patch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_file_name() {
+        assert_eq!(
+            patch_file_name(1, "Fix the  \"thing\", again."),
+            "0001-Fix-the-thing-again.patch"
+        );
+        assert_eq!(
+            patch_file_name(12, "v1.2_release"),
+            "0012-v1.2_release.patch"
+        );
+    }
+
+    #[test]
+    fn test_subject_prefix() {
+        assert_eq!(subject_prefix("PATCH", None), "[PATCH]");
+        assert_eq!(subject_prefix("PATCH", Some((3, 12))), "[PATCH 03/12]");
+    }
+
+    #[test]
+    fn test_header_encoding_round_trip() {
+        let name = "Zoë Müller <zoe@example.com>";
+        let encoded = encode_header(name);
+        assert!(encoded.is_ascii());
+        assert_eq!(decode_header(&encoded), name);
+        assert_eq!(decode_header("plain text"), "plain text");
+    }
+
+    #[test]
+    fn test_split_and_parse_mbox() {
+        let mbox = b"From 1234 Mon Sep 17 00:00:00 2001\n\
+            From: Ryan Ozawa <ryan.ozawa21@gmail.com>\n\
+            Date: Tue, 14 Nov 2023 23:13:20 +0100\n\
+            Subject: [PATCH 1/2] Fix the\n thing\n\
+            \n\
+            Longer explanation.\n\
+            ---\n \
+            a.txt | 2 +-\n\
+            \n\
+            diff --git a/a.txt b/a.txt\n\
+            -- \n\
+            og 0.1.0\n\
+            \n\
+            From 5678 Mon Sep 17 00:00:00 2001\n\
+            From: Ryan Ozawa <ryan.ozawa21@gmail.com>\n\
+            Date: Tue, 14 Nov 2023 23:14:20 +0100\n\
+            Subject: [PATCH 2/2] Re: Second\n\
+            \n\
+            diff --git a/b.txt b/b.txt\n";
+        let messages = split_mbox(mbox);
+        assert_eq!(messages.len(), 2);
+        let first = parse_email(messages[0]).unwrap();
+        assert_eq!(first.author, "Ryan Ozawa <ryan.ozawa21@gmail.com>");
+        assert_eq!(first.date.timestamp(), 1_700_000_000);
+        assert_eq!(first.message(), "Fix the thing\n\nLonger explanation.\n");
+        assert!(first.patch.starts_with(b"---\n a.txt"));
+        let second = parse_email(messages[1]).unwrap();
+        assert_eq!(second.message(), "Second\n");
+        assert!(second.patch.starts_with(b"diff --git a/b.txt"));
+    }
+}
@@ -0,0 +1,603 @@
+//! `og merge`: joining the history of another commit into HEAD.
+//!
+//! Paths changed on one side only are taken from that side, paths changed on both are merged line
+//! by line. Conflicts are left in the working tree with markers and in the index as stages 1 to 3
+//! (base, ours, theirs), while `.ogit/MERGE_HEAD` and `.ogit/MERGE_MSG` wait for the commit that
+//! concludes the merge.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    base, binary,
+    commits::OgitCommit,
+    diff::{self, DiffFormat, DiffOptions},
+    diffstat::StatOptions,
+    filesystem,
+    graph::CommitGraph,
+    index::{Index, IndexEntry},
+    objects::OgitObjectType,
+    porcelain, refs, revision, sequencer,
+    xmerge::{self, ConflictStyle, MergeLabels},
+};
+
+/// The commit being merged, while the merge waits to be committed.
+pub const MERGE_HEAD: &str = "MERGE_HEAD";
+/// Message the commit concluding the merge gets.
+pub const MERGE_MSG: &str = "MERGE_MSG";
+/// Message prepared by `--squash` for the next commit.
+pub const SQUASH_MSG: &str = "SQUASH_MSG";
+
+/// When a merge may just move HEAD forward instead of creating a merge commit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FastForward {
+    /// Fast-forward when possible, create a merge commit otherwise
+    #[default]
+    Allow,
+    /// Refuse to do anything but a fast-forward
+    Only,
+    /// Always create a merge commit
+    Never,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    pub fast_forward: FastForward,
+    /// Prepare the merged tree without committing or recording the merge
+    pub squash: bool,
+    pub style: ConflictStyle,
+    pub message: Option<String>,
+}
+
+/// What a merge ended with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStatus {
+    UpToDate,
+    FastForward,
+    Merged,
+    /// The merged tree was prepared, but nothing was committed
+    Squashed,
+    /// Conflicts were left to be resolved
+    Conflicted,
+    /// Only a fast-forward was allowed, but HEAD is not an ancestor of the merged commit
+    NotPossibleToFastForward,
+}
+
+impl MergeStatus {
+    /// Exit code of `og merge`, the same git uses for each outcome.
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::UpToDate | Self::FastForward | Self::Merged | Self::Squashed => 0,
+            Self::Conflicted => 1,
+            Self::NotPossibleToFastForward => 128,
+        }
+    }
+}
+
+/// Content and mode of a path, `None` when the path does not exist.
+type FileState = Option<(Vec<u8>, String)>;
+
+/// The outcome of merging three trees path by path.
+#[derive(Debug, Default)]
+struct TreeMerge {
+    index: Index,
+    /// Working tree files to write, or to delete when `None`
+    writes: BTreeMap<PathBuf, FileState>,
+    messages: Vec<String>,
+    conflicts: BTreeSet<PathBuf>,
+}
+
+fn tree_files(tree: Option<&str>) -> std::io::Result<BTreeMap<PathBuf, (String, String)>> {
+    let Some(tree) = tree else {
+        return Ok(BTreeMap::new());
+    };
+    Ok(Index::from_tree(tree)?
+        .entries()
+        .map(|e| (e.path.clone(), (e.mode.clone(), e.id.clone())))
+        .collect())
+}
+
+fn read_blob(id: &str) -> std::io::Result<Vec<u8>> {
+    Ok(filesystem::get_object(id, Some(OgitObjectType::Blob))?.data)
+}
+
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(7)]
+}
+
+impl TreeMerge {
+    fn stage(&mut self, path: &Path, stage: u8, side: Option<&(String, String)>) {
+        if let Some((mode, id)) = side {
+            self.index.add(IndexEntry {
+                mode: mode.clone(),
+                id: id.clone(),
+                stage,
+                path: path.to_path_buf(),
+            });
+        }
+    }
+
+    /// Record `path` as conflicted with the given sides as stages 1 to 3.
+    fn conflict(
+        &mut self,
+        path: &Path,
+        base: Option<&(String, String)>,
+        ours: Option<&(String, String)>,
+        theirs: Option<&(String, String)>,
+    ) {
+        self.index.remove(path);
+        self.stage(path, 1, base);
+        self.stage(path, 2, ours);
+        self.stage(path, 3, theirs);
+        self.conflicts.insert(path.to_path_buf());
+    }
+
+    /// Make `side` the merged version of `path`, in both the index and the working tree.
+    fn take(&mut self, path: &Path, side: Option<&(String, String)>) -> std::io::Result<()> {
+        match side {
+            Some((mode, id)) => {
+                self.stage(path, 0, side);
+                self.writes
+                    .insert(path.to_path_buf(), Some((read_blob(id)?, mode.clone())));
+            }
+            None => {
+                self.index.remove(path);
+                self.writes.insert(path.to_path_buf(), None);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The mode both sides agree on, or the one that changed; `None` when both changed it differently.
+fn merge_modes(base: Option<&str>, ours: &str, theirs: &str) -> Option<String> {
+    if ours == theirs || base == Some(theirs) {
+        Some(ours.to_string())
+    } else if base == Some(ours) {
+        Some(theirs.to_string())
+    } else {
+        None
+    }
+}
+
+/// Merge the changes `ours` and `theirs` made to `base` path by path, starting from `ours`.
+fn merge_trees(
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) -> std::io::Result<TreeMerge> {
+    let base_files = tree_files(base)?;
+    let our_files = tree_files(Some(ours))?;
+    let their_files = tree_files(Some(theirs))?;
+    let paths = base_files
+        .keys()
+        .chain(our_files.keys())
+        .chain(their_files.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let mut result = TreeMerge {
+        index: Index::from_tree(ours)?,
+        ..Default::default()
+    };
+    for path in paths {
+        let (b, o, t) = (
+            base_files.get(&path),
+            our_files.get(&path),
+            their_files.get(&path),
+        );
+        if o == t || b == t {
+            continue;
+        }
+        if b == o {
+            result.take(&path, t)?;
+            continue;
+        }
+        let name = path.display();
+        let (Some(o), Some(t)) = (o, t) else {
+            // one side deleted what the other modified, the modified version stays
+            let (deleted_in, modified_in) = if o.is_none() {
+                (labels.ours, labels.theirs)
+            } else {
+                (labels.theirs, labels.ours)
+            };
+            result.messages.push(format!(
+                "CONFLICT (modify/delete): {name} deleted in {deleted_in} and modified in {modified_in}.  Version {modified_in} of {name} left in tree."
+            ));
+            if let Some((mode, id)) = t.filter(|_| o.is_none()) {
+                result
+                    .writes
+                    .insert(path.clone(), Some((read_blob(id)?, mode.clone())));
+            }
+            result.conflict(&path, b, o, t);
+            continue;
+        };
+        let mode = merge_modes(b.map(|(mode, _)| mode.as_str()), &o.0, &t.0);
+        if o.1 == t.1 {
+            // same content, so only the modes can disagree
+            match mode {
+                Some(mode) => result.take(&path, Some(&(mode, o.1.clone())))?,
+                None => {
+                    result.messages.push(format!(
+                        "CONFLICT (mode): {name} had different mode changes on both sides"
+                    ));
+                    result.conflict(&path, b, Some(o), Some(t));
+                }
+            }
+            continue;
+        }
+        let base_content = b.map(|(_, id)| read_blob(id)).transpose()?;
+        let (our_content, their_content) = (read_blob(&o.1)?, read_blob(&t.1)?);
+        if [&our_content, &their_content]
+            .into_iter()
+            .chain(base_content.as_ref())
+            .any(|content| binary::is_binary(content))
+        {
+            result.messages.push(format!(
+                "warning: Cannot merge binary files: {name} ({} vs. {})",
+                labels.ours, labels.theirs
+            ));
+            result
+                .messages
+                .push(format!("CONFLICT (content): Merge conflict in {name}"));
+            result.conflict(&path, b, Some(o), Some(t));
+            continue;
+        }
+        result.messages.push(format!("Auto-merging {name}"));
+        let merged = xmerge::merge(
+            base_content.as_deref().unwrap_or_default(),
+            &our_content,
+            &their_content,
+            labels,
+            style,
+        );
+        let mode = mode.unwrap_or_else(|| o.0.clone());
+        if merged.conflicts > 0 {
+            let kind = if b.is_none() { "add/add" } else { "content" };
+            result
+                .messages
+                .push(format!("CONFLICT ({kind}): Merge conflict in {name}"));
+            result.conflict(&path, b, Some(o), Some(t));
+            result
+                .writes
+                .insert(path.clone(), Some((merged.content, mode)));
+            continue;
+        }
+        let id = filesystem::hash_object(&merged.content, Some(OgitObjectType::Blob))?.hex_string();
+        result.stage(&path, 0, Some(&(mode.clone(), id)));
+        result
+            .writes
+            .insert(path.clone(), Some((merged.content, mode)));
+    }
+    Ok(result)
+}
+
+/// Merge `ours` and `theirs` into the working tree and the index, which must be at `ours`.
+///
+/// Returns the merged tree, or `None` when conflicts were left to resolve. `operation` names the
+/// command in the error refusing to overwrite local changes.
+pub fn merge_into_worktree(
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    labels: &MergeLabels,
+    style: ConflictStyle,
+    operation: &str,
+) -> std::io::Result<Option<String>> {
+    let merged = merge_trees(base, ours, theirs, labels, style)?;
+    porcelain::check_local_changes(
+        Some(ours),
+        merged.writes.keys().map(PathBuf::as_path),
+        operation,
+    )?;
+    for (path, state) in &merged.writes {
+        match state {
+            Some((content, mode)) => filesystem::write_worktree_file(path, content, mode)?,
+            None => porcelain::remove_worktree_file(path)?,
+        }
+    }
+    merged.index.save()?;
+    for message in &merged.messages {
+        println!("{message}");
+    }
+    if merged.conflicts.is_empty() {
+        merged.index.write_tree().map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Whether a merge is waiting to be concluded.
+pub fn in_progress() -> std::io::Result<bool> {
+    Ok(filesystem::ogit_path(MERGE_HEAD)?.is_file())
+}
+
+/// The commit being merged, if a merge is waiting to be concluded.
+pub fn merge_head() -> std::io::Result<Option<String>> {
+    match fs::read_to_string(filesystem::ogit_path(MERGE_HEAD)?) {
+        Ok(id) => Ok(Some(id.trim().to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The message prepared for the commit concluding a merge or squash, if there is one.
+pub fn prepared_message() -> std::io::Result<Option<String>> {
+    for name in [MERGE_MSG, SQUASH_MSG] {
+        match fs::read_to_string(filesystem::ogit_path(name)?) {
+            Ok(message) => return Ok(Some(message)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// Forget a concluded or abandoned merge, pick or revert.
+pub fn clear_state() -> std::io::Result<()> {
+    for name in [
+        MERGE_HEAD,
+        MERGE_MSG,
+        SQUASH_MSG,
+        sequencer::CHERRY_PICK_HEAD,
+        sequencer::REVERT_HEAD,
+    ] {
+        let path = filesystem::ogit_path(name)?;
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Paths conflicted in the index whose working tree file still holds conflict markers.
+pub fn unresolved_paths(index: &Index) -> std::io::Result<BTreeSet<PathBuf>> {
+    let mut unresolved = BTreeSet::new();
+    for entry in index.entries().filter(|e| e.stage != 0) {
+        if unresolved.contains(&entry.path) || !entry.path.is_file() {
+            continue;
+        }
+        if xmerge::has_conflict_markers(&fs::read(&entry.path)?) {
+            unresolved.insert(entry.path.clone());
+        }
+    }
+    Ok(unresolved)
+}
+
+/// Print the diffstat of what moved between two trees.
+fn print_stat(old_tree: Option<&str>, new_tree: &str) -> std::io::Result<()> {
+    let options = DiffOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    let format = DiffFormat {
+        stat: Some(StatOptions::default()),
+        summary: true,
+        ..Default::default()
+    };
+    let entries = diff::diff_trees(old_tree, Some(new_tree), &options)?;
+    print!("{}", diff::render(&entries, &format)?);
+    Ok(())
+}
+
+/// The message `--squash` prepares: every commit that is squashed, newest first.
+fn squash_message(theirs: &str, head: &str) -> std::io::Result<String> {
+    let mut message = String::from("Squashed commit of the following:\n");
+    for (id, commit) in revision::walk_range(theirs, Some(head))? {
+        message.push_str(&format!("\ncommit {id}\nAuthor: {}\n\n", commit.author));
+        for line in String::from_utf8_lossy(&commit.message).lines() {
+            message.push_str(&format!("    {line}\n"));
+        }
+    }
+    Ok(message)
+}
+
+/// Merge the commit `rev` into HEAD.
+pub fn merge(rev: &str, options: &MergeOptions) -> std::io::Result<MergeStatus> {
+    if in_progress()? {
+        return Err(std::io::Error::other(
+            "You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge.",
+        ));
+    }
+    let theirs = revision::resolve(rev)?;
+    let their_commit = OgitCommit::get(&theirs)?;
+    let head = filesystem::read_head_file()?;
+    if head.is_empty() {
+        // nothing to merge with, HEAD simply starts at the merged commit
+        porcelain::update_working_tree(None, &their_commit.tree, "merge")?;
+        refs::move_head(&theirs, &head, &format!("merge {rev}: Fast-forward"))?;
+        return Ok(MergeStatus::FastForward);
+    }
+    let head_tree = OgitCommit::get(&head)?.tree;
+    let mut graph = CommitGraph::load()?;
+    if graph.is_ancestor(&theirs, &head)? {
+        graph.save()?;
+        println!("Already up to date.");
+        return Ok(MergeStatus::UpToDate);
+    }
+    let can_fast_forward = graph.is_ancestor(&head, &theirs)?;
+    if can_fast_forward && options.fast_forward != FastForward::Never && !options.squash {
+        graph.save()?;
+        println!("Updating {}..{}", short_id(&head), short_id(&theirs));
+        porcelain::update_working_tree(Some(&head_tree), &their_commit.tree, "merge")?;
+        refs::move_head(&theirs, &head, &format!("merge {rev}: Fast-forward"))?;
+        println!("Fast-forward");
+        print_stat(Some(&head_tree), &their_commit.tree)?;
+        return Ok(MergeStatus::FastForward);
+    }
+    if options.fast_forward == FastForward::Only {
+        graph.save()?;
+        eprintln!("fatal: Not possible to fast-forward, aborting.");
+        return Ok(MergeStatus::NotPossibleToFastForward);
+    }
+    // TODO: criss-cross histories have several bases, git merges them into a virtual one first
+    let bases = graph.merge_bases(&head, std::slice::from_ref(&theirs))?;
+    graph.save()?;
+    let Some(base) = bases.first() else {
+        return Err(std::io::Error::other(
+            "refusing to merge unrelated histories",
+        ));
+    };
+
+    let base_tree = OgitCommit::get(base)?.tree;
+    let labels = MergeLabels {
+        ours: "HEAD",
+        base: short_id(base),
+        theirs: rev,
+    };
+    let merged = merge_into_worktree(
+        Some(&base_tree),
+        &head_tree,
+        &their_commit.tree,
+        &labels,
+        options.style,
+        "merge",
+    )?;
+
+    if options.squash {
+        fs::write(
+            filesystem::ogit_path(SQUASH_MSG)?,
+            squash_message(&theirs, &head)?,
+        )?;
+        println!("Squash commit -- not updating HEAD");
+    } else {
+        let message = match &options.message {
+            Some(message) => message.clone(),
+            None => default_message(rev, refs::expand(rev)?.as_deref()),
+        };
+        fs::write(filesystem::ogit_path(MERGE_HEAD)?, format!("{theirs}\n"))?;
+        fs::write(
+            filesystem::ogit_path(MERGE_MSG)?,
+            format!("{}\n", message.trim_end()),
+        )?;
+    }
+    let Some(tree) = merged else {
+        println!("Automatic merge failed; fix conflicts and then commit the result.");
+        return Ok(MergeStatus::Conflicted);
+    };
+    if options.squash {
+        println!("Automatic merge went well; stopped before committing as requested");
+        return Ok(MergeStatus::Squashed);
+    }
+    let message = prepared_message()?.unwrap_or_default();
+    let commit = base::commit_tree(&tree, &[head.clone(), theirs], Some(message))?;
+    refs::move_head(
+        &commit.hex_string(),
+        &head,
+        &format!("merge {rev}: Merge made by the 'resolve' strategy."),
+    )?;
+    clear_state()?;
+    println!("Merge made by the 'resolve' strategy.");
+    print_stat(Some(&head_tree), &tree)?;
+    Ok(MergeStatus::Merged)
+}
+
+/// The message git gives a merge of `rev`, after the kind of ref `full_name` it stands for.
+fn default_message(rev: &str, full_name: Option<&str>) -> String {
+    let kind = match full_name {
+        Some(name) if name.starts_with("refs/heads/") => "branch",
+        Some(name) if name.starts_with("refs/tags/") => "tag",
+        Some(name) if name.starts_with("refs/remotes/") => "remote-tracking branch",
+        _ => "commit",
+    };
+    let name = full_name.map_or(rev, refs::shorten);
+    format!("Merge {kind} '{name}'")
+}
+
+/// Conclude a merge whose conflicts were resolved and added to the index.
+pub fn resume() -> std::io::Result<()> {
+    if !in_progress()? {
+        return Err(std::io::Error::other(
+            "There is no merge in progress (MERGE_HEAD missing).",
+        ));
+    }
+    porcelain::commit(None, false).map(|_| ())
+}
+
+/// Drop a merge waiting to be concluded, putting back HEAD's version of every path it touched.
+pub fn abort() -> std::io::Result<()> {
+    if !in_progress()? {
+        return Err(std::io::Error::other(
+            "There is no merge to abort (MERGE_HEAD missing).",
+        ));
+    }
+    reset_merge()
+}
+
+/// Put back HEAD's version of every path the index disagrees with, and forget any merge state.
+pub fn reset_merge() -> std::io::Result<()> {
+    let head_tree = revision::resolve_tree("HEAD")?;
+    let head_files = tree_files(Some(&head_tree))?;
+    let index = Index::load()?;
+    let mut touched = index
+        .entries()
+        .filter(|e| {
+            e.stage != 0 || head_files.get(&e.path) != Some(&(e.mode.clone(), e.id.clone()))
+        })
+        .map(|e| e.path.clone())
+        .collect::<BTreeSet<_>>();
+    touched.extend(
+        head_files
+            .keys()
+            .filter(|p| index.get(p).is_none())
+            .cloned(),
+    );
+    for path in touched {
+        match head_files.get(&path) {
+            Some((mode, id)) => filesystem::write_worktree_file(&path, &read_blob(id)?, mode)?,
+            None => porcelain::remove_worktree_file(&path)?,
+        }
+    }
+    Index::from_tree(&head_tree)?.save()?;
+    clear_state()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_modes() {
+        assert_eq!(
+            merge_modes(Some("100644"), "100755", "100644").as_deref(),
+            Some("100755")
+        );
+        assert_eq!(
+            merge_modes(Some("100644"), "100644", "100755").as_deref(),
+            Some("100755")
+        );
+        assert_eq!(
+            merge_modes(None, "100755", "100755").as_deref(),
+            Some("100755")
+        );
+        assert_eq!(merge_modes(None, "100644", "100755"), None);
+    }
+
+    #[test]
+    fn test_default_message() {
+        assert_eq!(
+            default_message("topic", Some("refs/heads/topic")),
+            "Merge branch 'topic'"
+        );
+        assert_eq!(
+            default_message("heads/topic", Some("refs/heads/topic")),
+            "Merge branch 'topic'"
+        );
+        assert_eq!(
+            default_message("v1", Some("refs/tags/v1")),
+            "Merge tag 'v1'"
+        );
+        assert_eq!(default_message("HEAD~2", None), "Merge commit 'HEAD~2'");
+    }
+
+    #[test]
+    fn test_exit_codes_tell_outcomes_apart() {
+        assert_eq!(MergeStatus::UpToDate.exit_code(), 0);
+        assert_eq!(MergeStatus::Conflicted.exit_code(), 1);
+        assert_eq!(MergeStatus::NotPossibleToFastForward.exit_code(), 128);
+    }
+}
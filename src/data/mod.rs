@@ -1,12 +1,52 @@
 /// These functions and operations interact with the ogit database
+pub mod am;
+pub mod apply;
+pub mod asciigraph;
+pub mod attributes;
 pub mod base;
+pub mod base85;
+pub mod binary;
+pub mod catfile;
 pub mod commits;
 pub mod config;
+pub mod diff;
+pub mod diffstat;
 pub mod filesystem;
+pub mod foreachref;
+pub mod graph;
+pub mod index;
+pub mod lsfiles;
+pub mod lstree;
+pub mod mailbox;
+pub mod merge;
 pub mod objects;
+pub mod packedrefs;
+pub mod patch;
 pub mod porcelain;
+pub mod pretty;
+pub mod rebase;
+pub mod reflog;
+pub mod refs;
+pub mod reset;
+pub mod revision;
+pub mod revwalk;
+pub mod sequencer;
+pub mod showref;
+pub mod simplify;
+pub mod stash;
+pub mod tags;
+#[cfg(test)]
+pub mod testrepo;
 pub mod time;
+pub mod worddiff;
+pub mod xdiff;
+pub mod xmerge;
 
 pub mod prelude {
-    pub use super::{base, commits, config, filesystem, objects, porcelain, time};
+    pub use super::{
+        am, apply, asciigraph, base, catfile, commits, config, diff, diffstat, filesystem,
+        foreachref, graph, index, lsfiles, lstree, mailbox, merge, objects, packedrefs, patch,
+        porcelain, pretty, rebase, refs, reset, revision, revwalk, sequencer, showref, simplify,
+        stash, tags, time,
+    };
 }
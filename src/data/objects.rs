@@ -1,12 +1,12 @@
-use itertools::Itertools;
 use sha1::{Digest, Sha1};
-use std::{fmt::Display, io::Read, str::FromStr};
+use std::{fmt::Display, str::FromStr};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OgitObjectType {
     Blob,
     Tree,
     Commit,
+    Tag,
 }
 
 impl FromStr for OgitObjectType {
@@ -17,6 +17,7 @@ impl FromStr for OgitObjectType {
             "blob" => Ok(Self::Blob),
             "tree" => Ok(Self::Tree),
             "commit" => Ok(Self::Commit),
+            "tag" => Ok(Self::Tag),
             _ => Err("Invalid object type".to_string()),
         }
     }
@@ -31,11 +32,105 @@ impl Display for OgitObjectType {
                 Self::Blob => "blob",
                 Self::Tree => "tree",
                 Self::Commit => "commit",
+                Self::Tag => "tag",
             }
         )
     }
 }
 
+/// Whether `text` is a full, 40 hex digit object id.
+pub fn is_object_id(text: &str) -> bool {
+    text.len() == 40 && text.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Whether `text` is a `Name <email> <epoch> <offset>` identity.
+fn is_identity(text: &str) -> bool {
+    let mut parts = text.rsplitn(3, ' ');
+    let (Some(offset), Some(epoch), Some(identity)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    let offset_digits = offset.strip_prefix(['+', '-']).unwrap_or_default();
+    identity.ends_with('>')
+        && identity.contains(" <")
+        && epoch.parse::<i64>().is_ok()
+        && offset_digits.len() == 4
+        && offset_digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Check that `data` is well formed for an object of type `variant`, blobs can hold anything.
+pub fn check_syntax(variant: &OgitObjectType, data: &[u8]) -> Result<(), String> {
+    let text = || std::str::from_utf8(data).map_err(|_| format!("{variant} is not valid UTF-8"));
+    match variant {
+        OgitObjectType::Blob => Ok(()),
+        OgitObjectType::Tree => {
+            for line in text()?.lines() {
+                let parts = line.splitn(4, ' ').collect::<Vec<_>>();
+                let valid = match parts.as_slice() {
+                    [mode, kind, id, name] => {
+                        mode.len() == 6
+                            && mode.bytes().all(|b| (b'0'..=b'7').contains(&b))
+                            && kind.parse::<OgitObjectType>().is_ok()
+                            && is_object_id(id)
+                            && !name.is_empty()
+                            && !name.contains('/')
+                    }
+                    _ => false,
+                };
+                if !valid {
+                    return Err(format!("invalid tree entry: {line}"));
+                }
+            }
+            Ok(())
+        }
+        OgitObjectType::Commit => {
+            let text = text()?;
+            let headers = text.split("\n\n").next().unwrap_or_default();
+            let mut lines = headers.lines().peekable();
+            match lines.next().and_then(|line| line.strip_prefix("tree ")) {
+                Some(id) if is_object_id(id) => (),
+                _ => return Err("invalid commit: missing tree".to_string()),
+            }
+            while let Some(id) = lines.next_if(|line| line.starts_with("parent ")) {
+                if !is_object_id(&id["parent ".len()..]) {
+                    return Err(format!("invalid commit: bad parent {id}"));
+                }
+            }
+            for header in ["author ", "committer "] {
+                match lines.next().and_then(|line| line.strip_prefix(header)) {
+                    Some(identity) if is_identity(identity) => (),
+                    _ => return Err(format!("invalid commit: bad {}line", header)),
+                }
+            }
+            Ok(())
+        }
+        OgitObjectType::Tag => {
+            let text = text()?;
+            let headers = text.split("\n\n").next().unwrap_or_default();
+            let mut lines = headers.lines();
+            match lines.next().and_then(|line| line.strip_prefix("object ")) {
+                Some(id) if is_object_id(id) => (),
+                _ => return Err("invalid tag: missing object".to_string()),
+            }
+            match lines.next().and_then(|line| line.strip_prefix("type ")) {
+                Some(kind) if kind.parse::<OgitObjectType>().is_ok() => (),
+                _ => return Err("invalid tag: missing type".to_string()),
+            }
+            match lines.next().and_then(|line| line.strip_prefix("tag ")) {
+                Some(name) if !name.is_empty() => (),
+                _ => return Err("invalid tag: missing tag name".to_string()),
+            }
+            match lines.next() {
+                None => Ok(()),
+                Some(line) => match line.strip_prefix("tagger ") {
+                    Some(identity) if is_identity(identity) => Ok(()),
+                    _ => Err(format!("invalid tag: unexpected {line}")),
+                },
+            }
+        }
+    }
+}
+
 /// Git creates objects of various types and allows references to them as args in exchangeable ways
 #[derive(Debug, Clone)]
 pub struct OgitObject {
@@ -52,6 +147,82 @@ impl Display for OgitObject {
     }
 }
 
+/// What can be wrong with an object read from the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectError {
+    /// The header is not `<type> <size>\0`
+    BadHeader(String),
+    /// The header declares another size than the content has
+    SizeMismatch { declared: usize, actual: usize },
+    /// The content does not hash to the id it was stored under
+    CorruptObject { expected: String, actual: String },
+}
+
+impl Display for ObjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadHeader(why) => write!(f, "bad object header: {why}"),
+            Self::SizeMismatch { declared, actual } => write!(
+                f,
+                "object size mismatch: header says {declared} bytes, content has {actual}"
+            ),
+            Self::CorruptObject { expected, actual } => write!(
+                f,
+                "corrupt object: expected {expected}, content hashes to {actual}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ObjectError {}
+
+impl From<ObjectError> for std::io::Error {
+    fn from(error: ObjectError) -> Self {
+        Self::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// The bytes of the full hex object id `id`.
+fn parse_id(id: &str) -> Option<Vec<u8>> {
+    if !is_object_id(id) {
+        return None;
+    }
+    (0..id.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&id[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Split a stored object into its type and content, checking the header against the content.
+fn parse_header(content: &[u8]) -> Result<(OgitObjectType, &[u8]), ObjectError> {
+    let bad = |why: &str| ObjectError::BadHeader(why.to_string());
+    let end = content
+        .iter()
+        .position(|c| *c == b'\0')
+        .ok_or_else(|| bad("missing NUL after the header"))?;
+    let header = std::str::from_utf8(&content[..end]).map_err(|_| bad("not UTF-8"))?;
+    let (variant, size) = header
+        .split_once(' ')
+        .ok_or_else(|| bad(&format!("'{header}' has no size")))?;
+    let variant = variant
+        .parse()
+        .map_err(|_| bad(&format!("unknown type '{variant}'")))?;
+    if size.is_empty() || !size.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(bad(&format!("invalid size '{size}'")));
+    }
+    let declared = size
+        .parse()
+        .map_err(|_| bad(&format!("invalid size '{size}'")))?;
+    let data = &content[end + 1..];
+    if declared != data.len() {
+        return Err(ObjectError::SizeMismatch {
+            declared,
+            actual: data.len(),
+        });
+    }
+    Ok((variant, data))
+}
+
 impl OgitObject {
     pub fn new(data: &[u8], variant: OgitObjectType) -> Self {
         let mut hasher = Sha1::new();
@@ -67,26 +238,36 @@ impl OgitObject {
             variant,
         }
     }
-    /// Initialize object from object database
-    pub fn from_bytes(content: &[u8]) -> Self {
-        let mut hasher = Sha1::new();
-        hasher.update(content);
-        let hash_result = hasher.finalize().to_vec();
-
-        // have to read out the object type from content header
-        let (mut header, data) = content.splitn(2, |c| *c == b'\0').collect_tuple().unwrap();
-        let mut header_content = String::new();
-        header.read_to_string(&mut header_content).unwrap();
-        let (variant, _) = header_content.split_once(' ').unwrap();
-        let mut header = header_content.to_string();
-        header.push('\0');
-        let variant = variant.parse().unwrap();
-
-        Self {
-            id: hash_result,
+    /// Initialize object from object database, its header checked against the content
+    ///
+    /// The object is taken to be `id`, the id it is stored under. Only with `verify` is the content
+    /// hashed, failing unless it really hashes to `id`.
+    pub fn from_bytes(content: &[u8], id: &str, verify: bool) -> Result<Self, ObjectError> {
+        let (variant, data) = parse_header(content)?;
+        let known = (!verify).then(|| parse_id(id)).flatten();
+        let Some(id) = known else {
+            let object = Self::new(data, variant);
+            object.verify(id)?;
+            return Ok(object);
+        };
+        Ok(Self {
+            id,
+            header: format!("{} {}\0", variant, data.len()),
             data: data.to_owned(),
-            header,
             variant,
+        })
+    }
+
+    /// Fail unless the object hashes to `expected`, the full id it was asked for.
+    pub fn verify(&self, expected: &str) -> Result<(), ObjectError> {
+        let actual = self.hex_string();
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(ObjectError::CorruptObject {
+                expected: expected.to_string(),
+                actual,
+            })
         }
     }
 
@@ -130,6 +311,70 @@ mod tests {
         );
     }
     #[test]
+    fn test_from_bytes() {
+        let id = "95d09f2b10159347eece71399a7e2e907ea3df4f";
+        let other = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+        let from_bytes = |content: &[u8], verify| OgitObject::from_bytes(content, id, verify);
+        let object = from_bytes(b"blob 11\0hello world", true).unwrap();
+        assert_eq!(object.variant, OgitObjectType::Blob);
+        assert_eq!(object.hex_string(), id);
+        assert!(object.verify(id).is_ok());
+        assert!(object.verify("95d09f2b").is_err());
+        assert_eq!(
+            OgitObject::from_bytes(b"blob 11\0hello world", other, true).unwrap_err(),
+            ObjectError::CorruptObject {
+                expected: other.to_string(),
+                actual: id.to_string(),
+            }
+        );
+        // without verifying, the content is trusted to be what it is stored as
+        let trusted = OgitObject::from_bytes(b"blob 11\0hello world", other, false).unwrap();
+        assert_eq!(trusted.hex_string(), other);
+        assert_eq!(trusted.file_content(), b"blob 11\0hello world");
+        assert_eq!(
+            from_bytes(b"blob 12\0hello world", false).unwrap_err(),
+            ObjectError::SizeMismatch {
+                declared: 12,
+                actual: 11
+            }
+        );
+        assert!(from_bytes(b"blob 11 hello world", false).is_err());
+        assert!(from_bytes(b"blub 11\0hello world", false).is_err());
+        assert!(from_bytes(b"blob -1\0hello world", false).is_err());
+    }
+    #[test]
+    fn test_check_syntax() {
+        let id = "95d09f2b10159347eece71399a7e2e907ea3df4f";
+        let tree = format!("100644 blob {id} hello world.txt\n040000 tree {id} src\n");
+        assert!(check_syntax(&OgitObjectType::Tree, tree.as_bytes()).is_ok());
+        assert!(check_syntax(&OgitObjectType::Tree, b"100644 blob nope a\n").is_err());
+        let commit = format!(
+            "tree {id}\nparent {id}\nauthor A <a@b> 1700000000 +0100\n\
+             committer A <a@b> 1700000000 -0500\n\nmessage\n"
+        );
+        assert!(check_syntax(&OgitObjectType::Commit, commit.as_bytes()).is_ok());
+        let no_committer = format!("tree {id}\nauthor A <a@b> 1700000000 +0100\n\nmessage\n");
+        assert!(check_syntax(&OgitObjectType::Commit, no_committer.as_bytes()).is_err());
+        let tag = format!("object {id}\ntype commit\ntag v1\n\nrelease\n");
+        assert!(check_syntax(&OgitObjectType::Tag, tag.as_bytes()).is_ok());
+        assert!(check_syntax(&OgitObjectType::Blob, &[0xff, 0]).is_ok());
+    }
+    #[test]
+    fn test_written_commits_pass_check_syntax() {
+        use crate::data::{base, filesystem, testrepo::TestRepo};
+        let _repo = TestRepo::new();
+        let blob = filesystem::hash_object(b"hello\n", None).unwrap();
+        let tree = format!("100644 blob {} hello.txt\n", blob.hex_string());
+        let tree = filesystem::hash_object(tree.as_bytes(), Some(OgitObjectType::Tree)).unwrap();
+        let tree = tree.hex_string();
+        let root = base::commit_tree(&tree, &[], Some("root\n".to_string())).unwrap();
+        let parents = [root.hex_string()];
+        let child = base::commit_tree(&tree, &parents, Some("child\n".to_string())).unwrap();
+        for commit in [root, child] {
+            assert_eq!(check_syntax(&OgitObjectType::Commit, &commit.data), Ok(()));
+        }
+    }
+    #[test]
     fn test_hashing() {
         let mut hasher = Sha1::new();
         hasher.update(b"hello world");
@@ -150,6 +395,7 @@ mod tests {
             (OgitObjectType::Blob, "blob"),
             (OgitObjectType::Tree, "tree"),
             (OgitObjectType::Commit, "commit"),
+            (OgitObjectType::Tag, "tag"),
         ];
         for (variant, expected) in variants_with_expected {
             assert_eq!(format!("{variant}"), expected);
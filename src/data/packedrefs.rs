@@ -0,0 +1,193 @@
+//! Refs packed together in `.ogit/packed-refs`, instead of one file per ref.
+//!
+//! Each line holds `<id> <refname>`, sorted by name, and a ref pointing at a tag is followed by
+//! a `^<id>` line with the object the tag leads to in the end. A loose ref file always wins over
+//! the packed entry of the same name, so refs are updated by writing loose files, and the packed
+//! entry only needs removing when the ref is deleted.
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use super::{
+    filesystem::{self, LockFile},
+    tags,
+};
+
+const PACKED_REFS_FILE: &str = "packed-refs";
+
+/// Git writes this header, peeled lines are there for every tag and refs come sorted.
+const HEADER: &str = "# pack-refs with: peeled fully-peeled sorted ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub id: String,
+    /// What the tag `id` leads to, when `id` is a tag
+    pub peeled: Option<String>,
+}
+
+/// The content of the packed-refs file, by ref name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackedRefs {
+    refs: BTreeMap<String, PackedRef>,
+}
+
+impl FromStr for PackedRefs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut refs = BTreeMap::new();
+        let mut last: Option<String> = None;
+        for line in s.lines() {
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            let invalid = || format!("unexpected line in {PACKED_REFS_FILE}: {line}");
+            if let Some(peeled) = line.strip_prefix('^') {
+                let entry: &mut PackedRef = last
+                    .as_ref()
+                    .and_then(|name| refs.get_mut(name))
+                    .ok_or_else(invalid)?;
+                entry.peeled = Some(peeled.to_string());
+                continue;
+            }
+            let (id, name) = line.split_once(' ').ok_or_else(invalid)?;
+            refs.insert(
+                name.to_string(),
+                PackedRef {
+                    name: name.to_string(),
+                    id: id.to_string(),
+                    peeled: None,
+                },
+            );
+            last = Some(name.to_string());
+        }
+        Ok(Self { refs })
+    }
+}
+
+impl Display for PackedRefs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for entry in self.refs.values() {
+            writeln!(f, "{} {}", entry.id, entry.name)?;
+            if let Some(peeled) = &entry.peeled {
+                writeln!(f, "^{peeled}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PackedRefs {
+    /// Read the packed-refs file, empty when there is none.
+    pub fn load() -> std::io::Result<Self> {
+        match std::fs::read_to_string(filesystem::ogit_path(PACKED_REFS_FILE)?) {
+            Ok(content) => content.parse().map_err(std::io::Error::other),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PackedRef> {
+        self.refs.get(name)
+    }
+
+    /// Every packed ref, sorted by name.
+    pub fn refs(&self) -> impl Iterator<Item = &PackedRef> {
+        self.refs.values()
+    }
+
+    /// Replace the packed-refs file by these refs, while holding `lock`.
+    fn save(&self, mut lock: LockFile) -> std::io::Result<()> {
+        lock.write(self.to_string().as_bytes())?;
+        lock.commit()
+    }
+}
+
+fn lock() -> std::io::Result<LockFile> {
+    LockFile::acquire(&filesystem::ogit_path(PACKED_REFS_FILE)?)
+}
+
+/// The packed entry of the ref `name`, if it has one.
+pub fn find(name: &str) -> std::io::Result<Option<PackedRef>> {
+    Ok(PackedRefs::load()?.get(name).cloned())
+}
+
+/// Drop `names` from the packed-refs file, leaving it untouched when none of them are packed.
+pub fn remove(names: &[&str]) -> std::io::Result<()> {
+    let lock = lock()?;
+    let mut packed = PackedRefs::load()?;
+    let before = packed.refs.len();
+    packed
+        .refs
+        .retain(|name, _| !names.contains(&name.as_str()));
+    if packed.refs.len() == before {
+        return Ok(());
+    }
+    packed.save(lock)
+}
+
+/// Move loose refs into the packed-refs file, the way `pack-refs` does.
+///
+/// Only tags are packed unless `all` is set, symbolic refs never are. With `prune` the loose
+/// files of packed refs are removed, unless they moved while packing.
+pub fn pack(all: bool, prune: bool) -> std::io::Result<()> {
+    let lock = lock()?;
+    let mut packed = PackedRefs::load()?;
+    let mut loose = Vec::new();
+    for name in filesystem::list_loose_refs()? {
+        let wanted = all || name.starts_with("refs/tags/");
+        if !wanted || filesystem::read_symbolic_ref(&name)?.is_some() {
+            continue;
+        }
+        let Some(id) = filesystem::read_ref(&name)? else {
+            continue;
+        };
+        let peeled = Some(tags::peel(&id)?).filter(|peeled| *peeled != id);
+        packed.refs.insert(
+            name.clone(),
+            PackedRef {
+                name: name.clone(),
+                id: id.clone(),
+                peeled,
+            },
+        );
+        loose.push((name, id));
+    }
+    packed.save(lock)?;
+    if prune {
+        for (name, id) in loose {
+            let lock = LockFile::acquire(&filesystem::ogit_path(&name)?)?;
+            // a ref moved since it was packed keeps its loose file, which wins anyway
+            if filesystem::read_ref(&name)?.as_deref() == Some(id.as_str()) {
+                lock.delete()?;
+                filesystem::remove_empty_ref_dirs(&name)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packed_refs_round_trip() {
+        let content = "# pack-refs with: peeled fully-peeled sorted \n\
+            95d09f2b10159347eece71399a7e2e907ea3df4f refs/heads/main\n\
+            7568ca340baac3ade265f02b6da0149295f472d1 refs/tags/v1\n\
+            ^d1e4f461440025bd2a99b9e2d466a43ea33e7056\n";
+        let packed = content.parse::<PackedRefs>().unwrap();
+        let tag = packed.get("refs/tags/v1").unwrap();
+        assert_eq!(tag.id, "7568ca340baac3ade265f02b6da0149295f472d1");
+        assert_eq!(
+            tag.peeled.as_deref(),
+            Some("d1e4f461440025bd2a99b9e2d466a43ea33e7056")
+        );
+        assert_eq!(packed.get("refs/heads/main").unwrap().peeled, None);
+        assert_eq!(packed.to_string(), content);
+        assert!("^d1e4f461440025bd2a99b9e2d466a43ea33e7056\n"
+            .parse::<PackedRefs>()
+            .is_err());
+    }
+}
@@ -0,0 +1,611 @@
+//! Unified diff output, in the format `git diff` produces, and reading such patches back.
+use std::{fmt::Write as _, path::PathBuf};
+
+use super::{
+    binary::{self, BinaryPatch},
+    diff::{DiffEntry, DiffFormat, DiffStatus, NULL_ID},
+    worddiff,
+    xdiff::{self, Edit},
+};
+
+/// Number of unchanged lines shown around every change.
+pub const DEFAULT_CONTEXT: usize = 3;
+/// Length ids are shortened to on the `index` line.
+const ABBREV_LENGTH: usize = 7;
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+fn abbreviate(id: &str) -> &str {
+    &id[..ABBREV_LENGTH.min(id.len())]
+}
+
+/// The extended header lines of a patch, from `diff --git` up to (but excluding) `---`.
+///
+/// `full_index` writes complete ids on the `index` line, which binary patches need to be applied.
+pub fn format_header(entry: &DiffEntry, full_index: bool) -> String {
+    let old_path = entry
+        .old
+        .as_ref()
+        .map_or(entry.path(), |s| s.path.as_path());
+    let new_path = entry.path();
+    let mut header = format!(
+        "diff --git a/{} b/{}\n",
+        old_path.display(),
+        new_path.display()
+    );
+    let old_mode = entry.old.as_ref().map(|s| s.mode.as_str());
+    let new_mode = entry.new.as_ref().map(|s| s.mode.as_str());
+    match entry.status {
+        DiffStatus::Added => {
+            writeln!(header, "new file mode {}", new_mode.unwrap()).unwrap();
+        }
+        DiffStatus::Deleted => {
+            writeln!(header, "deleted file mode {}", old_mode.unwrap()).unwrap();
+        }
+        DiffStatus::Renamed | DiffStatus::Copied => {
+            let verb = if entry.status == DiffStatus::Renamed {
+                "rename"
+            } else {
+                "copy"
+            };
+            writeln!(header, "similarity index {}%", entry.score.unwrap_or(0)).unwrap();
+            writeln!(header, "{verb} from {}", old_path.display()).unwrap();
+            writeln!(header, "{verb} to {}", new_path.display()).unwrap();
+        }
+        DiffStatus::Modified | DiffStatus::TypeChanged => (),
+    }
+    let mode_changed = old_mode.is_some() && new_mode.is_some() && old_mode != new_mode;
+    if mode_changed {
+        writeln!(header, "old mode {}", old_mode.unwrap()).unwrap();
+        writeln!(header, "new mode {}", new_mode.unwrap()).unwrap();
+    }
+    let old_id = entry.old.as_ref().map_or(NULL_ID, |s| s.id.as_str());
+    let new_id = entry.new.as_ref().map_or(NULL_ID, |s| s.id.as_str());
+    if old_id != new_id {
+        if full_index {
+            write!(header, "index {old_id}..{new_id}").unwrap();
+        } else {
+            write!(
+                header,
+                "index {}..{}",
+                abbreviate(old_id),
+                abbreviate(new_id)
+            )
+            .unwrap();
+        }
+        // the mode is only repeated here when it did not get its own lines above
+        match (old_mode, new_mode) {
+            (Some(old), Some(new)) if old == new => write!(header, " {old}").unwrap(),
+            _ => (),
+        }
+        header.push('\n');
+    }
+    header
+}
+
+fn push_line(out: &mut String, prefix: char, line: &[u8]) {
+    out.push(prefix);
+    out.push_str(&String::from_utf8_lossy(line));
+    if !line.ends_with(b"\n") {
+        out.push('\n');
+        out.push_str(NO_NEWLINE_MARKER);
+        out.push('\n');
+    }
+}
+
+/// Render the hunks of an edit script between `old_lines` and `new_lines`.
+pub fn format_hunks(
+    old_lines: &[&[u8]],
+    new_lines: &[&[u8]],
+    edits: &[Edit],
+    context: usize,
+) -> String {
+    let mut out = String::new();
+    for hunk in xdiff::hunks(edits, context) {
+        out.push_str(&hunk.header());
+        out.push('\n');
+        for edit in &hunk.edits {
+            match *edit {
+                Edit::Equal { old, .. } => push_line(&mut out, ' ', old_lines[old]),
+                Edit::Delete { old } => push_line(&mut out, '-', old_lines[old]),
+                Edit::Insert { new } => push_line(&mut out, '+', new_lines[new]),
+            }
+        }
+    }
+    out
+}
+
+fn side_names(entry: &DiffEntry) -> (String, String) {
+    let old_name = entry.old.as_ref().map_or_else(
+        || "/dev/null".to_string(),
+        |s| format!("a/{}", s.path.display()),
+    );
+    let new_name = entry.new.as_ref().map_or_else(
+        || "/dev/null".to_string(),
+        |s| format!("b/{}", s.path.display()),
+    );
+    (old_name, new_name)
+}
+
+/// Full patch for a single changed path, given the content of both sides.
+///
+/// Binary content only gets a notice, unless `format.binary` asks for an applicable binary patch.
+pub fn format_patch(
+    entry: &DiffEntry,
+    old: &[u8],
+    new: &[u8],
+    is_binary: bool,
+    format: &DiffFormat,
+) -> String {
+    let mut out = format_header(entry, is_binary && format.binary);
+    let (old_name, new_name) = side_names(entry);
+    if is_binary {
+        if old == new {
+            // pure renames and mode changes have no content to show
+        } else if format.binary {
+            out.push_str(&binary::format_binary_patch(old, new));
+        } else {
+            writeln!(out, "Binary files {old_name} and {new_name} differ").unwrap();
+        }
+        return out;
+    }
+    let old_lines = xdiff::split_lines(old);
+    let new_lines = xdiff::split_lines(new);
+    let edits = xdiff::diff(&old_lines, &new_lines);
+    let hunks = match format.word_diff {
+        Some(mode) => {
+            worddiff::format_word_diff_hunks(&old_lines, &new_lines, &edits, format.context, mode)
+        }
+        None => format_hunks(&old_lines, &new_lines, &edits, format.context),
+    };
+    if hunks.is_empty() {
+        return out;
+    }
+    writeln!(out, "--- {old_name}").unwrap();
+    writeln!(out, "+++ {new_name}").unwrap();
+    out.push_str(&hunks);
+    out
+}
+
+/// A line of a hunk read from a patch, with its line terminator unless the file lacked one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchLine {
+    Context(Vec<u8>),
+    Removed(Vec<u8>),
+    Added(Vec<u8>),
+}
+
+impl PatchLine {
+    fn content_mut(&mut self) -> &mut Vec<u8> {
+        match self {
+            Self::Context(line) | Self::Removed(line) | Self::Added(line) => line,
+        }
+    }
+}
+
+/// A hunk read from a patch, line numbers are one based as written in its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchHunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<PatchLine>,
+}
+
+impl PatchHunk {
+    /// Lines the hunk expects to find in the file.
+    pub fn preimage(&self) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                PatchLine::Context(l) | PatchLine::Removed(l) => Some(l.as_slice()),
+                PatchLine::Added(_) => None,
+            })
+            .collect()
+    }
+
+    /// Lines the hunk leaves in place of its preimage.
+    pub fn postimage(&self) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                PatchLine::Context(l) | PatchLine::Added(l) => Some(l.as_slice()),
+                PatchLine::Removed(_) => None,
+            })
+            .collect()
+    }
+
+    /// The hunk undoing this one.
+    pub fn reversed(&self) -> Self {
+        Self {
+            old_start: self.new_start,
+            old_len: self.new_len,
+            new_start: self.old_start,
+            new_len: self.old_len,
+            lines: self
+                .lines
+                .iter()
+                .map(|line| match line {
+                    PatchLine::Context(l) => PatchLine::Context(l.clone()),
+                    PatchLine::Removed(l) => PatchLine::Added(l.clone()),
+                    PatchLine::Added(l) => PatchLine::Removed(l.clone()),
+                })
+                .collect(),
+        }
+    }
+
+    /// The hunk as it appears in a patch.
+    pub fn to_text(&self) -> String {
+        let range = |start: usize, len: usize| match len {
+            1 => format!("{start}"),
+            _ => format!("{start},{len}"),
+        };
+        let mut out = format!(
+            "@@ -{} +{} @@\n",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        );
+        for line in &self.lines {
+            match line {
+                PatchLine::Context(l) => push_line(&mut out, ' ', l),
+                PatchLine::Removed(l) => push_line(&mut out, '-', l),
+                PatchLine::Added(l) => push_line(&mut out, '+', l),
+            }
+        }
+        out
+    }
+}
+
+/// The changes to a single path read from a patch.
+///
+/// A missing old path means the file is created, a missing new path that it is deleted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    pub old_path: Option<PathBuf>,
+    pub new_path: Option<PathBuf>,
+    pub old_mode: Option<String>,
+    pub new_mode: Option<String>,
+    /// Ids from the `index` line, possibly abbreviated
+    pub old_id: Option<String>,
+    pub new_id: Option<String>,
+    pub is_rename: bool,
+    pub is_copy: bool,
+    pub hunks: Vec<PatchHunk>,
+    pub binary: Option<BinaryPatch>,
+    /// Set for `Binary files ... differ` notices, which cannot be applied
+    pub binary_without_data: bool,
+}
+
+impl FilePatch {
+    /// The path the patch is reported under.
+    pub fn path(&self) -> PathBuf {
+        self.new_path
+            .clone()
+            .or_else(|| self.old_path.clone())
+            .unwrap_or_default()
+    }
+
+    /// The patch undoing this one.
+    pub fn reversed(&self) -> Self {
+        Self {
+            old_path: self.new_path.clone(),
+            new_path: self.old_path.clone(),
+            old_mode: self.new_mode.clone(),
+            new_mode: self.old_mode.clone(),
+            old_id: self.new_id.clone(),
+            new_id: self.old_id.clone(),
+            is_rename: self.is_rename,
+            is_copy: self.is_copy,
+            hunks: self.hunks.iter().map(PatchHunk::reversed).collect(),
+            binary: self.binary.as_ref().map(BinaryPatch::reversed),
+            binary_without_data: self.binary_without_data,
+        }
+    }
+}
+
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\n")
+        .map_or(line, |l| l.strip_suffix(b"\r").unwrap_or(l))
+}
+
+/// Drop the first `strip` components of a path written in a patch, `/dev/null` means no file.
+fn strip_path(name: &str, strip: usize, line_number: usize) -> Result<Option<PathBuf>, String> {
+    // timestamps written by other diff tools follow a tab
+    let name = name.split('\t').next().unwrap_or_default().trim_end();
+    if name == "/dev/null" {
+        return Ok(None);
+    }
+    let components = name
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>();
+    if components.len() <= strip {
+        return Err(format!(
+            "cannot strip {strip} components from '{name}' at line {line_number}"
+        ));
+    }
+    Ok(Some(components[strip..].iter().collect()))
+}
+
+/// Name of both sides of a `diff --git a/x b/x` line, only known when they are equal.
+fn git_line_name(rest: &str, strip: usize) -> Option<PathBuf> {
+    rest.match_indices(' ').find_map(|(i, _)| {
+        let old = strip_path(&rest[..i], strip, 0).ok()??;
+        let new = strip_path(&rest[i + 1..], strip, 0).ok()??;
+        (old == new).then_some(new)
+    })
+}
+
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize, usize)> {
+    let rest = line.strip_prefix("@@ -")?;
+    let (ranges, _) = rest.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let (old_start, old_len) = parse_range(old)?;
+    let (new_start, new_len) = parse_range(new)?;
+    Some((old_start, old_len, new_start, new_len))
+}
+
+/// Read the hunk starting at `lines[*i]`, leaving `i` after it.
+fn parse_hunk(lines: &[&[u8]], i: &mut usize) -> Result<PatchHunk, String> {
+    let header = String::from_utf8_lossy(trim_line_end(lines[*i]));
+    let (old_start, old_len, new_start, new_len) = parse_hunk_header(&header)
+        .ok_or_else(|| format!("corrupt hunk header at line {}", *i + 1))?;
+    *i += 1;
+    let mut hunk = PatchHunk {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+        lines: Vec::new(),
+    };
+    let (mut old_left, mut new_left) = (old_len, new_len);
+    while old_left > 0 || new_left > 0 || lines.get(*i).is_some_and(|l| l.starts_with(b"\\")) {
+        let line = lines
+            .get(*i)
+            .ok_or_else(|| format!("patch ends in the middle of a hunk at line {}", *i))?;
+        let corrupt = || format!("corrupt patch at line {}", *i + 1);
+        match line.first() {
+            // some mailers strip the space off empty context lines
+            Some(b' ') | Some(b'\n') if old_left > 0 && new_left > 0 => {
+                let content = line.get(1..).filter(|c| !c.is_empty()).unwrap_or(b"\n");
+                hunk.lines.push(PatchLine::Context(content.to_vec()));
+                old_left -= 1;
+                new_left -= 1;
+            }
+            Some(b'-') if old_left > 0 => {
+                hunk.lines.push(PatchLine::Removed(line[1..].to_vec()));
+                old_left -= 1;
+            }
+            Some(b'+') if new_left > 0 => {
+                hunk.lines.push(PatchLine::Added(line[1..].to_vec()));
+                new_left -= 1;
+            }
+            // `\ No newline at end of file` applies to the line before it
+            Some(b'\\') => {
+                let previous = hunk.lines.last_mut().ok_or_else(corrupt)?.content_mut();
+                if previous.ends_with(b"\n") {
+                    previous.pop();
+                }
+            }
+            _ => return Err(corrupt()),
+        }
+        *i += 1;
+    }
+    Ok(hunk)
+}
+
+/// Read every file patch in `text`, dropping `strip` leading components from the paths.
+///
+/// Anything before, between or after the diffs, such as an email, is skipped.
+pub fn parse_patch(text: &[u8], strip: usize) -> Result<Vec<FilePatch>, String> {
+    let lines = xdiff::split_lines(text);
+    let mut patches = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = String::from_utf8_lossy(trim_line_end(lines[i])).into_owned();
+        let mut patch = FilePatch::default();
+        let mut has_content_names = false;
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            let name = git_line_name(rest, strip);
+            patch.old_path.clone_from(&name);
+            patch.new_path = name;
+            let (mut created, mut deleted) = (false, false);
+            i += 1;
+            while let Some(header) = lines.get(i) {
+                let header = String::from_utf8_lossy(trim_line_end(header)).into_owned();
+                let path = |value: &str| Some(PathBuf::from(value));
+                if let Some(mode) = header.strip_prefix("old mode ") {
+                    patch.old_mode = Some(mode.to_string());
+                } else if let Some(mode) = header.strip_prefix("new mode ") {
+                    patch.new_mode = Some(mode.to_string());
+                } else if let Some(mode) = header.strip_prefix("deleted file mode ") {
+                    patch.old_mode = Some(mode.to_string());
+                    deleted = true;
+                } else if let Some(mode) = header.strip_prefix("new file mode ") {
+                    patch.new_mode = Some(mode.to_string());
+                    created = true;
+                } else if let Some(from) = header.strip_prefix("rename from ") {
+                    patch.old_path = path(from);
+                    patch.is_rename = true;
+                } else if let Some(to) = header.strip_prefix("rename to ") {
+                    patch.new_path = path(to);
+                    patch.is_rename = true;
+                } else if let Some(from) = header.strip_prefix("copy from ") {
+                    patch.old_path = path(from);
+                    patch.is_copy = true;
+                } else if let Some(to) = header.strip_prefix("copy to ") {
+                    patch.new_path = path(to);
+                    patch.is_copy = true;
+                } else if let Some(ids) = header.strip_prefix("index ") {
+                    let (ids, mode) = ids.split_once(' ').unwrap_or((ids, ""));
+                    let (old_id, new_id) = ids
+                        .split_once("..")
+                        .ok_or_else(|| format!("corrupt index line at line {}", i + 1))?;
+                    patch.old_id = Some(old_id.to_string());
+                    patch.new_id = Some(new_id.to_string());
+                    if !mode.is_empty() {
+                        patch.old_mode.get_or_insert_with(|| mode.to_string());
+                        patch.new_mode.get_or_insert_with(|| mode.to_string());
+                    }
+                } else if !header.starts_with("similarity index ")
+                    && !header.starts_with("dissimilarity index ")
+                {
+                    break;
+                }
+                i += 1;
+            }
+            if created {
+                patch.old_path = None;
+            }
+            if deleted {
+                patch.new_path = None;
+            }
+            if patch.old_path.is_none() && patch.new_path.is_none() {
+                return Err(format!("git diff header lacks filename at line {i}"));
+            }
+        } else if !(line.starts_with("--- ")
+            && lines.get(i + 1).is_some_and(|l| l.starts_with(b"+++ ")))
+        {
+            i += 1;
+            continue;
+        } else {
+            // a plain unified diff, named only by its `---` and `+++` lines
+            has_content_names = true;
+        }
+
+        let line = lines
+            .get(i)
+            .map(|l| String::from_utf8_lossy(trim_line_end(l)));
+        match line.as_deref() {
+            Some(old) if old.starts_with("--- ") => {
+                let new = String::from_utf8_lossy(trim_line_end(
+                    lines.get(i + 1).copied().unwrap_or_default(),
+                ))
+                .into_owned();
+                let new = new
+                    .strip_prefix("+++ ")
+                    .ok_or_else(|| format!("missing +++ line at line {}", i + 2))?;
+                let old_name = strip_path(&old[4..], strip, i + 1)?;
+                let new_name = strip_path(new, strip, i + 2)?;
+                if has_content_names || !patch.is_rename && !patch.is_copy {
+                    patch.old_path = old_name;
+                    patch.new_path = new_name;
+                }
+                i += 2;
+                while lines.get(i).is_some_and(|l| l.starts_with(b"@@ -")) {
+                    patch.hunks.push(parse_hunk(&lines, &mut i)?);
+                }
+            }
+            Some("GIT binary patch") => {
+                i += 1;
+                patch.binary = Some(binary::parse_binary_patch(&lines, &mut i)?);
+            }
+            Some(notice) if notice.starts_with("Binary files ") => {
+                patch.binary_without_data = true;
+                i += 1;
+            }
+            _ => (),
+        }
+        patches.push(patch);
+    }
+    Ok(patches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::diff::DiffSide;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_format_patch_modified() {
+        let side = |id: &str| DiffSide {
+            path: PathBuf::from("hello.txt"),
+            mode: "100644".to_string(),
+            id: id.to_string(),
+        };
+        let entry = DiffEntry {
+            status: DiffStatus::Modified,
+            old: Some(side("3b18e512dba79e4c8300dd08aeb37f8e728b8dad")),
+            new: Some(side("95d09f2b10159347eece71399a7e2e907ea3df4f")),
+            score: None,
+        };
+        let format = DiffFormat {
+            context: DEFAULT_CONTEXT,
+            ..Default::default()
+        };
+        let patch = format_patch(&entry, b"hello\nworld\n", b"hello\nworld", false, &format);
+        assert_eq!(
+            patch,
+            "diff --git a/hello.txt b/hello.txt\n\
+             index 3b18e51..95d09f2 100644\n\
+             --- a/hello.txt\n\
+             +++ b/hello.txt\n\
+             @@ -1,2 +1,2 @@\n \
+             hello\n\
+             -world\n\
+             +world\n\
+             \\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_patch() {
+        let text = b"From: someone\n\
+            Subject: [PATCH] change things\n\
+            \n\
+            diff --git a/hello.txt b/hello.txt\n\
+            index 3b18e51..95d09f2 100644\n\
+            --- a/hello.txt\n\
+            +++ b/hello.txt\n\
+            @@ -1,2 +1,2 @@\n \
+            hello\n\
+            -world\n\
+            +world\n\
+            \\ No newline at end of file\n\
+            diff --git a/old.txt b/new.txt\n\
+            similarity index 100%\n\
+            rename from old.txt\n\
+            rename to new.txt\n\
+            diff --git a/gone.txt b/gone.txt\n\
+            deleted file mode 100644\n\
+            index 3b18e51..0000000\n\
+            --- a/gone.txt\n\
+            +++ /dev/null\n\
+            @@ -1 +0,0 @@\n\
+            -bye\n\
+            -- \n\
+            2.40.0\n";
+        let patches = parse_patch(text, 1).unwrap();
+        assert_eq!(patches.len(), 3);
+        assert_eq!(patches[0].path(), PathBuf::from("hello.txt"));
+        assert_eq!(patches[0].old_mode.as_deref(), Some("100644"));
+        assert_eq!(
+            patches[0].hunks[0].postimage(),
+            vec![b"hello\n".as_slice(), b"world".as_slice()]
+        );
+        assert!(patches[1].is_rename);
+        assert_eq!(patches[1].old_path, Some(PathBuf::from("old.txt")));
+        assert_eq!(patches[1].new_path, Some(PathBuf::from("new.txt")));
+        assert!(patches[1].hunks.is_empty());
+        assert_eq!(patches[2].new_path, None);
+        assert_eq!(patches[2].hunks[0].preimage(), vec![b"bye\n".as_slice()]);
+    }
+
+    #[test]
+    fn test_reversed_hunk_text() {
+        let text = b"--- a/x\n+++ b/x\n@@ -1,2 +1 @@\n keep\n-drop\n";
+        let patches = parse_patch(text, 1).unwrap();
+        assert_eq!(
+            patches[0].hunks[0].reversed().to_text(),
+            "@@ -1 +1,2 @@\n keep\n+drop\n"
+        );
+    }
+}
@@ -1,4 +1,10 @@
 /// Collection of high-level functions for interacting with the database.
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
 use crate::data::prelude::*;
 
 use self::{
@@ -6,52 +12,333 @@ use self::{
     objects::{OgitObject, OgitObjectType},
 };
 
-pub fn commit(message: Option<String>) -> Result<OgitObject, std::io::Error> {
+/// Commit what the index holds, after taking in the tracked files of the working tree with `all`.
+pub fn commit(message: Option<String>, all: bool) -> Result<OgitObject, std::io::Error> {
+    let mut index = index::Index::load()?;
+    let mut unresolved = merge::unresolved_paths(&index)?;
+    if all && unresolved.is_empty() {
+        index.add_from_worktree(|_, tracked| tracked)?;
+    }
+    if unresolved.is_empty() {
+        unresolved = index.unmerged_paths();
+    }
+    if !unresolved.is_empty() {
+        let paths = unresolved
+            .iter()
+            .map(|path| format!("\t{}\n", path.display()))
+            .collect::<String>();
+        return Err(std::io::Error::other(format!(
+            "Committing is not possible because of unresolved conflicts in:\n{paths}Fix them up in the work tree, then add them before committing."
+        )));
+    }
     // for now let commit-tree handle the stdin message
-    let tree = base::write_tree(None)?;
-    let tree_id = tree.hex_string();
+    let message = match message {
+        Some(message) => Some(message),
+        None => merge::prepared_message()?,
+    };
+    let tree_id = index.write_tree()?;
     let head_parent = filesystem::read_head_file()?.trim().to_owned();
 
-    // porcelain commit is linear, so only one parent
+    // porcelain commit is linear, unless it concludes a merge
     let parents: Vec<String> = if head_parent.is_empty() {
         vec![]
     } else {
-        vec![head_parent]
+        vec![head_parent.clone()]
     };
+    let parents = parents
+        .into_iter()
+        .chain(merge::merge_head()?)
+        .collect::<Vec<_>>();
     let commit = base::commit_tree(&tree_id, &parents, message)?;
-    filesystem::update_head_file(&commit)?;
+    let kind = match parents.len() {
+        0 => "commit (initial)",
+        1 => "commit",
+        _ => "commit (merge)",
+    };
+    let subject = OgitCommit::get(&commit.hex_string())?.subject();
+    // fails when another commit moved HEAD since it was read
+    refs::move_head(
+        &commit.hex_string(),
+        &head_parent,
+        &format!("{kind}: {subject}"),
+    )?;
+    merge::clear_state()?;
+    index.save()?;
     Ok(commit)
 }
 
-/// Read the tree identified by `commit` and move update HEAD.
-pub fn checkout(commit: &str) {
-    let tobe_set_commit = match OgitCommit::get(commit) {
-        Ok(x) => x,
-        Err(e) => {
-            eprintln!("Error while fetching commit id {commit}: {e}");
-            return;
+/// Take the working tree content of `pathspecs` into the index, removed files included.
+///
+/// A pathspec names a file or a directory holding files, `.` is the whole working tree.
+pub fn add(pathspecs: &[PathBuf]) -> Result<(), std::io::Error> {
+    // `./dir/` and `dir` are the same pathspec, `.` becomes empty and matches everything
+    let pathspecs = pathspecs
+        .iter()
+        .map(|spec| {
+            spec.components()
+                .filter(|component| *component != Component::CurDir)
+                .collect::<PathBuf>()
+        })
+        .collect::<Vec<_>>();
+    let mut index = index::Index::load()?;
+    let mut matched = BTreeSet::new();
+    index.add_from_worktree(|path, _| {
+        let spec = pathspecs.iter().find(|spec| path.starts_with(spec));
+        matched.extend(spec.cloned());
+        spec.is_some()
+    })?;
+    if let Some(spec) = pathspecs.iter().find(|spec| !matched.contains(*spec)) {
+        return Err(std::io::Error::other(format!(
+            "pathspec '{}' did not match any files",
+            spec.display()
+        )));
+    }
+    index.save()
+}
+
+/// Remove a file of the working tree, along with the directories it leaves empty.
+pub fn remove_worktree_file(path: &Path) -> Result<(), std::io::Error> {
+    match fs::remove_file(path) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e),
+    }
+    for directory in path.ancestors().skip(1) {
+        if directory.as_os_str().is_empty() || fs::remove_dir(directory).is_err() {
+            break;
         }
+    }
+    Ok(())
+}
+
+/// Paths whose working tree content differs from `head_tree`, untracked files included.
+pub fn local_changes(head_tree: Option<&str>) -> Result<BTreeSet<PathBuf>, std::io::Error> {
+    let worktree = base::write_tree(None, false)?.hex_string();
+    let options = diff::DiffOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    Ok(diff::diff_trees(head_tree, Some(&worktree), &options)?
+        .iter()
+        .map(|entry| entry.path().to_path_buf())
+        .collect())
+}
+
+/// Trees `diff` compares when given at most one revision, the way git picks them.
+///
+/// Without `cached` the working tree is compared against `rev`, or against the index when there is
+/// none. With it the index is compared against `rev`, HEAD by default. Only tracked paths take
+/// part, untracked files are never shown.
+pub fn diff_sides(
+    rev: Option<&str>,
+    cached: bool,
+) -> Result<(Option<String>, String), std::io::Error> {
+    let index = index::Index::load()?;
+    let old = match rev {
+        Some(rev) => Some(revision::resolve_tree(rev)?),
+        None if cached => match filesystem::read_head_file()?.as_str() {
+            "" => None,
+            _ => Some(revision::resolve_tree("HEAD")?),
+        },
+        None => Some(index.hash_tree()?),
     };
-    let tree_object =
-        match filesystem::get_object(&tobe_set_commit.tree, Some(OgitObjectType::Tree)) {
-            Ok(obj) => obj,
-            Err(e) => {
-                eprintln!(
-                    "Error while readig tree of id {}: {e}",
-                    &tobe_set_commit.tree
-                );
-                return;
+    let new = if cached {
+        index.hash_tree()?
+    } else {
+        index.worktree()?.hash_tree()?
+    };
+    Ok((old, new))
+}
+
+/// Refuse to let `operation` touch `paths` when some of them hold changes that are not committed.
+pub fn check_local_changes<'a>(
+    head_tree: Option<&str>,
+    paths: impl Iterator<Item = &'a Path>,
+    operation: &str,
+) -> Result<(), std::io::Error> {
+    let changed = local_changes(head_tree)?;
+    let overwritten = paths
+        .filter(|path| changed.contains(*path))
+        .map(|path| format!("\t{}\n", path.display()))
+        .collect::<String>();
+    if overwritten.is_empty() {
+        return Ok(());
+    }
+    Err(std::io::Error::other(format!(
+        "Your local changes to the following files would be overwritten by {operation}:\n{overwritten}Please commit your changes or stash them before you {operation}.\nAborting"
+    )))
+}
+
+/// Move the working tree and the index from `old_tree` to `new_tree`, touching only what differs.
+///
+/// Files outside the difference, tracked or not, are left alone. Nothing is touched when local
+/// changes would be overwritten, `operation` names the command in that error.
+pub fn update_working_tree(
+    old_tree: Option<&str>,
+    new_tree: &str,
+    operation: &str,
+) -> Result<(), std::io::Error> {
+    let options = diff::DiffOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    let changes = diff::diff_trees(old_tree, Some(new_tree), &options)?;
+    check_local_changes(old_tree, changes.iter().map(|c| c.path()), operation)?;
+    for change in changes {
+        match &change.new {
+            Some(side) => {
+                let object = filesystem::get_object(&side.id, Some(OgitObjectType::Blob))?;
+                filesystem::write_worktree_file(&side.path, &object.data, &side.mode)?;
             }
-        };
-    match base::read_tree(&tobe_set_commit.tree) {
-        Ok(()) => (),
-        Err(e) => {
-            eprintln!("Error while writing tree to directory: {e}");
-            return;
+            None => remove_worktree_file(change.path())?,
         }
-    };
-    match filesystem::update_head_file(&tree_object) {
-        Ok(()) => (),
-        Err(e) => eprintln!("Error while updating HEAD file: {e}"),
+    }
+    index::Index::from_tree(new_tree)?.save()
+}
+
+/// Move HEAD to `commit`, updating the working tree and the index to its tree.
+pub fn checkout(commit: &str) {
+    let switched = revision::resolve(commit).and_then(|id| {
+        let target = OgitCommit::get(&id)?;
+        let head = filesystem::read_head_file()?;
+        let head_tree = match head.as_str() {
+            "" => None,
+            head => Some(OgitCommit::get(head)?.tree),
+        };
+        update_working_tree(head_tree.as_deref(), &target.tree, "checkout")?;
+        let from = match filesystem::read_symbolic_ref("HEAD")? {
+            Some(branch) => refs::shorten(&branch).to_string(),
+            None => head.clone(),
+        };
+        // HEAD itself moves, leaving whatever branch it was on
+        let mut transaction = refs::RefTransaction::new();
+        transaction.no_deref = true;
+        let old = if head.is_empty() {
+            diff::NULL_ID
+        } else {
+            &head
+        };
+        transaction.update("HEAD", &id, Some(old));
+        transaction.commit(Some(&format!("checkout: moving from {from} to {commit}")))
+    });
+    if let Err(e) = switched {
+        eprintln!("Error while checking out {commit}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testrepo::TestRepo;
+
+    fn committed_paths(commit: &OgitObject) -> Vec<PathBuf> {
+        let tree = OgitCommit::get(&commit.hex_string()).unwrap().tree;
+        index::Index::from_tree(&tree)
+            .unwrap()
+            .entries()
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_commit_takes_the_index() {
+        let repo = TestRepo::new();
+        repo.write("staged.txt", "one\n");
+        repo.write("unstaged.txt", "one\n");
+        let first = repo.commit_all("first");
+        repo.write("staged.txt", "two\n");
+        repo.write("unstaged.txt", "two\n");
+        repo.write("untracked.txt", "new\n");
+        add(&[PathBuf::from("staged.txt")]).unwrap();
+
+        let commit = commit(Some("second\n".to_string()), false).unwrap();
+        let parsed = OgitCommit::get(&commit.hex_string()).unwrap();
+        assert_eq!(parsed.parents, vec![first]);
+        let tree = index::Index::from_tree(&parsed.tree).unwrap();
+        let blob = |path: &str| {
+            let id = &tree.get(Path::new(path)).unwrap().id;
+            filesystem::get_object(id, None).unwrap().data
+        };
+        assert_eq!(blob("staged.txt"), b"two\n");
+        assert_eq!(blob("unstaged.txt"), b"one\n");
+        assert!(tree.get(Path::new("untracked.txt")).is_none());
+    }
+
+    #[test]
+    fn test_commit_all_takes_tracked_files_only() {
+        let repo = TestRepo::new();
+        repo.write("kept.txt", "one\n");
+        repo.write("removed.txt", "one\n");
+        repo.commit_all("first");
+        repo.write("kept.txt", "two\n");
+        fs::remove_file("removed.txt").unwrap();
+        repo.write("untracked.txt", "new\n");
+
+        let commit = commit(Some("second\n".to_string()), true).unwrap();
+        assert_eq!(committed_paths(&commit), vec![PathBuf::from("kept.txt")]);
+    }
+
+    #[test]
+    fn test_commit_refuses_unmerged_paths() {
+        let repo = TestRepo::new();
+        repo.write("file.txt", "one\n");
+        repo.commit_all("first");
+        let mut index = index::Index::load().unwrap();
+        let mut theirs = index.get(Path::new("file.txt")).unwrap().clone();
+        theirs.stage = 3;
+        index.add(theirs);
+        index.save().unwrap();
+
+        let error = commit(Some("second\n".to_string()), false).unwrap_err();
+        assert!(error.to_string().contains("unresolved conflicts"));
+    }
+
+    fn changed_paths(rev: Option<&str>, cached: bool) -> Vec<PathBuf> {
+        let (old, new) = diff_sides(rev, cached).unwrap();
+        let options = diff::DiffOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        diff::diff_trees(old.as_deref(), Some(&new), &options)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.path().to_path_buf())
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_sides() {
+        let repo = TestRepo::new();
+        repo.write("staged.txt", "one\n");
+        repo.write("unstaged.txt", "one\n");
+        repo.commit_all("first");
+        repo.write("staged.txt", "two\n");
+        add(&[PathBuf::from("staged.txt")]).unwrap();
+        repo.write("unstaged.txt", "two\n");
+        repo.write("untracked.txt", "new\n");
+        let objects = filesystem::list_object_ids().unwrap();
+
+        assert_eq!(
+            changed_paths(None, false),
+            vec![PathBuf::from("unstaged.txt")]
+        );
+        assert_eq!(changed_paths(None, true), vec![PathBuf::from("staged.txt")]);
+        assert_eq!(
+            changed_paths(Some("HEAD"), false),
+            vec![PathBuf::from("staged.txt"), PathBuf::from("unstaged.txt")]
+        );
+        assert_eq!(filesystem::list_object_ids().unwrap(), objects);
+    }
+
+    #[test]
+    fn test_add_rejects_unmatched_pathspec() {
+        let repo = TestRepo::new();
+        repo.write("dir/file.txt", "one\n");
+        add(&[PathBuf::from("./dir/")]).unwrap();
+        assert!(index::Index::load()
+            .unwrap()
+            .get(Path::new("dir/file.txt"))
+            .is_some());
+        assert!(add(&[PathBuf::from("missing")]).is_err());
     }
 }
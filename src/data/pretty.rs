@@ -0,0 +1,434 @@
+//! Showing commits the way `log --pretty` and `log --format` ask for.
+//!
+//! The builtin formats follow git's layouts, from a single `oneline` line to `raw`, which shows
+//! the commit headers as stored. A format string is copied as it is, with placeholders like `%h`
+//! or `%an` replaced by parts of the commit.
+use std::{fmt::Write, str::FromStr};
+
+use super::{
+    commits::OgitCommit,
+    mailbox,
+    time::{self, DateFormat, DateTime, FixedOffset},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PrettyFormat {
+    /// Id and subject on a single line
+    Oneline,
+    /// Id, author and subject
+    Short,
+    /// Id, author, author date and message
+    #[default]
+    Medium,
+    /// Id, author, committer and message
+    Full,
+    /// Id, author and committer with their dates, and message
+    Fuller,
+    /// The commit as stored
+    Raw,
+    /// Headers and message of a patch email
+    Email,
+    /// A string of placeholders
+    Format {
+        template: String,
+        /// Whether the newline goes between commits instead of after each one, as with `format:`
+        separator: bool,
+    },
+}
+
+impl FromStr for PrettyFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(template) = s.strip_prefix("format:") {
+            return Ok(Self::Format {
+                template: template.to_string(),
+                separator: true,
+            });
+        }
+        if let Some(template) = s.strip_prefix("tformat:") {
+            return Ok(Self::Format {
+                template: template.to_string(),
+                separator: false,
+            });
+        }
+        match s {
+            "oneline" => Ok(Self::Oneline),
+            "short" => Ok(Self::Short),
+            "medium" => Ok(Self::Medium),
+            "full" => Ok(Self::Full),
+            "fuller" => Ok(Self::Fuller),
+            "raw" => Ok(Self::Raw),
+            "email" => Ok(Self::Email),
+            // like git, anything with a placeholder is a format string
+            _ if s.contains('%') => Ok(Self::Format {
+                template: s.to_string(),
+                separator: false,
+            }),
+            _ => Err(format!("invalid --pretty format: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PrettyOptions {
+    pub format: PrettyFormat,
+    pub date: DateFormat,
+    /// Show abbreviated commit ids in the builtin formats
+    pub abbrev_commit: bool,
+}
+
+fn abbrev(id: &str) -> &str {
+    &id[..id.len().min(7)]
+}
+
+/// The name and the email of a `Name <email>` identity.
+fn split_identity(identity: &str) -> (&str, &str) {
+    match identity.split_once(" <") {
+        Some((name, email)) => (name, email.trim_end_matches('>')),
+        None => (identity, ""),
+    }
+}
+
+/// The message with every line indented by four spaces, as the builtin formats show it.
+fn indent(message: &str) -> String {
+    message
+        .trim_matches('\n')
+        .trim_end()
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                "\n".to_string()
+            } else {
+                format!("    {line}\n")
+            }
+        })
+        .collect()
+}
+
+/// The ANSI escape for a `%C(...)` color spec like `bold red` or `reset`.
+fn color(spec: &str) -> Option<String> {
+    let mut codes = Vec::new();
+    let mut colors = 0;
+    for word in spec.split_whitespace() {
+        let attribute = match word {
+            "reset" | "normal" => None,
+            "bold" => Some(1),
+            "dim" => Some(2),
+            "italic" => Some(3),
+            "ul" => Some(4),
+            "blink" => Some(5),
+            "reverse" => Some(7),
+            _ => {
+                let color = match word {
+                    "black" => 0,
+                    "red" => 1,
+                    "green" => 2,
+                    "yellow" => 3,
+                    "blue" => 4,
+                    "magenta" => 5,
+                    "cyan" => 6,
+                    "white" => 7,
+                    _ => return None,
+                };
+                // the first color is the foreground, the second the background
+                let base = if colors == 0 { 30 } else { 40 };
+                colors += 1;
+                Some(base + color)
+            }
+        };
+        codes.extend(attribute);
+    }
+    Some(format!(
+        "\x1b[{}m",
+        codes
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(";")
+    ))
+}
+
+/// Replace the placeholders of `template` with the parts of commit `id` they stand for.
+///
+/// Unknown placeholders are kept as they are.
+pub fn expand(
+    template: &str,
+    id: &str,
+    commit: &OgitCommit,
+    date: &DateFormat,
+    decorations: &[String],
+    now: &DateTime<FixedOffset>,
+) -> String {
+    let (author_name, author_email) = split_identity(&commit.author);
+    let (committer_name, committer_email) = split_identity(&commit.committer);
+    let person = |key: char, name: &str, email: &str, time: &DateTime<FixedOffset>| match key {
+        'n' => Some(name.to_string()),
+        'e' => Some(email.to_string()),
+        'd' => Some(date.format(time, now)),
+        'r' => Some(time::relative_date(time, now)),
+        't' => Some(time.timestamp().to_string()),
+        'i' => Some(DateFormat::Iso.format(time, now)),
+        _ => None,
+    };
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('%') {
+        out.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..];
+        let mut chars = placeholder.chars();
+        let (expansion, length) = match chars.next() {
+            Some('%') => (Some("%".to_string()), 1),
+            Some('n') => (Some("\n".to_string()), 1),
+            Some('H') => (Some(id.to_string()), 1),
+            Some('h') => (Some(abbrev(id).to_string()), 1),
+            Some('T') => (Some(commit.tree.clone()), 1),
+            Some('t') => (Some(abbrev(&commit.tree).to_string()), 1),
+            Some('P') => (Some(commit.parents.join(" ")), 1),
+            Some('p') => (
+                Some(
+                    commit
+                        .parents
+                        .iter()
+                        .map(|parent| abbrev(parent))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                1,
+            ),
+            Some('s') => (Some(commit.subject()), 1),
+            Some('b') => {
+                let body = commit.body();
+                (Some(if body.is_empty() { body } else { body + "\n" }), 1)
+            }
+            Some('B') => (
+                Some(String::from_utf8_lossy(&commit.message).into_owned()),
+                1,
+            ),
+            Some('d') if decorations.is_empty() => (Some(String::new()), 1),
+            Some('d') => (Some(format!(" ({})", decorations.join(", "))), 1),
+            Some('D') => (Some(decorations.join(", ")), 1),
+            Some('a') => (
+                chars
+                    .next()
+                    .and_then(|key| person(key, author_name, author_email, &commit.author_time)),
+                2,
+            ),
+            Some('c') => (
+                chars.next().and_then(|key| {
+                    person(key, committer_name, committer_email, &commit.committer_time)
+                }),
+                2,
+            ),
+            Some('C') => {
+                let named = ["red", "green", "blue", "reset"]
+                    .into_iter()
+                    .find(|name| placeholder[1..].starts_with(name));
+                if let Some(name) = named {
+                    (color(name), 1 + name.len())
+                } else if let Some(spec) = placeholder[1..]
+                    .strip_prefix('(')
+                    .and_then(|spec| spec.split_once(')'))
+                    .map(|(spec, _)| spec)
+                {
+                    // colors are always shown, so `auto` adds nothing
+                    let escape = if spec == "auto" {
+                        Some(String::new())
+                    } else {
+                        color(&spec.replace(',', " "))
+                    };
+                    (escape, spec.len() + 3)
+                } else {
+                    (None, 1)
+                }
+            }
+            _ => (None, 0),
+        };
+        match expansion {
+            Some(expansion) => {
+                out.push_str(&expansion);
+                rest = &placeholder[length..];
+            }
+            None => {
+                out.push('%');
+                rest = placeholder;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Commit `id` shown as `options` ask, with the names of the refs in `decorations`.
+///
+/// The builtin formats end with a newline, a format string ends where the string does.
+pub fn format_commit(
+    id: &str,
+    commit: &OgitCommit,
+    options: &PrettyOptions,
+    decorations: &[String],
+    now: &DateTime<FixedOffset>,
+) -> String {
+    let shown_id = if options.abbrev_commit {
+        abbrev(id)
+    } else {
+        id
+    };
+    let decoration = if decorations.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", decorations.join(", "))
+    };
+    let message = String::from_utf8_lossy(&commit.message);
+    let date = |time| options.date.format(time, now);
+    let mut out = String::new();
+    match &options.format {
+        PrettyFormat::Oneline => {
+            writeln!(out, "{shown_id}{decoration} {}", commit.subject()).unwrap();
+            return out;
+        }
+        PrettyFormat::Raw => {
+            writeln!(out, "commit {id}{decoration}").unwrap();
+            writeln!(out, "tree {}", commit.tree).unwrap();
+            for parent in &commit.parents {
+                writeln!(out, "parent {parent}").unwrap();
+            }
+            let raw = |time: &DateTime<FixedOffset>| DateFormat::Raw.format(time, now);
+            writeln!(out, "author {} {}", commit.author, raw(&commit.author_time)).unwrap();
+            writeln!(
+                out,
+                "committer {} {}",
+                commit.committer,
+                raw(&commit.committer_time)
+            )
+            .unwrap();
+            write!(out, "\n{}", indent(&message)).unwrap();
+            return out;
+        }
+        PrettyFormat::Email => {
+            mailbox::push_headers(
+                &mut out,
+                id,
+                &commit.author,
+                &commit.author_time.to_rfc2822(),
+                &format!("[PATCH] {}", commit.subject()),
+            );
+            let body = commit.body();
+            if !body.is_empty() {
+                write!(out, "\n{body}\n").unwrap();
+            }
+            return out;
+        }
+        PrettyFormat::Format { template, .. } => {
+            return expand(template, id, commit, &options.date, decorations, now);
+        }
+        PrettyFormat::Short | PrettyFormat::Medium | PrettyFormat::Full | PrettyFormat::Fuller => {}
+    }
+
+    writeln!(out, "commit {shown_id}{decoration}").unwrap();
+    if commit.parents.len() > 1 {
+        let parents = commit
+            .parents
+            .iter()
+            .map(|parent| abbrev(parent))
+            .collect::<Vec<_>>();
+        writeln!(out, "Merge: {}", parents.join(" ")).unwrap();
+    }
+    match options.format {
+        PrettyFormat::Short => {
+            writeln!(out, "Author: {}\n", commit.author).unwrap();
+            out.push_str(&indent(&commit.subject()));
+            return out;
+        }
+        PrettyFormat::Full => {
+            writeln!(out, "Author: {}", commit.author).unwrap();
+            writeln!(out, "Commit: {}", commit.committer).unwrap();
+        }
+        PrettyFormat::Fuller => {
+            writeln!(out, "Author:     {}", commit.author).unwrap();
+            writeln!(out, "AuthorDate: {}", date(&commit.author_time)).unwrap();
+            writeln!(out, "Commit:     {}", commit.committer).unwrap();
+            writeln!(out, "CommitDate: {}", date(&commit.committer_time)).unwrap();
+        }
+        _ => {
+            writeln!(out, "Author: {}", commit.author).unwrap();
+            writeln!(out, "Date:   {}", date(&commit.author_time)).unwrap();
+        }
+    }
+    write!(out, "\n{}", indent(&message)).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit() -> OgitCommit {
+        OgitCommit::new(
+            "4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string(),
+            vec![
+                "1111111111111111111111111111111111111111".to_string(),
+                "2222222222222222222222222222222222222222".to_string(),
+            ],
+            "A U Thor <author@example.com> 1112911993 -0700",
+            "C O Mitter <committer@example.com> 1112912053 -0700",
+            b"Merge the parser fixes\n\nThey were long overdue.\n".to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let now = DateTime::parse_from_rfc3339("2005-04-07T17:13:13-07:00").unwrap();
+        let id = "0123456789abcdef0123456789abcdef01234567";
+        let decorations = ["HEAD".to_string(), "tag: v1".to_string()];
+        let show = |template: &str| {
+            expand(
+                template,
+                id,
+                &commit(),
+                &DateFormat::Short,
+                &decorations,
+                &now,
+            )
+        };
+        assert_eq!(
+            show("%h %s%d"),
+            "0123456 Merge the parser fixes (HEAD, tag: v1)"
+        );
+        assert_eq!(
+            show("%an <%ae>%n%cn %ar"),
+            "A U Thor <author@example.com>\nC O Mitter 2 hours ago"
+        );
+        assert_eq!(show("%ad %p %t"), "2005-04-07 1111111 2222222 4b825dc");
+        assert_eq!(
+            show("%b|%D|100%%"),
+            "They were long overdue.\n|HEAD, tag: v1|100%"
+        );
+        assert_eq!(show("%C(bold red)x%Creset %Z"), "\x1b[1;31mx\x1b[m %Z");
+    }
+
+    #[test]
+    fn test_builtin_formats() {
+        let now = time::get_current_local().fixed_offset();
+        let id = "0123456789abcdef0123456789abcdef01234567";
+        let show = |format: &str| {
+            let options = PrettyOptions {
+                format: format.parse().unwrap(),
+                abbrev_commit: true,
+                ..Default::default()
+            };
+            format_commit(id, &commit(), &options, &[], &now)
+        };
+        assert_eq!(show("oneline"), "0123456 Merge the parser fixes\n");
+        assert_eq!(
+            show("medium"),
+            "commit 0123456\nMerge: 1111111 2222222\nAuthor: A U Thor <author@example.com>\n\
+             Date:   Thu Apr 7 15:13:13 2005 -0700\n\n    Merge the parser fixes\n\n    \
+             They were long overdue.\n"
+        );
+        assert!(show("fuller").contains("\nCommitDate: Thu Apr 7 15:14:13 2005 -0700\n"));
+        assert!(show("raw").starts_with("commit 0123456789abcdef0123456789abcdef01234567\ntree "));
+        assert!("nonsense".parse::<PrettyFormat>().is_err());
+    }
+}
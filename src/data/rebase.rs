@@ -0,0 +1,882 @@
+//! `og rebase`: replaying the commits of `upstream..HEAD` on top of a new base.
+//!
+//! The work is a todo list of commands, every commit is replayed with the cherry-pick machinery
+//! of [`sequencer::pick`]. With `-i` the list is handed to an editor first, so commits can be
+//! reordered, reworded, squashed or dropped. Progress is kept in `.ogit/rebase-merge`, so a run
+//! that stops can be continued, skipped past or aborted.
+use std::{fmt::Display, fs, path::PathBuf, process, str::FromStr};
+
+use super::{
+    base,
+    commits::OgitCommit,
+    config, filesystem,
+    graph::CommitGraph,
+    index::Index,
+    merge, porcelain,
+    refs::{self, RefTransaction},
+    revision,
+    sequencer::{self, Action, PickOptions, PickOutcome},
+    xmerge::ConflictStyle,
+};
+
+const STATE_DIR: &str = "rebase-merge";
+/// Message being edited by `reword` and `squash`.
+const EDIT_MESSAGE_FILE: &str = "COMMIT_EDITMSG";
+
+const TODO_HELP: &str = "
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# e, edit <commit> = use commit, but stop for amending
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\", but discard this commit's log message
+# x, exec <command> = run command (the rest of the line) using shell
+# b, break = stop here (continue rebase later with 'og rebase --continue')
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+#
+# If you remove a line here THAT COMMIT WILL BE LOST.
+#
+# However, if you remove everything, the rebase will be aborted.
+#
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Pick,
+    Reword,
+    Edit,
+    Squash,
+    Fixup,
+    Exec,
+    Break,
+    Drop,
+}
+
+impl Command {
+    /// Whether the command names a commit, rather than a shell command or nothing.
+    const fn takes_commit(self) -> bool {
+        !matches!(self, Self::Exec | Self::Break)
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Pick => "pick",
+            Self::Reword => "reword",
+            Self::Edit => "edit",
+            Self::Squash => "squash",
+            Self::Fixup => "fixup",
+            Self::Exec => "exec",
+            Self::Break => "break",
+            Self::Drop => "drop",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p" | "pick" => Ok(Self::Pick),
+            "r" | "reword" => Ok(Self::Reword),
+            "e" | "edit" => Ok(Self::Edit),
+            "s" | "squash" => Ok(Self::Squash),
+            "f" | "fixup" => Ok(Self::Fixup),
+            "x" | "exec" => Ok(Self::Exec),
+            "b" | "break" => Ok(Self::Break),
+            "d" | "drop" => Ok(Self::Drop),
+            _ => Err(format!("invalid command '{s}'")),
+        }
+    }
+}
+
+/// One line of the todo list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TodoItem {
+    pub command: Command,
+    /// The commit, or the shell command of `exec`
+    pub arg: String,
+    /// Subject of the commit, only there for whoever edits the list
+    pub subject: String,
+}
+
+impl Display for TodoItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.command)?;
+        for part in [&self.arg, &self.subject] {
+            if !part.is_empty() {
+                write!(f, " {part}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TodoItem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (command, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let command = command.parse::<Command>()?;
+        let rest = rest.trim();
+        let (arg, subject) = match command {
+            Command::Exec => (rest, ""),
+            Command::Break => ("", ""),
+            _ => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+        if arg.is_empty() && command != Command::Break {
+            return Err(format!("missing argument for {command}"));
+        }
+        Ok(Self {
+            command,
+            arg: arg.to_string(),
+            subject: subject.trim().to_string(),
+        })
+    }
+}
+
+/// Read a todo list, skipping comments and blank lines.
+pub fn parse_todo(text: &str) -> Result<Vec<TodoItem>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+fn format_todo(items: &[TodoItem]) -> String {
+    items.iter().map(|item| format!("{item}\n")).collect()
+}
+
+/// Move every `fixup! <subject>` and `squash! <subject>` commit right after the commit it names,
+/// turning its `pick` into a `fixup` or `squash`.
+pub fn autosquash(items: Vec<TodoItem>) -> Vec<TodoItem> {
+    let mut result: Vec<TodoItem> = Vec::new();
+    for mut item in items {
+        let marked = [("fixup! ", Command::Fixup), ("squash! ", Command::Squash)]
+            .into_iter()
+            .find_map(|(prefix, command)| Some((item.subject.strip_prefix(prefix)?, command)));
+        let target = marked.and_then(|(target, command)| {
+            let position = result.iter().position(|other| {
+                other.command.takes_commit()
+                    && (other.subject == target
+                        || other.subject.starts_with(target)
+                        || other.arg.starts_with(target))
+            })?;
+            Some((position, command))
+        });
+        match target {
+            Some((position, command)) => {
+                // after the target and the fixups already moved behind it
+                let insert_at = position
+                    + 1
+                    + result[position + 1..]
+                        .iter()
+                        .take_while(|other| {
+                            matches!(other.command, Command::Fixup | Command::Squash)
+                        })
+                        .count();
+                item.command = command;
+                result.insert(insert_at, item);
+            }
+            None => result.push(item),
+        }
+    }
+    result
+}
+
+/// Run `commands` after every commit that ends up in the rewritten history.
+pub fn add_exec(items: Vec<TodoItem>, commands: &[String]) -> Vec<TodoItem> {
+    let mut result = Vec::new();
+    let mut items = items.into_iter().peekable();
+    while let Some(item) = items.next() {
+        let replays = item.command.takes_commit() && item.command != Command::Drop;
+        result.push(item);
+        // squashes and fixups still change the commit before them
+        let continued = items
+            .peek()
+            .is_some_and(|next| matches!(next.command, Command::Fixup | Command::Squash));
+        if replays && !continued {
+            result.extend(commands.iter().map(|command| TodoItem {
+                command: Command::Exec,
+                arg: command.clone(),
+                subject: String::new(),
+            }));
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RebaseOptions {
+    /// Commit to replay onto, the upstream when not given
+    pub onto: Option<String>,
+    /// Let the todo list be edited before starting
+    pub interactive: bool,
+    pub autosquash: bool,
+    /// Shell commands to run after every replayed commit
+    pub exec: Vec<String>,
+    pub style: ConflictStyle,
+}
+
+fn state_path(name: &str) -> std::io::Result<PathBuf> {
+    filesystem::ogit_path(&format!("{STATE_DIR}/{name}"))
+}
+
+fn read_state(name: &str) -> std::io::Result<String> {
+    Ok(fs::read_to_string(state_path(name)?)?
+        .trim_end()
+        .to_string())
+}
+
+fn write_state(name: &str, value: &str) -> std::io::Result<()> {
+    fs::write(state_path(name)?, format!("{value}\n"))
+}
+
+fn remove_state(name: &str) -> std::io::Result<()> {
+    let path = state_path(name)?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Whether a stopped rebase is waiting to be continued.
+pub fn in_progress() -> std::io::Result<bool> {
+    Ok(filesystem::ogit_path(STATE_DIR)?.is_dir())
+}
+
+/// The branch being rebased, `None` when the rebase started on a detached HEAD.
+fn head_name() -> Option<String> {
+    read_state("head-name")
+        .ok()
+        .filter(|name| name.starts_with("refs/"))
+}
+
+fn ensure_in_progress() -> std::io::Result<()> {
+    if in_progress()? {
+        Ok(())
+    } else {
+        Err(std::io::Error::other("No rebase in progress?"))
+    }
+}
+
+fn read_todo() -> std::io::Result<Vec<TodoItem>> {
+    parse_todo(&fs::read_to_string(state_path("git-rebase-todo")?)?).map_err(std::io::Error::other)
+}
+
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(7)]
+}
+
+/// Run the editor on `path`, like git it gets the file name as its last argument.
+fn launch_editor(path: &std::path::Path) -> std::io::Result<()> {
+    let editor = config::editor();
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(&editor)
+        .arg(path)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "there was a problem with the editor '{editor}'"
+        )))
+    }
+}
+
+/// Let the user edit `message`, comment lines are dropped from the result.
+fn edit_message(message: &str) -> std::io::Result<String> {
+    let path = filesystem::ogit_path(EDIT_MESSAGE_FILE)?;
+    fs::write(
+        &path,
+        format!(
+            "{}\n\n# Lines starting with '#' will be ignored.\n",
+            message.trim_end()
+        ),
+    )?;
+    launch_editor(&path)?;
+    let edited = fs::read_to_string(&path)?
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let edited = edited.trim();
+    if edited.is_empty() {
+        return Err(std::io::Error::other(
+            "Aborting commit due to empty commit message.",
+        ));
+    }
+    Ok(format!("{edited}\n"))
+}
+
+/// Replace HEAD by a commit with `tree` and `message`, keeping its parents and author.
+///
+/// The move is logged as the rebase step `step`, like `squash`.
+fn amend_head(tree: &str, message: &str, step: &str) -> std::io::Result<()> {
+    let head_id = revision::resolve("HEAD")?;
+    let head = OgitCommit::get(&head_id)?;
+    let commit = base::commit_tree_with_author(
+        tree,
+        &head.parents,
+        message,
+        &head.author,
+        &head.author_time,
+    )?;
+    let subject = OgitCommit::get(&commit.hex_string())?.subject();
+    refs::move_head(
+        &commit.hex_string(),
+        &head_id,
+        &format!("rebase ({step}): {subject}"),
+    )
+}
+
+fn head_tree() -> std::io::Result<String> {
+    revision::resolve_tree("HEAD")
+}
+
+fn print_conflict_hints() {
+    eprintln!("hint: Resolve all conflicts manually, then run \"og rebase --continue\".");
+    eprintln!("hint: You can instead skip this commit: run \"og rebase --skip\".");
+    eprintln!(
+        "hint: To abort and get back to the state before \"og rebase\", run \"og rebase --abort\"."
+    );
+}
+
+/// Replay `id` on top of HEAD, moving HEAD straight to it when it already sits there.
+fn pick_onto_head(id: &str, options: &PickOptions) -> std::io::Result<PickOutcome> {
+    let commit = OgitCommit::get(id)?;
+    let head = filesystem::read_head_file()?;
+    if commit.parents == [head.clone()] {
+        porcelain::update_working_tree(Some(&head_tree()?), &commit.tree, "rebase")?;
+        let message = format!("rebase (pick): {}", commit.subject());
+        refs::move_head(id, &head, &message)?;
+        return Ok(PickOutcome::Committed(id.to_string()));
+    }
+    sequencer::pick(Action::Pick, id, options)
+}
+
+/// Meld the change of `id` into HEAD, `squash` keeps its message while `fixup` drops it.
+fn squash_into_head(id: &str, command: Command, options: &PickOptions) -> std::io::Result<bool> {
+    let head = OgitCommit::get(&revision::resolve("HEAD")?)?;
+    let commit = OgitCommit::get(id)?;
+    let mut message = String::from_utf8_lossy(&head.message)
+        .trim_end()
+        .to_string();
+    if command == Command::Squash {
+        message.push_str("\n\n");
+        message.push_str(String::from_utf8_lossy(&commit.message).trim_end());
+    }
+    message.push('\n');
+    let options = PickOptions {
+        no_commit: true,
+        ..options.clone()
+    };
+    if sequencer::pick(Action::Pick, id, &options)? == PickOutcome::Conflicted {
+        // the resolution gets melded in by --continue
+        write_state("amend", &revision::resolve("HEAD")?)?;
+        write_state("message", &message)?;
+        return Ok(false);
+    }
+    if command == Command::Squash {
+        message = edit_message(&message)?;
+    }
+    amend_head(
+        &Index::load()?.write_tree()?,
+        &message,
+        &command.to_string(),
+    )?;
+    Ok(true)
+}
+
+/// Work through the todo list until it is empty or a command stops.
+///
+/// Returns `false` when stopped, leaving the state for `--continue`, `--skip` or `--abort`.
+fn run() -> std::io::Result<bool> {
+    let options = PickOptions {
+        style: read_state("conflict-style")?.parse().unwrap_or_default(),
+        reflog_action: Some("rebase (pick)".to_string()),
+        ..Default::default()
+    };
+    loop {
+        let mut todo = read_todo()?;
+        if todo.is_empty() {
+            return finish().map(|()| true);
+        }
+        let item = todo.remove(0);
+        fs::write(state_path("git-rebase-todo")?, format_todo(&todo))?;
+        let mut done = fs::read_to_string(state_path("done")?).unwrap_or_default();
+        done.push_str(&format!("{item}\n"));
+        fs::write(state_path("done")?, done)?;
+
+        match item.command {
+            Command::Pick | Command::Reword | Command::Edit => {
+                let id = revision::resolve(&item.arg)?;
+                match pick_onto_head(&id, &options)? {
+                    PickOutcome::Conflicted => {
+                        write_state("stopped-sha", &id)?;
+                        print_conflict_hints();
+                        return Ok(false);
+                    }
+                    PickOutcome::Empty => {
+                        merge::reset_merge()?;
+                        println!(
+                            "dropping {} {} -- patch contents already upstream",
+                            short_id(&id),
+                            item.subject
+                        );
+                        continue;
+                    }
+                    PickOutcome::Committed(_) | PickOutcome::Applied => (),
+                }
+                if item.command == Command::Reword {
+                    let head = OgitCommit::get(&revision::resolve("HEAD")?)?;
+                    let message = edit_message(&String::from_utf8_lossy(&head.message))?;
+                    amend_head(&head.tree, &message, "reword")?;
+                }
+                if item.command == Command::Edit {
+                    write_state("amend", &revision::resolve("HEAD")?)?;
+                    println!("Stopped at {}... {}", short_id(&id), item.subject);
+                    println!("You can amend the commit now by changing the working tree.");
+                    println!(
+                        "Once you are satisfied with your changes, run\n\n  og rebase --continue\n"
+                    );
+                    return Ok(false);
+                }
+            }
+            Command::Squash | Command::Fixup => {
+                if !fs::read_to_string(state_path("done")?)?
+                    .lines()
+                    .filter_map(|line| line.parse::<TodoItem>().ok())
+                    .any(|done| {
+                        !matches!(done.command, Command::Exec | Command::Break | Command::Drop)
+                    })
+                {
+                    return Err(std::io::Error::other(format!(
+                        "cannot '{}' without a previous commit",
+                        item.command
+                    )));
+                }
+                let id = revision::resolve(&item.arg)?;
+                if !squash_into_head(&id, item.command, &options)? {
+                    print_conflict_hints();
+                    return Ok(false);
+                }
+            }
+            Command::Exec => {
+                println!("Executing: {}", item.arg);
+                let status = process::Command::new("sh")
+                    .arg("-c")
+                    .arg(&item.arg)
+                    .status()?;
+                if !status.success() {
+                    eprintln!("warning: execution failed: {}", item.arg);
+                    eprintln!("You can fix the problem, and then run\n\n  og rebase --continue\n");
+                    return Ok(false);
+                }
+            }
+            Command::Break => {
+                println!("Stopped at {}", short_id(&filesystem::read_head_file()?));
+                return Ok(false);
+            }
+            Command::Drop => (),
+        }
+    }
+}
+
+/// Move the rebased branch to where the detached HEAD ended up, and put HEAD back on it.
+fn finish() -> std::io::Result<()> {
+    let orig_head = read_state("orig-head")?;
+    let onto = read_state("onto")?;
+    let head = filesystem::read_head_file()?;
+    let Some(branch) = head_name() else {
+        fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+        println!("Successfully rebased and updated HEAD.");
+        return Ok(());
+    };
+    let mut transaction = RefTransaction::new();
+    transaction
+        .update(&branch, &head, Some(&orig_head))
+        .log_as(&format!("rebase (finish): {branch} onto {onto}"))
+        .update_symbolic("HEAD", &branch, Some(&head))
+        .log_as(&format!("rebase (finish): returning to {branch}"));
+    transaction.commit(None)?;
+    fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+    println!("Successfully rebased and updated {branch}.");
+    Ok(())
+}
+
+/// Start replaying `upstream..HEAD` onto `options.onto`, or onto `upstream` itself.
+pub fn start(upstream: &str, options: &RebaseOptions) -> std::io::Result<bool> {
+    if in_progress()? {
+        return Err(std::io::Error::other(
+            "It seems that there is already a rebase-merge directory.\nUse \"og rebase (--continue | --skip | --abort)\".",
+        ));
+    }
+    if merge::in_progress()? || sequencer::in_progress()? {
+        return Err(std::io::Error::other(
+            "cannot rebase while a merge, cherry-pick or revert is in progress",
+        ));
+    }
+    let head = revision::resolve("HEAD")?;
+    let head_tree = head_tree()?;
+    let tracked = Index::from_tree(&head_tree)?;
+    if porcelain::local_changes(Some(&head_tree))?
+        .iter()
+        .any(|path| tracked.get(path).is_some())
+    {
+        return Err(std::io::Error::other(
+            "cannot rebase: You have unstaged changes.\nPlease commit or stash them.",
+        ));
+    }
+    let upstream_id = revision::resolve(upstream)?;
+    let onto_name = options.onto.as_deref().unwrap_or(upstream);
+    let onto = revision::resolve(onto_name)?;
+
+    // merges are flattened away, like git does by default
+    let commits = revision::walk_range(&head, Some(&upstream_id))?
+        .into_iter()
+        .rev()
+        .filter(|(_, commit)| commit.parents.len() <= 1)
+        .collect::<Vec<_>>();
+    let mut graph = CommitGraph::load()?;
+    let based_on_onto = match commits.first() {
+        Some((_, first)) => first.parents.first() == Some(&onto),
+        None => head == onto,
+    };
+    if !options.interactive
+        && options.exec.is_empty()
+        && based_on_onto
+        && graph.is_ancestor(&onto, &head)?
+    {
+        graph.save()?;
+        println!("Current branch HEAD is up to date.");
+        return Ok(true);
+    }
+    graph.save()?;
+
+    let mut todo = commits
+        .iter()
+        .map(|(id, commit)| TodoItem {
+            command: Command::Pick,
+            arg: short_id(id).to_string(),
+            subject: commit.subject(),
+        })
+        .collect::<Vec<_>>();
+    if options.autosquash {
+        todo = autosquash(todo);
+    }
+    todo = add_exec(todo, &options.exec);
+
+    fs::create_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+    write_state("orig-head", &head)?;
+    let head_name = filesystem::read_symbolic_ref("HEAD")?;
+    write_state("head-name", head_name.as_deref().unwrap_or("detached HEAD"))?;
+    write_state("onto", &onto)?;
+    write_state("conflict-style", &options.style.to_string())?;
+    let todo_path = state_path("git-rebase-todo")?;
+    fs::write(&todo_path, format_todo(&todo))?;
+    if options.interactive {
+        let count = todo
+            .iter()
+            .filter(|item| item.command.takes_commit())
+            .count();
+        let help = format!(
+            "\n# Rebase {}..{} onto {} ({count} command{})\n{TODO_HELP}",
+            short_id(&upstream_id),
+            short_id(&head),
+            short_id(&onto),
+            if count == 1 { "" } else { "s" },
+        );
+        fs::write(&todo_path, format_todo(&todo) + &help)?;
+        let edited = launch_editor(&todo_path).and_then(|()| read_todo());
+        match edited {
+            Ok(edited) if edited.is_empty() => {
+                fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+                println!("Nothing to do");
+                return Ok(true);
+            }
+            Ok(edited) => fs::write(&todo_path, format_todo(&edited))?,
+            Err(e) => {
+                fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+                return Err(e);
+            }
+        }
+    }
+
+    porcelain::update_working_tree(Some(&head_tree), &OgitCommit::get(&onto)?.tree, "rebase")?;
+    // the branch only moves once the rebase is done, HEAD is detached until then
+    let mut transaction = RefTransaction::new();
+    transaction.no_deref = true;
+    transaction.update("HEAD", &onto, Some(&head));
+    transaction.commit(Some(&format!("rebase (start): checkout {onto_name}")))?;
+    run()
+}
+
+/// Carry on after a stop, committing the resolved or amended working tree first.
+pub fn resume() -> std::io::Result<bool> {
+    ensure_in_progress()?;
+    // the resolution is what was added to the index, untracked files stay out of it
+    let index = Index::load()?;
+    if let Some(path) = index.unmerged_paths().first() {
+        return Err(std::io::Error::other(format!(
+            "{}: needs merge\nhint: fix the conflicts and run \"og add <path>\" before continuing",
+            path.display()
+        )));
+    }
+    let tree = index.write_tree()?;
+    let stopped_pick = filesystem::ogit_path(sequencer::CHERRY_PICK_HEAD)?.exists();
+    if state_path("amend")?.exists() {
+        if tree != head_tree()? || state_path("message")?.exists() {
+            let message = match read_state("message") {
+                Ok(message) => message,
+                Err(_) => {
+                    String::from_utf8_lossy(&OgitCommit::get(&revision::resolve("HEAD")?)?.message)
+                        .into_owned()
+                }
+            };
+            amend_head(&tree, &message, "continue")?;
+        }
+    } else if stopped_pick {
+        let id = read_state("stopped-sha")?;
+        let message = merge::prepared_message()?.unwrap_or_default();
+        if tree == head_tree()? {
+            println!("dropping {} -- nothing left to commit", short_id(&id));
+        } else {
+            sequencer::commit_result(Action::Pick, &id, &tree, &message, "rebase (continue)")?;
+        }
+    }
+    merge::clear_state()?;
+    for name in ["amend", "message", "stopped-sha"] {
+        remove_state(name)?;
+    }
+    run()
+}
+
+/// Drop the stopped commit, putting back HEAD's version of what it touched, and carry on.
+pub fn skip() -> std::io::Result<bool> {
+    ensure_in_progress()?;
+    merge::reset_merge()?;
+    for name in ["amend", "message", "stopped-sha"] {
+        remove_state(name)?;
+    }
+    run()
+}
+
+/// Stop and move HEAD and the working tree back to where they were before the rebase.
+pub fn abort() -> std::io::Result<()> {
+    ensure_in_progress()?;
+    merge::reset_merge()?;
+    let orig_head = read_state("orig-head")?;
+    let head = filesystem::read_head_file()?;
+    if orig_head != head {
+        let orig_tree = OgitCommit::get(&orig_head)?.tree;
+        porcelain::update_working_tree(Some(&head_tree()?), &orig_tree, "rebase")?;
+    }
+    // the branch never moved, only HEAD has to go back
+    match head_name() {
+        Some(branch) => {
+            let mut transaction = RefTransaction::new();
+            transaction.update_symbolic("HEAD", &branch, Some(&head));
+            transaction.commit(Some(&format!("rebase (abort): returning to {branch}")))?;
+        }
+        None if orig_head != head => {
+            refs::move_head(&orig_head, &head, "rebase (abort): returning to HEAD")?;
+        }
+        None => {}
+    }
+    fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::data::{reflog, testrepo::TestRepo};
+
+    /// A commit on `topic` writing `ours`, and one on `main` writing `theirs`, on the same base.
+    ///
+    /// HEAD is on `topic`, returns the ids of the topic commit and of main.
+    fn diverged(repo: &TestRepo, ours: &str, theirs: &str) -> (String, String) {
+        repo.write("file", "base\n");
+        let base = repo.commit_all("base");
+        repo.write(ours, "topic\n");
+        let topic = repo.commit_all("topic");
+        repo.rewind(&base);
+        repo.write(theirs, "main\n");
+        let main = repo.commit_all("main");
+        filesystem::write_ref("refs/heads/topic", &topic).unwrap();
+        let tree = |id: &str| OgitCommit::get(id).unwrap().tree;
+        porcelain::update_working_tree(Some(&tree(&main)), &tree(&topic), "checkout").unwrap();
+        let mut transaction = RefTransaction::new();
+        transaction.update_symbolic("HEAD", "refs/heads/topic", Some(&main));
+        transaction.commit(None).unwrap();
+        (topic, main)
+    }
+
+    fn messages(name: &str) -> Vec<String> {
+        reflog::read(name)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect()
+    }
+
+    fn item(command: Command, arg: &str, subject: &str) -> TodoItem {
+        TodoItem {
+            command,
+            arg: arg.to_string(),
+            subject: subject.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rebase_moves_the_branch_when_done() {
+        let repo = TestRepo::new();
+        let (topic, main) = diverged(&repo, "other", "file");
+        let log_before = messages("refs/heads/topic").len();
+
+        assert!(start("main", &RebaseOptions::default()).unwrap());
+        let head = filesystem::read_symbolic_ref("HEAD").unwrap();
+        assert_eq!(head.as_deref(), Some("refs/heads/topic"));
+        let rebased = filesystem::read_ref("refs/heads/topic").unwrap().unwrap();
+        assert_eq!(
+            OgitCommit::get(&rebased).unwrap().parents,
+            vec![main.clone()]
+        );
+        assert_eq!(
+            filesystem::read_ref("refs/heads/main").unwrap(),
+            Some(main.clone())
+        );
+
+        let branch_log = reflog::read("refs/heads/topic").unwrap();
+        assert_eq!(branch_log.len(), log_before + 1);
+        let finish = branch_log.last().unwrap();
+        assert_eq!(
+            (finish.old.as_str(), finish.new.as_str()),
+            (topic.as_str(), rebased.as_str())
+        );
+        assert_eq!(
+            finish.message,
+            format!("rebase (finish): refs/heads/topic onto {main}")
+        );
+        let head_log = messages("HEAD");
+        assert_eq!(
+            head_log[head_log.len() - 3..],
+            [
+                "rebase (start): checkout main".to_string(),
+                "rebase (pick): topic".to_string(),
+                "rebase (finish): returning to refs/heads/topic".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_continue_commits_the_index() {
+        let repo = TestRepo::new();
+        let (_, main) = diverged(&repo, "file", "file");
+
+        assert!(!start("main", &RebaseOptions::default()).unwrap());
+        // the branch stays put while HEAD is detached
+        assert_eq!(filesystem::read_symbolic_ref("HEAD").unwrap(), None);
+        repo.write("untracked.log", "noise\n");
+        repo.write("file", "resolved\n");
+        assert!(resume().is_err());
+        porcelain::add(&[PathBuf::from("file")]).unwrap();
+        assert!(resume().unwrap());
+
+        let head = filesystem::read_head_file().unwrap();
+        let commit = OgitCommit::get(&head).unwrap();
+        assert_eq!(commit.parents, vec![main]);
+        let tree = Index::from_tree(&commit.tree).unwrap();
+        assert!(tree.get(Path::new("file")).is_some());
+        assert!(tree.get(Path::new("untracked.log")).is_none());
+        assert!(messages("HEAD").contains(&"rebase (continue): topic".to_string()));
+        let branch = filesystem::read_symbolic_ref("HEAD").unwrap();
+        assert_eq!(branch.as_deref(), Some("refs/heads/topic"));
+    }
+
+    #[test]
+    fn test_abort_returns_to_the_branch() {
+        let repo = TestRepo::new();
+        let (topic, _) = diverged(&repo, "file", "file");
+
+        assert!(!start("main", &RebaseOptions::default()).unwrap());
+        abort().unwrap();
+        let head = filesystem::read_symbolic_ref("HEAD").unwrap();
+        assert_eq!(head.as_deref(), Some("refs/heads/topic"));
+        assert_eq!(filesystem::read_head_file().unwrap(), topic);
+        assert_eq!(std::fs::read_to_string("file").unwrap(), "topic\n");
+        let aborted = messages("HEAD").pop().unwrap();
+        assert_eq!(aborted, "rebase (abort): returning to refs/heads/topic");
+    }
+
+    #[test]
+    fn test_parse_todo() {
+        let todo = parse_todo(
+            "p 1234567 Add parser\n# comment\n\nexec cargo test --all\nbreak\nfixup abcdef0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            todo,
+            [
+                item(Command::Pick, "1234567", "Add parser"),
+                item(Command::Exec, "cargo test --all", ""),
+                item(Command::Break, "", ""),
+                item(Command::Fixup, "abcdef0", ""),
+            ]
+        );
+        assert_eq!(todo[0].to_string(), "pick 1234567 Add parser");
+        assert!(parse_todo("frobnicate 1234567").is_err());
+        assert!(parse_todo("pick").is_err());
+    }
+
+    #[test]
+    fn test_autosquash_moves_fixups_after_their_target() {
+        let todo = autosquash(vec![
+            item(Command::Pick, "1111111", "Add parser"),
+            item(Command::Pick, "2222222", "Add lexer"),
+            item(Command::Pick, "3333333", "fixup! Add parser"),
+            item(Command::Pick, "4444444", "squash! Add parser"),
+        ]);
+        let order = todo
+            .iter()
+            .map(|i| (i.command, i.arg.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            [
+                (Command::Pick, "1111111"),
+                (Command::Fixup, "3333333"),
+                (Command::Squash, "4444444"),
+                (Command::Pick, "2222222"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exec_runs_after_whole_commits() {
+        let todo = add_exec(
+            vec![
+                item(Command::Pick, "1111111", "a"),
+                item(Command::Fixup, "2222222", "b"),
+                item(Command::Drop, "3333333", "c"),
+            ],
+            &["make".to_string()],
+        );
+        let commands = todo.iter().map(|i| i.command).collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            [Command::Pick, Command::Fixup, Command::Exec, Command::Drop]
+        );
+    }
+}
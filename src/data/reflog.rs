@@ -0,0 +1,135 @@
+//! The reflog records every commit HEAD pointed at, so history rewritten by commands like rebase
+//! can still be found. Other refs, like `refs/stash`, keep a log of their own.
+//!
+//! Logs live under `.ogit/logs`, named after their ref, using git's format, one
+//! `<old id> <new id> <identity> <epoch> <offset><TAB><message>` line per move.
+use std::{fmt::Display, fs, io::Write as _, str::FromStr};
+
+use super::{config, diff::NULL_ID, filesystem, time};
+
+const LOG_DIR: &str = "logs";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// Where HEAD pointed before, [`NULL_ID`] when it did not point anywhere
+    pub old: String,
+    pub new: String,
+    pub identity: String,
+    pub time: time::DateTime<time::FixedOffset>,
+    pub message: String,
+}
+
+impl Display for ReflogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}\t{}",
+            self.old,
+            self.new,
+            self.identity,
+            self.time.timestamp(),
+            self.time.format("%z"),
+            self.message
+        )
+    }
+}
+
+impl FromStr for ReflogEntry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid reflog line '{s}'");
+        let (info, message) = s.split_once('\t').unwrap_or((s, ""));
+        let (old, rest) = info.split_once(' ').ok_or_else(invalid)?;
+        let (new, rest) = rest.split_once(' ').ok_or_else(invalid)?;
+        let mut parts = rest.rsplitn(3, ' ');
+        let (Some(offset), Some(epoch), Some(identity)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let time = time::DateTime::parse_from_str(&format!("{epoch} {offset}"), "%s %z")
+            .map_err(|_| invalid())?;
+        Ok(Self {
+            old: old.to_string(),
+            new: new.to_string(),
+            identity: identity.to_string(),
+            time,
+            message: message.to_string(),
+        })
+    }
+}
+
+fn log_path(name: &str) -> std::io::Result<std::path::PathBuf> {
+    filesystem::ogit_path(&format!("{LOG_DIR}/{name}"))
+}
+
+/// Record that HEAD moved from `old` (empty when it pointed nowhere) to `new`.
+pub fn append(old: &str, new: &str, message: &str) -> std::io::Result<()> {
+    append_ref("HEAD", old, new, message)
+}
+
+/// Record that the ref `name` moved from `old` (empty when it did not exist) to `new`.
+pub fn append_ref(name: &str, old: &str, new: &str, message: &str) -> std::io::Result<()> {
+    let entry = ReflogEntry {
+        old: if old.is_empty() { NULL_ID } else { old }.to_string(),
+        new: new.to_string(),
+        identity: config::AUTHOR.to_string(),
+        time: time::get_current_local().fixed_offset(),
+        // a message is a single line, whatever the commit message looked like
+        message: message.lines().next().unwrap_or_default().to_string(),
+    };
+    let path = log_path(name)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{entry}")
+}
+
+/// Entries of the log of `name`, oldest first, none when it has no log.
+pub fn read(name: &str) -> std::io::Result<Vec<ReflogEntry>> {
+    let content = match fs::read_to_string(log_path(name)?) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    content
+        .lines()
+        .map(|line| line.parse().map_err(std::io::Error::other))
+        .collect()
+}
+
+/// Replace the log of `name` by `entries`, removing it when there are none left.
+pub fn write(name: &str, entries: &[ReflogEntry]) -> std::io::Result<()> {
+    let path = log_path(name)?;
+    if entries.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let content = entries
+        .iter()
+        .map(|entry| format!("{entry}\n"))
+        .collect::<String>();
+    fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reflog_entry_round_trip() {
+        let line = "0000000000000000000000000000000000000000 95d09f2b10159347eece71399a7e2e907ea3df4f A U Thor <a@example.com> 1700000000 +0100\trebase (finish): returning";
+        let entry = line.parse::<ReflogEntry>().unwrap();
+        assert_eq!(entry.identity, "A U Thor <a@example.com>");
+        assert_eq!(entry.time.timestamp(), 1_700_000_000);
+        assert_eq!(entry.message, "rebase (finish): returning");
+        assert_eq!(entry.to_string(), line);
+    }
+}
@@ -0,0 +1,483 @@
+//! Refs by name: checking and expanding names, and updating several refs at once.
+//!
+//! A [`RefTransaction`] collects updates, each optionally checked against the id the ref is
+//! expected to be at, and only applies them once every check passed. [`NULL_ID`] stands for a
+//! ref that does not exist, as the expected id it means the ref must not exist yet and as the
+//! new id that the ref is deleted.
+//!
+//! Every ref is locked with its `<ref>.lock` file while the transaction checks and updates it,
+//! so two processes never move the same ref from under each other.
+use std::io::{BufRead, Write};
+
+use super::{
+    diff::NULL_ID,
+    filesystem::{self, LockFile},
+    packedrefs, reflog, revision,
+};
+
+/// Where a short name is looked for, in this order, same as git.
+const LOOKUP_RULES: [&str; 6] = [
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+    "refs/remotes/{}/HEAD",
+];
+
+/// Whether `name` is a valid ref name, following the rules of `git check-ref-format`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "@"
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.ends_with('.')
+        && !name.ends_with(".lock")
+        && !name.chars().any(|c| {
+            c.is_ascii_control() || matches!(c, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\')
+        })
+        && name
+            .split('/')
+            .all(|part| !part.is_empty() && !part.starts_with('.'))
+}
+
+/// Whether `name` names a ref kept right in the ogit directory, like `HEAD` or `ORIG_HEAD`.
+fn is_pseudo_ref(name: &str) -> bool {
+    name.ends_with("HEAD") && name.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+}
+
+/// The full name of the existing ref `name` is short for, e.g. `refs/heads/main` for `main`.
+pub fn expand(name: &str) -> std::io::Result<Option<String>> {
+    if !is_valid_name(name) {
+        return Ok(None);
+    }
+    for rule in LOOKUP_RULES {
+        let full = rule.replace("{}", name);
+        let allowed = rule != "{}" || full.starts_with("refs/") || is_pseudo_ref(&full);
+        if allowed && filesystem::read_ref(&full)?.is_some() {
+            return Ok(Some(full));
+        }
+    }
+    Ok(None)
+}
+
+/// `name` without the prefix that [`expand`] would add back, e.g. `main` for `refs/heads/main`.
+pub fn shorten(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+/// Whether changes to `name` are recorded in its reflog, like git's `core.logAllRefUpdates`.
+fn is_logged(name: &str) -> bool {
+    name == "HEAD"
+        || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RefUpdate {
+    name: String,
+    /// `None` only verifies the ref
+    new: Option<String>,
+    /// `None` does not check where the ref is
+    old: Option<String>,
+    /// Ref that `name` is made a symbolic ref to, instead of moving it to `new`
+    symbolic: Option<String>,
+    /// Logged instead of the message of the whole transaction
+    message: Option<String>,
+}
+
+/// Updates to several refs, applied all together or not at all.
+#[derive(Debug, Clone, Default)]
+pub struct RefTransaction {
+    updates: Vec<RefUpdate>,
+    /// Update symbolic refs themselves instead of the refs they point at
+    pub no_deref: bool,
+}
+
+impl RefTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Point `name` at `new`, when it is at `old`.
+    pub fn update(&mut self, name: &str, new: &str, old: Option<&str>) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            new: Some(new.to_string()),
+            old: old.map(ToString::to_string),
+            symbolic: None,
+            message: None,
+        });
+        self
+    }
+
+    /// Make `name` a symbolic ref pointing at the ref `target`, when it is at `old`.
+    ///
+    /// `name` itself is rewritten, the ref it pointed at before stays where it is. Its log gets
+    /// the move from `old` to the id of `target`, when `target` exists.
+    pub fn update_symbolic(&mut self, name: &str, target: &str, old: Option<&str>) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            new: None,
+            old: old.map(ToString::to_string),
+            symbolic: Some(target.to_string()),
+            message: None,
+        });
+        self
+    }
+
+    /// Log the update queued last with `message`, rather than with the one given to `commit`.
+    pub fn log_as(&mut self, message: &str) -> &mut Self {
+        if let Some(update) = self.updates.last_mut() {
+            update.message = Some(message.to_string());
+        }
+        self
+    }
+
+    /// Create `name` pointing at `new`, it must not exist yet.
+    pub fn create(&mut self, name: &str, new: &str) -> &mut Self {
+        self.update(name, new, Some(NULL_ID))
+    }
+
+    /// Delete `name`, when it is at `old`.
+    pub fn delete(&mut self, name: &str, old: Option<&str>) -> &mut Self {
+        self.update(name, NULL_ID, old)
+    }
+
+    /// Only check that `name` is at `old`, [`NULL_ID`] when it must not exist.
+    pub fn verify(&mut self, name: &str, old: &str) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            new: None,
+            old: Some(old.to_string()),
+            symbolic: None,
+            message: None,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Lock every ref and check its expected id, then apply every update.
+    ///
+    /// Updates are logged with `message` when there is one, with `None` the caller logs them
+    /// itself. Refs updated before one fails are put back where they were.
+    pub fn commit(self, message: Option<&str>) -> std::io::Result<()> {
+        let mut locked: Vec<(String, Option<String>, &RefUpdate, LockFile)> = Vec::new();
+        for update in &self.updates {
+            let named = update.name.starts_with("refs/") || is_pseudo_ref(&update.name);
+            if !named || !is_valid_name(&update.name) {
+                return Err(std::io::Error::other(format!(
+                    "refusing to update ref with bad name '{}'",
+                    update.name
+                )));
+            }
+            if let Some(symbolic) = &update.symbolic {
+                if !symbolic.starts_with("refs/") || !is_valid_name(symbolic) {
+                    return Err(std::io::Error::other(format!(
+                        "refusing to point {} outside of refs/",
+                        update.name
+                    )));
+                }
+            }
+            let target = if self.no_deref || update.symbolic.is_some() {
+                update.name.clone()
+            } else {
+                filesystem::resolve_symbolic_ref(&update.name)?
+            };
+            if locked.iter().any(|(other, ..)| *other == target) {
+                return Err(std::io::Error::other(format!(
+                    "multiple updates for ref '{target}' not allowed"
+                )));
+            }
+            let lock = LockFile::acquire(&filesystem::ogit_path(&target)?).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("cannot lock ref '{}': {e}", update.name))
+            })?;
+            // only read once nobody else can move it anymore
+            let current = filesystem::read_ref(&target)?;
+            check_old(&update.name, current.as_deref(), update.old.as_deref())?;
+            locked.push((target, current, update, lock));
+        }
+        // symbolic refs are rewritten, so rolling them back needs what they pointed at
+        let mut symrefs = Vec::new();
+        for (target, _, update, lock) in &mut locked {
+            if let Some(symbolic) = &update.symbolic {
+                symrefs.push((target.clone(), filesystem::read_symbolic_ref(target)?));
+                lock.write(format!("{}{symbolic}\n", filesystem::SYMREF_PREFIX).as_bytes())?;
+                continue;
+            }
+            match update.new.as_deref() {
+                Some(NULL_ID) | None => {}
+                Some(new) => lock.write(format!("{new}\n").as_bytes())?,
+            }
+        }
+
+        // deleted refs must not live on in packed-refs, their loose files are dropped next
+        let deleted = locked
+            .iter()
+            .filter(|(_, _, update, _)| update.new.as_deref() == Some(NULL_ID))
+            .map(|(target, current, ..)| (target.clone(), current.clone()))
+            .collect::<Vec<_>>();
+        if !deleted.is_empty() {
+            let names = deleted
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            packedrefs::remove(&names)?;
+        }
+
+        let mut applied = Vec::new();
+        let mut failure = None;
+        let mut pending = locked.into_iter();
+        for (target, current, update, lock) in pending.by_ref() {
+            let result = match update.new.as_deref() {
+                None if update.symbolic.is_some() => lock.commit(),
+                None => Ok(()),
+                Some(NULL_ID) => lock
+                    .delete()
+                    .and_then(|()| filesystem::remove_empty_ref_dirs(&target)),
+                Some(_) => lock.commit(),
+            };
+            if let Err(e) = result {
+                failure = Some(e);
+                break;
+            }
+            applied.push((target, current, update));
+        }
+        // releases the locks of the refs that were not updated
+        drop(pending);
+        if let Some(e) = failure {
+            let restored = applied
+                .iter()
+                .map(|(target, current, _)| (target.clone(), current.clone()))
+                .chain(deleted);
+            rollback(restored.collect(), &symrefs);
+            return Err(e);
+        }
+
+        for (target, current, update) in applied {
+            let message = update.message.as_deref().or(message);
+            if let (Some(symbolic), Some(message)) = (&update.symbolic, message) {
+                let old = current.as_deref().unwrap_or_default();
+                match filesystem::read_ref(symbolic)? {
+                    Some(new) if is_logged(&target) => {
+                        reflog::append_ref(&target, old, &new, message)?;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            let Some(new) = &update.new else {
+                continue;
+            };
+            if new == NULL_ID {
+                reflog::write(&target, &[])?;
+                continue;
+            }
+            let Some(message) = message else {
+                continue;
+            };
+            let old = current.as_deref().unwrap_or_default();
+            if is_logged(&target) {
+                reflog::append_ref(&target, old, new, message)?;
+            }
+            // moving the branch HEAD is on moves HEAD too
+            if update.name == "HEAD" && target != "HEAD" {
+                reflog::append(old, new, message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Put `refs` back at the ids they had before the transaction, as far as possible.
+///
+/// Deletions are put back even when they did not happen yet, their packed entries are gone.
+/// Refs found in `symrefs` were symbolic refs, pointing at the ref given there if any.
+fn rollback(refs: Vec<(String, Option<String>)>, symrefs: &[(String, Option<String>)]) {
+    for (target, current) in refs.into_iter().rev() {
+        let symref = symrefs
+            .iter()
+            .find(|(name, _)| *name == target)
+            .and_then(|(_, symref)| symref.as_deref());
+        // the first error is what gets reported, failing to undo cannot be helped
+        let _ = match (symref, current) {
+            (Some(symref), _) => filesystem::write_symbolic_ref(&target, symref),
+            (None, Some(id)) => filesystem::write_ref(&target, &id),
+            (None, None) => filesystem::delete_ref(&target),
+        };
+    }
+}
+
+/// Move HEAD, or the branch it is on, from `old` to `new`, failing when it moved meanwhile.
+///
+/// `old` is empty when HEAD did not point at a commit yet. The move is logged with `message`.
+pub fn move_head(new: &str, old: &str, message: &str) -> std::io::Result<()> {
+    let old = if old.is_empty() { NULL_ID } else { old };
+    let mut transaction = RefTransaction::new();
+    transaction.update("HEAD", new, Some(old));
+    transaction.commit(Some(message))
+}
+
+/// Fail unless the ref `name`, currently at `current`, is where `old` expects it.
+fn check_old(name: &str, current: Option<&str>, old: Option<&str>) -> std::io::Result<()> {
+    let error = |why: String| std::io::Error::other(format!("cannot lock ref '{name}': {why}"));
+    match (old, current) {
+        (None, _) => Ok(()),
+        (Some(NULL_ID), None) => Ok(()),
+        (Some(NULL_ID), Some(_)) => Err(error("reference already exists".to_string())),
+        (Some(_), None) => Err(error(format!("unable to resolve reference '{name}'"))),
+        (Some(old), Some(current)) if old == current => Ok(()),
+        (Some(old), Some(current)) => Err(error(format!("is at {current} but expected {old}"))),
+    }
+}
+
+/// Resolve the id argument `value` of `update-ref`, empty or the null id meaning no ref.
+pub fn resolve_value(value: &str) -> std::io::Result<String> {
+    match value {
+        "" | NULL_ID => Ok(NULL_ID.to_string()),
+        value => revision::resolve(value),
+    }
+}
+
+/// Run the `update-ref --stdin` commands read from `input`.
+///
+/// Commands are `update <ref> <new> [<old>]`, `create <ref> <new>`, `delete <ref> [<old>]` and
+/// `verify <ref> [<old>]`. They are committed together at the end of the input, or grouped
+/// explicitly between `start` and `commit`, with `abort` dropping the group.
+pub fn run_stdin(
+    input: impl BufRead,
+    out: &mut impl Write,
+    message: &str,
+    no_deref: bool,
+) -> std::io::Result<()> {
+    let new_transaction = || RefTransaction {
+        no_deref,
+        ..Default::default()
+    };
+    let mut transaction = new_transaction();
+    let mut started = false;
+    for line in input.lines() {
+        let line = line?;
+        let args = line.split_whitespace().collect::<Vec<_>>();
+        let invalid = || std::io::Error::other(format!("invalid command '{line}'"));
+        match args.as_slice() {
+            [] => {}
+            ["start"] => {
+                if started || !transaction.is_empty() {
+                    return Err(std::io::Error::other("cannot start a transaction twice"));
+                }
+                started = true;
+                writeln!(out, "start: ok")?;
+            }
+            ["commit"] => {
+                std::mem::replace(&mut transaction, new_transaction()).commit(Some(message))?;
+                started = false;
+                writeln!(out, "commit: ok")?;
+            }
+            ["abort"] => {
+                transaction = new_transaction();
+                started = false;
+                writeln!(out, "abort: ok")?;
+            }
+            ["update", name, new, old @ ..] if old.len() <= 1 => {
+                let old = old.first().map(|old| resolve_value(old)).transpose()?;
+                transaction.update(name, &resolve_value(new)?, old.as_deref());
+            }
+            ["create", name, new] => {
+                transaction.create(name, &resolve_value(new)?);
+            }
+            ["delete", name, old @ ..] if old.len() <= 1 => {
+                let old = old.first().map(|old| resolve_value(old)).transpose()?;
+                transaction.delete(name, old.as_deref());
+            }
+            ["verify", name, old @ ..] if old.len() <= 1 => {
+                let old = old.first().map(|old| resolve_value(old)).transpose()?;
+                transaction.verify(name, old.as_deref().unwrap_or(NULL_ID));
+            }
+            _ => return Err(invalid()),
+        }
+        out.flush()?;
+    }
+    if started {
+        return Err(std::io::Error::other("transaction was not committed"));
+    }
+    transaction.commit(Some(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_name() {
+        assert!(is_valid_name("refs/heads/main"));
+        assert!(is_valid_name("refs/tags/v1.0"));
+        assert!(!is_valid_name("refs/heads/../HEAD"));
+        assert!(!is_valid_name("refs/heads/.hidden"));
+        assert!(!is_valid_name("refs/heads/main.lock"));
+        assert!(!is_valid_name("refs//heads"));
+        assert!(!is_valid_name("refs/heads/a b"));
+        assert!(!is_valid_name("main@{1}"));
+    }
+
+    #[test]
+    fn test_check_old() {
+        let id = "95d09f2b10159347eece71399a7e2e907ea3df4f";
+        assert!(check_old("refs/heads/main", Some(id), None).is_ok());
+        assert!(check_old("refs/heads/main", Some(id), Some(id)).is_ok());
+        assert!(check_old("refs/heads/main", None, Some(NULL_ID)).is_ok());
+        assert!(check_old("refs/heads/main", Some(id), Some(NULL_ID)).is_err());
+        assert!(check_old("refs/heads/main", None, Some(id)).is_err());
+        let other = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+        let error = check_old("refs/heads/main", Some(id), Some(other)).unwrap_err();
+        assert!(error.to_string().contains("but expected"));
+    }
+
+    #[test]
+    fn test_symbolic_update() {
+        let repo = crate::data::testrepo::TestRepo::new();
+        repo.write("file", "one\n");
+        let first = repo.commit_all("first");
+        repo.write("file", "two\n");
+        let second = repo.commit_all("second");
+
+        let mut transaction = RefTransaction::new();
+        transaction
+            .create("refs/heads/topic", &first)
+            .update_symbolic("HEAD", "refs/heads/topic", Some(&second))
+            .log_as("checkout: moving from main to topic");
+        transaction.commit(Some("branch: Created")).unwrap();
+        let head = filesystem::read_symbolic_ref("HEAD").unwrap();
+        assert_eq!(head.as_deref(), Some("refs/heads/topic"));
+        assert_eq!(
+            filesystem::read_ref("refs/heads/main").unwrap(),
+            Some(second.clone())
+        );
+        let moved = reflog::read("HEAD").unwrap().pop().unwrap();
+        assert_eq!(
+            (moved.old.as_str(), moved.new.as_str()),
+            (second.as_str(), first.as_str())
+        );
+        assert_eq!(moved.message, "checkout: moving from main to topic");
+        let created = reflog::read("refs/heads/topic").unwrap().pop().unwrap();
+        assert_eq!(created.message, "branch: Created");
+
+        // HEAD moved on, so nothing changes
+        let mut transaction = RefTransaction::new();
+        transaction.update_symbolic("HEAD", "refs/heads/main", Some(&second));
+        assert!(transaction.commit(Some("checkout")).is_err());
+        let head = filesystem::read_symbolic_ref("HEAD").unwrap();
+        assert_eq!(head.as_deref(), Some("refs/heads/topic"));
+
+        let mut transaction = RefTransaction::new();
+        transaction.update_symbolic("HEAD", "HEAD", None);
+        assert!(transaction.commit(None).is_err());
+    }
+}
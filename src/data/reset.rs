@@ -0,0 +1,294 @@
+//! Moving HEAD to another commit, taking the index and possibly the working tree along.
+//!
+//! The mode decides how far the reset goes, from only moving HEAD (`--soft`) to making the index
+//! and every tracked file match the new commit (`--hard`). The previous HEAD is kept in
+//! `ORIG_HEAD` and the move is recorded in the reflog.
+use std::{collections::BTreeSet, path::PathBuf};
+
+use super::{
+    base,
+    commits::OgitCommit,
+    diff::{self, matches_pathspec, DiffOptions},
+    filesystem,
+    index::Index,
+    merge, porcelain,
+    refs::RefTransaction,
+    revision,
+};
+
+/// Where HEAD pointed before the last command that moved it a long way.
+pub const ORIG_HEAD: &str = "ORIG_HEAD";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Only move HEAD
+    Soft,
+    /// Move HEAD and reset the index, local changes stay in the working tree
+    #[default]
+    Mixed,
+    /// Move HEAD and make the index and every tracked file match the new commit
+    Hard,
+    /// Like hard, but keep local changes and refuse when one would be overwritten
+    Keep,
+    /// Like keep, but also throw away unresolved conflicts, to get out of a failed merge
+    Merge,
+}
+
+impl ResetMode {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Soft => "soft",
+            Self::Mixed => "mixed",
+            Self::Hard => "hard",
+            Self::Keep => "keep",
+            Self::Merge => "merge",
+        }
+    }
+}
+
+fn paths_between(old_tree: &str, new_tree: &str) -> std::io::Result<BTreeSet<PathBuf>> {
+    let options = DiffOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    Ok(diff::diff_trees(Some(old_tree), Some(new_tree), &options)?
+        .iter()
+        .map(|change| change.path().to_path_buf())
+        .collect())
+}
+
+/// Give `paths` the content they have in `target`, removing those it does not have.
+fn checkout_paths(target: &Index, paths: &BTreeSet<PathBuf>) -> std::io::Result<()> {
+    for path in paths {
+        match target.get(path) {
+            Some(entry) => {
+                let object = filesystem::get_object(&entry.id, None)?;
+                filesystem::write_worktree_file(path, &object.data, &entry.mode)?;
+            }
+            None => porcelain::remove_worktree_file(path)?,
+        }
+    }
+    Ok(())
+}
+
+/// Make every tracked file match `target_tree`, untracked files are left alone.
+fn reset_worktree(head_tree: &str, target_tree: &str) -> std::io::Result<()> {
+    let target = Index::from_tree(target_tree)?;
+    let mut tracked = Index::load()?
+        .entries()
+        .chain(Index::from_tree(head_tree)?.entries())
+        .chain(target.entries())
+        .map(|entry| entry.path.clone())
+        .collect::<BTreeSet<_>>();
+    let worktree = Index::from_tree(&base::write_tree(None, false)?.hex_string())?;
+    tracked.retain(|path| match (target.get(path), worktree.get(path)) {
+        (Some(wanted), Some(found)) => wanted.id != found.id || wanted.mode != found.mode,
+        (None, None) => false,
+        _ => true,
+    });
+    checkout_paths(&target, &tracked)
+}
+
+/// Refuse to touch `paths` when the working tree holds changes to them.
+fn check_up_to_date<'a>(
+    head_tree: &str,
+    paths: impl Iterator<Item = &'a PathBuf>,
+) -> std::io::Result<()> {
+    let changed = porcelain::local_changes(Some(head_tree))?;
+    match paths.into_iter().find(|path| changed.contains(*path)) {
+        Some(path) => Err(std::io::Error::other(format!(
+            "Entry '{}' not uptodate. Cannot merge.",
+            path.display()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Tracked files of `tree` that differ in the working tree, as `git reset` lists them.
+fn unstaged_changes(tree: &str) -> std::io::Result<Vec<String>> {
+    let tracked = Index::from_tree(tree)?;
+    Ok(porcelain::local_changes(Some(tree))?
+        .into_iter()
+        .filter(|path| tracked.get(path).is_some())
+        .map(|path| {
+            let status = if path.exists() { 'M' } else { 'D' };
+            format!("{status}\t{}", path.display())
+        })
+        .collect())
+}
+
+/// Move HEAD to `rev`, taking the index and the working tree along as far as `mode` says.
+pub fn reset(rev: &str, mode: ResetMode) -> std::io::Result<()> {
+    let head = revision::resolve("HEAD")?;
+    let target = revision::resolve(rev)?;
+    let target_commit = OgitCommit::get(&target)?;
+    let head_tree = OgitCommit::get(&head)?.tree;
+    let index = Index::load()?;
+    let unmerged = index
+        .entries()
+        .filter(|entry| entry.stage != 0)
+        .map(|entry| entry.path.clone())
+        .collect::<BTreeSet<_>>();
+    match mode {
+        ResetMode::Soft => {
+            if let Some(path) = unmerged.first() {
+                return Err(std::io::Error::other(format!(
+                    "Cannot do a soft reset in the middle of a merge.\n{}: needs merge",
+                    path.display()
+                )));
+            }
+        }
+        ResetMode::Mixed => Index::from_tree(&target_commit.tree)?.save()?,
+        ResetMode::Hard => {
+            reset_worktree(&head_tree, &target_commit.tree)?;
+            Index::from_tree(&target_commit.tree)?.save()?;
+        }
+        ResetMode::Keep => {
+            if let Some(path) = unmerged.first() {
+                return Err(std::io::Error::other(format!(
+                    "Cannot do a keep reset in the middle of a merge.\n{}: needs merge",
+                    path.display()
+                )));
+            }
+            porcelain::update_working_tree(Some(&head_tree), &target_commit.tree, "reset")?;
+        }
+        ResetMode::Merge => {
+            let mut paths = paths_between(&head_tree, &target_commit.tree)?;
+            check_up_to_date(&head_tree, paths.difference(&unmerged))?;
+            paths.extend(unmerged);
+            checkout_paths(&Index::from_tree(&target_commit.tree)?, &paths)?;
+            Index::from_tree(&target_commit.tree)?.save()?;
+        }
+    }
+
+    // ORIG_HEAD is not logged, HEAD and the branch it is on both are
+    let mut transaction = RefTransaction::new();
+    transaction
+        .update(ORIG_HEAD, &head, None)
+        .update("HEAD", &target, Some(&head));
+    transaction.commit(Some(&format!("reset: moving to {rev}")))?;
+    merge::clear_state()?;
+
+    match mode {
+        ResetMode::Mixed => {
+            let unstaged = unstaged_changes(&target_commit.tree)?;
+            if !unstaged.is_empty() {
+                println!("Unstaged changes after reset:");
+                for line in unstaged {
+                    println!("{line}");
+                }
+            }
+        }
+        ResetMode::Hard => println!(
+            "HEAD is now at {} {}",
+            &target[..7],
+            target_commit.subject()
+        ),
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Set the index entries of `pathspecs` to their version in `rev`, leaving HEAD and files alone.
+///
+/// Paths `rev` does not have are removed from the index, which unstages newly added files.
+pub fn reset_paths(rev: &str, pathspecs: &[PathBuf], mode: ResetMode) -> std::io::Result<()> {
+    if mode != ResetMode::Mixed {
+        return Err(std::io::Error::other(format!(
+            "Cannot do {} reset with paths.",
+            mode.name()
+        )));
+    }
+    let target = Index::from_tree(&revision::resolve_tree(rev)?)?;
+    let mut index = Index::load()?;
+    let paths = index
+        .entries()
+        .chain(target.entries())
+        .map(|entry| entry.path.clone())
+        .filter(|path| matches_pathspec(path, pathspecs))
+        .collect::<BTreeSet<_>>();
+    for path in paths {
+        index.remove(&path);
+        if let Some(entry) = target.get(&path) {
+            index.add(entry.clone());
+        }
+    }
+    index.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::data::{reflog, testrepo::TestRepo};
+
+    /// Two commits changing `a.txt`, then `b.txt` staged, `a.txt` changed and `c.txt` untracked.
+    fn setup(repo: &TestRepo) -> (String, String) {
+        repo.write("a.txt", "one\n");
+        let first = repo.commit_all("first");
+        repo.write("a.txt", "two\n");
+        let second = repo.commit_all("second");
+        repo.write("b.txt", "staged\n");
+        porcelain::add(&[PathBuf::from("b.txt")]).unwrap();
+        repo.write("a.txt", "local\n");
+        (first, second)
+    }
+
+    fn tree_of(id: &str) -> Index {
+        Index::from_tree(&OgitCommit::get(id).unwrap().tree).unwrap()
+    }
+
+    /// HEAD, the branch and ORIG_HEAD after moving from `old` to `new` with `reset <rev>`.
+    fn assert_moved(old: &str, new: &str, rev: &str) {
+        assert_eq!(filesystem::read_head_file().unwrap(), new);
+        assert_eq!(
+            filesystem::read_ref(ORIG_HEAD).unwrap().as_deref(),
+            Some(old)
+        );
+        for name in ["HEAD", "refs/heads/main"] {
+            let moved = reflog::read(name).unwrap().pop().unwrap();
+            assert_eq!((moved.old.as_str(), moved.new.as_str()), (old, new));
+            assert_eq!(moved.message, format!("reset: moving to {rev}"));
+        }
+        assert!(reflog::read(ORIG_HEAD).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_soft_reset_keeps_the_index_and_worktree() {
+        let repo = TestRepo::new();
+        let (first, second) = setup(&repo);
+        let before = Index::load().unwrap();
+
+        reset(&first, ResetMode::Soft).unwrap();
+        assert_moved(&second, &first, &first);
+        assert_eq!(Index::load().unwrap(), before);
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "local\n");
+    }
+
+    #[test]
+    fn test_mixed_reset_resets_the_index_only() {
+        let repo = TestRepo::new();
+        let (first, second) = setup(&repo);
+
+        reset("HEAD~1", ResetMode::Mixed).unwrap();
+        assert_moved(&second, &first, "HEAD~1");
+        assert_eq!(Index::load().unwrap(), tree_of(&first));
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "local\n");
+        assert!(Path::new("b.txt").exists());
+    }
+
+    #[test]
+    fn test_hard_reset_resets_the_index_and_tracked_files() {
+        let repo = TestRepo::new();
+        let (first, second) = setup(&repo);
+        repo.write("c.txt", "untracked\n");
+
+        reset(&first, ResetMode::Hard).unwrap();
+        assert_moved(&second, &first, &first);
+        assert_eq!(Index::load().unwrap(), tree_of(&first));
+        assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n");
+        assert!(!Path::new("b.txt").exists());
+        assert_eq!(fs::read_to_string("c.txt").unwrap(), "untracked\n");
+    }
+}
@@ -0,0 +1,380 @@
+//! Turning the names a user types on the command line into object ids.
+use std::{collections::HashMap, path::Path};
+
+use super::{
+    base,
+    commits::OgitCommit,
+    diff::NULL_ID,
+    filesystem,
+    graph::CommitGraph,
+    index::Index,
+    objects::OgitObjectType,
+    reflog, refs,
+    tags::{self, OgitTag},
+};
+
+/// Abbreviated ids shorter than this are never looked up, same as git.
+const MIN_ABBREV: usize = 4;
+
+fn unknown_revision(name: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("unknown revision '{name}'"),
+    )
+}
+
+/// Resolve `name` into the full id of an object.
+///
+/// Understands `HEAD` (and its `@` alias), ref names like `main` or `refs/tags/v1.0`, full or
+/// unambiguous abbreviated ids, and `<rev>:<path>` or `:<path>` for a path in the tree of a
+/// revision or in the index. `<rev>~<n>` and `<rev>^<n>` go back to ancestors, `<ref>@{n}` to
+/// where a ref was `n` moves ago.
+pub fn resolve(name: &str) -> std::io::Result<String> {
+    if let Some((rev, path)) = name.split_once(':') {
+        return resolve_path(rev, path);
+    }
+    if let Some((rev, step, number)) = split_ancestry(name) {
+        return ancestor(name, rev, step, number);
+    }
+    if let Some((rev, number)) = split_reflog_position(name) {
+        return reflog_entry(rev, number);
+    }
+    if name == "HEAD" || name == "@" {
+        let head = filesystem::read_head_file()?;
+        if head.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "HEAD does not point to a commit yet",
+            ));
+        }
+        return Ok(head);
+    }
+    if let Some(full) = refs::expand(name)? {
+        if let Some(id) = filesystem::read_ref(&full)? {
+            return Ok(id);
+        }
+    }
+    if name.len() < MIN_ABBREV || !name.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(unknown_revision(name));
+    }
+    let name = name.to_ascii_lowercase();
+    let mut candidates = filesystem::find_object_ids(&name)?;
+    match candidates.len() {
+        0 => Err(unknown_revision(&name)),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(std::io::Error::other(format!(
+            "short object id {name} is ambiguous"
+        ))),
+    }
+}
+
+/// Split a trailing `~<n>` or `^<n>` off `name`, the number is `None` when not given.
+fn split_ancestry(name: &str) -> Option<(&str, char, Option<usize>)> {
+    let without_number = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    let step = without_number
+        .chars()
+        .last()
+        .filter(|c| matches!(c, '~' | '^'))?;
+    let rev = &without_number[..without_number.len() - 1];
+    Some((rev, step, name[without_number.len()..].parse().ok()))
+}
+
+/// The commit `~<n>` first parents back from `rev`, or its `^<n>`th parent, `^0` being itself.
+fn ancestor(name: &str, rev: &str, step: char, number: Option<usize>) -> std::io::Result<String> {
+    let mut id = tags::peel(&resolve(rev)?)?;
+    let parent = |id: &str, n: usize| {
+        OgitCommit::get(id)?
+            .parents
+            .get(n.wrapping_sub(1))
+            .cloned()
+            .ok_or_else(|| unknown_revision(name))
+    };
+    match (step, number.unwrap_or(1)) {
+        ('^', 0) => OgitCommit::get(&id).map(|_| id),
+        ('^', n) => parent(&id, n),
+        (_, n) => {
+            for _ in 0..n {
+                id = parent(&id, 1)?;
+            }
+            Ok(id)
+        }
+    }
+}
+
+/// Split `<ref>@{n}` into the ref, empty for the branch HEAD is on, and `n`.
+fn split_reflog_position(name: &str) -> Option<(&str, usize)> {
+    let (rev, position) = name.strip_suffix('}')?.rsplit_once("@{")?;
+    Some((rev, position.parse().ok()?))
+}
+
+/// Where `rev` pointed `number` moves ago according to its reflog, `0` being where it is now.
+fn reflog_entry(rev: &str, number: usize) -> std::io::Result<String> {
+    let name = match rev {
+        "" => filesystem::read_symbolic_ref("HEAD")?.unwrap_or_else(|| "HEAD".to_string()),
+        "HEAD" | "@" => "HEAD".to_string(),
+        _ => refs::expand(rev)?.ok_or_else(|| unknown_revision(rev))?,
+    };
+    let entries = reflog::read(&name)?;
+    entries
+        .iter()
+        .rev()
+        .nth(number)
+        .map(|entry| entry.new.clone())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("log for '{name}' only has {} entries", entries.len()),
+            )
+        })
+}
+
+/// Every commit the ref `name` pointed at according to its reflog, newest first.
+///
+/// Its current commit comes first even when the ref has no log.
+pub fn ref_history(name: &str) -> std::io::Result<Vec<String>> {
+    let full = match name {
+        "HEAD" | "@" => "HEAD".to_string(),
+        _ => refs::expand(name)?.ok_or_else(|| unknown_revision(name))?,
+    };
+    let mut history = vec![resolve(&full)?];
+    for entry in reflog::read(&full)?.into_iter().rev() {
+        for id in [entry.new, entry.old] {
+            if id != NULL_ID && !history.contains(&id) {
+                history.push(id);
+            }
+        }
+    }
+    Ok(history)
+}
+
+/// Names pointing at each commit, as `log --decorate` shows them.
+///
+/// `HEAD` comes first, joined as `HEAD -> <branch>` with the branch it points at, then branches
+/// by their short name, tags as `tag: <name>` and other refs by their full name.
+pub fn decorations() -> std::io::Result<HashMap<String, Vec<String>>> {
+    let mut decorations = HashMap::<String, Vec<String>>::new();
+    let head = filesystem::read_head_file()?;
+    if !head.is_empty() {
+        decorations
+            .entry(head.clone())
+            .or_default()
+            .push("HEAD".to_string());
+    }
+    let head_target = filesystem::read_symbolic_ref("HEAD")?;
+    for (name, id) in filesystem::list_refs()? {
+        if head_target.as_deref() == Some(name.as_str()) && id == head {
+            let names = decorations.get_mut(&id).unwrap();
+            names[0] = format!("HEAD -> {}", refs::shorten(&name));
+            continue;
+        }
+        let name = if let Some(branch) = name.strip_prefix("refs/heads/") {
+            branch.to_string()
+        } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+            format!("tag: {tag}")
+        } else {
+            name
+        };
+        decorations.entry(id).or_default().push(name);
+    }
+    Ok(decorations)
+}
+
+/// Resolve `name` and follow it down to a tree, so that commits can be used where trees are expected.
+pub fn resolve_tree(name: &str) -> std::io::Result<String> {
+    let id = resolve(name)?;
+    let object = filesystem::get_object(&id, None)?;
+    match object.variant {
+        OgitObjectType::Tree => Ok(id),
+        OgitObjectType::Commit => Ok(OgitCommit::get(&id)?.tree),
+        OgitObjectType::Tag => resolve_tree(&OgitTag::get(&id)?.object),
+        OgitObjectType::Blob => Err(std::io::Error::other(format!(
+            "{name} does not name a tree-ish object"
+        ))),
+    }
+}
+
+/// The id of `path` in the tree of `rev`, or in the index when `rev` is empty.
+fn resolve_path(rev: &str, path: &str) -> std::io::Result<String> {
+    let missing = |place: &str| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("path '{path}' does not exist in {place}"),
+        )
+    };
+    if rev.is_empty() {
+        return Index::load()?
+            .get(Path::new(path))
+            .map(|entry| entry.id.clone())
+            .ok_or_else(|| missing("the index"));
+    }
+    let mut id = resolve_tree(rev)?;
+    let mut variant = OgitObjectType::Tree;
+    for component in Path::new(path).components() {
+        if variant != OgitObjectType::Tree {
+            return Err(missing(&format!("'{rev}'")));
+        }
+        let entry = base::parse_tree_data(Some(&id))?
+            .into_iter()
+            .find(|entry| entry.filename == Path::new(component.as_os_str()))
+            .ok_or_else(|| missing(&format!("'{rev}'")))?;
+        id = entry.id;
+        variant = entry.variant;
+    }
+    Ok(id)
+}
+
+/// Split `a..b` into the excluded and included revision, a missing side means `HEAD`.
+///
+/// A lone revision has nothing excluded.
+pub fn parse_range(spec: &str) -> (Option<&str>, &str) {
+    match spec.split_once("..") {
+        Some((exclude, include)) => (Some(or_head(exclude)), or_head(include)),
+        None => (None, spec),
+    }
+}
+
+/// The sides a diff of `left...right` compares: the merge base of both, and `right`.
+///
+/// A missing side means `HEAD`, the first merge base is used when there are several, like git.
+pub fn symmetric_diff_sides(left: &str, right: &str) -> std::io::Result<(String, String)> {
+    let (left, right) = (resolve(or_head(left))?, resolve(or_head(right))?);
+    let bases = CommitGraph::load()?.merge_bases(&left, std::slice::from_ref(&right))?;
+    match bases.into_iter().next() {
+        Some(base) => Ok((base, right)),
+        None => Err(std::io::Error::other(format!(
+            "{left}...{right}: no merge base"
+        ))),
+    }
+}
+
+fn or_head(name: &str) -> &str {
+    if name.is_empty() {
+        "HEAD"
+    } else {
+        name
+    }
+}
+
+/// Commits reachable from `include` but not from `exclude`, newest first.
+pub fn walk_range(
+    include: &str,
+    exclude: Option<&str>,
+) -> std::io::Result<Vec<(String, OgitCommit)>> {
+    let include = [resolve(include)?];
+    let exclude = exclude.map(resolve).transpose()?;
+    walk(&include, exclude.as_slice(), &mut CommitGraph::load()?)
+}
+
+/// Split revision arguments into the commits to start from and the commits to exclude.
+///
+/// Besides plain revisions, `^rev` excludes what `rev` reaches, `a..b` is `^a b` and the
+/// symmetric `a...b` takes both sides but excludes their merge bases.
+pub fn parse_revisions(
+    specs: &[String],
+    graph: &mut CommitGraph,
+) -> std::io::Result<(Vec<String>, Vec<String>)> {
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    for spec in specs {
+        if let Some(name) = spec.strip_prefix('^') {
+            exclude.push(resolve(name)?);
+        } else if let Some((left, right)) = spec.split_once("...") {
+            let (left, right) = (resolve(or_head(left))?, resolve(or_head(right))?);
+            exclude.extend(graph.merge_bases(&left, std::slice::from_ref(&right))?);
+            include.extend([left, right]);
+        } else {
+            let (excluded, included) = parse_range(spec);
+            exclude.extend(excluded.map(resolve).transpose()?);
+            include.push(resolve(included)?);
+        }
+    }
+    Ok((include, exclude))
+}
+
+/// Commits a revision range names, newest first.
+///
+/// Any single argument [`parse_revisions`] understands can be used.
+pub fn walk_spec(spec: &str) -> std::io::Result<Vec<(String, OgitCommit)>> {
+    let mut graph = CommitGraph::load()?;
+    let (include, exclude) = parse_revisions(&[spec.to_string()], &mut graph)?;
+    walk(&include, &exclude, &mut graph)
+}
+
+fn walk(
+    include: &[String],
+    exclude: &[String],
+    graph: &mut CommitGraph,
+) -> std::io::Result<Vec<(String, OgitCommit)>> {
+    let ids = graph.walk(include, exclude, false)?;
+    ids.into_iter()
+        .map(|id| OgitCommit::get(&id).map(|commit| (id, commit)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::testrepo::TestRepo;
+
+    #[test]
+    fn test_split_suffixes() {
+        assert_eq!(split_ancestry("HEAD~2"), Some(("HEAD", '~', Some(2))));
+        assert_eq!(split_ancestry("main^"), Some(("main", '^', None)));
+        assert_eq!(
+            split_ancestry("HEAD@{1}^2"),
+            Some(("HEAD@{1}", '^', Some(2)))
+        );
+        assert_eq!(split_ancestry("95d09f2b"), None);
+        assert_eq!(split_reflog_position("main@{3}"), Some(("main", 3)));
+        assert_eq!(split_reflog_position("@{1}"), Some(("", 1)));
+        assert_eq!(split_reflog_position("main@{upstream}"), None);
+    }
+
+    #[test]
+    fn test_resolve_ancestors_and_reflog() {
+        let repo = TestRepo::new();
+        repo.write("a.txt", "one\n");
+        let first = repo.commit_all("first");
+        repo.write("a.txt", "two\n");
+        let second = repo.commit_all("second");
+        repo.write("a.txt", "three\n");
+        let third = repo.commit_all("third");
+        let side = base::commit_tree(
+            &OgitCommit::get(&first).unwrap().tree,
+            std::slice::from_ref(&first),
+            Some("side\n".to_string()),
+        )
+        .unwrap()
+        .hex_string();
+        let merge = base::commit_tree(
+            &OgitCommit::get(&third).unwrap().tree,
+            &[third.clone(), side.clone()],
+            Some("merge\n".to_string()),
+        )
+        .unwrap()
+        .hex_string();
+        refs::move_head(&merge, &third, "merge side").unwrap();
+
+        let resolved = |name: &str| resolve(name).unwrap();
+        assert_eq!(resolved("HEAD^0"), merge);
+        assert_eq!(resolved("HEAD^"), third);
+        assert_eq!(resolved("HEAD^2"), side);
+        assert_eq!(resolved("HEAD^2~1"), first);
+        assert_eq!(resolved("main~2"), second);
+        assert_eq!(resolved("@~3"), first);
+        assert_eq!(resolved("HEAD^^^"), first);
+        assert!(resolve("HEAD^3").is_err());
+        assert!(resolve("HEAD~4").is_err());
+
+        assert_eq!(resolved("HEAD@{0}"), merge);
+        assert_eq!(resolved("HEAD@{1}"), third);
+        assert_eq!(resolved("main@{3}"), first);
+        assert_eq!(resolved("@{2}"), second);
+        assert_eq!(resolved("HEAD@{1}~1"), second);
+        assert!(resolve("HEAD@{4}").is_err());
+        assert_eq!(
+            resolved("HEAD~1:a.txt"),
+            filesystem::hash_object_in_memory(b"three\n", None).hex_string()
+        );
+    }
+}
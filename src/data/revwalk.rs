@@ -0,0 +1,317 @@
+//! Listing the commits of revision ranges the way `log` shows them: ordered, filtered and limited.
+//!
+//! The graph walk visits every commit once, however many lines of history lead to it. Ordering
+//! happens on the whole selection afterwards, then the filters and finally the limits, so that
+//! `-n` counts the commits that are actually shown.
+use std::{
+    collections::{BinaryHeap, HashMap},
+    path::PathBuf,
+};
+
+use regex::{Regex, RegexBuilder};
+
+use super::{
+    commits::OgitCommit,
+    graph::CommitGraph,
+    revision,
+    simplify::{self, Simplification},
+    time,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// Newest commit date first
+    #[default]
+    Default,
+    /// No parent before all of its children, otherwise newest commit date first
+    Date,
+    /// No parent before all of its children, otherwise newest author date first
+    AuthorDate,
+    /// No parent before all of its children, keeping lines of history together
+    Topo,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    pub max_count: Option<usize>,
+    /// Commits to leave out before starting to count
+    pub skip: usize,
+    pub since: Option<time::DateTime<time::FixedOffset>>,
+    pub until: Option<time::DateTime<time::FixedOffset>>,
+    /// Patterns the author must match one of
+    pub authors: Vec<String>,
+    /// Patterns the committer must match one of
+    pub committers: Vec<String>,
+    /// Patterns the message must match one of, or all of with `all_match`
+    pub grep: Vec<String>,
+    pub all_match: bool,
+    /// Match every pattern without regard to case
+    pub ignore_case: bool,
+    pub merges: bool,
+    pub no_merges: bool,
+    pub first_parent: bool,
+    /// Show the selected commits oldest first
+    pub reverse: bool,
+    pub order: WalkOrder,
+    /// Only show commits changing these paths
+    pub paths: Vec<PathBuf>,
+    pub simplification: Simplification,
+    /// Follow the single path of `paths` back through renames
+    pub follow: bool,
+}
+
+/// The compiled filters of [`WalkOptions`].
+struct Filters {
+    authors: Vec<Regex>,
+    committers: Vec<Regex>,
+    grep: Vec<Regex>,
+}
+
+impl Filters {
+    fn new(options: &WalkOptions) -> std::io::Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(options.ignore_case)
+                        .multi_line(true)
+                        .build()
+                        .map_err(std::io::Error::other)
+                })
+                .collect::<std::io::Result<Vec<_>>>()
+        };
+        Ok(Self {
+            authors: compile(&options.authors)?,
+            committers: compile(&options.committers)?,
+            grep: compile(&options.grep)?,
+        })
+    }
+
+    fn matches(&self, commit: &OgitCommit, options: &WalkOptions) -> bool {
+        let any = |patterns: &[Regex], text: &str| {
+            patterns.is_empty() || patterns.iter().any(|p| p.is_match(text))
+        };
+        let message = String::from_utf8_lossy(&commit.message);
+        let grep = if options.all_match {
+            self.grep.iter().all(|p| p.is_match(&message))
+        } else {
+            any(&self.grep, &message)
+        };
+        let merge = commit.parents.len() > 1;
+        grep && any(&self.authors, &commit.author)
+            && any(&self.committers, &commit.committer)
+            && (merge || !options.merges)
+            && !(merge && options.no_merges)
+            && options
+                .since
+                .is_none_or(|since| commit.committer_time >= since)
+            && options
+                .until
+                .is_none_or(|until| commit.committer_time <= until)
+    }
+}
+
+/// Order `commits` so that no parent comes before any of its children.
+///
+/// Commits that are ready to be shown are taken newest first by `time` when given. Without it
+/// the last commit whose children were all shown comes next, which keeps lines of history
+/// together the way `--topo-order` does.
+fn sort_topologically(
+    commits: Vec<(String, OgitCommit)>,
+    first_parent: bool,
+    time: Option<fn(&OgitCommit) -> i64>,
+) -> Vec<(String, OgitCommit)> {
+    let parents_of = |commit: &OgitCommit| {
+        let count = if first_parent {
+            1
+        } else {
+            commit.parents.len()
+        };
+        commit
+            .parents
+            .iter()
+            .take(count)
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let position = commits
+        .iter()
+        .enumerate()
+        .map(|(i, (id, _))| (id.clone(), i))
+        .collect::<HashMap<_, _>>();
+    let mut children = vec![0; commits.len()];
+    for (_, commit) in &commits {
+        for parent in parents_of(commit) {
+            if let Some(&i) = position.get(&parent) {
+                children[i] += 1;
+            }
+        }
+    }
+    // the input is newest first, so ties go to the newest commit either way
+    let key = |i: usize| (time.map_or(0, |time| time(&commits[i].1)), usize::MAX - i);
+    let mut ready = BinaryHeap::new();
+    let mut stack = Vec::new();
+    for i in (0..commits.len()).rev().filter(|&i| children[i] == 0) {
+        match time {
+            Some(_) => ready.push(key(i)),
+            None => stack.push(i),
+        }
+    }
+    let mut order = Vec::with_capacity(commits.len());
+    while let Some(i) = stack
+        .pop()
+        .or_else(|| ready.pop().map(|(_, i)| usize::MAX - i))
+    {
+        order.push(i);
+        // like git, the last parent is pushed last and its line of history is shown first
+        for parent in &parents_of(&commits[i].1) {
+            let Some(&p) = position.get(parent) else {
+                continue;
+            };
+            children[p] -= 1;
+            if children[p] == 0 {
+                match time {
+                    Some(_) => ready.push(key(p)),
+                    None => stack.push(p),
+                }
+            }
+        }
+    }
+    let mut commits = commits.into_iter().map(Some).collect::<Vec<_>>();
+    order
+        .into_iter()
+        .filter_map(|i| commits[i].take())
+        .collect()
+}
+
+/// The commits `revisions` name, as `log` shows them with `options`.
+///
+/// Limiting the history to paths happens before the ordering, which then sees the rewritten
+/// parents.
+pub fn walk(
+    revisions: &[String],
+    options: &WalkOptions,
+) -> std::io::Result<Vec<(String, OgitCommit)>> {
+    let filters = Filters::new(options)?;
+    let mut graph = CommitGraph::load()?;
+    let (include, exclude) = revision::parse_revisions(revisions, &mut graph)?;
+    let ids = graph.walk(&include, &exclude, options.first_parent)?;
+    let mut commits = ids
+        .into_iter()
+        .map(|id| OgitCommit::get(&id).map(|commit| (id, commit)))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if options.follow {
+        let [path] = options.paths.as_slice() else {
+            return Err(std::io::Error::other(
+                "--follow requires exactly one pathspec",
+            ));
+        };
+        commits = simplify::follow(commits, path)?;
+    } else if !options.paths.is_empty() {
+        commits = simplify::simplify(
+            commits,
+            &include,
+            &options.paths,
+            options.simplification,
+            &mut graph,
+        )?;
+    }
+    let commits = match options.order {
+        WalkOrder::Default => commits,
+        WalkOrder::Date => sort_topologically(
+            commits,
+            options.first_parent,
+            Some(|c| c.committer_time.timestamp()),
+        ),
+        WalkOrder::AuthorDate => sort_topologically(
+            commits,
+            options.first_parent,
+            Some(|c| c.author_time.timestamp()),
+        ),
+        WalkOrder::Topo => sort_topologically(commits, options.first_parent, None),
+    };
+    let mut selected = commits
+        .into_iter()
+        .filter(|(_, commit)| filters.matches(commit, options))
+        .skip(options.skip)
+        .take(options.max_count.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+    if options.reverse {
+        selected.reverse();
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(parents: &[&str], author_time: i64, committer_time: i64) -> OgitCommit {
+        OgitCommit::new(
+            String::new(),
+            parents.iter().map(ToString::to_string).collect(),
+            &format!("A U Thor <author@example.com> {author_time} +0000"),
+            &format!("C O Mitter <committer@example.com> {committer_time} +0000"),
+            b"Fix the parser\n\nIt choked on empty input.\n".to_vec(),
+        )
+    }
+
+    /// Two lines of history forking from `base` and merged in `merge`, newest commit date first.
+    fn history() -> Vec<(String, OgitCommit)> {
+        [
+            ("merge", commit(&["main2", "side2"], 60, 60)),
+            ("side2", commit(&["side1"], 10, 50)),
+            ("main2", commit(&["main1"], 40, 40)),
+            ("side1", commit(&["base"], 50, 30)),
+            ("main1", commit(&["base"], 20, 20)),
+            ("base", commit(&[], 0, 0)),
+        ]
+        .into_iter()
+        .map(|(id, commit)| (id.to_string(), commit))
+        .collect()
+    }
+
+    fn ids(commits: &[(String, OgitCommit)]) -> Vec<&str> {
+        commits.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_orders_keep_children_first() {
+        let topo = sort_topologically(history(), false, None);
+        assert_eq!(
+            ids(&topo),
+            ["merge", "side2", "side1", "main2", "main1", "base"]
+        );
+        let by_author = sort_topologically(history(), false, Some(|c| c.author_time.timestamp()));
+        assert_eq!(
+            ids(&by_author),
+            ["merge", "main2", "main1", "side2", "side1", "base"]
+        );
+        let first_parent = sort_topologically(history(), true, None);
+        assert_eq!(ids(&first_parent)[..3], ["merge", "main2", "main1"]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let options = WalkOptions {
+            grep: vec!["parser".to_string(), "EMPTY".to_string()],
+            all_match: true,
+            ignore_case: true,
+            authors: vec!["author@".to_string()],
+            no_merges: true,
+            ..Default::default()
+        };
+        let filters = Filters::new(&options).unwrap();
+        let history = history();
+        assert!(!filters.matches(&history[0].1, &options));
+        assert!(filters.matches(&history[1].1, &options));
+        let options = WalkOptions {
+            grep: vec!["EMPTY".to_string()],
+            ..Default::default()
+        };
+        assert!(!Filters::new(&options)
+            .unwrap()
+            .matches(&history[1].1, &options));
+    }
+}
@@ -0,0 +1,573 @@
+//! `og cherry-pick` and `og revert`: replaying the change a commit made, or undoing it, on top of HEAD.
+//!
+//! Each commit is turned into a three-way merge: the parent against the commit for a pick, the
+//! other way around for a revert. Commits are worked through in order, and when one stops on a
+//! conflict the remaining ones are kept in `.ogit/sequencer` so the run can be continued, skipped
+//! past or aborted.
+use std::{fmt::Display, fs, path::PathBuf, str::FromStr};
+
+use super::{
+    base,
+    commits::OgitCommit,
+    filesystem,
+    index::Index,
+    merge, porcelain, refs, revision,
+    xmerge::{ConflictStyle, MergeLabels},
+};
+
+const STATE_DIR: &str = "sequencer";
+/// The commit being picked, while its conflicts wait to be resolved.
+pub const CHERRY_PICK_HEAD: &str = "CHERRY_PICK_HEAD";
+/// The commit being reverted, while its conflicts wait to be resolved.
+pub const REVERT_HEAD: &str = "REVERT_HEAD";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Pick,
+    Revert,
+}
+
+impl Action {
+    /// The command doing this action, as named in hints.
+    pub const fn command(self) -> &'static str {
+        match self {
+            Self::Pick => "cherry-pick",
+            Self::Revert => "revert",
+        }
+    }
+    const fn head_file(self) -> &'static str {
+        match self {
+            Self::Pick => CHERRY_PICK_HEAD,
+            Self::Revert => REVERT_HEAD,
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pick => write!(f, "pick"),
+            Self::Revert => write!(f, "revert"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pick" => Ok(Self::Pick),
+            "revert" => Ok(Self::Revert),
+            _ => Err(format!("unknown sequencer action '{s}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PickOptions {
+    /// Leave the changes in the working tree and the index instead of committing them
+    pub no_commit: bool,
+    /// Note the picked commit at the end of the message
+    pub record_origin: bool,
+    /// Parent (counting from 1) a merge commit is compared against
+    pub mainline: Option<usize>,
+    pub style: ConflictStyle,
+    /// What commits are logged as, like `rebase (pick)`, the action's command by default
+    pub reflog_action: Option<String>,
+}
+
+/// How replaying a single commit ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PickOutcome {
+    /// Committed, giving the new commit id
+    Committed(String),
+    /// Applied to the working tree and the index only
+    Applied,
+    /// The change is already there, so there is nothing to commit
+    Empty,
+    /// Conflicts were left to resolve
+    Conflicted,
+}
+
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(7)]
+}
+
+/// The parent `commit` is compared against, `None` for a root commit.
+fn parent_of(
+    id: &str,
+    commit: &OgitCommit,
+    mainline: Option<usize>,
+) -> std::io::Result<Option<String>> {
+    match (commit.parents.len(), mainline) {
+        (0 | 1, Some(_)) => Err(std::io::Error::other(format!(
+            "mainline was specified but commit {id} is not a merge."
+        ))),
+        (0 | 1, None) => Ok(commit.parents.first().cloned()),
+        (_, None) => Err(std::io::Error::other(format!(
+            "commit {id} is a merge but no -m option was given."
+        ))),
+        (count, Some(n)) => match n.checked_sub(1).and_then(|i| commit.parents.get(i)) {
+            Some(parent) => Ok(Some(parent.clone())),
+            None => Err(std::io::Error::other(format!(
+                "commit {id} does not have parent {n}, it has {count}"
+            ))),
+        },
+    }
+}
+
+/// The message the commit replaying `id` gets.
+fn message(
+    action: Action,
+    id: &str,
+    commit: &OgitCommit,
+    parent: Option<&str>,
+    options: &PickOptions,
+) -> String {
+    match action {
+        Action::Pick => {
+            let mut message = String::from_utf8_lossy(&commit.message)
+                .trim_end()
+                .to_string();
+            if options.record_origin {
+                message.push_str(&format!("\n\n(cherry picked from commit {id})"));
+            }
+            format!("{message}\n")
+        }
+        Action::Revert => {
+            let mut message = format!(
+                "Revert \"{}\"\n\nThis reverts commit {id}",
+                commit.subject()
+            );
+            match parent.filter(|_| commit.parents.len() > 1) {
+                Some(parent) => {
+                    message.push_str(&format!(", reversing\nchanges made to {parent}.\n"));
+                }
+                None => message.push_str(".\n"),
+            }
+            message
+        }
+    }
+}
+
+/// Commit `tree` on top of HEAD for the replayed `id`, picks keep the original author.
+///
+/// The move of HEAD is logged as `<reflog_action>: <subject>`.
+pub fn commit_result(
+    action: Action,
+    id: &str,
+    tree: &str,
+    message: &str,
+    reflog_action: &str,
+) -> std::io::Result<String> {
+    let head = filesystem::read_head_file()?;
+    let parents = if head.is_empty() {
+        vec![]
+    } else {
+        vec![head.clone()]
+    };
+    let commit = match action {
+        Action::Pick => {
+            let original = OgitCommit::get(id)?;
+            base::commit_tree_with_author(
+                tree,
+                &parents,
+                message,
+                &original.author,
+                &original.author_time,
+            )?
+        }
+        Action::Revert => base::commit_tree(tree, &parents, Some(message.to_string()))?,
+    };
+    let new_id = commit.hex_string();
+    let subject = OgitCommit::get(&new_id)?.subject();
+    refs::move_head(&new_id, &head, &format!("{reflog_action}: {subject}"))?;
+    println!(
+        "[{}] {}",
+        short_id(&new_id),
+        message.lines().next().unwrap_or_default()
+    );
+    Ok(new_id)
+}
+
+/// Replay the change `id` made (or its inverse, for a revert) on top of HEAD.
+///
+/// On conflicts the message is left in `MERGE_MSG` and `CHERRY_PICK_HEAD` or `REVERT_HEAD` names
+/// the commit, waiting for the resolution to be committed.
+pub fn pick(action: Action, id: &str, options: &PickOptions) -> std::io::Result<PickOutcome> {
+    let commit = OgitCommit::get(id)?;
+    let parent = parent_of(id, &commit, options.mainline)?;
+    let parent_tree = match &parent {
+        Some(parent) => OgitCommit::get(parent)?.tree,
+        None => Index::default().write_tree()?,
+    };
+    let (base, theirs) = match action {
+        Action::Pick => (parent_tree, commit.tree.clone()),
+        Action::Revert => (commit.tree.clone(), parent_tree),
+    };
+    let head = filesystem::read_head_file()?;
+    let head_tree = match head.as_str() {
+        "" => Index::default().write_tree()?,
+        head => OgitCommit::get(head)?.tree,
+    };
+    // without committing, earlier picks pile up in the index
+    let ours = if options.no_commit {
+        Index::load()?.write_tree()?
+    } else {
+        head_tree.clone()
+    };
+
+    let described = format!("{}... {}", short_id(id), commit.subject());
+    let this = format!("{} ({})", short_id(id), commit.subject());
+    let parent_of_this = format!("parent of {this}");
+    let (base_label, their_label) = match action {
+        Action::Pick => (&parent_of_this, &this),
+        Action::Revert => (&this, &parent_of_this),
+    };
+    let labels = MergeLabels {
+        ours: "HEAD",
+        base: base_label,
+        theirs: their_label,
+    };
+    let merged = merge::merge_into_worktree(
+        Some(&base),
+        &ours,
+        &theirs,
+        &labels,
+        options.style,
+        action.command(),
+    )?;
+    let message = message(action, id, &commit, parent.as_deref(), options);
+    let Some(tree) = merged else {
+        fs::write(filesystem::ogit_path(merge::MERGE_MSG)?, &message)?;
+        fs::write(
+            filesystem::ogit_path(action.head_file())?,
+            format!("{id}\n"),
+        )?;
+        let verb = match action {
+            Action::Pick => "apply",
+            Action::Revert => "revert",
+        };
+        eprintln!("error: could not {verb} {described}");
+        return Ok(PickOutcome::Conflicted);
+    };
+    if options.no_commit {
+        return Ok(PickOutcome::Applied);
+    }
+    if tree == head_tree {
+        return Ok(PickOutcome::Empty);
+    }
+    let reflog_action = options.reflog_action.as_deref();
+    commit_result(
+        action,
+        id,
+        &tree,
+        &message,
+        reflog_action.unwrap_or(action.command()),
+    )
+    .map(PickOutcome::Committed)
+}
+
+fn state_path(name: &str) -> std::io::Result<PathBuf> {
+    filesystem::ogit_path(&format!("{STATE_DIR}/{name}"))
+}
+
+/// Whether a stopped cherry-pick or revert is waiting to be continued.
+pub fn in_progress() -> std::io::Result<bool> {
+    Ok(filesystem::ogit_path(STATE_DIR)?.is_dir())
+}
+
+/// The commits still to replay, the stopped one first.
+fn read_todo() -> std::io::Result<Vec<(Action, String)>> {
+    fs::read_to_string(state_path("todo")?)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let action = parts.next().unwrap_or_default().parse::<Action>();
+            match (action, parts.next()) {
+                (Ok(action), Some(id)) => Ok((action, revision::resolve(id)?)),
+                _ => Err(std::io::Error::other(format!("invalid todo line '{line}'"))),
+            }
+        })
+        .collect()
+}
+
+fn write_todo(todo: &[(Action, String)]) -> std::io::Result<()> {
+    let mut content = String::new();
+    for (action, id) in todo {
+        let subject = OgitCommit::get(id)?.subject();
+        content.push_str(&format!("{action} {} {subject}\n", short_id(id)));
+    }
+    fs::write(state_path("todo")?, content)
+}
+
+fn write_options(options: &PickOptions) -> std::io::Result<()> {
+    let mut content = format!("conflict-style {}\n", options.style);
+    if options.no_commit {
+        content.push_str("no-commit\n");
+    }
+    if options.record_origin {
+        content.push_str("record-origin\n");
+    }
+    if let Some(mainline) = options.mainline {
+        content.push_str(&format!("mainline {mainline}\n"));
+    }
+    fs::write(state_path("opts")?, content)
+}
+
+fn read_options() -> std::io::Result<PickOptions> {
+    let mut options = PickOptions::default();
+    for line in fs::read_to_string(state_path("opts")?)?.lines() {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "no-commit" => options.no_commit = true,
+            "record-origin" => options.record_origin = true,
+            "mainline" => options.mainline = value.parse().ok(),
+            "conflict-style" => options.style = value.parse().unwrap_or_default(),
+            _ => (),
+        }
+    }
+    Ok(options)
+}
+
+fn print_stop_hints(action: Action) {
+    let command = action.command();
+    eprintln!("hint: After resolving the conflicts, run \"og {command} --continue\".");
+    eprintln!("hint: You can instead skip this commit with \"og {command} --skip\".");
+    eprintln!("hint: To abort and get back to the state before \"og {command}\",");
+    eprintln!("hint: run \"og {command} --abort\".");
+}
+
+/// Replay the remaining commits of the todo list in order.
+///
+/// Returns `false` when one stopped, leaving the state for `--continue`, `--skip` or `--abort`.
+fn run(options: &PickOptions) -> std::io::Result<bool> {
+    let mut todo = read_todo()?;
+    while let Some((action, id)) = todo.first().cloned() {
+        match pick(action, &id, options)? {
+            PickOutcome::Committed(_) | PickOutcome::Applied => (),
+            PickOutcome::Empty => {
+                eprintln!(
+                    "The previous {} is now empty, possibly due to conflict resolution.",
+                    action.command()
+                );
+                eprintln!(
+                    "hint: Drop it with \"og {} --skip\", or give up with \"og {} --abort\".",
+                    action.command(),
+                    action.command()
+                );
+                return Ok(false);
+            }
+            PickOutcome::Conflicted => {
+                print_stop_hints(action);
+                return Ok(false);
+            }
+        }
+        todo.remove(0);
+        write_todo(&todo)?;
+    }
+    fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+    Ok(true)
+}
+
+/// The commits `specs` name in the order `action` replays them.
+///
+/// Ranges are expanded, oldest first for picks and newest first for reverts, the same as git.
+pub fn commits_to_replay(action: Action, specs: &[String]) -> std::io::Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for spec in specs {
+        if !spec.contains("..") {
+            ids.push(revision::resolve(spec)?);
+            continue;
+        }
+        let mut range = revision::walk_spec(spec)?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        if action == Action::Pick {
+            range.reverse();
+        }
+        ids.extend(range);
+    }
+    if ids.is_empty() {
+        return Err(std::io::Error::other("empty commit set passed"));
+    }
+    Ok(ids)
+}
+
+/// Start replaying `ids` on top of HEAD.
+pub fn start(action: Action, ids: &[String], options: &PickOptions) -> std::io::Result<bool> {
+    if in_progress()? {
+        return Err(std::io::Error::other(format!(
+            "a cherry-pick or revert is already in progress\nhint: try \"og {} (--continue | --skip | --abort)\"",
+            action.command()
+        )));
+    }
+    if merge::in_progress()? {
+        return Err(std::io::Error::other(
+            "You have not concluded your merge (MERGE_HEAD exists).",
+        ));
+    }
+    // catch merges without a mainline before touching anything
+    for id in ids {
+        parent_of(id, &OgitCommit::get(id)?, options.mainline)?;
+    }
+    fs::create_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+    fs::write(
+        state_path("head")?,
+        format!("{}\n", filesystem::read_head_file()?),
+    )?;
+    write_options(options)?;
+    let todo = ids
+        .iter()
+        .map(|id| (action, id.clone()))
+        .collect::<Vec<_>>();
+    write_todo(&todo)?;
+    let result = run(options);
+    // a run that failed before replaying anything leaves nothing to continue
+    if result.is_err() && read_todo()?.len() == todo.len() {
+        fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)?;
+    }
+    result
+}
+
+fn ensure_in_progress() -> std::io::Result<()> {
+    if in_progress()? {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(
+            "no cherry-pick or revert in progress",
+        ))
+    }
+}
+
+/// Carry on after a stopped commit, committing the index as its resolution.
+pub fn resume() -> std::io::Result<bool> {
+    ensure_in_progress()?;
+    let options = read_options()?;
+    let mut todo = read_todo()?;
+    if let Some((action, id)) = todo.first().cloned() {
+        // without the head file the stopped commit was empty or committed by hand already
+        if filesystem::ogit_path(action.head_file())?.exists() {
+            // the resolution is what was added to the index, untracked files stay out of it
+            let index = Index::load()?;
+            if let Some(path) = index.unmerged_paths().first() {
+                return Err(std::io::Error::other(format!(
+                    "{}: needs merge\nhint: fix the conflicts and run \"og add <path>\" before continuing",
+                    path.display()
+                )));
+            }
+            if !options.no_commit {
+                let message = merge::prepared_message()?.unwrap_or_default();
+                let tree = index.write_tree()?;
+                commit_result(action, &id, &tree, &message, action.command())?;
+            }
+            merge::clear_state()?;
+        }
+        todo.remove(0);
+        write_todo(&todo)?;
+    }
+    run(&options)
+}
+
+/// Drop the stopped commit, putting back HEAD's version of what it touched, and carry on.
+pub fn skip() -> std::io::Result<bool> {
+    ensure_in_progress()?;
+    merge::reset_merge()?;
+    let mut todo = read_todo()?;
+    if !todo.is_empty() {
+        todo.remove(0);
+    }
+    write_todo(&todo)?;
+    run(&read_options()?)
+}
+
+/// Stop and move HEAD and the working tree back to where they were before the run started.
+pub fn abort() -> std::io::Result<()> {
+    ensure_in_progress()?;
+    merge::reset_merge()?;
+    let orig_head = fs::read_to_string(state_path("head")?)?;
+    let orig_head = orig_head.trim();
+    let head = filesystem::read_head_file()?;
+    if !orig_head.is_empty() && orig_head != head {
+        let head_tree = OgitCommit::get(&head)?.tree;
+        let orig_tree = OgitCommit::get(orig_head)?.tree;
+        porcelain::update_working_tree(Some(&head_tree), &orig_tree, "abort")?;
+        refs::move_head(orig_head, &head, &format!("reset: moving to {orig_head}"))?;
+    }
+    fs::remove_dir_all(filesystem::ogit_path(STATE_DIR)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::data::{reflog, testrepo::TestRepo};
+
+    #[test]
+    fn test_action_round_trip() {
+        for action in [Action::Pick, Action::Revert] {
+            assert_eq!(action.to_string().parse::<Action>(), Ok(action));
+        }
+        assert!("squash".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn test_mainline_must_match_parents() {
+        let commit = |parents: &[&str]| OgitCommit {
+            tree: String::new(),
+            parents: parents.iter().map(|p| p.to_string()).collect(),
+            author: String::new(),
+            author_time: chrono::DateTime::default(),
+            committer: String::new(),
+            committer_time: chrono::DateTime::default(),
+            message: Vec::new(),
+        };
+        assert_eq!(
+            parent_of("c", &commit(&["p"]), None).unwrap().as_deref(),
+            Some("p")
+        );
+        assert!(parent_of("c", &commit(&["p"]), Some(1)).is_err());
+        assert!(parent_of("c", &commit(&["p", "q"]), None).is_err());
+        assert_eq!(
+            parent_of("c", &commit(&["p", "q"]), Some(2))
+                .unwrap()
+                .as_deref(),
+            Some("q")
+        );
+        assert!(parent_of("c", &commit(&["p", "q"]), Some(3)).is_err());
+    }
+
+    #[test]
+    fn test_continue_commits_the_index() {
+        let repo = TestRepo::new();
+        repo.write("a.txt", "one\n");
+        let base = repo.commit_all("first");
+        repo.write("a.txt", "two\n");
+        let picked = repo.commit_all("second");
+        repo.rewind(&base);
+        repo.write("a.txt", "three\n");
+        let head = repo.commit_all("third");
+
+        let options = PickOptions::default();
+        assert!(!start(Action::Pick, std::slice::from_ref(&picked), &options).unwrap());
+        repo.write("untracked.log", "noise\n");
+        repo.write("a.txt", "resolved\n");
+        assert!(resume().is_err());
+        porcelain::add(&[PathBuf::from("a.txt")]).unwrap();
+        assert!(resume().unwrap());
+
+        let commit = OgitCommit::get(&filesystem::read_head_file().unwrap()).unwrap();
+        assert_eq!(commit.parents, vec![head]);
+        assert_eq!(commit.subject(), "second");
+        let tree = Index::from_tree(&commit.tree).unwrap();
+        assert!(tree.get(Path::new("a.txt")).is_some());
+        assert!(tree.get(Path::new("untracked.log")).is_none());
+        let logged = reflog::read("HEAD").unwrap().pop().unwrap();
+        assert_eq!(logged.message, "cherry-pick: second");
+        assert!(!in_progress().unwrap());
+    }
+}
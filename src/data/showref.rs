@@ -0,0 +1,96 @@
+//! Listing refs with the ids they point at, the way `show-ref` does.
+//!
+//! A pattern matches a ref when it is the full name or its last path components, so `main`
+//! matches `refs/heads/main` and `refs/remotes/origin/main` but not `refs/heads/domain`.
+use super::{filesystem, refs, tags};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShowRefOptions {
+    /// Show HEAD too
+    pub head: bool,
+    /// Only branches
+    pub heads: bool,
+    /// Only tags
+    pub tags: bool,
+    /// Only show the ids, abbreviated to this length
+    pub hash: Option<usize>,
+    /// Show what tags point at, as `<ref>^{}`
+    pub dereference: bool,
+    /// Patterns are full ref names that must all exist
+    pub verify: bool,
+}
+
+/// Whether `name` matches `pattern` by its trailing path components.
+fn matches_tail(name: &str, pattern: &str) -> bool {
+    name == pattern
+        || name
+            .strip_suffix(pattern)
+            .is_some_and(|rest| rest.ends_with('/'))
+}
+
+fn format_line(id: &str, name: &str, options: &ShowRefOptions) -> String {
+    match options.hash {
+        Some(length) => format!("{}\n", &id[..length.clamp(4, id.len())]),
+        None => format!("{id} {name}\n"),
+    }
+}
+
+/// The lines for the ref `name` at `id`, with the peeled tag when asked for.
+fn show_one(name: &str, id: &str, options: &ShowRefOptions) -> std::io::Result<String> {
+    let mut out = format_line(id, name, options);
+    if options.dereference {
+        let peeled = tags::peel(id)?;
+        if peeled != id {
+            out.push_str(&format_line(&peeled, &format!("{name}^{{}}"), options));
+        }
+    }
+    Ok(out)
+}
+
+/// The refs matching `patterns`, or all of them, `None` when none matched.
+pub fn show_ref(patterns: &[String], options: &ShowRefOptions) -> std::io::Result<Option<String>> {
+    let mut out = String::new();
+    if options.verify {
+        for name in patterns {
+            let valid = name == "HEAD" || name.starts_with("refs/") && refs::is_valid_name(name);
+            match filesystem::read_ref(name)?.filter(|_| valid) {
+                Some(id) => out.push_str(&show_one(name, &id, options)?),
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("'{name}' - not a valid ref"),
+                    ))
+                }
+            }
+        }
+        return Ok(Some(out));
+    }
+    if options.head {
+        if let Some(id) = filesystem::read_ref("HEAD")? {
+            out.push_str(&show_one("HEAD", &id, options)?);
+        }
+    }
+    for (name, id) in filesystem::list_refs()? {
+        let kind_wanted = !(options.heads || options.tags)
+            || options.heads && name.starts_with("refs/heads/")
+            || options.tags && name.starts_with("refs/tags/");
+        let matched = patterns.is_empty() || patterns.iter().any(|p| matches_tail(&name, p));
+        if kind_wanted && matched {
+            out.push_str(&show_one(&name, &id, options)?);
+        }
+    }
+    Ok(Some(out).filter(|out| !out.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_tail() {
+        assert!(matches_tail("refs/heads/main", "main"));
+        assert!(matches_tail("refs/remotes/origin/main", "origin/main"));
+        assert!(matches_tail("refs/heads/main", "refs/heads/main"));
+        assert!(!matches_tail("refs/heads/domain", "main"));
+    }
+}
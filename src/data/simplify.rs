@@ -0,0 +1,321 @@
+//! History simplification for `log -- <paths>`, the way git does it.
+//!
+//! A commit is TREESAME to a parent when the paths look the same in both, and only commits that
+//! are not TREESAME are shown. By default a merge TREESAME to one of its parents only follows that
+//! parent, so side branches whose changes to the paths did not survive are never looked at.
+//! `--full-history` follows every parent and shows every merge, and `--simplify-merges` then
+//! only keeps the merges that still join lines of history changing the paths. The parents of the shown commits are
+//! rewritten to their nearest shown ancestors, so the history stays connected.
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    commits::OgitCommit,
+    diff::{self, DiffEntry, DiffOptions, DiffStatus},
+    graph::CommitGraph,
+};
+
+/// How a path-limited history is simplified.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Simplification {
+    /// Follow every parent of a merge, not only the one it is TREESAME to
+    pub full_history: bool,
+    /// Only show merges that join lines of history changing the paths, implies `full_history`
+    pub simplify_merges: bool,
+}
+
+/// Whether the paths inside `pathspecs` look the same in both trees, a missing tree is empty.
+fn is_treesame(
+    old_tree: Option<&str>,
+    new_tree: &str,
+    pathspecs: &[PathBuf],
+) -> std::io::Result<bool> {
+    let options = DiffOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    let changes = diff::diff_trees(old_tree, Some(new_tree), &options)?;
+    Ok(diff::limit_to_paths(changes, pathspecs).is_empty())
+}
+
+/// A walked commit with what simplification found out about it.
+struct Node {
+    commit: OgitCommit,
+    /// The parents the walk continues through
+    followed: Vec<String>,
+    /// TREESAME to one of its parents, or a root without any of the paths
+    treesame: bool,
+}
+
+/// Order `ids` so that every commit comes after the ones it follows.
+fn parents_first(ids: &[String], nodes: &HashMap<String, Node>) -> Vec<String> {
+    let mut order = Vec::with_capacity(ids.len());
+    let mut visited = HashSet::new();
+    for id in ids {
+        if !visited.insert(id.clone()) {
+            continue;
+        }
+        // a commit is ordered once every followed parent was
+        let mut stack = vec![(id.clone(), 0)];
+        while let Some((id, next)) = stack.pop() {
+            let followed = &nodes[&id].followed;
+            match followed[next..]
+                .iter()
+                .position(|parent| nodes.contains_key(parent) && !visited.contains(parent))
+            {
+                Some(offset) => {
+                    let parent = followed[next + offset].clone();
+                    stack.push((id, next + offset + 1));
+                    visited.insert(parent.clone());
+                    stack.push((parent, 0));
+                }
+                None => order.push(id),
+            }
+        }
+    }
+    order
+}
+
+/// Keep the commits of `commits` that change `pathspecs`, with their parents rewritten.
+///
+/// `commits` is the whole walk, newest first, and `tips` the commits it started from. Parents
+/// outside of the walk are still compared against, but never shown.
+pub fn simplify(
+    commits: Vec<(String, OgitCommit)>,
+    tips: &[String],
+    pathspecs: &[PathBuf],
+    options: Simplification,
+    graph: &mut CommitGraph,
+) -> std::io::Result<Vec<(String, OgitCommit)>> {
+    let full_history = options.full_history || options.simplify_merges;
+    let mut trees = commits
+        .iter()
+        .map(|(id, commit)| (id.clone(), commit.tree.clone()))
+        .collect::<HashMap<_, _>>();
+    let walked = commits.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>();
+    let mut nodes = HashMap::new();
+    for (id, commit) in commits {
+        let mut same = None;
+        for parent in &commit.parents {
+            if !trees.contains_key(parent) {
+                trees.insert(parent.clone(), OgitCommit::get(parent)?.tree);
+            }
+            if is_treesame(Some(&trees[parent]), &commit.tree, pathspecs)? {
+                same = Some(parent.clone());
+                break;
+            }
+        }
+        let treesame = if commit.parents.is_empty() {
+            is_treesame(None, &commit.tree, pathspecs)?
+        } else {
+            same.is_some()
+        };
+        let followed = match same {
+            Some(parent) if !full_history => vec![parent],
+            _ => commit.parents.clone(),
+        };
+        nodes.insert(
+            id,
+            Node {
+                commit,
+                followed,
+                treesame,
+            },
+        );
+    }
+
+    let tips = tips
+        .iter()
+        .filter(|id| nodes.contains_key(*id))
+        .cloned()
+        .collect::<Vec<_>>();
+    let order = parents_first(&tips, &nodes);
+    // like git with rewritten parents, the full history shows every merge it reached
+    let shown = |node: &Node| !node.treesame || (full_history && node.commit.parents.len() > 1);
+    // the nearest shown commits each reached commit leads to, itself when shown
+    let mut nearest: HashMap<String, Vec<String>> = HashMap::new();
+    let mut rewritten: HashMap<String, Vec<String>> = HashMap::new();
+    for id in &order {
+        let node = &nodes[id];
+        let mut parents = Vec::new();
+        for parent in &node.followed {
+            for ancestor in nearest.get(parent).into_iter().flatten() {
+                if !parents.contains(ancestor) {
+                    parents.push(ancestor.clone());
+                }
+            }
+        }
+        if shown(node) {
+            nearest.insert(id.clone(), vec![id.clone()]);
+            rewritten.insert(id.clone(), parents);
+        } else {
+            nearest.insert(id.clone(), parents);
+        }
+    }
+
+    if options.simplify_merges {
+        // what each shown commit turned into, a dropped merge is replaced by its one parent
+        let mut replaced: HashMap<String, Option<String>> = HashMap::new();
+        for id in &order {
+            let Some(shown_parents) = rewritten.get(id) else {
+                continue;
+            };
+            let mut parents = Vec::new();
+            for parent in shown_parents {
+                if let Some(Some(parent)) = replaced.get(parent) {
+                    if !parents.contains(parent) {
+                        parents.push(parent.clone());
+                    }
+                }
+            }
+            let mut kept = Vec::new();
+            for parent in &parents {
+                let mut redundant = false;
+                for other in parents.iter().filter(|other| *other != parent) {
+                    redundant |= graph.is_ancestor(parent, other)?;
+                }
+                if !redundant {
+                    kept.push(parent.clone());
+                }
+            }
+            let node = &nodes[id];
+            let dropped = match kept.as_slice() {
+                [parent] if node.commit.parents.len() > 1 => {
+                    is_treesame(Some(&trees[parent]), &node.commit.tree, pathspecs)?
+                }
+                _ => false,
+            };
+            if dropped {
+                replaced.insert(id.clone(), kept.pop());
+                rewritten.remove(id);
+            } else {
+                replaced.insert(id.clone(), Some(id.clone()));
+                rewritten.insert(id.clone(), kept);
+            }
+        }
+    }
+
+    Ok(walked
+        .into_iter()
+        .filter_map(|id| {
+            let parents = rewritten.remove(&id)?;
+            let mut commit = nodes.remove(&id)?.commit;
+            commit.parents = parents;
+            Some((id, commit))
+        })
+        .collect())
+}
+
+/// Keep the commits of `commits` that change `path`, following it back through renames.
+///
+/// `commits` is newest first, each commit is compared against its first parent.
+pub fn follow(
+    commits: Vec<(String, OgitCommit)>,
+    path: &Path,
+) -> std::io::Result<Vec<(String, OgitCommit)>> {
+    let mut path = path.to_path_buf();
+    let mut shown = Vec::new();
+    for (id, commit) in commits {
+        let Some(change) = followed_change(&commit, &path)? else {
+            continue;
+        };
+        if let Some(old) = renamed_from(&change) {
+            path = old;
+        }
+        shown.push((id, commit));
+    }
+    Ok(shown)
+}
+
+/// How `commit` changed `path` against its first parent, with renames detected.
+fn followed_change(commit: &OgitCommit, path: &Path) -> std::io::Result<Option<DiffEntry>> {
+    let options = DiffOptions {
+        recursive: true,
+        rename_score: Some(diff::DEFAULT_SIMILARITY),
+        ..Default::default()
+    };
+    Ok(diff::diff_commit(commit, &options)?
+        .into_iter()
+        .find(|change| change.path() == path))
+}
+
+fn renamed_from(change: &DiffEntry) -> Option<PathBuf> {
+    match (change.status, &change.old) {
+        (DiffStatus::Renamed | DiffStatus::Copied, Some(old)) => Some(old.path.clone()),
+        _ => None,
+    }
+}
+
+/// The name the followed `path` had before `commit`, when `commit` renamed or copied it there.
+pub fn name_before(commit: &OgitCommit, path: &Path) -> std::io::Result<Option<PathBuf>> {
+    Ok(followed_change(commit, path)?
+        .as_ref()
+        .and_then(renamed_from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(followed: &[&str]) -> Node {
+        Node {
+            commit: OgitCommit::new(
+                String::new(),
+                followed.iter().map(ToString::to_string).collect(),
+                "A U Thor <author@example.com> 0 +0000",
+                "A U Thor <author@example.com> 0 +0000",
+                Vec::new(),
+            ),
+            followed: followed.iter().map(ToString::to_string).collect(),
+            treesame: false,
+        }
+    }
+
+    #[test]
+    fn test_parents_first() {
+        let nodes = [
+            ("merge", node(&["main", "side"])),
+            ("main", node(&["base"])),
+            ("side", node(&["base", "outside"])),
+            ("base", node(&[])),
+        ]
+        .into_iter()
+        .map(|(id, node)| (id.to_string(), node))
+        .collect::<HashMap<_, _>>();
+        let order = parents_first(&["merge".to_string()], &nodes);
+        assert_eq!(order, ["base", "main", "side", "merge"]);
+    }
+
+    #[test]
+    fn test_name_before_follows_renames() {
+        let repo = crate::data::testrepo::TestRepo::new();
+        repo.write("old.txt", "one\ntwo\nthree\n");
+        repo.write("other.txt", "other\n");
+        let first = repo.commit_all("first");
+        std::fs::rename("old.txt", "new.txt").unwrap();
+        let renamed = repo.commit_all("rename");
+
+        let commit = |id: &str| OgitCommit::get(id).unwrap();
+        let before = name_before(&commit(&renamed), Path::new("new.txt")).unwrap();
+        assert_eq!(before, Some(PathBuf::from("old.txt")));
+        assert_eq!(
+            name_before(&commit(&renamed), Path::new("other.txt")).unwrap(),
+            None
+        );
+        assert_eq!(
+            name_before(&commit(&first), Path::new("old.txt")).unwrap(),
+            None
+        );
+        let history = follow(
+            vec![
+                (renamed.clone(), commit(&renamed)),
+                (first.clone(), commit(&first)),
+            ],
+            Path::new("new.txt"),
+        )
+        .unwrap();
+        assert_eq!(history.len(), 2);
+    }
+}
@@ -0,0 +1,377 @@
+//! Stashes shelve local changes as commits, so the working tree can go back to HEAD and the
+//! changes can be brought back later on top of any commit.
+//!
+//! A stash is a commit holding the working tree of tracked files. Its first parent is the HEAD it
+//! was made on and its second a commit holding the index. With `-u` a third parent holds the
+//! untracked files. `refs/stash` points at the newest stash, the others are only remembered by its
+//! reflog, where `stash@{0}` is the last entry.
+use std::collections::BTreeSet;
+
+use super::{
+    base,
+    commits::OgitCommit,
+    diff::{self, DiffFormat, DiffOptions, NULL_ID},
+    diffstat::StatOptions,
+    filesystem,
+    index::Index,
+    merge, porcelain,
+    reflog::{self, ReflogEntry},
+    refs::{self, RefTransaction},
+    revision,
+    xmerge::{ConflictStyle, MergeLabels},
+};
+
+const STASH_REF: &str = "refs/stash";
+
+/// Which stash `name` refers to: `stash@{n}` or plain `n`, the newest one when not given.
+pub fn parse_stash_name(name: Option<&str>) -> Result<usize, String> {
+    let Some(name) = name else {
+        return Ok(0);
+    };
+    let number = name
+        .strip_prefix("stash@{")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(name);
+    number
+        .parse()
+        .map_err(|_| format!("{name} is not a valid reference"))
+}
+
+/// Stashes newest first, so that the position of an entry is its number.
+fn entries() -> std::io::Result<Vec<ReflogEntry>> {
+    let mut entries = reflog::read(STASH_REF)?;
+    entries.reverse();
+    Ok(entries)
+}
+
+fn stash_id(number: usize) -> std::io::Result<String> {
+    let entries = entries()?;
+    if entries.is_empty() {
+        return Err(std::io::Error::other("No stash entries found."));
+    }
+    entries
+        .get(number)
+        .map(|entry| entry.new.clone())
+        .ok_or_else(|| {
+            std::io::Error::other(format!("stash@{{{number}}} is not a valid reference"))
+        })
+}
+
+fn head_commit() -> std::io::Result<(String, OgitCommit)> {
+    match revision::resolve("HEAD") {
+        Ok(id) => Ok((id.clone(), OgitCommit::get(&id)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(std::io::Error::other(
+            "You do not have the initial commit yet",
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Split the working tree into a tree of the files HEAD or the index track, and one of the rest.
+fn snapshot_worktree(head_tree: &str, index: &Index) -> std::io::Result<(Index, Index)> {
+    let tracked = Index::from_tree(head_tree)?;
+    let mut worktree = Index::from_tree(&base::write_tree(None, true)?.hex_string())?;
+    let mut untracked = Index::default();
+    let paths = worktree
+        .entries()
+        .map(|entry| entry.path.clone())
+        .collect::<Vec<_>>();
+    for path in paths {
+        if tracked.get(&path).is_none() && index.get(&path).is_none() {
+            untracked.add(worktree.get(&path).unwrap().clone());
+            worktree.remove(&path);
+        }
+    }
+    Ok((worktree, untracked))
+}
+
+/// Save the local changes as a new stash and bring the working tree back to HEAD.
+///
+/// Returns `false` when there was nothing to save.
+pub fn push(message: Option<&str>, include_untracked: bool) -> std::io::Result<bool> {
+    let (head, head_commit) = head_commit()?;
+    let previous = filesystem::read_ref(STASH_REF)?.unwrap_or_default();
+    let index = Index::load()?;
+    let unresolved = merge::unresolved_paths(&index)?;
+    if let Some(path) = unresolved.first() {
+        return Err(std::io::Error::other(format!(
+            "{}: needs merge\ncould not save index tree",
+            path.display()
+        )));
+    }
+    let index_tree = index.write_tree()?;
+    let (worktree, untracked) = snapshot_worktree(&head_commit.tree, &index)?;
+    let worktree_tree = worktree.write_tree()?;
+    let untracked = if include_untracked && untracked.entries().next().is_some() {
+        Some(untracked)
+    } else {
+        None
+    };
+    if worktree_tree == head_commit.tree && index_tree == head_commit.tree && untracked.is_none() {
+        println!("No local changes to save");
+        return Ok(false);
+    }
+
+    let branch = filesystem::read_symbolic_ref("HEAD")?
+        .and_then(|target| target.strip_prefix("refs/heads/").map(str::to_string))
+        .unwrap_or_else(|| "(no branch)".to_string());
+    let on = format!("{branch}: {} {}", &head[..7], head_commit.subject());
+    let message = match message {
+        Some(message) => format!("On {branch}: {message}"),
+        None => format!("WIP on {on}"),
+    };
+    let mut parents = vec![head.clone()];
+    parents.push(
+        base::commit_tree(
+            &index_tree,
+            std::slice::from_ref(&head),
+            Some(format!("index on {on}\n")),
+        )?
+        .hex_string(),
+    );
+    if let Some(untracked) = &untracked {
+        let tree = untracked.write_tree()?;
+        parents.push(
+            base::commit_tree(&tree, &[], Some(format!("untracked files on {on}\n")))?.hex_string(),
+        );
+    }
+    let stash =
+        base::commit_tree(&worktree_tree, &parents, Some(format!("{message}\n")))?.hex_string();
+    let mut transaction = RefTransaction::new();
+    let expected = if previous.is_empty() {
+        NULL_ID
+    } else {
+        &previous
+    };
+    transaction.update(STASH_REF, &stash, Some(expected));
+    // refs/stash is not a branch, so its log is kept here rather than by the transaction
+    transaction.commit(None)?;
+    reflog::append_ref(STASH_REF, &previous, &stash, &message)?;
+
+    porcelain::update_working_tree(Some(&worktree_tree), &head_commit.tree, "stash")?;
+    for entry in untracked.iter().flat_map(Index::entries) {
+        porcelain::remove_worktree_file(&entry.path)?;
+    }
+    println!("Saved working directory and index state {message}");
+    Ok(true)
+}
+
+/// Every stash as `stash@{n}: message`, newest first.
+pub fn list() -> std::io::Result<Vec<String>> {
+    Ok(entries()?
+        .iter()
+        .enumerate()
+        .map(|(number, entry)| format!("stash@{{{number}}}: {}", entry.message))
+        .collect())
+}
+
+/// The changes a stash holds against the commit it was made on, as a diffstat unless asked otherwise.
+pub fn show(number: usize, options: &DiffOptions, format: &DiffFormat) -> std::io::Result<String> {
+    let stash = OgitCommit::get(&stash_id(number)?)?;
+    let base = OgitCommit::get(&stash.parents[0])?;
+    let mut format = format.clone();
+    if !format.is_enabled() {
+        format.stat = Some(StatOptions::default());
+    }
+    let changes = diff::diff_trees(Some(&base.tree), Some(&stash.tree), options)?;
+    diff::render(&changes, &format)
+}
+
+/// Bring the changes of a stash back into the working tree, merging them with what changed since.
+///
+/// Returns `false` when conflicts were left in the working tree.
+pub fn apply(number: usize) -> std::io::Result<bool> {
+    if merge::in_progress()? {
+        return Err(std::io::Error::other(
+            "You have not concluded your merge (MERGE_HEAD exists).",
+        ));
+    }
+    let stash = OgitCommit::get(&stash_id(number)?)?;
+    let (_, head_commit) = head_commit()?;
+    let untracked = match stash.parents.get(2) {
+        Some(id) => Index::from_tree(&OgitCommit::get(id)?.tree)?,
+        None => Index::default(),
+    };
+    let existing = untracked
+        .entries()
+        .filter(|entry| entry.path.exists())
+        .map(|entry| format!("{} already exists, no checkout\n", entry.path.display()))
+        .collect::<String>();
+    if !existing.is_empty() {
+        return Err(std::io::Error::other(format!(
+            "{existing}could not restore untracked files from stash"
+        )));
+    }
+
+    let staged = Index::load()?;
+    let base = OgitCommit::get(&stash.parents[0])?;
+    let labels = MergeLabels {
+        ours: "Updated upstream",
+        base: "Stash base",
+        theirs: "Stashed changes",
+    };
+    let merged = merge::merge_into_worktree(
+        Some(&base.tree),
+        &head_commit.tree,
+        &stash.tree,
+        &labels,
+        ConflictStyle::default(),
+        "stash apply",
+    )?;
+    for entry in untracked.entries() {
+        let object = filesystem::get_object(&entry.id, None)?;
+        filesystem::write_worktree_file(&entry.path, &object.data, &entry.mode)?;
+    }
+    // the stashed changes come back as local changes, unless the merge left them conflicted
+    let mut index = match merged {
+        Some(_) => Index::from_tree(&head_commit.tree)?,
+        None => Index::load()?,
+    };
+    keep_staged(&mut index, &staged, &base.tree, &stash.tree)?;
+    index.save()?;
+    Ok(merged.is_some())
+}
+
+/// Put back into `index` what was `staged` for the paths the stash did not change.
+fn keep_staged(index: &mut Index, staged: &Index, base: &str, stash: &str) -> std::io::Result<()> {
+    let (base, stash) = (Index::from_tree(base)?, Index::from_tree(stash)?);
+    let paths = staged
+        .entries()
+        .chain(index.entries())
+        .map(|entry| entry.path.clone())
+        .collect::<BTreeSet<_>>();
+    for path in paths
+        .iter()
+        .filter(|path| base.get(path) == stash.get(path))
+    {
+        match staged.get(path) {
+            Some(entry) => index.add(entry.clone()),
+            None => index.remove(path),
+        }
+    }
+    Ok(())
+}
+
+/// Forget a stash, the newer ones keep their numbers and the older ones move up.
+pub fn drop(number: usize) -> std::io::Result<()> {
+    let id = stash_id(number)?;
+    let current = filesystem::read_ref(STASH_REF)?.unwrap_or_else(|| NULL_ID.to_string());
+    let mut entries = entries()?;
+    entries.remove(number);
+    let mut transaction = RefTransaction::new();
+    match entries.first() {
+        Some(newest) => transaction.update(STASH_REF, &newest.new, Some(&current)),
+        None => transaction.delete(STASH_REF, Some(&current)),
+    };
+    transaction.commit(None)?;
+    entries.reverse();
+    reflog::write(STASH_REF, &entries)?;
+    println!("Dropped stash@{{{number}}} ({id})");
+    Ok(())
+}
+
+/// Apply a stash and drop it, unless applying it left conflicts.
+pub fn pop(number: usize) -> std::io::Result<bool> {
+    if !apply(number)? {
+        println!("The stash entry is kept in case you need it again.");
+        return Ok(false);
+    }
+    drop(number)?;
+    Ok(true)
+}
+
+/// Forget every stash.
+pub fn clear() -> std::io::Result<()> {
+    filesystem::delete_ref(STASH_REF)?;
+    reflog::write(STASH_REF, &[])
+}
+
+/// Create the branch `name` at the commit a stash was made on, and pop the stash there.
+///
+/// HEAD is switched to the new branch, the way `checkout -b` would.
+pub fn branch(name: &str, number: usize) -> std::io::Result<bool> {
+    let branch_ref = format!("refs/heads/{name}");
+    if filesystem::read_ref(&branch_ref)?.is_some() {
+        return Err(std::io::Error::other(format!(
+            "a branch named '{name}' already exists"
+        )));
+    }
+    let stash = OgitCommit::get(&stash_id(number)?)?;
+    let start = stash.parents[0].clone();
+    let (head, head_commit) = head_commit()?;
+    porcelain::update_working_tree(
+        Some(&head_commit.tree),
+        &OgitCommit::get(&start)?.tree,
+        "stash branch",
+    )?;
+    let from = match filesystem::read_symbolic_ref("HEAD")? {
+        Some(branch) => refs::shorten(&branch).to_string(),
+        None => head.clone(),
+    };
+    let mut transaction = RefTransaction::new();
+    transaction
+        .create(&branch_ref, &start)
+        .update_symbolic("HEAD", &branch_ref, Some(&head))
+        .log_as(&format!("checkout: moving from {from} to {name}"));
+    transaction.commit(Some(&format!("branch: Created from {start}")))?;
+    pop(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::data::testrepo::TestRepo;
+
+    #[test]
+    fn test_parse_stash_name() {
+        assert_eq!(parse_stash_name(None), Ok(0));
+        assert_eq!(parse_stash_name(Some("stash@{2}")), Ok(2));
+        assert_eq!(parse_stash_name(Some("1")), Ok(1));
+        assert!(parse_stash_name(Some("stash@{x}")).is_err());
+        assert!(parse_stash_name(Some("HEAD")).is_err());
+    }
+
+    #[test]
+    fn test_apply_keeps_staged_changes() {
+        let repo = TestRepo::new();
+        repo.write("a.txt", "one\n");
+        repo.write("b.txt", "one\n");
+        let head = repo.commit_all("first");
+        repo.write("a.txt", "stashed\n");
+        assert!(push(None, false).unwrap());
+        repo.write("b.txt", "staged\n");
+        porcelain::add(&[PathBuf::from("b.txt")]).unwrap();
+
+        assert!(apply(0).unwrap());
+        assert_eq!(std::fs::read_to_string("a.txt").unwrap(), "stashed\n");
+        let index = Index::load().unwrap();
+        let tree = Index::from_tree(&OgitCommit::get(&head).unwrap().tree).unwrap();
+        assert_eq!(index.get(Path::new("a.txt")), tree.get(Path::new("a.txt")));
+        assert_ne!(index.get(Path::new("b.txt")), tree.get(Path::new("b.txt")));
+    }
+
+    #[test]
+    fn test_branch_switches_head_to_the_new_branch() {
+        let repo = TestRepo::new();
+        repo.write("a.txt", "one\n");
+        let start = repo.commit_all("first");
+        repo.write("a.txt", "stashed\n");
+        assert!(push(None, false).unwrap());
+        repo.write("b.txt", "two\n");
+        repo.commit_all("second");
+
+        assert!(branch("topic", 0).unwrap());
+        let head = filesystem::read_symbolic_ref("HEAD").unwrap();
+        assert_eq!(head.as_deref(), Some("refs/heads/topic"));
+        assert_eq!(filesystem::read_head_file().unwrap(), start);
+        assert_eq!(std::fs::read_to_string("a.txt").unwrap(), "stashed\n");
+        assert!(!Path::new("b.txt").exists());
+        let moved = reflog::read("HEAD").unwrap().pop().unwrap();
+        assert_eq!(moved.message, "checkout: moving from main to topic");
+        let created = reflog::read("refs/heads/topic").unwrap().pop().unwrap();
+        assert_eq!(created.message, format!("branch: Created from {start}"));
+        assert!(list().unwrap().is_empty());
+    }
+}
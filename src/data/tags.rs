@@ -0,0 +1,82 @@
+//! Annotated tags: objects naming another object, with who tagged it and a message.
+use super::{commits::OgitCommit, filesystem::get_object, objects::OgitObjectType, time};
+
+#[derive(Debug, Clone)]
+pub struct OgitTag {
+    /// Id of the tagged object
+    pub object: String,
+    pub variant: OgitObjectType,
+    pub name: String,
+    /// Old tags were made without a tagger
+    pub tagger: Option<(String, time::DateTime<time::FixedOffset>)>,
+    pub message: Vec<u8>,
+}
+
+impl OgitTag {
+    /// Read the tag from its object data, the headers up to the first empty line then the message.
+    pub fn parse(data: &[u8]) -> std::io::Result<Self> {
+        let invalid = |what: &str| std::io::Error::other(format!("invalid tag: {what}"));
+        let (headers, message) = match data.windows(2).position(|w| w == b"\n\n") {
+            Some(end) => (&data[..end], data[end + 2..].to_vec()),
+            None => (data, Vec::new()),
+        };
+        let headers = std::str::from_utf8(headers).map_err(|_| invalid("headers are not UTF-8"))?;
+        let mut object = None;
+        let mut variant = None;
+        let mut name = None;
+        let mut tagger = None;
+        for line in headers.lines() {
+            match line.split_once(' ') {
+                Some(("object", id)) => object = Some(id.to_string()),
+                Some(("type", kind)) => variant = Some(kind.parse().map_err(|_| invalid(kind))?),
+                Some(("tag", tag)) => name = Some(tag.to_string()),
+                Some(("tagger", identity)) => {
+                    tagger = Some(OgitCommit::split_identity_line(identity));
+                }
+                _ => return Err(invalid(line)),
+            }
+        }
+        Ok(Self {
+            object: object.ok_or_else(|| invalid("missing object"))?,
+            variant: variant.ok_or_else(|| invalid("missing type"))?,
+            name: name.ok_or_else(|| invalid("missing tag"))?,
+            tagger,
+            message,
+        })
+    }
+
+    pub fn get(id: &str) -> std::io::Result<Self> {
+        Self::parse(&get_object(id, Some(OgitObjectType::Tag))?.data)
+    }
+}
+
+/// What `id` leads to once every tag on the way is followed, `id` itself when it is no tag.
+pub fn peel(id: &str) -> std::io::Result<String> {
+    let mut object = get_object(id, None)?;
+    while object.variant == OgitObjectType::Tag {
+        object = get_object(&OgitTag::parse(&object.data)?.object, None)?;
+    }
+    Ok(object.hex_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tag() {
+        let tag = OgitTag::parse(
+            b"object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\ntype tree\ntag v1.0\n\
+              tagger A U Thor <author@example.com> 1700000000 +0100\n\nFirst release\n",
+        )
+        .unwrap();
+        assert_eq!(tag.object, "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+        assert_eq!(tag.variant, OgitObjectType::Tree);
+        assert_eq!(tag.name, "v1.0");
+        let (tagger, time) = tag.tagger.unwrap();
+        assert_eq!(tagger, "A U Thor <author@example.com>");
+        assert_eq!(time.timestamp(), 1_700_000_000);
+        assert_eq!(tag.message, b"First release\n");
+        assert!(OgitTag::parse(b"object abc\ntag v1\n\nno type\n").is_err());
+    }
+}
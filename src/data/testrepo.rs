@@ -0,0 +1,75 @@
+//! Scratch repositories for tests of commands that work on the current directory.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use super::{commits::OgitCommit, filesystem, porcelain, refs};
+
+/// The current directory belongs to the whole process, so only one repository is used at a time.
+static CURRENT: Mutex<()> = Mutex::new(());
+
+/// An empty repository on the unborn branch `main`, the current directory while it lives.
+pub struct TestRepo {
+    path: PathBuf,
+    previous: PathBuf,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl TestRepo {
+    pub fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let guard = CURRENT.lock().unwrap_or_else(PoisonError::into_inner);
+        let path = std::env::temp_dir().join(format!(
+            "ogit-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        let previous = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&path).unwrap();
+        filesystem::ogit_init().unwrap();
+        filesystem::write_symbolic_ref("HEAD", "refs/heads/main").unwrap();
+        Self {
+            path,
+            previous,
+            _guard: guard,
+        }
+    }
+
+    /// Write `content` to the working tree file `path`, creating its directories.
+    pub fn write(&self, path: &str, content: &str) {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, content).unwrap();
+    }
+
+    /// Stage every file of the working tree and commit them, returning the commit id.
+    pub fn commit_all(&self, message: &str) -> String {
+        porcelain::add(&[PathBuf::from(".")]).unwrap();
+        porcelain::commit(Some(format!("{message}\n")), false)
+            .unwrap()
+            .hex_string()
+    }
+
+    /// Move the branch, the index and the working tree back to the commit `id`.
+    pub fn rewind(&self, id: &str) {
+        let head = filesystem::read_head_file().unwrap();
+        let tree = |id: &str| OgitCommit::get(id).unwrap().tree;
+        porcelain::update_working_tree(Some(&tree(&head)), &tree(id), "rewind").unwrap();
+        refs::move_head(id, &head, &format!("reset: moving to {id}")).unwrap();
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(&self.previous);
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
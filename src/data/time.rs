@@ -1,9 +1,195 @@
-pub use chrono::prelude::*;
+use std::str::FromStr;
+
+use chrono::format::{Item, StrftimeItems};
+pub use chrono::{prelude::*, Duration, Months};
 
 pub fn get_current_local() -> DateTime<Local> {
     Local::now()
 }
 
+/// How dates are shown, as chosen with `--date`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `Thu Apr 7 15:13:13 2005 -0700`
+    #[default]
+    Default,
+    /// `2005-04-07 15:13:13 -0700`
+    Iso,
+    /// `2005-04-07T15:13:13-07:00`
+    IsoStrict,
+    /// `Thu, 7 Apr 2005 15:13:13 -0700`
+    Rfc,
+    /// `2005-04-07`
+    Short,
+    /// Seconds since the epoch
+    Unix,
+    /// Seconds since the epoch and the offset, as stored in commits
+    Raw,
+    /// How long ago, like `2 hours ago`
+    Relative,
+    /// A strftime format
+    Format(String),
+}
+
+impl FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(format) = s.strip_prefix("format:") {
+            // chrono panics on a bad spec when formatting, so it is checked here
+            if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                return Err(format!("invalid date format '{format}'"));
+            }
+            return Ok(Self::Format(format.to_string()));
+        }
+        match s {
+            "default" => Ok(Self::Default),
+            "iso" | "iso8601" => Ok(Self::Iso),
+            "iso-strict" | "iso8601-strict" => Ok(Self::IsoStrict),
+            "rfc" | "rfc2822" => Ok(Self::Rfc),
+            "short" => Ok(Self::Short),
+            "unix" => Ok(Self::Unix),
+            "raw" => Ok(Self::Raw),
+            "relative" => Ok(Self::Relative),
+            _ => Err(format!("unknown date format {s}")),
+        }
+    }
+}
+
+impl DateFormat {
+    /// Show `time` in this format, relative dates count back from `now`.
+    pub fn format(&self, time: &DateTime<FixedOffset>, now: &DateTime<FixedOffset>) -> String {
+        match self {
+            Self::Default => time.format("%a %b %-d %H:%M:%S %Y %z").to_string(),
+            Self::Iso => time.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+            Self::IsoStrict => time.to_rfc3339(),
+            Self::Rfc => time.to_rfc2822(),
+            Self::Short => time.format("%Y-%m-%d").to_string(),
+            Self::Unix => time.timestamp().to_string(),
+            Self::Raw => time.format("%s %z").to_string(),
+            Self::Relative => relative_date(time, now),
+            Self::Format(format) => time.format(format).to_string(),
+        }
+    }
+}
+
+fn plural(count: i64, unit: &str) -> String {
+    if count == 1 {
+        format!("{count} {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
+
+/// How long before `now` `time` was, rounded the way git does.
+pub fn relative_date(time: &DateTime<FixedOffset>, now: &DateTime<FixedOffset>) -> String {
+    let seconds = now.timestamp() - time.timestamp();
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+    if seconds < 90 {
+        return format!("{} ago", plural(seconds, "second"));
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return format!("{} ago", plural(minutes, "minute"));
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return format!("{} ago", plural(hours, "hour"));
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return format!("{} ago", plural(days, "day"));
+    }
+    if days < 70 {
+        return format!("{} ago", plural((days + 3) / 7, "week"));
+    }
+    if days < 365 {
+        return format!("{} ago", plural((days + 15) / 30, "month"));
+    }
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months == 0 {
+            return format!("{} ago", plural(years, "year"));
+        }
+        return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+    }
+    format!("{} ago", plural((days + 183) / 365, "year"))
+}
+
+/// Read a date the way options like `--since` accept them, relative to `now`.
+///
+/// Understands `now`, `yesterday`, `<n> <unit>s ago` (also written `<n>.<unit>s.ago`), `@<epoch>`,
+/// RFC 2822 dates and ISO 8601 dates with an optional time and offset. Without an offset the
+/// one of `now` is used.
+pub fn parse_date(text: &str, now: DateTime<FixedOffset>) -> Result<DateTime<FixedOffset>, String> {
+    let text = text.trim();
+    let invalid = || format!("invalid date '{text}'");
+    match text {
+        "now" => return Ok(now),
+        "yesterday" => return Ok(now - Duration::try_days(1).unwrap()),
+        _ => (),
+    }
+    if let Some(epoch) = text.strip_prefix('@') {
+        let epoch = epoch.parse().map_err(|_| invalid())?;
+        return DateTime::from_timestamp(epoch, 0)
+            .map(|time| time.with_timezone(now.offset()))
+            .ok_or_else(invalid);
+    }
+    if text.ends_with("ago") {
+        let words = text
+            .split([' ', '.'])
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>();
+        let [count, unit, "ago"] = words.as_slice() else {
+            return Err(invalid());
+        };
+        let count: u32 = count.parse().map_err(|_| invalid())?;
+        let unit = unit.strip_suffix('s').unwrap_or(unit);
+        let span = match unit {
+            "second" => Duration::try_seconds(count.into()),
+            "minute" => Duration::try_minutes(count.into()),
+            "hour" => Duration::try_hours(count.into()),
+            "day" => Duration::try_days(count.into()),
+            "week" => Duration::try_weeks(count.into()),
+            "month" => {
+                return now
+                    .checked_sub_months(Months::new(count))
+                    .ok_or_else(invalid)
+            }
+            "year" => {
+                return now
+                    .checked_sub_months(Months::new(count * 12))
+                    .ok_or_else(invalid)
+            }
+            _ => None,
+        };
+        let earlier = span.and_then(|span| now.checked_sub_signed(span));
+        return earlier.ok_or_else(invalid);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc2822(text) {
+        return Ok(time);
+    }
+    let text = text.replacen('T', " ", 1);
+    for format in ["%Y-%m-%d %H:%M:%S %z", "%Y-%m-%d %H:%M %z"] {
+        if let Ok(time) = DateTime::parse_from_str(&text, format) {
+            return Ok(time);
+        }
+    }
+    let local = NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(&text, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN))
+        })
+        .map_err(|_| invalid())?;
+    local
+        .and_local_timezone(*now.offset())
+        .single()
+        .ok_or_else(invalid)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -18,4 +204,39 @@ mod tests {
         let year = duration.as_secs() / (365 * 24 * 60 * 60);
         assert_eq!(time.year(), 1970 + year as i32);
     }
+
+    #[test]
+    fn test_parse_date() {
+        let now = DateTime::parse_from_rfc3339("2024-03-10T12:00:00+01:00").unwrap();
+        let parse = |text| parse_date(text, now).map(|time| time.to_rfc3339());
+        assert_eq!(parse("2 weeks ago").unwrap(), "2024-02-25T12:00:00+01:00");
+        assert_eq!(parse("1.month.ago").unwrap(), "2024-02-10T12:00:00+01:00");
+        assert_eq!(parse("yesterday").unwrap(), "2024-03-09T12:00:00+01:00");
+        assert_eq!(parse("2024-01-02").unwrap(), "2024-01-02T00:00:00+01:00");
+        assert_eq!(
+            parse("2024-01-02 03:04:05 -0500").unwrap(),
+            "2024-01-02T03:04:05-05:00"
+        );
+        assert_eq!(parse("@0").unwrap(), "1970-01-01T01:00:00+01:00");
+        assert!(parse("next tuesday").is_err());
+    }
+
+    #[test]
+    fn test_date_formats() {
+        let now = DateTime::parse_from_rfc3339("2024-03-10T12:00:00+01:00").unwrap();
+        let time = DateTime::parse_from_rfc3339("2005-04-07T15:13:13-07:00").unwrap();
+        let show = |format: &str| format.parse::<DateFormat>().unwrap().format(&time, &now);
+        assert_eq!(show("default"), "Thu Apr 7 15:13:13 2005 -0700");
+        assert_eq!(show("iso"), "2005-04-07 15:13:13 -0700");
+        assert_eq!(show("short"), "2005-04-07");
+        assert_eq!(show("unix"), "1112911993");
+        assert_eq!(show("format:%d/%m"), "07/04");
+        assert!("yesterday".parse::<DateFormat>().is_err());
+        assert!("format:%Q".parse::<DateFormat>().is_err());
+        let ago = |seconds| relative_date(&(now - Duration::try_seconds(seconds).unwrap()), &now);
+        assert_eq!(ago(1), "1 second ago");
+        assert_eq!(ago(3 * 3600), "3 hours ago");
+        assert_eq!(ago(20 * 86400), "3 weeks ago");
+        assert_eq!(ago(400 * 86400), "1 year, 1 month ago");
+    }
 }
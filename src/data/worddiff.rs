@@ -0,0 +1,251 @@
+//! Word level diff output for `--word-diff` and `--color-words`.
+//!
+//! Changed lines of a hunk are split into words and compared again, so prose edits show up
+//! as the words that changed instead of whole rewritten paragraphs.
+use std::str::FromStr;
+
+use super::xdiff::{self, Edit};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordDiffMode {
+    /// Changes are wrapped in `[-removed-]` and `{+added+}`
+    Plain,
+    /// Changes are only shown with colors
+    Color,
+    /// Line based format meant for scripts
+    Porcelain,
+}
+
+impl FromStr for WordDiffMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" | "plain" => Ok(Self::Plain),
+            "color" => Ok(Self::Color),
+            "porcelain" => Ok(Self::Porcelain),
+            _ => Err(format!("invalid --word-diff mode '{s}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Common,
+    Removed,
+    Added,
+}
+
+/// Byte ranges of the whitespace separated words in `text`.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut result = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                result.push((s, i));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        result.push((s, text.len()));
+    }
+    result
+}
+
+fn write_segment(out: &mut String, kind: Segment, text: &str, mode: WordDiffMode) {
+    if text.is_empty() {
+        return;
+    }
+    if mode == WordDiffMode::Porcelain {
+        let prefix = match kind {
+            Segment::Common => ' ',
+            Segment::Removed => '-',
+            Segment::Added => '+',
+        };
+        for (i, piece) in text.split('\n').enumerate() {
+            if i > 0 {
+                out.push_str("~\n");
+            }
+            if !piece.is_empty() {
+                out.push(prefix);
+                out.push_str(piece);
+                out.push('\n');
+            }
+        }
+        return;
+    }
+    let (open, close) = match (kind, mode) {
+        (Segment::Common, _) => ("", ""),
+        (Segment::Removed, WordDiffMode::Plain) => ("[-", "-]"),
+        (Segment::Added, WordDiffMode::Plain) => ("{+", "+}"),
+        (Segment::Removed, _) => (RED, RESET),
+        (Segment::Added, _) => (GREEN, RESET),
+    };
+    // markers never span a line break, so every line stays readable on its own
+    for (i, piece) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if !piece.is_empty() {
+            out.push_str(open);
+            out.push_str(piece);
+            out.push_str(close);
+        }
+    }
+}
+
+/// Word diff of one block of removed lines against the block of lines replacing them.
+fn format_block(old: &str, new: &str, mode: WordDiffMode) -> String {
+    let old_words = words(old);
+    let new_words = words(new);
+    let old_tokens = old_words
+        .iter()
+        .map(|&(s, e)| &old[s..e])
+        .collect::<Vec<_>>();
+    let new_tokens = new_words
+        .iter()
+        .map(|&(s, e)| &new[s..e])
+        .collect::<Vec<_>>();
+    let edits = xdiff::diff(&old_tokens, &new_tokens);
+
+    let mut out = String::new();
+    // everything common, including whitespace, is copied from the new text
+    let mut printed_up_to = 0;
+    let mut i = 0;
+    while i < edits.len() {
+        if !edits[i].is_change() {
+            i += 1;
+            continue;
+        }
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        let mut new_position = match edits[..i].iter().rev().find_map(|e| match e {
+            Edit::Equal { new, .. } | Edit::Insert { new } => Some(*new),
+            Edit::Delete { .. } => None,
+        }) {
+            Some(previous) => new_words[previous].1,
+            None => 0,
+        };
+        while i < edits.len() && edits[i].is_change() {
+            match edits[i] {
+                Edit::Delete { old } => removed.push(old),
+                Edit::Insert { new } => added.push(new),
+                Edit::Equal { .. } => unreachable!(),
+            }
+            i += 1;
+        }
+        if let Some(&first) = added.first() {
+            new_position = new_words[first].0;
+        }
+        write_segment(
+            &mut out,
+            Segment::Common,
+            &new[printed_up_to..new_position],
+            mode,
+        );
+        if let (Some(&first), Some(&last)) = (removed.first(), removed.last()) {
+            let text = &old[old_words[first].0..old_words[last].1];
+            write_segment(&mut out, Segment::Removed, text, mode);
+        }
+        printed_up_to = new_position;
+        if let (Some(&first), Some(&last)) = (added.first(), added.last()) {
+            let text = &new[new_words[first].0..new_words[last].1];
+            write_segment(&mut out, Segment::Added, text, mode);
+            printed_up_to = new_words[last].1;
+        }
+    }
+    write_segment(&mut out, Segment::Common, &new[printed_up_to..], mode);
+    if !out.ends_with('\n') {
+        // the last line had no newline, or only removed text followed it
+        out.push('\n');
+        if mode == WordDiffMode::Porcelain {
+            out.push_str("~\n");
+        }
+    }
+    out
+}
+
+/// Render the hunks of an edit script between `old_lines` and `new_lines` as a word diff.
+pub fn format_word_diff_hunks(
+    old_lines: &[&[u8]],
+    new_lines: &[&[u8]],
+    edits: &[Edit],
+    context: usize,
+    mode: WordDiffMode,
+) -> String {
+    let mut out = String::new();
+    for hunk in xdiff::hunks(edits, context) {
+        out.push_str(&hunk.header());
+        out.push('\n');
+        let (mut old_block, mut new_block) = (Vec::new(), Vec::new());
+        let mut hunk_edits = hunk.edits.iter().peekable();
+        while let Some(edit) = hunk_edits.next() {
+            match *edit {
+                Edit::Equal { old, .. } => {
+                    let line = String::from_utf8_lossy(old_lines[old]);
+                    write_segment(&mut out, Segment::Common, &line, mode);
+                    if !line.ends_with('\n') {
+                        out.push('\n');
+                    }
+                }
+                Edit::Delete { old } => old_block.extend_from_slice(old_lines[old]),
+                Edit::Insert { new } => new_block.extend_from_slice(new_lines[new]),
+            }
+            let block_ends = hunk_edits.peek().is_none_or(|next| !next.is_change());
+            if edit.is_change() && block_ends {
+                out.push_str(&format_block(
+                    &String::from_utf8_lossy(&old_block),
+                    &String::from_utf8_lossy(&new_block),
+                    mode,
+                ));
+                old_block.clear();
+                new_block.clear();
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_word_diff() {
+        assert_eq!(
+            format_block(
+                "hello big world\n",
+                "hello small world\n",
+                WordDiffMode::Plain
+            ),
+            "hello [-big-]{+small+} world\n"
+        );
+        assert_eq!(
+            format_block("second line here\n", "second line\n", WordDiffMode::Plain),
+            "second line[-here-]\n"
+        );
+        assert_eq!(
+            format_block("a b\n", "c d\n", WordDiffMode::Plain),
+            "[-a b-]{+c d+}\n"
+        );
+    }
+
+    #[test]
+    fn test_porcelain_word_diff() {
+        assert_eq!(
+            format_block(
+                "hello big world\n",
+                "hello small world\n",
+                WordDiffMode::Porcelain
+            ),
+            " hello \n-big\n+small\n  world\n~\n"
+        );
+    }
+}
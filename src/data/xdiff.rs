@@ -0,0 +1,293 @@
+//! Line level difference between two sequences, using Myers' O(ND) algorithm in linear space.
+//!
+//! More info: <http://www.xmailserver.org/diff2.pdf>
+
+/// A single step of the edit script turning the old sequence into the new one.
+///
+/// Indices point into the old and new sequences that were compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal { old: usize, new: usize },
+    Delete { old: usize },
+    Insert { new: usize },
+}
+
+impl Edit {
+    pub const fn is_change(&self) -> bool {
+        !matches!(self, Self::Equal { .. })
+    }
+}
+
+/// A group of changes together with the unchanged lines surrounding them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// Zero based index of the first old line covered by the hunk
+    pub old_start: usize,
+    pub old_len: usize,
+    /// Zero based index of the first new line covered by the hunk
+    pub new_start: usize,
+    pub new_len: usize,
+    pub edits: Vec<Edit>,
+}
+
+impl Hunk {
+    /// The `@@ -a,b +c,d @@` line introducing a hunk in a unified diff.
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{} +{} @@",
+            Self::range(self.old_start, self.old_len),
+            Self::range(self.new_start, self.new_len)
+        )
+    }
+    fn range(start: usize, len: usize) -> String {
+        // empty ranges name the line *before* the change, so they are not shifted to one based
+        match len {
+            0 => format!("{start},0"),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{len}", start + 1),
+        }
+    }
+}
+
+/// Split content into lines, keeping the line terminators so content can be rebuilt exactly.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|b| *b == b'\n').collect()
+}
+
+/// Compute the shortest edit script turning `old` into `new`.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    compare(old, new, 0, 0, &mut edits);
+    edits
+}
+
+/// Push the edits turning `old` into `new`, slices starting at `old_start` and `new_start`.
+///
+/// The middle snake of the shortest edit path splits the problem in two halves, so only the
+/// frontiers of one search are kept in memory at any time.
+fn compare<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    old_start: usize,
+    new_start: usize,
+    edits: &mut Vec<Edit>,
+) {
+    // common prefix and suffix never need the expensive search
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    edits.extend((0..prefix).map(|i| Edit::Equal {
+        old: old_start + i,
+        new: new_start + i,
+    }));
+    let (old_middle, new_middle) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let (old_offset, new_offset) = (old_start + prefix, new_start + prefix);
+    if old_middle.is_empty() {
+        edits.extend((0..new_middle.len()).map(|i| Edit::Insert {
+            new: new_offset + i,
+        }));
+    } else if new_middle.is_empty() {
+        edits.extend((0..old_middle.len()).map(|i| Edit::Delete {
+            old: old_offset + i,
+        }));
+    } else {
+        let (x0, y0, x1, y1) = middle_snake(old_middle, new_middle);
+        compare(
+            &old_middle[..x0],
+            &new_middle[..y0],
+            old_offset,
+            new_offset,
+            edits,
+        );
+        edits.extend((0..x1 - x0).map(|i| Edit::Equal {
+            old: old_offset + x0 + i,
+            new: new_offset + y0 + i,
+        }));
+        compare(
+            &old_middle[x1..],
+            &new_middle[y1..],
+            old_offset + x1,
+            new_offset + y1,
+            edits,
+        );
+    }
+    let (old_tail, new_tail) = (
+        old_start + old.len() - suffix,
+        new_start + new.len() - suffix,
+    );
+    edits.extend((0..suffix).map(|i| Edit::Equal {
+        old: old_tail + i,
+        new: new_tail + i,
+    }));
+}
+
+/// The snake in the middle of a shortest edit path, as `(x0, y0, x1, y1)`.
+///
+/// Searches forward from the start and backward from the end at the same time until the two
+/// frontiers overlap. The backward frontier counts how far each diagonal got from the end.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T]) -> (usize, usize, usize, usize) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    let index = |k: isize| (k + offset) as usize;
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = vec![0isize; 2 * max as usize + 3];
+    let next = |frontier: &[isize], d: isize, k: isize| {
+        if k == -d || (k != d && frontier[index(k - 1)] < frontier[index(k + 1)]) {
+            frontier[index(k + 1)]
+        } else {
+            frontier[index(k - 1)] + 1
+        }
+    };
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let start = next(&forward, d, k);
+            let mut x = start;
+            while x < n && x - k < m && old[x as usize] == new[(x - k) as usize] {
+                x += 1;
+            }
+            forward[index(k)] = x;
+            // the backward diagonal `delta - k` is this one, seen from the end
+            let reverse = delta - k;
+            if odd && (1 - d..d).contains(&reverse) && x + backward[index(reverse)] >= n {
+                return (
+                    start as usize,
+                    (start - k) as usize,
+                    x as usize,
+                    (x - k) as usize,
+                );
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let start = next(&backward, d, k);
+            let mut x = start;
+            while x < n && x - k < m && old[(n - x - 1) as usize] == new[(m - x + k - 1) as usize] {
+                x += 1;
+            }
+            backward[index(k)] = x;
+            let reverse = delta - k;
+            if !odd && (-d..=d).contains(&reverse) && x + forward[index(reverse)] >= n {
+                return (
+                    (n - x) as usize,
+                    (m - x + k) as usize,
+                    (n - start) as usize,
+                    (m - start + k) as usize,
+                );
+            }
+        }
+    }
+    unreachable!("the forward and backward searches always meet")
+}
+
+/// Group an edit script into hunks, keeping `context` unchanged lines around every change.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    // position in the old and new sequences before each edit
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in edits {
+        positions.push((old_pos, new_pos));
+        match edit {
+            Edit::Equal { .. } => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Delete { .. } => old_pos += 1,
+            Edit::Insert { .. } => new_pos += 1,
+        }
+    }
+    positions.push((old_pos, new_pos));
+
+    let changes = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.is_change())
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut result = Vec::new();
+    let mut group_start = 0;
+    while group_start < changes.len() {
+        let mut group_end = group_start;
+        // changes separated by less than two contexts worth of lines share a hunk
+        while group_end + 1 < changes.len()
+            && changes[group_end + 1] - changes[group_end] <= 2 * context + 1
+        {
+            group_end += 1;
+        }
+        let first = changes[group_start].saturating_sub(context);
+        let last = (changes[group_end] + context).min(edits.len() - 1);
+        let (old_start, new_start) = positions[first];
+        let (old_end, new_end) = positions[last + 1];
+        result.push(Hunk {
+            old_start,
+            old_len: old_end - old_start,
+            new_start,
+            new_len: new_end - new_start,
+            edits: edits[first..=last].to_vec(),
+        });
+        group_start = group_end + 1;
+    }
+    result
+}
+
+/// Count the inserted and deleted entries of an edit script.
+pub fn count_changes(edits: &[Edit]) -> (usize, usize) {
+    edits
+        .iter()
+        .fold((0, 0), |(added, deleted), edit| match edit {
+            Edit::Insert { .. } => (added + 1, deleted),
+            Edit::Delete { .. } => (added, deleted + 1),
+            Edit::Equal { .. } => (added, deleted),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_edit_script() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let edits = diff(&old, &new);
+        let (added, deleted) = count_changes(&edits);
+        // the shortest edit script for this classic example has length 5
+        assert_eq!(added + deleted, 5);
+        let rebuilt = edits
+            .iter()
+            .filter_map(|e| match e {
+                Edit::Equal { new: i, .. } | Edit::Insert { new: i } => Some(new[*i]),
+                Edit::Delete { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rebuilt, new);
+    }
+
+    #[test]
+    fn test_hunks_merge_nearby_changes() {
+        let old = (0..20).collect::<Vec<_>>();
+        let mut new = old.clone();
+        new[5] = 100;
+        new[9] = 101;
+        new[18] = 102;
+        let hunks = hunks(&diff(&old, &new), 3);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header(), "@@ -3,11 +3,11 @@");
+        assert_eq!(hunks[1].header(), "@@ -16,5 +16,5 @@");
+    }
+
+    #[test]
+    fn test_hunk_header_empty_side() {
+        let hunks = hunks(&diff::<&str>(&[], &["a", "b"]), 3);
+        assert_eq!(hunks[0].header(), "@@ -0,0 +1,2 @@");
+    }
+}
@@ -0,0 +1,323 @@
+//! Line level three-way merge of file content, following the diff3 algorithm.
+//!
+//! Both sides are compared against their common base, changes made by only one side are taken as
+//! they are and overlapping changes that differ become conflicts.
+//!
+//! More info: <https://www.cis.upenn.edu/~bcpierce/papers/diff3-short.pdf>
+use std::{fmt::Display, str::FromStr};
+
+use super::xdiff::{self, Edit};
+
+/// Length of the conflict marker lines, same as git.
+const MARKER_SIZE: usize = 7;
+
+/// How conflicting regions are written out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictStyle {
+    /// Only our and their version, lines both sides agree on are moved out of the conflict
+    #[default]
+    Merge,
+    /// Our, the base and their version, exactly as they are
+    Diff3,
+    /// Like `Diff3`, but with the agreeing lines moved out like `Merge` does
+    Zdiff3,
+}
+
+impl FromStr for ConflictStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merge" => Ok(Self::Merge),
+            "diff3" => Ok(Self::Diff3),
+            "zdiff3" => Ok(Self::Zdiff3),
+            _ => Err(format!("unknown conflict style '{s}'")),
+        }
+    }
+}
+
+impl Display for ConflictStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Merge => write!(f, "merge"),
+            Self::Diff3 => write!(f, "diff3"),
+            Self::Zdiff3 => write!(f, "zdiff3"),
+        }
+    }
+}
+
+/// Names put on the conflict markers.
+#[derive(Debug, Clone, Copy)]
+pub struct MergeLabels<'a> {
+    pub ours: &'a str,
+    pub base: &'a str,
+    pub theirs: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeOutput {
+    pub content: Vec<u8>,
+    /// Number of conflicting regions written with markers
+    pub conflicts: usize,
+}
+
+/// A run of base lines `base_start..base_end` replaced by `side_start..side_end` of one side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Change {
+    base_start: usize,
+    base_end: usize,
+    side_start: usize,
+    side_end: usize,
+}
+
+fn changes(base: &[&[u8]], side: &[&[u8]]) -> Vec<Change> {
+    let mut result: Vec<Change> = Vec::new();
+    let (mut base_position, mut side_position) = (0, 0);
+    let mut in_change = false;
+    for edit in xdiff::diff(base, side) {
+        if !edit.is_change() {
+            in_change = false;
+        } else if !in_change {
+            result.push(Change {
+                base_start: base_position,
+                base_end: base_position,
+                side_start: side_position,
+                side_end: side_position,
+            });
+            in_change = true;
+        }
+        match edit {
+            Edit::Equal { .. } => {
+                base_position += 1;
+                side_position += 1;
+            }
+            Edit::Delete { .. } => {
+                base_position += 1;
+                result.last_mut().unwrap().base_end = base_position;
+            }
+            Edit::Insert { .. } => {
+                side_position += 1;
+                result.last_mut().unwrap().side_end = side_position;
+            }
+        }
+    }
+    result
+}
+
+/// Lines of one side covering the base region `start..end`, given the changes it made inside it.
+///
+/// `offset` tracks how far side line numbers are ahead of base line numbers outside changes.
+fn side_range(changes: &[Change], start: usize, end: usize, offset: &mut isize) -> (usize, usize) {
+    let range = match (changes.first(), changes.last()) {
+        (Some(first), Some(last)) => (
+            first.side_start - (first.base_start - start),
+            last.side_end + (end - last.base_end),
+        ),
+        _ => (
+            start.saturating_add_signed(*offset),
+            end.saturating_add_signed(*offset),
+        ),
+    };
+    *offset = range.1 as isize - end as isize;
+    range
+}
+
+fn push_lines(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        out.extend_from_slice(line);
+    }
+}
+
+/// Markers have to start on a line of their own, even after a last line without a newline.
+fn push_marker(out: &mut Vec<u8>, marker: u8, label: &str) {
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    out.extend(std::iter::repeat_n(marker, MARKER_SIZE));
+    if !label.is_empty() {
+        out.push(b' ');
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(b'\n');
+}
+
+fn push_conflict(
+    out: &mut Vec<u8>,
+    ours: &[&[u8]],
+    base: &[&[u8]],
+    theirs: &[&[u8]],
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) {
+    let (ours, theirs, suffix) = if style == ConflictStyle::Diff3 {
+        (ours, theirs, &[][..])
+    } else {
+        // lines both sides added the same way are not part of the conflict
+        let prefix = ours.iter().zip(theirs).take_while(|(a, b)| a == b).count();
+        push_lines(out, &ours[..prefix]);
+        let (ours, theirs) = (&ours[prefix..], &theirs[prefix..]);
+        let suffix = ours
+            .iter()
+            .rev()
+            .zip(theirs.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        (
+            &ours[..ours.len() - suffix],
+            &theirs[..theirs.len() - suffix],
+            &ours[ours.len() - suffix..],
+        )
+    };
+    push_marker(out, b'<', labels.ours);
+    push_lines(out, ours);
+    if style != ConflictStyle::Merge {
+        push_marker(out, b'|', labels.base);
+        push_lines(out, base);
+    }
+    push_marker(out, b'=', "");
+    push_lines(out, theirs);
+    push_marker(out, b'>', labels.theirs);
+    push_lines(out, suffix);
+}
+
+/// Merge the changes `ours` and `theirs` made to `base`.
+pub fn merge(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) -> MergeOutput {
+    let base_lines = xdiff::split_lines(base);
+    let our_lines = xdiff::split_lines(ours);
+    let their_lines = xdiff::split_lines(theirs);
+    let our_changes = changes(&base_lines, &our_lines);
+    let their_changes = changes(&base_lines, &their_lines);
+
+    let mut out = Vec::new();
+    let mut conflicts = 0;
+    let (mut next_ours, mut next_theirs) = (0, 0);
+    let (mut our_offset, mut their_offset) = (0isize, 0isize);
+    let mut position = 0;
+    loop {
+        let start = match (our_changes.get(next_ours), their_changes.get(next_theirs)) {
+            (Some(a), Some(b)) => a.base_start.min(b.base_start),
+            (Some(a), None) => a.base_start,
+            (None, Some(b)) => b.base_start,
+            (None, None) => break,
+        };
+        push_lines(&mut out, &base_lines[position..start]);
+
+        // grow the region until no change of either side touches it
+        let (first_ours, first_theirs) = (next_ours, next_theirs);
+        let mut end = start;
+        loop {
+            let mut grew = false;
+            while let Some(change) = our_changes.get(next_ours).filter(|c| c.base_start <= end) {
+                end = end.max(change.base_end);
+                next_ours += 1;
+                grew = true;
+            }
+            while let Some(change) = their_changes
+                .get(next_theirs)
+                .filter(|c| c.base_start <= end)
+            {
+                end = end.max(change.base_end);
+                next_theirs += 1;
+                grew = true;
+            }
+            if !grew {
+                break;
+            }
+        }
+        let ours_in_region = &our_changes[first_ours..next_ours];
+        let theirs_in_region = &their_changes[first_theirs..next_theirs];
+        let (our_start, our_end) = side_range(ours_in_region, start, end, &mut our_offset);
+        let (their_start, their_end) = side_range(theirs_in_region, start, end, &mut their_offset);
+        let ours_side = &our_lines[our_start..our_end];
+        let theirs_side = &their_lines[their_start..their_end];
+
+        if theirs_in_region.is_empty() || ours_side == theirs_side {
+            push_lines(&mut out, ours_side);
+        } else if ours_in_region.is_empty() {
+            push_lines(&mut out, theirs_side);
+        } else {
+            push_conflict(
+                &mut out,
+                ours_side,
+                &base_lines[start..end],
+                theirs_side,
+                labels,
+                style,
+            );
+            conflicts += 1;
+        }
+        position = end;
+    }
+    push_lines(&mut out, &base_lines[position..]);
+    MergeOutput {
+        content: out,
+        conflicts,
+    }
+}
+
+/// Whether `content` still holds conflict markers left by a merge.
+pub fn has_conflict_markers(content: &[u8]) -> bool {
+    xdiff::split_lines(content).iter().any(|line| {
+        [b'<', b'>', b'=']
+            .iter()
+            .any(|marker| line.starts_with(&[*marker; MARKER_SIZE]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABELS: MergeLabels = MergeLabels {
+        ours: "HEAD",
+        base: "base",
+        theirs: "topic",
+    };
+
+    #[test]
+    fn test_clean_merge() {
+        let base = b"1\n2\n3\n4\n5\n6\n7\n";
+        let ours = b"one\n2\n3\n4\n5\n6\n7\n";
+        let theirs = b"1\n2\n3\n4\n5\n6\nseven\neight\n";
+        let merged = merge(base, ours, theirs, &LABELS, ConflictStyle::Merge);
+        assert_eq!(merged.conflicts, 0);
+        assert_eq!(merged.content, b"one\n2\n3\n4\n5\n6\nseven\neight\n");
+    }
+
+    #[test]
+    fn test_conflict_styles() {
+        let base = b"a\nb\nc\n";
+        let ours = b"a\nx\nsame\nc\n";
+        let theirs = b"a\ny\nsame\nc\n";
+        let merged = merge(base, ours, theirs, &LABELS, ConflictStyle::Merge);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            String::from_utf8(merged.content).unwrap(),
+            "a\n<<<<<<< HEAD\nx\n=======\ny\n>>>>>>> topic\nsame\nc\n"
+        );
+        let merged = merge(base, ours, theirs, &LABELS, ConflictStyle::Diff3);
+        assert_eq!(
+            String::from_utf8(merged.content).unwrap(),
+            "a\n<<<<<<< HEAD\nx\nsame\n||||||| base\nb\n=======\ny\nsame\n>>>>>>> topic\nc\n"
+        );
+        let merged = merge(base, ours, theirs, &LABELS, ConflictStyle::Zdiff3);
+        assert_eq!(
+            String::from_utf8(merged.content).unwrap(),
+            "a\n<<<<<<< HEAD\nx\n||||||| base\nb\n=======\ny\n>>>>>>> topic\nsame\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_identical_changes_do_not_conflict() {
+        let merged = merge(b"a\n", b"b\n", b"b\n", &LABELS, ConflictStyle::Merge);
+        assert_eq!(merged.conflicts, 0);
+        assert_eq!(merged.content, b"b\n");
+        assert!(!has_conflict_markers(&merged.content));
+    }
+}
@@ -1,11 +1,25 @@
 mod cli;
 mod data;
 
+use std::io::IsTerminal;
+
 use cli::prelude::*;
-use data::porcelain;
+use data::{
+    apply::ApplyOptions,
+    catfile::{BatchMode, CatFileMode},
+    foreachref::ForEachRefOptions,
+    lsfiles::LsFilesOptions,
+    lstree::LsTreeOptions,
+    merge::{FastForward, MergeOptions},
+    porcelain,
+    rebase::RebaseOptions,
+    reset::ResetMode,
+    sequencer::{Action, PickOptions},
+    showref::ShowRefOptions,
+};
 
 fn main() {
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(git_style_args(std::env::args()));
     match cli.debug {
         0 => eprint!(""),
         1 => eprintln!("Some debug info is displayed"),
@@ -14,10 +28,165 @@ fn main() {
     };
     match cli.command {
         Commands::Init => functions::init_cmd(),
-        Commands::HashObject { file } => functions::hash_object_cmd(&file),
-        Commands::CatObject { object_id } => functions::cat_object_cmd(&object_id),
+        Commands::HashObject {
+            write,
+            object_type,
+            stdin,
+            stdin_paths,
+            literally,
+            no_filters: _,
+            files,
+        } => functions::hash_object_cmd(&files, &object_type, write, stdin, stdin_paths, literally),
+        Commands::CatObject {
+            show_type,
+            size,
+            exists,
+            pretty,
+            batch,
+            batch_check,
+            batch_all_objects,
+            object,
+        } => {
+            if batch || batch_check {
+                let mode = if batch {
+                    BatchMode::Contents
+                } else {
+                    BatchMode::Check
+                };
+                functions::cat_file_batch_cmd(mode, batch_all_objects);
+            } else {
+                let mode = if show_type {
+                    CatFileMode::Type
+                } else if size {
+                    CatFileMode::Size
+                } else if exists {
+                    CatFileMode::Exists
+                } else if pretty {
+                    CatFileMode::Pretty
+                } else {
+                    CatFileMode::Raw
+                };
+                functions::cat_object_cmd(&object.unwrap_or_default(), mode);
+            }
+        }
         Commands::WriteTree { directory } => functions::write_tree_cmd(directory.as_deref()),
         Commands::ReadTree { tree_id } => functions::read_tree_cmd(&tree_id),
+        Commands::LsTree {
+            recursive,
+            show_trees,
+            only_trees,
+            long,
+            name_only,
+            null_terminated,
+            tree_ish,
+            paths,
+        } => functions::ls_tree_cmd(
+            &tree_ish,
+            &paths,
+            &LsTreeOptions {
+                recursive,
+                show_trees,
+                only_trees,
+                long,
+                name_only,
+                null_terminated,
+            },
+        ),
+        Commands::LsFiles {
+            stage,
+            modified,
+            deleted,
+            others,
+            ignored,
+            exclude_standard,
+            null_terminated,
+        } => functions::ls_files_cmd(&LsFilesOptions {
+            stage,
+            modified,
+            deleted,
+            others,
+            ignored,
+            exclude_standard,
+            null_terminated,
+        }),
+        Commands::UpdateRef {
+            message,
+            delete,
+            no_deref,
+            stdin,
+            name,
+            values,
+        } => {
+            let message = message.unwrap_or_default();
+            match name {
+                Some(name) if !stdin => {
+                    functions::update_ref_cmd(&name, &values, delete, no_deref, &message)
+                }
+                _ => functions::update_ref_stdin_cmd(no_deref, &message),
+            }
+        }
+        Commands::SymbolicRef {
+            quiet,
+            short,
+            delete,
+            message,
+            name,
+            target,
+        } => functions::symbolic_ref_cmd(
+            &name,
+            target.as_deref(),
+            delete,
+            quiet,
+            short,
+            message.as_deref(),
+        ),
+        Commands::ShowRef {
+            head,
+            heads,
+            tags,
+            hash,
+            dereference,
+            verify,
+            quiet,
+            patterns,
+        } => functions::show_ref_cmd(
+            &patterns,
+            &ShowRefOptions {
+                head,
+                heads,
+                tags,
+                hash,
+                dereference,
+                verify,
+            },
+            quiet,
+        ),
+        Commands::ForEachRef {
+            format,
+            sort,
+            count,
+            points_at,
+            contains,
+            no_contains,
+            merged,
+            no_merged,
+            patterns,
+        } => functions::for_each_ref_cmd(&ForEachRefOptions {
+            format,
+            sort,
+            count,
+            patterns,
+            points_at,
+            contains,
+            no_contains,
+            merged,
+            no_merged,
+        }),
+        Commands::PackRefs {
+            all,
+            prune: _,
+            no_prune,
+        } => functions::pack_refs_cmd(all, !no_prune),
         Commands::CommitTree {
             tree_id,
             parent,
@@ -25,8 +194,230 @@ fn main() {
         } => {
             functions::commit_tree_cmd(&tree_id, parent.as_deref(), message);
         }
-        Commands::Commit { message } => functions::commit_cmd(message),
-        Commands::Log { commit } => functions::log_cmd(commit),
+        Commands::Commit { message, all } => functions::commit_cmd(message, all),
+        Commands::Add { pathspecs } => functions::add_cmd(&pathspecs),
+        Commands::Log {
+            revisions,
+            walk,
+            graph,
+            pretty,
+            decorate,
+            no_decorate,
+            format,
+            renames,
+        } => functions::log_cmd(
+            &revisions,
+            &walk.walk_options(),
+            &renames.diff_options(true),
+            &format.diff_format(false),
+            graph,
+            &pretty.pretty_options(),
+            decorate || (!no_decorate && std::io::stdout().is_terminal()),
+        ),
         Commands::Checkout { commit } => porcelain::checkout(&commit),
+        Commands::DiffTree {
+            recursive,
+            name_status,
+            renames,
+            tree_ish,
+            other_tree_ish,
+        } => functions::diff_tree_cmd(
+            &tree_ish,
+            other_tree_ish.as_deref(),
+            &renames.diff_options(recursive),
+            name_status,
+        ),
+        Commands::Diff {
+            format,
+            renames,
+            cached,
+            commits,
+        } => functions::diff_cmd(
+            &commits,
+            cached,
+            &renames.diff_options(true),
+            &format.diff_format(true),
+        ),
+        Commands::Show {
+            pretty,
+            format,
+            renames,
+            objects,
+        } => functions::show_cmd(
+            &objects,
+            &pretty.pretty_options(),
+            &renames.diff_options(true),
+            &format.diff_format(true),
+        ),
+        Commands::Apply {
+            check,
+            stat,
+            index,
+            cached,
+            reverse,
+            strip,
+            context,
+            reject,
+            patches,
+        } => functions::apply_cmd(
+            &patches,
+            strip,
+            stat,
+            &ApplyOptions {
+                check,
+                index,
+                cached,
+                reverse,
+                reject,
+                context,
+            },
+        ),
+        Commands::CommitGraph {
+            action: CommitGraphCommand::Write,
+        } => functions::commit_graph_write_cmd(),
+        Commands::MergeBase {
+            all,
+            octopus,
+            is_ancestor,
+            fork_point,
+            commits,
+        } => functions::merge_base_cmd(&commits, all, octopus, is_ancestor, fork_point),
+        Commands::Merge {
+            resume,
+            abort,
+            ff_only,
+            no_ff,
+            squash,
+            conflict,
+            message,
+            rev,
+        } => functions::merge_cmd(
+            rev.as_deref(),
+            resume,
+            abort,
+            &MergeOptions {
+                fast_forward: if ff_only {
+                    FastForward::Only
+                } else if no_ff {
+                    FastForward::Never
+                } else {
+                    FastForward::Allow
+                },
+                squash,
+                style: conflict,
+                message,
+            },
+        ),
+        Commands::CherryPick {
+            resume,
+            skip,
+            abort,
+            no_commit,
+            record_origin,
+            mainline,
+            conflict,
+            commits,
+        } => functions::sequencer_cmd(
+            Action::Pick,
+            &commits,
+            resume,
+            skip,
+            abort,
+            &PickOptions {
+                no_commit,
+                record_origin,
+                mainline,
+                style: conflict,
+                ..Default::default()
+            },
+        ),
+        Commands::Revert {
+            resume,
+            skip,
+            abort,
+            no_commit,
+            mainline,
+            conflict,
+            commits,
+        } => functions::sequencer_cmd(
+            Action::Revert,
+            &commits,
+            resume,
+            skip,
+            abort,
+            &PickOptions {
+                no_commit,
+                mainline,
+                style: conflict,
+                ..Default::default()
+            },
+        ),
+        Commands::Rebase {
+            interactive,
+            onto,
+            autosquash,
+            exec,
+            conflict,
+            resume,
+            skip,
+            abort,
+            upstream,
+        } => functions::rebase_cmd(
+            upstream.as_deref(),
+            resume,
+            skip,
+            abort,
+            &RebaseOptions {
+                onto,
+                interactive,
+                autosquash,
+                exec,
+                style: conflict,
+            },
+        ),
+        Commands::Reset {
+            soft,
+            mixed: _,
+            hard,
+            keep,
+            merge,
+            rev,
+            paths,
+        } => {
+            let mode = match (soft, hard, keep, merge) {
+                (true, ..) => ResetMode::Soft,
+                (_, true, ..) => ResetMode::Hard,
+                (_, _, true, _) => ResetMode::Keep,
+                (.., true) => ResetMode::Merge,
+                _ => ResetMode::Mixed,
+            };
+            functions::reset_cmd(&rev, &paths, mode);
+        }
+        Commands::Stash { push, action } => {
+            functions::stash_cmd(action.unwrap_or(StashCommand::Push(push)));
+        }
+        Commands::FormatPatch {
+            output_directory,
+            stdout,
+            cover_letter,
+            numbered,
+            start_number,
+            subject_prefix,
+            range,
+        } => functions::format_patch_cmd(
+            &range,
+            output_directory.as_deref(),
+            stdout,
+            cover_letter,
+            numbered,
+            start_number,
+            &subject_prefix,
+        ),
+        Commands::Am {
+            resume,
+            skip,
+            abort,
+            mbox,
+        } => functions::am_cmd(&mbox, resume, skip, abort),
     };
 }